@@ -4,6 +4,8 @@ use std::collections::BTreeMap;
 use std::path::PathBuf;
 use zellij_tile::prelude::*;
 
+mod markdown;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TaskState {
     Wip,
@@ -11,61 +13,185 @@ pub enum TaskState {
     Done,
 }
 
+impl TaskState {
+    fn as_str(self) -> &'static str {
+        match self {
+            TaskState::Wip => "wip",
+            TaskState::Todo => "todo",
+            TaskState::Done => "done",
+        }
+    }
+}
+
+/// A task deleted into `.yaks/.trash/<stamp>/<path>`, `stamp` being the
+/// UTC date and time it was deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashEntry {
+    pub stamp: String,
+    pub path: String,
+}
+
 pub struct TaskRepository {
     yaks_dir: PathBuf,
+    store: Box<dyn TaskStore>,
+    /// Refuse every change to the tasks, for `read_only`.
+    read_only: bool,
 }
 
 impl Default for TaskRepository {
     fn default() -> Self {
-        Self {
-            yaks_dir: PathBuf::new(),
-        }
+        Self::new(PathBuf::new())
     }
 }
 
 impl TaskRepository {
+    /// Tasks kept the yx way, a directory per task under `yaks_dir`.
     pub fn new(yaks_dir: PathBuf) -> Self {
-        Self { yaks_dir }
+        let store = Box::new(DirectoryStore {
+            yaks_dir: yaks_dir.clone(),
+            index: None,
+        });
+        Self::with_store(yaks_dir, store)
+    }
+
+    /// Tasks kept in `store`, with the plugin's own files still in `yaks_dir`.
+    pub fn with_store(yaks_dir: PathBuf, store: Box<dyn TaskStore>) -> Self {
+        Self {
+            yaks_dir,
+            store,
+            read_only: false,
+        }
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    fn check_writable(&self) -> std::io::Result<()> {
+        match self.read_only {
+            true => Err(read_only_error()),
+            false => Ok(()),
+        }
     }
 
     pub fn yaks_dir(&self) -> &PathBuf {
         &self.yaks_dir
     }
 
-    pub fn list_tasks(&self) -> Vec<(String, usize)> {
-        let mut tasks = Vec::new();
-        if self.yaks_dir.exists() {
-            self.walk_dir(&self.yaks_dir, 0, &mut tasks);
+    pub fn get_field(&self, task_path: &str, field: &str) -> Option<String> {
+        self.read_field(task_path, field).ok().flatten()
+    }
+
+    pub fn read_field(&self, task_path: &str, field: &str) -> Result<Option<String>, String> {
+        self.store.read_field(task_path, field)
+    }
+
+    pub fn set_field(&self, task_path: &str, field: &str, value: &str) -> std::io::Result<()> {
+        self.check_writable()?;
+        self.store.set_field(task_path, field, value)
+    }
+
+    pub fn clear_field(&self, task_path: &str, field: &str) -> std::io::Result<()> {
+        self.check_writable()?;
+        self.store.clear_field(task_path, field)
+    }
+
+    pub fn field_names(&self, task_path: &str) -> Vec<String> {
+        self.store.field_names(task_path)
+    }
+
+    pub fn add_task(&self, parent: &str, name: &str) -> std::io::Result<String> {
+        self.check_writable()?;
+        self.store.add_task(parent, name)
+    }
+
+    pub fn move_task(&self, task_path: &str, parent: &str) -> std::io::Result<String> {
+        self.check_writable()?;
+        self.store.move_task(task_path, parent)
+    }
+
+    pub fn remove_task(&self, task_path: &str) -> std::io::Result<()> {
+        self.check_writable()?;
+        self.store.remove_task(task_path)
+    }
+
+    pub fn load_snapshot(&self) -> Result<Vec<TaskLine>, String> {
+        self.store.load_snapshot()
+    }
+
+    pub fn cached_snapshot(&self) -> Option<Vec<TaskLine>> {
+        self.store.cached_snapshot()
+    }
+
+    pub fn fetch(&self) {
+        self.store.fetch()
+    }
+
+    pub fn web_response(&self, status: u16, body: &[u8]) -> bool {
+        self.store.web_response(status, body)
+    }
+
+    /// Path to the file holding the plugin's saved UI state.
+    pub fn ui_state_path(&self) -> PathBuf {
+        self.yaks_dir.join(".yak-map-state")
+    }
+
+    pub fn load_ui_state(&self) -> UiState {
+        std::fs::read_to_string(self.ui_state_path())
+            .map(|s| UiState::parse(&s))
+            .unwrap_or_default()
+    }
+
+    /// Path to the plugin's debug log, kept out of the task tree by its dot.
+    pub fn log_path(&self) -> PathBuf {
+        self.yaks_dir.join(".log").join("yak-map.log")
+    }
+
+    pub fn append_log(&self, line: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let path = self.log_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
         }
-        tasks
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}", line)
     }
 
-    fn walk_dir(&self, dir: &std::path::Path, depth: usize, tasks: &mut Vec<(String, usize)>) {
-        if let Ok(entries) = std::fs::read_dir(dir) {
-            let mut entries: Vec<_> = entries.filter_map(|e| e.ok()).collect();
-            entries.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    pub fn save_ui_state(&self, ui_state: &UiState) -> std::io::Result<()> {
+        std::fs::write(self.ui_state_path(), ui_state.serialize())
+    }
 
-            for entry in entries {
-                let path = entry.path();
-                if path.is_dir() {
-                    if let Ok(relative) = path.strip_prefix(&self.yaks_dir) {
-                        let task_path = relative.to_string_lossy().replace('\\', "/");
-                        if !task_path.starts_with('.') {
-                            tasks.push((task_path.clone(), depth));
-                            self.walk_dir(&path, depth + 1, tasks);
-                        }
-                    }
-                }
+    /// Path to the per-project configuration, layered under the layout's.
+    pub fn config_path(&self) -> PathBuf {
+        self.yaks_dir.join(".yak-map.toml")
+    }
+
+    pub fn config_modified(&self) -> Option<std::time::SystemTime> {
+        std::fs::metadata(self.config_path())
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    /// Keys of the project configuration file, flattened like the layout's.
+    /// A missing file is no keys; one that cannot be read or parsed is an
+    /// error naming the file and line.
+    pub fn load_config_file(&self) -> Result<BTreeMap<String, String>, String> {
+        let path = self.config_path();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                parse_config_file(&contents).map_err(|e| format!("{}: {}", path.display(), e))
             }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
         }
     }
 
-    pub fn get_field(&self, task_path: &str, field: &str) -> Option<String> {
-        let field_path = self.yaks_dir.join(task_path).join(field);
-        std::fs::read_to_string(&field_path)
-            .ok()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
+    /// Path to the keybinding cheatsheet written by `:cheatsheet`.
+    pub fn cheatsheet_path(&self) -> PathBuf {
+        self.yaks_dir.join(".yak-map-keys.md")
     }
 
     /// Path to the context.md file for a task (may not exist yet).
@@ -73,1028 +199,14254 @@ impl TaskRepository {
         self.yaks_dir.join(task_path).join("context.md")
     }
 
-    pub fn get_task(&self, path: &str, depth: usize) -> TaskLine {
-        let state_str = self.get_field(path, "state");
-        let state = match state_str.as_deref() {
-            Some("wip") => TaskState::Wip,
-            Some("done") => TaskState::Done,
-            _ => TaskState::Todo,
+    /// Append a line to a task's context.md, creating the file if needed and
+    /// starting a new line if the file does not end with one.
+    pub fn append_context(&self, task_path: &str, line: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        self.check_writable()?;
+        let path = self.context_path(task_path);
+        let existing = std::fs::read(&path).unwrap_or_default();
+        let separator = if existing.is_empty() || existing.ends_with(b"\n") {
+            ""
+        } else {
+            "\n"
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        writeln!(file, "{}{}", separator, line)
+    }
+
+    /// Files in a task's directory other than its fields and context.md,
+    /// such as designs, logs or patches, as paths relative to the task.
+    /// Hidden directories (`.attachments/`) are searched too; other
+    /// directories are subtasks and are left out.
+    pub fn task_files(&self, task_path: &str) -> Vec<String> {
+        let mut files = Vec::new();
+        collect_files(&self.yaks_dir.join(task_path), "", &mut files);
+        files.sort();
+        files
+    }
+
+    /// Whether tasks are kept a directory each, so that a recurring task
+    /// can be archived and recreated with `roll_over`.
+    pub fn keeps_task_dirs(&self) -> bool {
+        self.store.keeps_task_dirs()
+    }
+
+    /// Move a finished recurring task to `.archive/<path>@<stamp>` and
+    /// recreate it in place for its next occurrence, due `next_due`. Fields
+    /// and files are carried over except per-occurrence ones (state,
+    /// agent-status, branch, due); subtasks and notes stay in the archive.
+    /// The next occurrence is built beside the archive and swapped in last,
+    /// so a failure leaves the task as it was. Returns where it was
+    /// archived, or `None` if it is no longer done, as when another pane
+    /// rolled it over first.
+    pub fn roll_over(
+        &self,
+        task_path: &str,
+        stamp: &str,
+        next_due: i64,
+    ) -> std::io::Result<Option<PathBuf>> {
+        self.check_writable()?;
+        let task_dir = self.yaks_dir.join(task_path);
+        let archive_base = self
+            .yaks_dir
+            .join(".archive")
+            .join(format!("{}@{}", task_path, stamp));
+        let mut archive = archive_base.clone();
+        let mut n = 2;
+        while archive.exists() {
+            archive = PathBuf::from(format!("{}-{}", archive_base.display(), n));
+            n += 1;
+        }
+        if let Some(parent) = archive.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let staging = PathBuf::from(format!("{}.partial", archive.display()));
+        // Left by a roll-over that was cut short
+        let _ = std::fs::remove_dir_all(&staging);
+        let built = build_next_occurrence(&task_dir, &staging, next_due);
+        let is_done = self.get_field(task_path, "state").as_deref() == Some("done");
+        if built.is_err() || !is_done {
+            // Only a leftover: the task itself was not touched
+            let _ = std::fs::remove_dir_all(&staging);
+            return built.map(|()| None);
+        }
+        std::fs::rename(&task_dir, &archive)?;
+        if let Err(e) = std::fs::rename(&staging, &task_dir) {
+            let _ = std::fs::rename(&archive, &task_dir);
+            let _ = std::fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+        Ok(Some(archive))
+    }
+
+    /// Deleted tasks that can be restored, newest first.
+    pub fn trash_entries(&self) -> Vec<TrashEntry> {
+        let trash = self.yaks_dir.join(TRASH_DIR);
+        let Ok(dirs) = std::fs::read_dir(&trash) else {
+            return Vec::new();
         };
+        let mut entries = Vec::new();
+        for dir in dirs.filter_map(|e| e.ok()) {
+            let stamp = dir.file_name().to_string_lossy().into_owned();
+            let list = std::fs::read_to_string(dir.path().join(TRASH_LIST)).unwrap_or_default();
+            for path in list.lines().filter(|path| dir.path().join(path).is_dir()) {
+                entries.push(TrashEntry {
+                    stamp: stamp.clone(),
+                    path: path.to_string(),
+                });
+            }
+        }
+        entries.sort_by(|a, b| b.stamp.cmp(&a.stamp).then_with(|| a.path.cmp(&b.path)));
+        entries
+    }
+
+    /// Move a deleted task back to its path, or under its nearest ancestor
+    /// that still exists, with a numeric suffix if the name is taken there.
+    /// Returns its new path.
+    pub fn restore_from_trash(&self, entry: &TrashEntry) -> std::io::Result<String> {
+        self.check_writable()?;
+        let stamp_dir = self.yaks_dir.join(TRASH_DIR).join(&entry.stamp);
+        let (mut parent, name) = entry.path.rsplit_once('/').unwrap_or(("", &entry.path));
+        while !parent.is_empty() && !self.yaks_dir.join(parent).is_dir() {
+            parent = parent.rsplit_once('/').map_or("", |(parent, _)| parent);
+        }
+        let path = free_child_path(parent, name, |path| self.yaks_dir.join(path).exists());
+        std::fs::rename(stamp_dir.join(&entry.path), self.yaks_dir.join(&path))?;
+        let list = std::fs::read_to_string(stamp_dir.join(TRASH_LIST)).unwrap_or_default();
+        let remaining: Vec<&str> = list.lines().filter(|line| *line != entry.path).collect();
+        if remaining.is_empty() {
+            std::fs::remove_dir_all(&stamp_dir)?;
+        } else {
+            std::fs::write(stamp_dir.join(TRASH_LIST), remaining.join("\n") + "\n")?;
+        }
+        Ok(path)
+    }
 
-        let name = self
-            .get_field(path, "name")
-            .unwrap_or_else(|| path.split('/').last().unwrap_or(path).to_string());
+    /// Permanently remove everything deleted before `day`, returning how
+    /// many deletions went.
+    pub fn empty_trash(&self, day: i64) -> std::io::Result<usize> {
+        self.check_writable()?;
+        let Ok(dirs) = std::fs::read_dir(self.yaks_dir.join(TRASH_DIR)) else {
+            return Ok(0);
+        };
+        let mut emptied = 0;
+        for dir in dirs.filter_map(|e| e.ok()) {
+            let stamp = dir.file_name().to_string_lossy().into_owned();
+            if stamp
+                .get(..10)
+                .and_then(parse_date)
+                .is_some_and(|deleted| deleted < day)
+            {
+                std::fs::remove_dir_all(dir.path())?;
+                emptied += 1;
+            }
+        }
+        Ok(emptied)
+    }
 
-        let yak_id = self
-            .get_field(path, "id")
-            .unwrap_or_else(|| path.split('/').last().unwrap_or(path).to_string());
+    /// Directory holding a task's extra markdown notes.
+    pub fn notes_dir(&self, task_path: &str) -> PathBuf {
+        self.yaks_dir.join(task_path).join(NOTES_DIR)
+    }
 
-        TaskLine {
-            path: path.to_string(),
-            name,
-            yak_id,
-            depth,
-            state,
-            assigned_to: self.get_field(path, "assigned-to"),
-            agent_status: self.get_field(path, "agent-status"),
-            has_children: false,
-            is_last_sibling: false,
-            ancestor_continuations: Vec::new(),
+    /// Names of a task's note files, oldest (by name) first.
+    pub fn note_files(&self, task_path: &str) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(self.notes_dir(task_path)) else {
+            return Vec::new();
+        };
+        let mut notes: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| is_note_name(name))
+            .collect();
+        notes.sort();
+        notes
+    }
+
+    /// Create a note named after `stamp` (`2026-10-16-1032.md`, with a
+    /// numeric suffix if taken) headed by `title`, returning its path.
+    pub fn create_note(
+        &self,
+        task_path: &str,
+        stamp: &str,
+        title: &str,
+    ) -> std::io::Result<PathBuf> {
+        self.check_writable()?;
+        let dir = self.notes_dir(task_path);
+        std::fs::create_dir_all(&dir)?;
+        let mut path = dir.join(format!("{}.md", stamp));
+        let mut n = 2;
+        while path.exists() {
+            path = dir.join(format!("{}-{}.md", stamp, n));
+            n += 1;
         }
+        std::fs::write(&path, format!("# {}\n\n", title))?;
+        Ok(path)
     }
 }
 
-#[derive(Default)]
-struct State {
-    repository: TaskRepository,
-    tasks: Vec<TaskLine>,
-    selected_index: usize,
-    scroll_offset: usize,
-    error: Option<String>,
-    toast_message: Option<String>,
-    toast_ticks_remaining: u8,
+/// Fill `staging` with the next occurrence of the recurring task in
+/// `task_dir`: its files except the per-occurrence ones, todo, due
+/// `next_due`.
+fn build_next_occurrence(
+    task_dir: &std::path::Path,
+    staging: &std::path::Path,
+    next_due: i64,
+) -> std::io::Result<()> {
+    std::fs::create_dir_all(staging)?;
+    for entry in std::fs::read_dir(task_dir)?.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let per_occurrence = ["state", "agent-status", "branch", "due"].contains(&name.as_str());
+        if !entry.path().is_dir() && !per_occurrence {
+            std::fs::copy(entry.path(), staging.join(&name))?;
+        }
+    }
+    std::fs::write(staging.join("state"), "todo\n")?;
+    std::fs::write(staging.join("due"), format!("{}\n", format_day(next_due)))
 }
 
-#[derive(Debug, Clone)]
-pub struct TaskLine {
-    path: String,
-    name: String,
-    yak_id: String,
+/// Why a change was refused with `read_only` on.
+fn read_only_error() -> std::io::Error {
+    std::io::Error::other("read-only")
+}
+
+/// Where tasks and their fields are kept. The plugin's own files (UI state,
+/// notes, logs) stay in `.yaks` whichever store holds the tasks.
+pub trait TaskStore {
+    /// Every task in tree order, fully populated.
+    fn load_snapshot(&self) -> Result<Vec<TaskLine>, String>;
+    /// A field of a task, trimmed. A missing or blank field is `Ok(None)`.
+    fn read_field(&self, task_path: &str, field: &str) -> Result<Option<String>, String>;
+    /// Set a field of a task, replacing any previous value.
+    fn set_field(&self, task_path: &str, field: &str, value: &str) -> std::io::Result<()>;
+    /// Remove a field of a task. A field that is already absent is fine.
+    fn clear_field(&self, task_path: &str, field: &str) -> std::io::Result<()>;
+    /// Fields a task has, plus the fields the plugin knows about, sorted.
+    fn field_names(&self, task_path: &str) -> Vec<String>;
+    /// Create a todo task called `name` under `parent` (`""` for the top
+    /// level) and return its path.
+    fn add_task(&self, parent: &str, name: &str) -> std::io::Result<String>;
+    /// Move a task under `parent` and return its new path.
+    fn move_task(&self, task_path: &str, parent: &str) -> std::io::Result<String>;
+    /// Remove a task, subtasks and all.
+    fn remove_task(&self, task_path: &str) -> std::io::Result<()>;
+    /// Tasks as last indexed, without reading them, for a first render
+    /// while the real read waits. Stores without an index have none.
+    fn cached_snapshot(&self) -> Option<Vec<TaskLine>> {
+        None
+    }
+    /// Whether each task is a directory under `.yaks`, as yx keeps them.
+    fn keeps_task_dirs(&self) -> bool {
+        false
+    }
+    /// Ask a remote source for fresh tasks, which arrive in `web_response`.
+    fn fetch(&self) {}
+    /// Take the answer to a request `fetch` made. Returns whether the tasks
+    /// should be loaded again.
+    fn web_response(&self, _status: u16, _body: &[u8]) -> bool {
+        false
+    }
+}
+
+/// A directory per task under `.yaks`, a file per field, as yx keeps them.
+struct DirectoryStore {
+    yaks_dir: PathBuf,
+    /// The tasks as of the last read, in tree order, when `index_cache` is
+    /// on. Saved to `.yaks/.cache/index`.
+    index: Option<std::cell::RefCell<Vec<(String, TaskRecord)>>>,
+}
+
+/// A task as read from its directory, before it becomes a `TaskLine`.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TaskRecord {
     depth: usize,
-    state: TaskState,
-    assigned_to: Option<String>,
-    agent_status: Option<String>,
-    has_children: bool,
-    is_last_sibling: bool,
-    ancestor_continuations: Vec<bool>,
+    /// Latest modification time, in nanoseconds since the epoch, of the task
+    /// directory and its files; `None` when too recent to trust, as a write
+    /// later in the same clock tick would not change it.
+    modified: Option<u128>,
+    fields: BTreeMap<String, Result<Option<String>, String>>,
+    note_count: usize,
+    state_since: Option<u64>,
+    last_log_line: Option<String>,
 }
 
-impl Default for TaskLine {
-    fn default() -> Self {
-        Self {
-            path: String::new(),
-            name: String::new(),
-            yak_id: String::new(),
-            depth: 0,
-            state: TaskState::Todo,
-            assigned_to: None,
-            agent_status: None,
-            has_children: false,
-            is_last_sibling: false,
-            ancestor_continuations: Vec::new(),
-        }
+impl TaskRecord {
+    fn to_task_line(&self, path: &str) -> TaskLine {
+        let mut task = task_from_fields(path, self.depth, self.fields.clone());
+        task.note_count = self.note_count;
+        task.state_since = self.state_since;
+        task.last_log_line = self.last_log_line.clone();
+        task
     }
 }
 
-/// Escape a string for use inside single-quoted shell literal (replace ' with '\'').
-fn escape_single_quoted(s: &str) -> String {
-    let mut out = String::with_capacity(s.len() + 2);
-    out.push('\'');
-    for c in s.chars() {
-        if c == '\'' {
-            out.push_str("'\\''");
-        } else {
-            out.push(c);
+/// How much older than now a task's files must be before the index trusts
+/// their modification time.
+const INDEX_SETTLE_NANOS: u128 = 2_000_000_000;
+
+/// First line of the index file; an index without it is ignored.
+const INDEX_HEADER: &str = "yak-map index 1";
+
+/// The index file: a header line, then a line per task in tree order with
+/// its path, depth and modification time, then `field=value` columns
+/// (`!field=error` for a field that could not be read) and `.notes`,
+/// `.since` and `.log`, tab-separated and escaped.
+fn serialize_index(entries: &[(String, TaskRecord)]) -> String {
+    let mut out = format!("{}\n", INDEX_HEADER);
+    for (path, record) in entries {
+        let modified = record.modified.map(|m| m.to_string()).unwrap_or_default();
+        let mut columns = vec![escape_index(path), record.depth.to_string(), modified];
+        for (field, value) in &record.fields {
+            match value {
+                Ok(Some(value)) => columns.push(format!("{}={}", field, escape_index(value))),
+                Ok(None) => {}
+                Err(e) => columns.push(format!("!{}={}", field, escape_index(e))),
+            }
+        }
+        columns.push(format!(".notes={}", record.note_count));
+        if let Some(since) = record.state_since {
+            columns.push(format!(".since={}", since));
+        }
+        if let Some(line) = &record.last_log_line {
+            columns.push(format!(".log={}", escape_index(line)));
         }
+        out.push_str(&columns.join("\t"));
+        out.push('\n');
     }
-    out.push('\'');
     out
 }
 
-/// Copy the yak name (yx-style, space-separated) to the system clipboard.
-/// Tries pbcopy (macOS), then xclip, then xsel (Linux). Name is passed as $1 to avoid escaping.
-fn copy_yak_name_to_clipboard(yx_name: &str) {
-    let script = r#"if command -v pbcopy >/dev/null 2>&1; then printf '%s' "$1" | pbcopy
-elif command -v xclip >/dev/null 2>&1; then printf '%s' "$1" | xclip -selection clipboard
-elif command -v xsel >/dev/null 2>&1; then printf '%s' "$1" | xsel --clipboard
-else echo "No clipboard tool (pbcopy/xclip/xsel) found" >&2; exit 1
-fi"#;
-    run_command(&["sh", "-c", script, "sh", yx_name], BTreeMap::new());
-}
-
-/// Strip ANSI escape sequences (CSI sequences like \x1b[...m) from a string,
-/// returning only the visible characters.
-fn strip_ansi(s: &str) -> String {
-    let mut result = String::new();
-    let mut chars = s.chars().peekable();
-    while let Some(c) = chars.next() {
-        if c == '\x1b' && chars.peek() == Some(&'[') {
-            chars.next(); // consume '['
-            for inner in chars.by_ref() {
-                if inner.is_ascii_alphabetic() {
-                    break;
+fn parse_index(contents: &str) -> Option<Vec<(String, TaskRecord)>> {
+    let mut lines = contents.lines();
+    if lines.next() != Some(INDEX_HEADER) {
+        return None;
+    }
+    let mut entries = Vec::new();
+    for line in lines {
+        let mut columns = line.split('\t');
+        let path = unescape_index(columns.next()?);
+        let mut record = TaskRecord {
+            depth: columns.next()?.parse().ok()?,
+            modified: columns.next()?.parse().ok(),
+            ..TaskRecord::default()
+        };
+        for column in columns {
+            let (key, value) = column.split_once('=')?;
+            let value = unescape_index(value);
+            match key {
+                ".notes" => record.note_count = value.parse().ok()?,
+                ".since" => record.state_since = value.parse().ok(),
+                ".log" => record.last_log_line = Some(value),
+                key => {
+                    let (field, value) = match key.strip_prefix('!') {
+                        Some(field) => (field, Err(value)),
+                        None => (key, Ok(Some(value))),
+                    };
+                    record.fields.insert(field.to_string(), value);
                 }
             }
-        } else {
-            result.push(c);
         }
+        entries.push((path, record));
     }
-    result
+    Some(entries)
 }
 
-impl State {
-    fn refresh_tasks(&mut self) {
-        let task_paths = self.repository.list_tasks();
-        let mut tasks: Vec<TaskLine> = task_paths
-            .into_iter()
-            .map(|(path, depth)| self.repository.get_task(&path, depth))
-            .collect();
+fn escape_index(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
 
-        if tasks.is_empty() {
-            self.tasks = tasks;
-            self.selected_index = 0;
-            return;
+fn unescape_index(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
         }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
 
-        let path_to_index: std::collections::HashMap<String, usize> = tasks
-            .iter()
-            .enumerate()
-            .map(|(i, t)| (t.path.clone(), i))
-            .collect();
+/// The index being rebuilt by a snapshot: the last one's records to reuse
+/// and this one's, in tree order.
+struct IndexUpdate {
+    previous: std::collections::HashMap<String, TaskRecord>,
+    entries: Vec<(String, TaskRecord)>,
+    now: u128,
+    changed: bool,
+}
 
-        for i in 0..tasks.len() {
-            let path = &tasks[i].path;
-            let prefix = format!("{}/", path);
-            tasks[i].has_children = tasks.iter().any(|t| t.path.starts_with(&prefix));
+impl TaskStore for DirectoryStore {
+    /// Every task in tree order, fully populated. Each task directory is read
+    /// with a single `read_dir` that yields both its field files and its
+    /// subtasks, so a refresh costs one directory listing per task.
+    fn load_snapshot(&self) -> Result<Vec<TaskLine>, String> {
+        let mut tasks = Vec::new();
+        let Some(index) = &self.index else {
+            self.snapshot_dir(&self.yaks_dir, None, &mut tasks, &mut None);
+            return Ok(tasks);
+        };
+        let previous = index.take();
+        let count = previous.len();
+        let mut update = Some(IndexUpdate {
+            previous: previous.into_iter().collect(),
+            entries: Vec::new(),
+            now: nanos_since_epoch(std::time::SystemTime::now()).unwrap_or(0),
+            changed: false,
+        });
+        self.snapshot_dir(&self.yaks_dir, None, &mut tasks, &mut update);
+        if let Some(update) = update {
+            if update.changed || update.entries.len() != count {
+                // Only a cache: a failed write means a slower next start
+                let _ = self.save_index(&update.entries);
+            }
+            index.replace(update.entries);
         }
+        Ok(tasks)
+    }
 
-        let mut by_parent: std::collections::BTreeMap<String, Vec<usize>> =
-            std::collections::BTreeMap::new();
-        for (i, task) in tasks.iter().enumerate() {
-            let parent = match task.path.rfind('/') {
-                Some(pos) => task.path[..pos].to_string(),
-                None => String::new(),
-            };
-            by_parent.entry(parent).or_default().push(i);
+    /// Read a field file, trimmed. A missing or blank field is `Ok(None)`; a
+    /// field that cannot be read or is not UTF-8 is an error describing why.
+    fn read_field(&self, task_path: &str, field: &str) -> Result<Option<String>, String> {
+        read_field_file(&self.yaks_dir.join(task_path).join(field), field)
+    }
+
+    /// Write a field file of a task, replacing any previous value.
+    fn set_field(&self, task_path: &str, field: &str, value: &str) -> std::io::Result<()> {
+        std::fs::write(
+            self.yaks_dir.join(task_path).join(field),
+            format!("{}\n", value),
+        )
+    }
+
+    /// Remove a field file of a task. A field that is already absent is fine.
+    fn clear_field(&self, task_path: &str, field: &str) -> std::io::Result<()> {
+        match std::fs::remove_file(self.yaks_dir.join(task_path).join(field)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
         }
+    }
 
-        for indices in by_parent.values() {
-            if let Some(&last) = indices.last() {
-                tasks[last].is_last_sibling = true;
+    /// Field files a task has, plus the fields the plugin knows about, sorted.
+    /// Fields are the top-level files with no extension.
+    fn field_names(&self, task_path: &str) -> Vec<String> {
+        let mut names: std::collections::BTreeSet<String> =
+            TASK_FIELDS.iter().map(|f| f.to_string()).collect();
+        if let Ok(entries) = std::fs::read_dir(self.yaks_dir.join(task_path)) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if entry.path().is_file() && is_field_name(&name) {
+                    names.insert(name);
+                }
             }
         }
+        names.into_iter().collect()
+    }
 
-        for i in 0..tasks.len() {
-            let path = &tasks[i].path;
-            let mut continuations = Vec::new();
+    /// Create a todo task called `name` under `parent` (`""` for the top
+    /// level), in a directory named after its slug with a numeric suffix if
+    /// taken, and return its path.
+    fn add_task(&self, parent: &str, name: &str) -> std::io::Result<String> {
+        let path = free_child_path(parent, &task_dir_name(name), |path| {
+            self.yaks_dir.join(path).exists()
+        });
+        std::fs::create_dir_all(self.yaks_dir.join(&path))?;
+        self.set_field(&path, "name", name)?;
+        self.set_field(&path, "state", "todo")?;
+        Ok(path)
+    }
 
-            // For each ancestor level (parent, grandparent, etc.), check if that ancestor has siblings after it
-            let mut current = if let Some(pos) = path.rfind('/') {
-                Some(path[..pos].to_string())
-            } else {
-                None
-            };
+    /// Move a task's directory under `parent`, keeping its name unless a
+    /// sibling there has it, and return its new path.
+    fn move_task(&self, task_path: &str, parent: &str) -> std::io::Result<String> {
+        let name = task_path.rsplit('/').next().unwrap_or(task_path);
+        let path = free_child_path(parent, name, |path| self.yaks_dir.join(path).exists());
+        std::fs::rename(self.yaks_dir.join(task_path), self.yaks_dir.join(&path))?;
+        Ok(path)
+    }
 
-            while let Some(ancestor) = current {
-                // Get the parent's parent (to find siblings of the ancestor)
-                let ancestors_parent = if let Some(pos) = ancestor.rfind('/') {
-                    Some(ancestor[..pos].to_string())
-                } else {
-                    Some(String::new()) // root level
-                };
+    /// Move a task's directory to `.trash/<stamp>/<path>`, stamped with
+    /// the UTC date and time, and list it there for restoring.
+    fn remove_task(&self, task_path: &str) -> std::io::Result<()> {
+        use std::io::Write;
+        let now = now_secs();
+        let stamp = format!(
+            "{}-{}",
+            format_date(now, 0),
+            format_clock(now, 0).replace(':', "")
+        );
+        let trash = self.yaks_dir.join(TRASH_DIR);
+        // A fresh stamp directory whenever the path or one of its ancestors
+        // was trashed there, so no deletion nests in another
+        let ancestors: Vec<&str> = task_path
+            .match_indices('/')
+            .map(|(i, _)| &task_path[..i])
+            .chain([task_path])
+            .collect();
+        let mut stamp_dir = trash.join(&stamp);
+        let mut n = 2;
+        while ancestors.iter().any(|path| stamp_dir.join(path).exists()) {
+            stamp_dir = trash.join(format!("{}-{}", stamp, n));
+            n += 1;
+        }
+        let target = stamp_dir.join(task_path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::rename(self.yaks_dir.join(task_path), &target)?;
+        let mut list = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(stamp_dir.join(TRASH_LIST))?;
+        writeln!(list, "{}", task_path)
+    }
 
-                if let Some(parent_of_ancestor) = ancestors_parent {
-                    let ancestors_siblings = by_parent
-                        .get(&parent_of_ancestor)
-                        .map(|v| v.as_slice())
-                        .unwrap_or(&[]);
-                    let pos_in_ancestors_siblings = ancestors_siblings.iter().position(|&x| {
-                        x == path_to_index.get(&ancestor).copied().unwrap_or(usize::MAX)
-                    });
-
-                    if let Some(pos) = pos_in_ancestors_siblings {
-                        let has_more_siblings = pos + 1 < ancestors_siblings.len();
-                        continuations.push(has_more_siblings);
-                    }
-                }
+    fn keeps_task_dirs(&self) -> bool {
+        true
+    }
 
-                current = if let Some(pos) = ancestor.rfind('/') {
-                    Some(ancestor[..pos].to_string())
-                } else {
-                    None
-                };
-            }
-            tasks[i].ancestor_continuations = continuations;
+    fn cached_snapshot(&self) -> Option<Vec<TaskLine>> {
+        let index = self.index.as_ref()?.borrow();
+        if index.is_empty() {
+            return None;
         }
+        Some(
+            index
+                .iter()
+                .map(|(path, record)| record.to_task_line(path))
+                .collect(),
+        )
+    }
+}
 
-        self.tasks = tasks;
-
-        if self.selected_index >= self.tasks.len() && !self.tasks.is_empty() {
-            self.selected_index = self.tasks.len() - 1;
+impl DirectoryStore {
+    /// A directory store that keeps an index of what it read, starting from
+    /// the one saved by the last session.
+    fn indexed(yaks_dir: PathBuf) -> Self {
+        let index_path = yaks_dir.join(".cache").join("index");
+        let saved = std::fs::read_to_string(index_path)
+            .ok()
+            .and_then(|s| parse_index(&s));
+        Self {
+            yaks_dir,
+            index: Some(std::cell::RefCell::new(saved.unwrap_or_default())),
         }
     }
 
-    fn task_color(&self, task: &TaskLine) -> &'static str {
-        if let Some(status) = &task.agent_status {
-            if status.starts_with("blocked:") {
-                return "\x1b[31m";
-            }
-            if status.starts_with("done:") {
-                return "\x1b[32m";
-            }
-            if status.starts_with("wip:") {
-                return "\x1b[33m";
+    fn save_index(&self, entries: &[(String, TaskRecord)]) -> std::io::Result<()> {
+        let dir = self.yaks_dir.join(".cache");
+        std::fs::create_dir_all(&dir)?;
+        let partial = dir.join("index.partial");
+        std::fs::write(&partial, serialize_index(entries))?;
+        std::fs::rename(partial, dir.join("index"))
+    }
+
+    /// Read `dir` once, push its own task (unless it is the `.yaks` root) and
+    /// then recurse into its subtasks in name order. With an index, a task
+    /// whose directory and files are as old as when it was last read is
+    /// taken from the index instead of its files being read again.
+    fn snapshot_dir(
+        &self,
+        dir: &std::path::Path,
+        task: Option<(&str, usize)>,
+        tasks: &mut Vec<TaskLine>,
+        index: &mut Option<IndexUpdate>,
+    ) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut children = Vec::new();
+        let mut files = Vec::new();
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let path = entry.path();
+            if path.is_dir() && (task.is_none() || name != NOTES_DIR) {
+                if is_task_dir_name(&name) {
+                    children.push((name, path));
+                }
+            } else if task.is_some()
+                && (name == NOTES_DIR || name == AGENT_LOG || TASK_FIELDS.contains(&name.as_str()))
+            {
+                files.push((name, path));
             }
         }
-        match task.state {
-            TaskState::Wip => "\x1b[33m",
-            TaskState::Done => "\x1b[90m",
-            TaskState::Todo => "\x1b[37m",
+
+        let child_depth = match task {
+            Some((task_path, depth)) => {
+                let record = match index {
+                    Some(update) => update.record(task_path, dir, &files, |files| {
+                        read_task_record(depth, files)
+                    }),
+                    None => read_task_record(depth, &files),
+                };
+                tasks.push(record.to_task_line(task_path));
+                depth + 1
+            }
+            None => 0,
+        };
+        children.sort();
+        for (name, path) in children {
+            let child_path = match task {
+                Some((task_path, _)) => format!("{}/{}", task_path, name),
+                None => name,
+            };
+            self.snapshot_dir(&path, Some((&child_path, child_depth)), tasks, index);
         }
     }
+}
 
-    fn status_symbol(&self, task: &TaskLine) -> char {
-        if let Some(status) = &task.agent_status {
-            if status.starts_with("done:") {
-                return '●';
+impl IndexUpdate {
+    /// The record for a task: the indexed one if nothing in its directory
+    /// changed since, otherwise a fresh one from `read`.
+    fn record(
+        &mut self,
+        task_path: &str,
+        dir: &std::path::Path,
+        files: &[(String, PathBuf)],
+        read: impl FnOnce(&[(String, PathBuf)]) -> TaskRecord,
+    ) -> TaskRecord {
+        let mut paths = vec![dir];
+        paths.extend(files.iter().map(|(_, path)| path.as_path()));
+        let modified = paths
+            .iter()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .map(|time| time.and_then(nanos_since_epoch))
+            .try_fold(0, |latest: u128, time| Some(latest.max(time?)))
+            .filter(|&latest| latest + INDEX_SETTLE_NANOS < self.now);
+        let record = match self.previous.remove(task_path) {
+            Some(previous) if modified.is_some() && previous.modified == modified => previous,
+            _ => {
+                self.changed = true;
+                TaskRecord {
+                    modified,
+                    ..read(files)
+                }
             }
-            if status.starts_with("wip:") || status.starts_with("blocked:") {
-                return '●';
+        };
+        self.entries.push((task_path.to_string(), record.clone()));
+        record
+    }
+}
+
+/// Read a task's field files, `agent.log` and notes.
+fn read_task_record(depth: usize, files: &[(String, PathBuf)]) -> TaskRecord {
+    let mut record = TaskRecord {
+        depth,
+        ..TaskRecord::default()
+    };
+    for (name, path) in files {
+        if name == NOTES_DIR {
+            record.note_count = count_notes(path);
+        } else if name == AGENT_LOG {
+            record.last_log_line = last_line_of(path);
+        } else {
+            if name == "state" {
+                record.state_since = modified_secs(path);
             }
-        }
-        match task.state {
-            TaskState::Wip | TaskState::Done => '●',
-            TaskState::Todo => '○',
+            record
+                .fields
+                .insert(name.clone(), read_field_file(path, name));
         }
     }
+    record
+}
 
-    fn tree_prefix(&self, task: &TaskLine) -> String {
-        if task.depth == 0 {
-            return String::new();
-        }
+fn nanos_since_epoch(time: std::time::SystemTime) -> Option<u128> {
+    Some(time.duration_since(std::time::UNIX_EPOCH).ok()?.as_nanos())
+}
 
-        let mut prefix = String::new();
-        let line_color = "\x1b[90m";
-        let reset = "\x1b[0m";
+/// Paths and fields of the tasks a yaks server sent, in tree order.
+type RemoteSnapshot = Vec<(String, BTreeMap<String, String>)>;
+
+/// Tasks browsed read-only from a yaks server, which serves a JSON snapshot:
+/// `{"tasks": [{"path": "api/retries", "fields": {"state": "wip"}}]}`.
+pub struct HttpStore {
+    url: String,
+    /// The last snapshot received as paths and fields, or why the last
+    /// fetch failed.
+    snapshot: std::cell::RefCell<Result<RemoteSnapshot, String>>,
+    /// Whether a fetch is on its way, so a slow server is not asked twice.
+    pending: std::cell::Cell<bool>,
+}
 
-        // Show continuation columns for each ancestor level (from root-most to parent).
-        // ancestor_continuations is ordered [parent, grandparent, ...], so we take
-        // the first depth-1 entries (excluding the root-most) and reverse them to
-        // render columns from left (root-most) to right (parent-most).
-        let col_count = task.depth.saturating_sub(1);
-        let cols = &task.ancestor_continuations[..col_count.min(task.ancestor_continuations.len())];
-        for &has_continuation in cols.iter().rev() {
-            if has_continuation {
-                prefix.push_str(&format!("{}│ {}", line_color, reset));
-            } else {
-                prefix.push_str("  ");
-            }
+impl HttpStore {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            snapshot: std::cell::RefCell::new(Ok(Vec::new())),
+            pending: std::cell::Cell::new(false),
         }
+    }
 
-        if task.is_last_sibling {
-            prefix.push_str(&format!("{}╰─{}", line_color, reset));
-        } else {
-            prefix.push_str(&format!("{}├─{}", line_color, reset));
-        }
+    fn read_only(&self) -> std::io::Error {
+        std::io::Error::other(format!("{} is read-only", self.url))
+    }
+}
 
-        prefix
+impl TaskStore for HttpStore {
+    fn load_snapshot(&self) -> Result<Vec<TaskLine>, String> {
+        let snapshot = self.snapshot.borrow();
+        let tasks = snapshot.as_ref().map_err(Clone::clone)?;
+        let lines = tasks.iter().map(|(path, fields)| {
+            let fields = fields
+                .iter()
+                .map(|(k, v)| (k.clone(), Ok(non_empty(Some(v)))));
+            task_from_fields(path, path.matches('/').count(), fields.collect())
+        });
+        Ok(lines.collect())
     }
 
-    fn highlight_line(&self, line: &str, padding: &str) -> String {
-        let bg = "\x1b[48;5;237m";
-        let highlighted = line.replace("\x1b[0m", &format!("\x1b[0m{bg}"));
-        format!("{bg}{}{}\x1b[0m", highlighted, padding)
+    fn read_field(&self, task_path: &str, field: &str) -> Result<Option<String>, String> {
+        let snapshot = self.snapshot.borrow();
+        let tasks = snapshot.as_ref().map_err(Clone::clone)?;
+        let task = tasks.iter().find(|(path, _)| path == task_path);
+        Ok(task.and_then(|(_, fields)| non_empty(fields.get(field))))
     }
 
-    fn render_task(&self, task: &TaskLine) -> String {
-        let prefix = self.tree_prefix(task);
-        let status = self.status_symbol(task);
+    fn set_field(&self, _task_path: &str, _field: &str, _value: &str) -> std::io::Result<()> {
+        Err(self.read_only())
+    }
 
-        let color = self.task_color(task);
+    fn clear_field(&self, _task_path: &str, _field: &str) -> std::io::Result<()> {
+        Err(self.read_only())
+    }
 
-        let name = if matches!(task.state, TaskState::Done) {
-            format!("\x1b[9m{}\x1b[0m", task.name)
-        } else {
-            task.name.clone()
-        };
+    fn field_names(&self, task_path: &str) -> Vec<String> {
+        let mut names: std::collections::BTreeSet<String> =
+            TASK_FIELDS.iter().map(|f| f.to_string()).collect();
+        if let Ok(tasks) = &*self.snapshot.borrow() {
+            if let Some((_, fields)) = tasks.iter().find(|(path, _)| path == task_path) {
+                names.extend(fields.keys().cloned());
+            }
+        }
+        names.into_iter().collect()
+    }
 
-        let assignment = if let Some(agent) = &task.assigned_to {
-            format!(" [\x1b[36m{}\x1b[0m]", agent)
-        } else {
-            String::new()
-        };
+    fn add_task(&self, _parent: &str, _name: &str) -> std::io::Result<String> {
+        Err(self.read_only())
+    }
 
-        let status_color = if matches!(task.state, TaskState::Done) {
-            "\x1b[90m"
-        } else {
-            color
-        };
+    fn move_task(&self, _task_path: &str, _parent: &str) -> std::io::Result<String> {
+        Err(self.read_only())
+    }
 
-        format!(
-            "{}{}{} {}{}\x1b[0m",
-            prefix, status_color, status, name, assignment
-        )
+    fn remove_task(&self, _task_path: &str) -> std::io::Result<()> {
+        Err(self.read_only())
     }
 
-    /// Open the selected task's context.md in a floating pane via $PAGER (or less).
-    fn open_selected_task_context(&self) {
-        let Some(task) = self.tasks.get(self.selected_index) else {
-            return;
-        };
-        let context_path = self.repository.context_path(&task.path);
-        if !context_path.exists() {
-            return;
+    fn fetch(&self) {
+        if !self.pending.replace(true) {
+            let context = BTreeMap::from([("action".to_string(), "task-snapshot".to_string())]);
+            fetch_url(&self.url, context);
         }
-        let pager = std::env::var("PAGER").unwrap_or_else(|_| "less".to_string());
-        let host_path = context_path.strip_prefix("/host").unwrap_or(&context_path).to_path_buf();
-        let path_str = host_path.to_string_lossy().to_string();
-        let command = CommandToRun {
-            path: PathBuf::from(&pager),
-            args: vec![path_str],
-            cwd: None,
+    }
+
+    fn web_response(&self, status: u16, body: &[u8]) -> bool {
+        self.pending.set(false);
+        let snapshot = match status {
+            200..=299 => parse_task_snapshot(body),
+            status => Err(format!("HTTP {}", status)),
         };
-        open_command_pane_floating(command, None, BTreeMap::new());
+        *self.snapshot.borrow_mut() = snapshot.map_err(|e| format!("{}: {}", self.url, e));
+        true
     }
 }
 
-impl ZellijPlugin for State {
-    fn load(&mut self, configuration: BTreeMap<String, String>) {
-        subscribe(&[EventType::Timer, EventType::Key]);
-        set_timeout(2.0);
-        request_permission(&[PermissionType::OpenFiles, PermissionType::RunCommands]);
+/// The tasks in a JSON snapshot, put in tree order. Field
+/// values that are not strings are kept as their JSON text; nulls are
+/// dropped. A path that could reach outside `.yaks` fails the snapshot.
+fn parse_task_snapshot(body: &[u8]) -> Result<RemoteSnapshot, String> {
+    use serde_json::Value;
+    let value: Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    let tasks = value.get("tasks").unwrap_or(&value);
+    let tasks = tasks.as_array().ok_or("expected a list of tasks")?;
+    let mut snapshot = Vec::new();
+    for task in tasks {
+        let path = task
+            .get("path")
+            .and_then(Value::as_str)
+            .ok_or("a task has no path")?;
+        if !is_relative_task_path(path) {
+            return Err(format!("bad task path {:?}", path));
+        }
+        let fields = task.get("fields").and_then(Value::as_object);
+        let fields = fields
+            .into_iter()
+            .flatten()
+            .filter_map(|(field, value)| match value {
+                Value::Null => None,
+                Value::String(text) => Some((field.clone(), text.clone())),
+                other => Some((field.clone(), other.to_string())),
+            });
+        snapshot.push((path.to_string(), fields.collect()));
+    }
+    snapshot.sort_by(|(a, _), (b, _)| a.split('/').cmp(b.split('/')));
+    Ok(snapshot)
+}
 
-        let yaks_dir = PathBuf::from("/host/.yaks");
+/// Whether `path` names a task under `.yaks`: not empty or absolute, and
+/// with no `.` or `..` segments.
+fn is_relative_task_path(path: &str) -> bool {
+    path.split('/')
+        .all(|segment| !segment.is_empty() && segment != "." && segment != "..")
+}
 
-        if !yaks_dir.exists() {
-            self.error = Some(format!(
-                "Yaks directory not found: {}\nRun `yx add <name>` to create a task.",
-                yaks_dir.display()
-            ));
-            return;
-        }
+/// How a task file is laid out, going by its extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TaskFileFormat {
+    /// `.yaml` or `.yml`: see `parse_task_yaml`.
+    Yaml,
+    /// Anything else, usually `TASKS.md`: see `parse_checklist`.
+    Checklist,
+}
 
-        self.repository = TaskRepository::new(yaks_dir);
-        self.refresh_tasks();
-    }
+/// A task in a task file, or the file itself at the root: its fields, its
+/// subtasks and, in a checklist, the lines after it that are not tasks, kept
+/// so that a rewrite does not lose them.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FileTask {
+    fields: BTreeMap<String, String>,
+    children: Vec<FileTask>,
+    text: Vec<String>,
+}
 
-    fn update(&mut self, event: Event) -> bool {
-        match event {
-            Event::Timer(_) => {
-                set_timeout(2.0);
-                self.refresh_tasks();
-                if self.toast_ticks_remaining > 0 {
-                    self.toast_ticks_remaining -= 1;
-                    if self.toast_ticks_remaining == 0 {
-                        self.toast_message = None;
-                    }
-                }
-                true
-            }
-            Event::Key(key) => {
-                let handled = match key.bare_key {
-                    BareKey::Up | BareKey::Char('k') if key.has_no_modifiers() => {
-                        if self.selected_index > 0 {
-                            self.selected_index -= 1;
-                        }
-                        true
-                    }
-                    BareKey::Down | BareKey::Char('j') if key.has_no_modifiers() => {
-                        if self.selected_index + 1 < self.tasks.len() {
-                            self.selected_index += 1;
-                        }
-                        true
-                    }
-                    BareKey::Char('r') if key.has_no_modifiers() => {
-                        self.refresh_tasks();
-                        true
-                    }
-                    BareKey::Char('e') if key.has_no_modifiers() => {
-                        if let Some(task) = self.tasks.get(self.selected_index) {
-                            let context_path = self.repository.context_path(&task.path);
-                            if let Some(parent) = context_path.parent() {
-                                let _ = std::fs::create_dir_all(parent);
-                            }
-                            if !context_path.exists() {
-                                let _ = std::fs::write(&context_path, "");
-                            }
-                            let host_path = context_path.strip_prefix("/host").unwrap_or(&context_path).to_path_buf();
-                            let file_to_open = FileToOpen::new(host_path);
-                            open_file_floating(file_to_open, None, BTreeMap::new());
-                        }
-                        true
-                    }
-                    BareKey::Char('y') if key.has_no_modifiers() => {
-                        if let Some(task) = self.tasks.get(self.selected_index) {
-                            copy_yak_name_to_clipboard(&task.yak_id);
-                            self.toast_message = Some(format!("Copied: {}", task.yak_id));
-                            self.toast_ticks_remaining = 1;
-                        }
-                        true
-                    }
-                    BareKey::Enter if key.has_no_modifiers() => {
-                        self.open_selected_task_context();
-                        true
-                    }
-                    _ => false,
-                };
-                if handled {
-                    true
-                } else {
-                    false
-                }
+impl FileTask {
+    /// Path segments of the subtasks, in order: the `id` field if set, else
+    /// the slug of the name, with a numeric suffix if a sibling took it.
+    fn child_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for child in &self.children {
+            let base = match child.fields.get("id") {
+                Some(id) => id.clone(),
+                None => slugify(child.fields.get("name").map_or("", String::as_str)),
+            };
+            let base = if base.is_empty() {
+                "task".to_string()
+            } else {
+                base
+            };
+            let mut name = base.clone();
+            let mut n = 2;
+            while names.contains(&name) {
+                name = format!("{}-{}", base, n);
+                n += 1;
             }
-            _ => false,
+            names.push(name);
         }
+        names
     }
 
-    fn render(&mut self, rows: usize, cols: usize) {
-        if let Some(error) = &self.error {
-            println!("\x1b[31mError: {}\x1b[0m", error);
-            return;
+    fn task(&self, path: &str) -> Option<&FileTask> {
+        if path.is_empty() {
+            return Some(self);
         }
+        let (first, rest) = path.split_once('/').unwrap_or((path, ""));
+        let index = self.child_names().iter().position(|name| name == first)?;
+        self.children[index].task(rest)
+    }
 
-        if self.tasks.is_empty() {
-            println!("No tasks. Run `yx add <name>` to create one.");
-            println!("(Refresh interval: 2s)");
-            return;
+    fn task_mut(&mut self, path: &str) -> Option<&mut FileTask> {
+        if path.is_empty() {
+            return Some(self);
         }
+        let (first, rest) = path.split_once('/').unwrap_or((path, ""));
+        let index = self.child_names().iter().position(|name| name == first)?;
+        self.children[index].task_mut(rest)
+    }
 
-        let toast_rows = if self.toast_message.is_some() { 2 } else { 0 };
-        let max_rows = rows.saturating_sub(3 + toast_rows);
+    /// Take the task at `path` out of the tree.
+    fn remove(&mut self, path: &str) -> Option<FileTask> {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let parent = self.task_mut(parent)?;
+        let index = parent.child_names().iter().position(|n| n == name)?;
+        Some(parent.children.remove(index))
+    }
+
+    /// Add `task` under the task at `parent` and return its path.
+    fn adopt(&mut self, parent: &str, task: FileTask) -> Option<String> {
+        let node = self.task_mut(parent)?;
+        node.children.push(task);
+        let name = node.child_names().pop()?;
+        Some(match parent {
+            "" => name,
+            parent => format!("{}/{}", parent, name),
+        })
+    }
 
-        // Keep scroll_offset in sync with selected_index
-        if self.selected_index < self.scroll_offset {
-            self.scroll_offset = self.selected_index;
-        } else if max_rows > 0 && self.selected_index >= self.scroll_offset + max_rows {
-            self.scroll_offset = self.selected_index - max_rows + 1;
+    fn snapshot(&self, path: &str, depth: usize, tasks: &mut Vec<TaskLine>) {
+        for (child, name) in self.children.iter().zip(self.child_names()) {
+            let child_path = match path {
+                "" => name,
+                path => format!("{}/{}", path, name),
+            };
+            let fields = child
+                .fields
+                .iter()
+                .map(|(k, v)| (k.clone(), Ok(non_empty(Some(v)))));
+            tasks.push(task_from_fields(&child_path, depth, fields.collect()));
+            child.snapshot(&child_path, depth + 1, tasks);
         }
+    }
+}
 
-        for (i, task) in self.tasks.iter().skip(self.scroll_offset).take(max_rows).enumerate() {
-            let line = self.render_task(task);
+/// Tasks kept in one file, a `tasks.yaml` or a `TASKS.md` checklist, for
+/// projects too small to want a directory per task. The file is read afresh
+/// for every call and rewritten whole on every change.
+pub struct TaskFile {
+    path: PathBuf,
+    format: TaskFileFormat,
+}
 
-            if self.scroll_offset + i == self.selected_index {
-                let visible_len = strip_ansi(&line).chars().count();
-                let padding = " ".repeat(cols.saturating_sub(visible_len));
-                println!("{}", self.highlight_line(&line, &padding));
-            } else {
-                println!("{}", line);
+impl TaskFile {
+    pub fn new(path: PathBuf) -> Self {
+        let yaml = path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml");
+        let format = if yaml {
+            TaskFileFormat::Yaml
+        } else {
+            TaskFileFormat::Checklist
+        };
+        Self { path, format }
+    }
+
+    /// The file as a tree under a root task. A missing file has no tasks.
+    fn read(&self) -> Result<FileTask, String> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", self.path.display(), e)),
+        };
+        match self.format {
+            TaskFileFormat::Yaml => {
+                parse_task_yaml(&contents).map_err(|e| format!("{}: {}", self.path.display(), e))
             }
+            TaskFileFormat::Checklist => Ok(parse_checklist(&contents)),
         }
+    }
 
-        if let Some(msg) = &self.toast_message.clone() {
-            println!();
-            let toast = format!(" {} ", msg);
-            println!("\x1b[7m\x1b[1m{}\x1b[0m", toast);
+    /// Read the file, apply `change` to the tree and write it back.
+    fn update<T>(
+        &self,
+        change: impl FnOnce(&mut FileTask) -> Result<T, String>,
+    ) -> std::io::Result<T> {
+        let mut root = self.read().map_err(std::io::Error::other)?;
+        let result = change(&mut root).map_err(std::io::Error::other)?;
+        let mut out = String::new();
+        match self.format {
+            TaskFileFormat::Yaml => write_task_yaml(&root.children, 0, &mut out),
+            TaskFileFormat::Checklist => write_checklist(&root, 0, &mut out),
+        }
+        std::fs::write(&self.path, out)?;
+        Ok(result)
+    }
+
+    /// A checklist only has room for a name and a state.
+    fn check_field(&self, field: &str) -> Result<(), String> {
+        if self.format == TaskFileFormat::Checklist && field != "name" && field != "state" {
+            return Err(format!(
+                "{} only keeps names and states",
+                self.path.display()
+            ));
         }
+        Ok(())
     }
 }
 
-register_plugin!(State);
+impl TaskStore for TaskFile {
+    fn load_snapshot(&self) -> Result<Vec<TaskLine>, String> {
+        let mut tasks = Vec::new();
+        self.read()?.snapshot("", 0, &mut tasks);
+        Ok(tasks)
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::path::Path;
-    use tempfile::TempDir;
+    fn read_field(&self, task_path: &str, field: &str) -> Result<Option<String>, String> {
+        let root = self.read()?;
+        Ok(root
+            .task(task_path)
+            .and_then(|task| non_empty(task.fields.get(field))))
+    }
 
-    fn mock_yaks() -> (TempDir, PathBuf) {
-        let temp = TempDir::new().unwrap();
-        let yaks = temp.path().join(".yaks");
-        fs::create_dir_all(&yaks).unwrap();
-        (temp, yaks)
+    fn set_field(&self, task_path: &str, field: &str, value: &str) -> std::io::Result<()> {
+        self.check_field(field).map_err(std::io::Error::other)?;
+        self.update(|root| {
+            let task = root
+                .task_mut(task_path)
+                .ok_or(format!("No task {}", task_path))?;
+            task.fields.insert(field.to_string(), value.to_string());
+            Ok(())
+        })
     }
 
-    fn create_task(yaks: &Path, path: &str) {
-        fs::create_dir_all(yaks.join(path)).unwrap();
+    fn clear_field(&self, task_path: &str, field: &str) -> std::io::Result<()> {
+        self.update(|root| {
+            let task = root
+                .task_mut(task_path)
+                .ok_or(format!("No task {}", task_path))?;
+            task.fields.remove(field);
+            Ok(())
+        })
     }
 
-    fn set_field(yaks: &Path, task_path: &str, field: &str, value: &str) {
-        fs::write(yaks.join(task_path).join(field), value).unwrap();
+    fn field_names(&self, task_path: &str) -> Vec<String> {
+        let mut names: std::collections::BTreeSet<String> =
+            TASK_FIELDS.iter().map(|f| f.to_string()).collect();
+        if let Some(task) = self
+            .read()
+            .ok()
+            .as_ref()
+            .and_then(|root| root.task(task_path))
+        {
+            names.extend(task.fields.keys().cloned());
+        }
+        names.into_iter().collect()
     }
 
-    #[test]
-    fn get_task_uses_name_file_when_present() {
-        let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-hyphenated-slug");
-        set_field(&yaks, "my-hyphenated-slug", "name", "my hyphenated slug");
+    fn add_task(&self, parent: &str, name: &str) -> std::io::Result<String> {
+        let fields = [("name", name), ("state", "todo")];
+        let task = FileTask {
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..FileTask::default()
+        };
+        self.update(|root| {
+            root.adopt(parent, task)
+                .ok_or(format!("No task {}", parent))
+        })
+    }
 
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("my-hyphenated-slug", 0);
+    fn move_task(&self, task_path: &str, parent: &str) -> std::io::Result<String> {
+        self.update(|root| {
+            let task = root
+                .remove(task_path)
+                .ok_or(format!("No task {}", task_path))?;
+            root.adopt(parent, task)
+                .ok_or(format!("No task {}", parent))
+        })
+    }
 
-        assert_eq!(task.name, "my hyphenated slug");
+    fn remove_task(&self, task_path: &str) -> std::io::Result<()> {
+        self.update(|root| {
+            root.remove(task_path)
+                .map(drop)
+                .ok_or(format!("No task {}", task_path))
+        })
     }
+}
 
-    #[test]
-    fn get_task_falls_back_to_slug_when_name_file_absent() {
-        let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-hyphenated-slug");
+/// Nest tasks by indentation under `root`: each is a subtask of the closest
+/// task before it that is indented less.
+fn nest_by_indent(items: Vec<(usize, FileTask)>, root: FileTask) -> FileTask {
+    let mut stack: Vec<(Option<usize>, FileTask)> = vec![(None, root)];
+    for (indent, task) in items {
+        while stack.last().is_some_and(|(open, _)| *open >= Some(indent)) {
+            let (_, done) = stack.pop().unwrap_or_default();
+            if let Some((_, parent)) = stack.last_mut() {
+                parent.children.push(done);
+            }
+        }
+        stack.push((Some(indent), task));
+    }
+    while stack.len() > 1 {
+        let (_, done) = stack.pop().unwrap_or_default();
+        if let Some((_, parent)) = stack.last_mut() {
+            parent.children.push(done);
+        }
+    }
+    stack.pop().map(|(_, root)| root).unwrap_or_default()
+}
 
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("my-hyphenated-slug", 0);
+/// Read a markdown checklist: `- [ ] name` is a todo task, `- [/] name` a
+/// wip one and `- [x] name` a done one, nested by indentation. Any other
+/// line stays with the task above it, or heads the file.
+fn parse_checklist(contents: &str) -> FileTask {
+    let mut root = FileTask::default();
+    let mut items: Vec<(usize, FileTask)> = Vec::new();
+    for line in contents.lines() {
+        let Some((indent, mark, name)) = checklist_item(line) else {
+            match items.last_mut() {
+                Some((_, task)) => task.text.push(line.to_string()),
+                None => root.text.push(line.to_string()),
+            }
+            continue;
+        };
+        let state = match mark {
+            'x' | 'X' => "done",
+            '/' => "wip",
+            _ => "todo",
+        };
+        let fields = [("name", name), ("state", state)];
+        let task = FileTask {
+            fields: fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..FileTask::default()
+        };
+        items.push((indent, task));
+    }
+    nest_by_indent(items, root)
+}
 
-        assert_eq!(task.name, "my-hyphenated-slug");
+/// The indentation, checkbox mark and name of a checklist item line.
+fn checklist_item(line: &str) -> Option<(usize, char, &str)> {
+    let text = line.trim_start();
+    let rest = text.strip_prefix(['-', '*', '+'])?.strip_prefix(" [")?;
+    let mut chars = rest.chars();
+    let mark = chars.next()?;
+    let name = chars.as_str().strip_prefix(']')?;
+    if !name.is_empty() && !name.starts_with(' ') {
+        return None;
     }
+    Some((line.len() - text.len(), mark, name.trim()))
+}
+
+fn write_checklist(task: &FileTask, depth: usize, out: &mut String) {
+    for line in &task.text {
+        out.push_str(line);
+        out.push('\n');
+    }
+    for child in &task.children {
+        let mark = match child.fields.get("state").map(String::as_str) {
+            Some("done") => 'x',
+            Some("wip") => '/',
+            _ => ' ',
+        };
+        let name = child.fields.get("name").map_or("", String::as_str);
+        out.push_str(&format!("{}- [{}] {}\n", "  ".repeat(depth), mark, name));
+        write_checklist(child, depth + 1, out);
+    }
+}
+
+/// Read the YAML subset `tasks.yaml` uses: a list of tasks, each a map of
+/// field names to one-line values, with its subtasks in a `tasks` list:
+///
+/// ```yaml
+/// - name: Ship the API
+///   state: wip
+///   tasks:
+///     - name: Write the docs
+///       assigned-to: alice
+/// ```
+fn parse_task_yaml(contents: &str) -> Result<FileTask, String> {
+    // Each task with the indentation of its `-` and of its fields
+    let mut items: Vec<(usize, usize, FileTask)> = Vec::new();
+    for (number, line) in contents.lines().enumerate() {
+        let error = |what: &str| format!("line {}: {}", number + 1, what);
+        let text = line.trim_start();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - text.len();
+        let bullet = text
+            .strip_prefix('-')
+            .filter(|rest| rest.is_empty() || rest.starts_with(' '));
+        let entry = match bullet {
+            Some(rest) => {
+                let entry = rest.trim_start();
+                items.push((indent, line.len() - entry.len(), FileTask::default()));
+                entry
+            }
+            None => text,
+        };
+        if entry.is_empty() {
+            continue;
+        }
+        let column = line.len() - entry.len();
+        let task = match items
+            .iter_mut()
+            .rev()
+            .find(|(_, fields, _)| *fields == column)
+        {
+            Some((_, _, task)) => task,
+            None => return Err(error("expected a list of tasks")),
+        };
+        let (key, value) = entry
+            .split_once(':')
+            .ok_or_else(|| error("expected key: value"))?;
+        let (key, value) = (key.trim(), value.trim());
+        if key == "tasks" {
+            if value.is_empty() || value == "[]" {
+                continue;
+            }
+            return Err(error("tasks must be a list on the lines below"));
+        }
+        let value = yaml_scalar(value).ok_or_else(|| error("unsupported value"))?;
+        task.fields.insert(key.to_string(), value);
+    }
+    let items = items
+        .into_iter()
+        .map(|(indent, _, task)| (indent, task))
+        .collect();
+    Ok(nest_by_indent(items, FileTask::default()))
+}
+
+/// A one-line YAML value: quoted, or plain up to any ` #` comment.
+fn yaml_scalar(value: &str) -> Option<String> {
+    if value.starts_with('"') || value.starts_with('\'') {
+        let (string, rest) = toml_string(value)?;
+        return (rest.is_empty() || rest.starts_with('#')).then_some(string);
+    }
+    if value.starts_with(['|', '>', '[', '{', '&', '*', '!']) {
+        return None;
+    }
+    Some(value.split(" #").next().unwrap_or("").trim().to_string())
+}
+
+/// A value as a plain YAML scalar when it reads back unchanged, else quoted.
+fn yaml_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.trim() == value
+        && !value.starts_with(|c: char| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !value.contains(": ")
+        && !value.contains(" #")
+        && !value.ends_with(':')
+        && !value.contains(char::is_control);
+    if plain {
+        return value.to_string();
+    }
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    format!("\"{}\"", escaped)
+}
+
+fn write_task_yaml(tasks: &[FileTask], indent: usize, out: &mut String) {
+    let pad = " ".repeat(indent);
+    for task in tasks {
+        // The name leads, so each entry reads as the task it is
+        let name = task.fields.get_key_value("name");
+        let others = task.fields.iter().filter(|(key, _)| key.as_str() != "name");
+        let mut bullet = "- ";
+        for (key, value) in name.into_iter().chain(others) {
+            out.push_str(&format!(
+                "{}{}{}: {}\n",
+                pad,
+                bullet,
+                key,
+                yaml_quote(value)
+            ));
+            bullet = "  ";
+        }
+        if bullet == "- " {
+            out.push_str(&format!("{}-\n", pad));
+            bullet = "  ";
+        }
+        if !task.children.is_empty() {
+            out.push_str(&format!("{}{}tasks:\n", pad, bullet));
+            write_task_yaml(&task.children, indent + 4, out);
+        }
+    }
+}
+
+/// Whether `name` can be a field file: no extension, path separator or
+/// leading dot.
+fn is_field_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['.', '/', '\\']) && name != NOTES_DIR
+}
+
+/// File an agent writes its output to, whose last line is shown on wide panes.
+const AGENT_LOG: &str = "agent.log";
+
+/// Characters of the last `agent.log` line shown in a row.
+const LOG_LINE_MAX_CHARS: usize = 60;
+
+/// How much of the end of `agent.log` is read for its last line.
+const LOG_TAIL_BYTES: u64 = 4096;
+
+/// The last non-blank line of a file, reading only its final
+/// `LOG_TAIL_BYTES` so large logs stay cheap to refresh.
+fn last_line_of(path: &std::path::Path) -> Option<String> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = std::fs::File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(LOG_TAIL_BYTES)))
+        .ok()?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail).ok()?;
+    let tail = String::from_utf8_lossy(&tail);
+    tail.lines()
+        .rev()
+        .map(|line| {
+            strip_ansi(line)
+                .chars()
+                .filter(|c| !c.is_control())
+                .collect::<String>()
+        })
+        .map(|line| line.trim().to_string())
+        .find(|line| !line.is_empty())
+}
+
+/// A file's modification time in seconds since the epoch.
+fn modified_secs(path: &std::path::Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(
+        modified
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?
+            .as_secs(),
+    )
+}
+
+fn collect_files(dir: &std::path::Path, prefix: &str, files: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = entry.path();
+        if path.is_dir() {
+            if name.starts_with('.') {
+                collect_files(&path, &format!("{}{}/", prefix, name), files);
+            }
+        } else {
+            let is_task_file =
+                prefix.is_empty() && (name == "context.md" || TASK_FIELDS.contains(&name.as_str()));
+            if !name.starts_with('.') && !is_task_file {
+                files.push(format!("{}{}", prefix, name));
+            }
+        }
+    }
+}
+
+/// Subdirectory of a task holding its extra notes; never a subtask.
+const NOTES_DIR: &str = "notes";
+
+/// Directory in `.yaks` that deleted tasks are moved to.
+const TRASH_DIR: &str = ".trash";
+
+/// File in each `.trash/<stamp>` directory listing the task paths deleted
+/// into it, one per line.
+const TRASH_LIST: &str = ".deleted";
+
+/// Whether a directory inside `.yaks` is a task: hidden directories hold
+/// attachments and plugin data, and `notes/` holds notes.
+fn is_task_dir_name(name: &str) -> bool {
+    !name.starts_with('.') && name != NOTES_DIR
+}
+
+fn is_note_name(name: &str) -> bool {
+    !name.starts_with('.') && name.ends_with(".md")
+}
+
+fn count_notes(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir).map_or(0, |entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .filter(|entry| is_note_name(&entry.file_name().to_string_lossy()))
+            .count()
+    })
+}
+
+/// The field files a `TaskLine` is built from. Anything else in a task
+/// directory, such as context.md, is left unread on refresh.
+const TASK_FIELDS: [&str; 14] = [
+    "state",
+    "name",
+    "id",
+    "assigned-to",
+    "agent-status",
+    "queue-position",
+    "url",
+    "branch",
+    "blocked-by",
+    "estimate",
+    "due",
+    "repeat",
+    "defer",
+    "pinned",
+];
+
+/// Read a field file, trimmed; see `TaskRepository::read_field`.
+fn read_field_file(field_path: &std::path::Path, field: &str) -> Result<Option<String>, String> {
+    match std::fs::read_to_string(field_path) {
+        Ok(s) => Ok(Some(s.trim().to_string()).filter(|s| !s.is_empty())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+            Err(format!("{}: not valid UTF-8", field))
+        }
+        Err(e) => Err(format!("{}: {}", field, e)),
+    }
+}
+
+/// Build a task from its field values, keyed by field name. Absent fields
+/// fall back to defaults; unreadable or unrecognized ones are noted in
+/// `problems`.
+fn task_from_fields(
+    path: &str,
+    depth: usize,
+    mut fields: BTreeMap<String, Result<Option<String>, String>>,
+) -> TaskLine {
+    let mut problems = Vec::new();
+    let mut field = |name: &str| {
+        fields.remove(name).unwrap_or(Ok(None)).unwrap_or_else(|e| {
+            problems.push(e);
+            None
+        })
+    };
+
+    let state_str = field("state");
+    let name =
+        field("name").unwrap_or_else(|| path.split('/').next_back().unwrap_or(path).to_string());
+
+    let yak_id =
+        field("id").unwrap_or_else(|| path.split('/').next_back().unwrap_or(path).to_string());
+    let assigned_to = field("assigned-to");
+    let agent_status = field("agent-status");
+    let queue_position_str = field("queue-position");
+    let url = field("url");
+    let branch = field("branch");
+    let blocked_by = field("blocked-by")
+        .map(|ids| {
+            ids.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default();
+    let estimate_str = field("estimate");
+    let due_str = field("due");
+    let repeat_str = field("repeat");
+    let defer_str = field("defer");
+    let pinned = field("pinned").is_some_and(|v| parse_bool(&v));
+
+    let state = match state_str.as_deref() {
+        Some("wip") => TaskState::Wip,
+        Some("done") => TaskState::Done,
+        None | Some("todo") => TaskState::Todo,
+        Some(other) => {
+            problems.push(format!(
+                "state: unrecognized value {:?}, shown as todo",
+                other
+            ));
+            TaskState::Todo
+        }
+    };
+    let queue_position = queue_position_str.and_then(|v| match v.parse() {
+        Ok(n) => Some(n),
+        Err(_) => {
+            problems.push(format!("queue-position: {:?} is not a number", v));
+            None
+        }
+    });
+    let estimate = estimate_str.and_then(|v| match v.parse::<f64>() {
+        Ok(n) if n.is_finite() && n >= 0.0 => Some(n),
+        _ => {
+            problems.push(format!("estimate: {:?} is not a non-negative number", v));
+            None
+        }
+    });
+    let due = due_str.and_then(|v| {
+        let day = parse_date(&v);
+        if day.is_none() {
+            problems.push(format!("due: {:?} is not a YYYY-MM-DD date", v));
+        }
+        day
+    });
+    let defer = defer_str.and_then(|v| {
+        let day = parse_date(&v);
+        if day.is_none() {
+            problems.push(format!("defer: {:?} is not a YYYY-MM-DD date", v));
+        }
+        day
+    });
+    let repeat = repeat_str.and_then(|v| {
+        let repeat = Repeat::parse(&v);
+        if repeat.is_none() {
+            problems.push(format!(
+                "repeat: {:?} is not daily, weekly, monthly, yearly or every <n><d|w|m|y>",
+                v
+            ));
+        }
+        repeat
+    });
+
+    TaskLine {
+        path: path.to_string(),
+        name,
+        yak_id,
+        depth,
+        state,
+        assigned_to,
+        agent_status,
+        queue_position,
+        url,
+        branch,
+        note_count: 0,
+        last_log_line: None,
+        blocked_by,
+        blockers: Vec::new(),
+        estimate,
+        due,
+        repeat,
+        defer,
+        pinned,
+        state_since: None,
+        problems,
+        has_children: false,
+        child_count: 0,
+        descendant_count: 0,
+        subtree_done: state == TaskState::Done,
+        is_last_sibling: false,
+        ancestor_continuations: Vec::new(),
+    }
+}
+
+/// The parts of the view that survive a restart, stored as `key=value` lines
+/// in `.yaks/.yak-map-state`. Unknown keys are ignored so older and newer
+/// plugin builds can share the file.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UiState {
+    selected: Option<String>,
+    scroll_offset: usize,
+    marks: BTreeMap<char, String>,
+    collapsed: std::collections::BTreeSet<String>,
+    expanded: std::collections::BTreeSet<String>,
+    /// Applied `/` filter query.
+    filter: Option<String>,
+    /// Name of the view shown, from `View::SAVED`.
+    view: Option<String>,
+    /// Name of the view tab.
+    tab: Option<String>,
+    /// Done-last sorting as toggled with `S`; `None` defers to the configuration.
+    sort_done_last: Option<bool>,
+    mine_only: bool,
+    zoom: Option<String>,
+    show_feed: bool,
+    show_preview: bool,
+    /// Height of the preview panel as resized with `+`/`-`.
+    preview_rows: Option<usize>,
+    /// Views saved with `:view save <name>`, stored as `view.<name>.<key>`.
+    views: BTreeMap<String, SavedView>,
+}
+
+/// A named combination of filter, sorting, tab, zoom and folds, saved with
+/// `:view save <name>` and recalled with `:view <name>`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedView {
+    filter: Option<String>,
+    sort_done_last: bool,
+    mine_only: bool,
+    /// Name of the view tab.
+    tab: Option<String>,
+    zoom: Option<String>,
+    collapsed: std::collections::BTreeSet<String>,
+}
+
+impl SavedView {
+    fn parse_line(&mut self, key: &str, value: &str) {
+        match key {
+            "filter" => self.filter = Some(value.to_string()),
+            "sort" => self.sort_done_last = value == "done-last",
+            "mine" => self.mine_only = parse_bool(value),
+            "tab" => self.tab = Some(value.to_string()),
+            "zoom" => self.zoom = Some(value.to_string()),
+            "collapsed" => {
+                self.collapsed.insert(value.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    fn serialize(&self, name: &str, out: &mut String) {
+        // Always written, so a view with nothing else set is still saved
+        let sort = if self.sort_done_last {
+            "done-last"
+        } else {
+            "name"
+        };
+        out.push_str(&format!("view.{}.sort={}\n", name, sort));
+        if let Some(filter) = &self.filter {
+            out.push_str(&format!("view.{}.filter={}\n", name, filter));
+        }
+        if self.mine_only {
+            out.push_str(&format!("view.{}.mine=true\n", name));
+        }
+        if let Some(tab) = &self.tab {
+            out.push_str(&format!("view.{}.tab={}\n", name, tab));
+        }
+        if let Some(zoom) = &self.zoom {
+            out.push_str(&format!("view.{}.zoom={}\n", name, zoom));
+        }
+        for path in &self.collapsed {
+            out.push_str(&format!("view.{}.collapsed={}\n", name, path));
+        }
+    }
+}
+
+impl UiState {
+    pub fn parse(contents: &str) -> Self {
+        let mut ui_state = UiState::default();
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                continue;
+            }
+            match key.trim() {
+                "selected" => ui_state.selected = Some(value.to_string()),
+                "scroll" => ui_state.scroll_offset = value.parse().unwrap_or(0),
+                "collapsed" => {
+                    ui_state.collapsed.insert(value.to_string());
+                }
+                "expanded" => {
+                    ui_state.expanded.insert(value.to_string());
+                }
+                "filter" => ui_state.filter = Some(value.to_string()),
+                "view" => ui_state.view = Some(value.to_string()),
+                "tab" => ui_state.tab = Some(value.to_string()),
+                "sort" => ui_state.sort_done_last = Some(value == "done-last"),
+                "mine" => ui_state.mine_only = parse_bool(value),
+                "zoom" => ui_state.zoom = Some(value.to_string()),
+                "feed" => ui_state.show_feed = parse_bool(value),
+                "preview" => ui_state.show_preview = parse_bool(value),
+                "preview_rows" => ui_state.preview_rows = value.parse().ok(),
+                key => {
+                    if let Some(letter) = key.strip_prefix("mark.").and_then(single_char) {
+                        ui_state.marks.insert(letter, value.to_string());
+                    } else if let Some((name, key)) = key
+                        .strip_prefix("view.")
+                        .and_then(|rest| rest.rsplit_once('.'))
+                    {
+                        let view = ui_state.views.entry(name.to_string()).or_default();
+                        view.parse_line(key, value);
+                    }
+                }
+            }
+        }
+        ui_state
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+        if let Some(selected) = &self.selected {
+            out.push_str(&format!("selected={}\n", selected));
+        }
+        if self.scroll_offset > 0 {
+            out.push_str(&format!("scroll={}\n", self.scroll_offset));
+        }
+        for (letter, path) in &self.marks {
+            out.push_str(&format!("mark.{}={}\n", letter, path));
+        }
+        for path in &self.collapsed {
+            out.push_str(&format!("collapsed={}\n", path));
+        }
+        for path in &self.expanded {
+            out.push_str(&format!("expanded={}\n", path));
+        }
+        if let Some(filter) = &self.filter {
+            out.push_str(&format!("filter={}\n", filter));
+        }
+        if let Some(view) = &self.view {
+            out.push_str(&format!("view={}\n", view));
+        }
+        if let Some(tab) = &self.tab {
+            out.push_str(&format!("tab={}\n", tab));
+        }
+        if let Some(done_last) = self.sort_done_last {
+            let sort = if done_last { "done-last" } else { "name" };
+            out.push_str(&format!("sort={}\n", sort));
+        }
+        if self.mine_only {
+            out.push_str("mine=true\n");
+        }
+        if let Some(zoom) = &self.zoom {
+            out.push_str(&format!("zoom={}\n", zoom));
+        }
+        if self.show_feed {
+            out.push_str("feed=true\n");
+        }
+        if self.show_preview {
+            out.push_str("preview=true\n");
+        }
+        if let Some(rows) = self.preview_rows {
+            out.push_str(&format!("preview_rows={}\n", rows));
+        }
+        for (name, view) in &self.views {
+            view.serialize(name, &mut out);
+        }
+        out
+    }
+}
+
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Plugin options read from the Zellij layout configuration.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// Render a vim-style relative line-number gutter.
+    relative_numbers: bool,
+    /// Collapse subtrees in which every task is done.
+    auto_collapse_done: bool,
+    /// Sort done tasks after their todo/wip siblings.
+    sort_done_last: bool,
+    /// Identity used by the "my tasks" filter, matched against `assigned-to`.
+    me: Option<String>,
+    /// Agent to follow from startup.
+    follow: Option<String>,
+    /// Explicit agent colors from `agent_color.<name>` keys, as SGR sequences.
+    agent_colors: BTreeMap<String, String>,
+    /// Agents offered by the assign picker even before they own a task.
+    agents: Vec<String>,
+    /// Most wip tasks per agent, from `wip_limit`; unlimited when `None`.
+    wip_limit: Option<usize>,
+    /// Per-agent overrides of `wip_limit` from `wip_limit.<name>` keys.
+    wip_limits: BTreeMap<String, usize>,
+    /// Backends to notify through for each event, from `notify.<event>` keys.
+    notify: BTreeMap<NotifyEvent, Vec<NotifyBackend>>,
+    /// Command run by the `command` backend, with title and body as arguments.
+    notify_command: String,
+    /// Editor command line for `e`, with `{file}` and `{line}` placeholders;
+    /// `None` uses Zellij's default editor.
+    editor: Option<Vec<String>>,
+    /// Open context in a tiled rather than a floating pane.
+    editor_tiled: bool,
+    /// Git branch name for a task, with `{id}`, `{slug}` and `{assignee}`
+    /// placeholders.
+    branch_template: String,
+    /// Mark the critical path from startup.
+    critical_path: bool,
+    /// Show the status line (version, yaks directory, last refresh) from startup.
+    status_line: bool,
+    /// Command that opens a task's `url`, given the URL as its last argument.
+    opener: Vec<String>,
+    /// Viewer command line for Enter; `None` uses `$PAGER`, then `less`.
+    pager: Option<Vec<String>>,
+    /// Where the viewer's pane opens.
+    pager_pane: PanePlacement,
+    /// File manager command line for `O`, run in the selected task's
+    /// directory; unset by default.
+    file_manager: Option<Vec<String>>,
+    /// Single file holding the tasks instead of `.yaks`, relative to the
+    /// directory `.yaks` is in.
+    task_file: Option<String>,
+    /// Refuse every change and command, for dashboards and other people's
+    /// panes. Always on with `task_url`.
+    read_only: bool,
+    /// URL of a yaks server's JSON snapshot to browse instead of `.yaks`.
+    task_url: Option<String>,
+    /// Keep an index of the tree in `.yaks/.cache` to start from and to
+    /// skip re-reading unchanged tasks.
+    index_cache: bool,
+    /// Extra view tabs from `tab.<name>` keys.
+    tabs: Vec<(String, TabFilter)>,
+    /// Command printing the clipboard for `Ctrl+v`; `None` tries pbpaste,
+    /// xclip and xsel.
+    paste_command: Option<Vec<String>>,
+    /// Command lines offered by `.`, from `action.<name>` keys, with `{id}`,
+    /// `{path}`, `{dir}` and `{name}` placeholders.
+    actions: BTreeMap<String, Vec<String>>,
+    /// What to do when every subtask of an open task becomes done.
+    complete_parents: ParentCompletion,
+    /// Whether field writes go to the files or through `yx`.
+    backend: WriteBackend,
+    /// `yx` command lines from `yx.<action>` keys, overriding `YX_COMMANDS`.
+    yx_commands: BTreeMap<String, Vec<String>>,
+    /// Oldest `yx` version `backend=yx` accepts.
+    yx_min_version: Option<String>,
+    /// Color preset chosen with the `theme` key.
+    theme: Theme,
+    /// Colors the terminal can show; richer colors are downgraded to fit.
+    color_depth: ColorDepth,
+    /// Render without color or box drawing, for screen readers and piping.
+    plain: bool,
+    /// Show the selected task's ancestry under the tree.
+    breadcrumb: bool,
+    /// Pin the off-screen ancestors of the top row above the tree.
+    sticky_ancestors: bool,
+    /// Show a minimap column on wide panes when the tree does not fit.
+    minimap: bool,
+    /// How far back the changes view looks, in minutes.
+    changes_window_mins: u64,
+    /// Days deleted tasks stay in `.yaks/.trash`; 0 keeps them.
+    trash_days: u64,
+    /// Hours a task can stay wip before it counts as stalled; 0 disables.
+    stale_wip_hours: u64,
+    /// Also append debug log entries to `.yaks/.log/yak-map.log`.
+    log_file: bool,
+    /// Seconds between automatic refreshes.
+    refresh_secs: f64,
+    /// Rows kept visible above and below the selection while scrolling.
+    scroll_margin: usize,
+    /// Offset from UTC for clock times, in minutes. The plugin sandbox has
+    /// no time zone database, so this is configured rather than detected.
+    utc_offset_mins: i64,
+    /// How the selected row is marked.
+    selection: SelectionStyle,
+    /// Where the preview goes: under the tree, or beside it on wide panes.
+    preview_split: PreviewSplit,
+    /// Share of the pane width given to a preview beside the tree.
+    preview_ratio: f64,
+    /// Background for the `background` selection style, overriding the theme's.
+    selection_bg: Option<String>,
+}
+
+impl Config {
+    pub fn from_map(configuration: &BTreeMap<String, String>) -> Self {
+        let flag = |key: &str| {
+            configuration
+                .get(key)
+                .map(|v| parse_bool(v))
+                .unwrap_or(false)
+        };
+        Self {
+            relative_numbers: flag("relative_numbers"),
+            plain: flag("plain"),
+            log_file: flag("log_file"),
+            refresh_secs: configuration
+                .get("refresh_secs")
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|secs| secs.is_finite())
+                .map_or(DEFAULT_REFRESH_SECS, |secs| secs.max(MIN_REFRESH_SECS)),
+            breadcrumb: configuration
+                .get("breadcrumb")
+                .is_none_or(|v| parse_bool(v)),
+            sticky_ancestors: configuration
+                .get("sticky_ancestors")
+                .is_none_or(|v| parse_bool(v)),
+            minimap: configuration.get("minimap").is_none_or(|v| parse_bool(v)),
+            changes_window_mins: configuration
+                .get("changes_window_mins")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(60),
+            trash_days: configuration
+                .get("trash_days")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(30),
+            stale_wip_hours: configuration
+                .get("stale_wip_hours")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(24),
+            scroll_margin: configuration
+                .get("scroll_margin")
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_SCROLL_MARGIN),
+            utc_offset_mins: configuration
+                .get("utc_offset")
+                .and_then(|v| parse_utc_offset(v))
+                .unwrap_or(0),
+            auto_collapse_done: flag("auto_collapse_done"),
+            sort_done_last: flag("sort_done_last"),
+            me: non_empty(configuration.get("me")),
+            follow: non_empty(configuration.get("follow")),
+            agent_colors: configuration
+                .iter()
+                .filter_map(|(key, value)| {
+                    let agent = key.strip_prefix("agent_color.")?;
+                    Some((agent.to_string(), parse_color(value)?))
+                })
+                .collect(),
+            agents: configuration
+                .get("agents")
+                .map(|v| {
+                    v.split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|agent| !agent.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            wip_limit: configuration
+                .get("wip_limit")
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|&limit| limit > 0),
+            wip_limits: configuration
+                .iter()
+                .filter_map(|(key, value)| {
+                    let agent = key.strip_prefix("wip_limit.")?;
+                    Some((agent.to_string(), value.trim().parse().ok()?))
+                })
+                .collect(),
+            notify: NotifyEvent::ALL
+                .iter()
+                .map(|&event| {
+                    let backends = match configuration.get(&format!("notify.{}", event.key())) {
+                        Some(value) => NotifyBackend::parse_list(value),
+                        None => event.default_backends(),
+                    };
+                    (event, backends)
+                })
+                .collect(),
+            notify_command: non_empty(configuration.get("notify_command"))
+                .unwrap_or_else(|| "notify-send".to_string()),
+            editor: configuration
+                .get("editor")
+                .map(|v| split_words(v))
+                .filter(|words| !words.is_empty()),
+            editor_tiled: flag("editor_tiled"),
+            branch_template: non_empty(configuration.get("branch_template"))
+                .unwrap_or_else(|| "yak/{id}".to_string()),
+            critical_path: flag("critical_path"),
+            status_line: flag("status_line"),
+            opener: configuration
+                .get("opener")
+                .map(|v| split_words(v))
+                .filter(|words| !words.is_empty())
+                .unwrap_or_else(|| vec!["xdg-open".to_string()]),
+            pager: configuration
+                .get("pager")
+                .map(|v| split_words(v))
+                .filter(|words| !words.is_empty()),
+            pager_pane: configuration
+                .get("pager_pane")
+                .and_then(|value| PanePlacement::parse(value))
+                .unwrap_or_default(),
+            file_manager: configuration
+                .get("file_manager")
+                .map(|v| split_words(v))
+                .filter(|words| !words.is_empty()),
+            task_file: non_empty(configuration.get("task_file")),
+            index_cache: flag("index_cache"),
+            task_url: non_empty(configuration.get("task_url")),
+            // A remote tree has no local task directories to write to
+            read_only: flag("read_only") || non_empty(configuration.get("task_url")).is_some(),
+            tabs: configuration
+                .iter()
+                .filter_map(|(key, value)| {
+                    let name = key.strip_prefix("tab.")?;
+                    Some((name.to_string(), TabFilter::parse(value)?))
+                })
+                .collect(),
+            paste_command: configuration
+                .get("paste_command")
+                .map(|v| split_words(v))
+                .filter(|words| !words.is_empty()),
+            actions: configuration
+                .iter()
+                .filter_map(|(key, value)| {
+                    let name = key.strip_prefix("action.")?;
+                    Some((name.to_string(), split_words(value)))
+                })
+                .filter(|(_, words)| !words.is_empty())
+                .collect(),
+            complete_parents: configuration
+                .get("complete_parents")
+                .and_then(|value| ParentCompletion::parse(value))
+                .unwrap_or_default(),
+            backend: configuration
+                .get("backend")
+                .and_then(|value| WriteBackend::parse(value))
+                .unwrap_or_default(),
+            yx_commands: configuration
+                .iter()
+                .filter_map(|(key, value)| {
+                    let action = key.strip_prefix("yx.")?;
+                    Some((action.to_string(), split_words(value)))
+                })
+                .filter(|(_, words)| !words.is_empty())
+                .collect(),
+            yx_min_version: non_empty(configuration.get("yx_min_version")),
+            theme: configuration
+                .get("theme")
+                .and_then(|name| Theme::named(name))
+                .unwrap_or_default(),
+            selection: configuration
+                .get("selection")
+                .and_then(|value| SelectionStyle::parse(value))
+                .unwrap_or_default(),
+            preview_split: configuration
+                .get("preview_split")
+                .and_then(|value| PreviewSplit::parse(value))
+                .unwrap_or_default(),
+            preview_ratio: configuration
+                .get("preview_ratio")
+                .and_then(|v| v.trim().parse::<f64>().ok())
+                .filter(|ratio| ratio.is_finite())
+                .map_or(DEFAULT_PREVIEW_RATIO, |ratio| ratio.clamp(0.2, 0.8)),
+            selection_bg: configuration
+                .get("selection_bg")
+                .and_then(|value| parse_color(value))
+                .map(|fg| to_background(&fg)),
+            color_depth: configuration
+                .get("color_depth")
+                .and_then(|value| ColorDepth::parse(value))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// An agent's WIP limit; a `wip_limit.<name>` of 0 lifts the default.
+    fn wip_limit_for(&self, agent: &str) -> Option<usize> {
+        match self.wip_limits.get(agent) {
+            Some(&limit) => Some(limit).filter(|&limit| limit > 0),
+            None => self.wip_limit,
+        }
+    }
+
+    fn notify_backends(&self, event: NotifyEvent) -> Vec<NotifyBackend> {
+        self.notify.get(&event).cloned().unwrap_or_default()
+    }
+
+    /// The `yx` command line that writes `value` to a task's field, or
+    /// clears it: `yx.<state>` for a state change when one is configured
+    /// (`done` by default), otherwise `yx.set` / `yx.unset`.
+    fn yx_command(&self, yak_id: &str, field: &str, value: Option<&str>) -> Vec<String> {
+        let action = match value {
+            None => "unset",
+            Some(state) if field == "state" && self.yx_template(state).is_some() => state,
+            Some(_) => "set",
+        };
+        let template = self.yx_template(action).unwrap_or_default();
+        template
+            .iter()
+            .map(|word| {
+                word.replace("{id}", yak_id)
+                    .replace("{field}", field)
+                    .replace("{value}", value.unwrap_or(""))
+            })
+            .collect()
+    }
+
+    fn yx_template(&self, action: &str) -> Option<Vec<String>> {
+        self.yx_commands.get(action).cloned().or_else(|| {
+            YX_COMMANDS
+                .iter()
+                .find(|(name, _)| *name == action)
+                .map(|(_, line)| split_words(line))
+        })
+    }
+}
+
+/// Default `yx` command lines for the `backend=yx` writes.
+const YX_COMMANDS: [(&str, &str); 4] = [
+    ("done", "yx done {id}"),
+    ("rm", "yx rm {id}"),
+    ("set", "yx field {id} {field} {value}"),
+    ("unset", "yx field {id} {field} --clear"),
+];
+
+/// SGR sequences for task states and the selection background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Theme {
+    /// agent-status `blocked:`.
+    blocked: &'static str,
+    /// agent-status `done:`.
+    agent_done: &'static str,
+    /// agent-status `wip:`, or the wip state.
+    wip: &'static str,
+    /// The done state.
+    done: &'static str,
+    /// The todo state.
+    todo: &'static str,
+    selection_bg: &'static str,
+}
+
+impl Theme {
+    const DEFAULT: Theme = Theme {
+        blocked: "\x1b[31m",
+        agent_done: "\x1b[32m",
+        wip: "\x1b[33m",
+        done: "\x1b[90m",
+        todo: "\x1b[37m",
+        selection_bg: "\x1b[48;5;237m",
+    };
+
+    /// Bold, bright colors, a lighter done state and a saturated selection.
+    const HIGH_CONTRAST: Theme = Theme {
+        blocked: "\x1b[1;91m",
+        agent_done: "\x1b[1;92m",
+        wip: "\x1b[1;93m",
+        done: "\x1b[37m",
+        todo: "\x1b[97m",
+        selection_bg: "\x1b[48;5;24m",
+    };
+
+    /// Orange/blue instead of red/green, which deuteranopes cannot tell apart.
+    const DEUTERANOPIA: Theme = Theme {
+        blocked: "\x1b[38;5;208m",
+        agent_done: "\x1b[38;5;33m",
+        wip: "\x1b[38;5;220m",
+        done: "\x1b[90m",
+        todo: "\x1b[37m",
+        selection_bg: "\x1b[48;5;237m",
+    };
+
+    fn named(name: &str) -> Option<Theme> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "default" => Some(Theme::DEFAULT),
+            "high-contrast" => Some(Theme::HIGH_CONTRAST),
+            "deuteranopia" | "deuteranopia-safe" => Some(Theme::DEUTERANOPIA),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::DEFAULT
+    }
+}
+
+/// Events worth interrupting the user for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NotifyEvent {
+    /// An agent-status changed to `blocked:`.
+    Blocked,
+    /// An agent-status changed to `done:`.
+    Done,
+    /// An open task's due date passed.
+    Overdue,
+}
+
+impl NotifyEvent {
+    const ALL: [NotifyEvent; 3] = [
+        NotifyEvent::Blocked,
+        NotifyEvent::Done,
+        NotifyEvent::Overdue,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            NotifyEvent::Blocked => "blocked",
+            NotifyEvent::Done => "done",
+            NotifyEvent::Overdue => "overdue",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            NotifyEvent::Blocked => "Agent blocked",
+            NotifyEvent::Done => "Agent done",
+            NotifyEvent::Overdue => "Overdue",
+        }
+    }
+
+    fn default_backends(self) -> Vec<NotifyBackend> {
+        match self {
+            NotifyEvent::Blocked | NotifyEvent::Overdue => vec![NotifyBackend::Toast],
+            NotifyEvent::Done => Vec::new(),
+        }
+    }
+}
+
+/// Where a notification is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NotifyBackend {
+    Toast,
+    /// `OSC 777;notify`, understood by urxvt, foot, WezTerm and others.
+    Osc777,
+    /// `OSC 9`, understood by iTerm2, Windows Terminal and others.
+    Osc9,
+    /// The configured `notify_command`.
+    Command,
+    /// The terminal bell.
+    Bell,
+}
+
+impl NotifyBackend {
+    /// Parse a comma-separated backend list; `off` or unknown names add nothing.
+    fn parse_list(value: &str) -> Vec<NotifyBackend> {
+        value
+            .split(',')
+            .filter_map(|name| match name.trim().to_ascii_lowercase().as_str() {
+                "toast" => Some(NotifyBackend::Toast),
+                "osc777" => Some(NotifyBackend::Osc777),
+                "osc9" => Some(NotifyBackend::Osc9),
+                "command" => Some(NotifyBackend::Command),
+                "bell" => Some(NotifyBackend::Bell),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Notification {
+    event: NotifyEvent,
+    title: String,
+    body: String,
+}
+
+/// Terminal escape sequence that raises a desktop notification. Control
+/// characters are dropped so the text cannot end the sequence early.
+fn osc_notification(backend: NotifyBackend, notification: &Notification) -> Option<String> {
+    let clean = |text: &str| text.chars().filter(|c| !c.is_control()).collect::<String>();
+    let title = clean(&notification.title);
+    let body = clean(&notification.body);
+    match backend {
+        NotifyBackend::Osc777 => Some(format!(
+            "\x1b]777;notify;{};{}\x07",
+            title.replace(';', ","),
+            body
+        )),
+        NotifyBackend::Osc9 => Some(format!("\x1b]9;{}: {}\x07", title, body)),
+        NotifyBackend::Toast | NotifyBackend::Command | NotifyBackend::Bell => None,
+    }
+}
+
+/// The command line running the configured notify command. Title and body
+/// are passed as $1 and $2 to avoid escaping.
+fn notify_command_args(command: &str, notification: &Notification) -> Vec<String> {
+    let script = format!("{} \"$1\" \"$2\"", command);
+    [
+        "sh",
+        "-c",
+        &script,
+        "sh",
+        &notification.title,
+        &notification.body,
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
+
+/// The command for an `editor` or `pager` template: `{file}` and `{line}`
+/// (the line to open at) are substituted, and the file is appended when the
+/// template does not mention it.
+fn command_from_template(template: &[String], file: &str, line: usize) -> CommandToRun {
+    let mut words: Vec<String> = template
+        .iter()
+        .map(|word| {
+            word.replace("{file}", file)
+                .replace("{line}", &line.to_string())
+        })
+        .collect();
+    if !template.iter().any(|word| word.contains("{file}")) {
+        words.push(file.to_string());
+    }
+    let program = words.remove(0);
+    CommandToRun {
+        path: PathBuf::from(program),
+        args: words,
+        cwd: None,
+    }
+}
+
+/// Command that runs a file manager command line in `dir`.
+fn file_manager_command(words: &[String], dir: &std::path::Path) -> CommandToRun {
+    CommandToRun {
+        path: PathBuf::from(&words[0]),
+        args: words[1..].to_vec(),
+        cwd: Some(dir.to_path_buf()),
+    }
+}
+
+/// Command for a user-defined action, with the task's `{id}`, `{path}`,
+/// `{dir}` and `{name}` substituted into each word.
+fn action_command(template: &[String], task: &TaskLine, dir: &std::path::Path) -> CommandToRun {
+    let dir = dir.to_string_lossy();
+    let mut words: Vec<String> = template
+        .iter()
+        .map(|word| {
+            word.replace("{id}", &task.yak_id)
+                .replace("{path}", &task.path)
+                .replace("{dir}", &dir)
+                .replace("{name}", &task.name)
+        })
+        .collect();
+    let program = words.remove(0);
+    CommandToRun {
+        path: PathBuf::from(program),
+        args: words,
+        cwd: None,
+    }
+}
+
+/// `sh -c` command for a `:!` line, run through `env` since command panes
+/// don't take environment variables.
+fn shell_command(
+    shell: &str,
+    yak_id: &str,
+    task_path: &str,
+    dir: &std::path::Path,
+) -> CommandToRun {
+    CommandToRun {
+        path: PathBuf::from("env"),
+        args: vec![
+            format!("YAK_ID={}", yak_id),
+            format!("YAK_PATH={}", task_path),
+            format!("YAK_DIR={}", dir.display()),
+            "sh".to_string(),
+            "-c".to_string(),
+            shell.to_string(),
+        ],
+        cwd: None,
+    }
+}
+
+/// Foreground SGR sequence for a configured color: a basic color name
+/// (`red`, `bright-blue`, ...), a 256-color palette index or `#rrggbb`.
+fn parse_color(value: &str) -> Option<String> {
+    const NAMES: [&str; 8] = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    let value = value.trim().to_ascii_lowercase();
+    if let Some(hex) = value.strip_prefix('#') {
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+        };
+        if hex.len() != 6 {
+            return None;
+        }
+        return Some(format!(
+            "\x1b[38;2;{};{};{}m",
+            channel(0)?,
+            channel(2)?,
+            channel(4)?
+        ));
+    }
+    if let Ok(index) = value.parse::<u8>() {
+        return Some(format!("\x1b[38;5;{}m", index));
+    }
+    let (base, name) = match value.strip_prefix("bright-") {
+        Some(name) => (90, name),
+        None => (30, value.as_str()),
+    };
+    NAMES
+        .iter()
+        .position(|n| *n == name)
+        .map(|i| format!("\x1b[{}m", base + i))
+}
+
+/// Turn a foreground SGR sequence from `parse_color` into the matching
+/// background one.
+fn to_background(fg: &str) -> String {
+    let Some(params) = fg.strip_prefix("\x1b[").and_then(|p| p.strip_suffix('m')) else {
+        return fg.to_string();
+    };
+    let params = match params.split_once(';') {
+        Some(("38", rest)) => format!("48;{}", rest),
+        _ => match params.parse::<u8>() {
+            Ok(n @ 30..=37) | Ok(n @ 90..=97) => (n + 10).to_string(),
+            _ => params.to_string(),
+        },
+    };
+    format!("\x1b[{}m", params)
+}
+
+/// How the selected row stands out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SelectionStyle {
+    /// The theme's (or `selection_bg`'s) background across the pane width.
+    #[default]
+    Background,
+    /// Reverse video across the pane width.
+    Reverse,
+    /// A `>` in a gutter column.
+    Marker,
+    /// Bold text only.
+    Bold,
+}
+
+impl SelectionStyle {
+    fn parse(value: &str) -> Option<SelectionStyle> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "background" => Some(SelectionStyle::Background),
+            "reverse" => Some(SelectionStyle::Reverse),
+            "marker" => Some(SelectionStyle::Marker),
+            "bold" => Some(SelectionStyle::Bold),
+            _ => None,
+        }
+    }
+}
+
+/// Where the preview panel is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PreviewSplit {
+    /// Beside the tree on panes at least `SIDE_PREVIEW_MIN_COLS` wide,
+    /// otherwise under it.
+    #[default]
+    Auto,
+    /// Under the tree, shown and hidden with `v`.
+    Bottom,
+    /// Beside the tree, always shown.
+    Right,
+}
+
+impl PreviewSplit {
+    fn parse(value: &str) -> Option<PreviewSplit> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "auto" => Some(PreviewSplit::Auto),
+            "bottom" => Some(PreviewSplit::Bottom),
+            "right" => Some(PreviewSplit::Right),
+            _ => None,
+        }
+    }
+}
+
+/// Where a command pane opened by the plugin goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PanePlacement {
+    #[default]
+    Floating,
+    Tiled,
+    /// Temporarily replacing the plugin pane.
+    InPlace,
+}
+
+impl PanePlacement {
+    fn parse(value: &str) -> Option<PanePlacement> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "floating" => Some(PanePlacement::Floating),
+            "tiled" => Some(PanePlacement::Tiled),
+            "in-place" | "in_place" => Some(PanePlacement::InPlace),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the `yx` binary is on the host's PATH, and its version.
+#[derive(Debug, Clone, Default, PartialEq)]
+enum YxProbe {
+    /// The probe hasn't answered yet.
+    #[default]
+    Pending,
+    Missing,
+    Found(String),
+}
+
+/// The version in `yx --version` output such as `yx 0.4.2`.
+fn parse_yx_version(output: &str) -> Option<String> {
+    let line = output.lines().next()?;
+    let version = line
+        .split_whitespace()
+        .map(|word| word.trim_start_matches('v'))
+        .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))?;
+    Some(version.to_string())
+}
+
+/// Whether dotted version `version` is older than `minimum`; parts that
+/// aren't numbers compare as 0.
+fn version_older_than(version: &str, minimum: &str) -> bool {
+    let parts = |v: &str| -> Vec<u64> {
+        v.split('.')
+            .map(|part| {
+                let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+                digits.parse().unwrap_or(0)
+            })
+            .collect()
+    };
+    parts(version) < parts(minimum)
+}
+
+/// Where the plugin's field writes go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum WriteBackend {
+    /// Write the field files under `.yaks` directly.
+    #[default]
+    Files,
+    /// Run the configured `yx` subcommand for each write.
+    Yx,
+}
+
+impl WriteBackend {
+    fn parse(value: &str) -> Option<WriteBackend> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "files" => Some(WriteBackend::Files),
+            "yx" => Some(WriteBackend::Yx),
+            _ => None,
+        }
+    }
+}
+
+/// What happens when the last open subtask of a task is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ParentCompletion {
+    #[default]
+    Off,
+    /// Offer to mark the parent done.
+    Ask,
+    /// Mark the parent done straight away (`u` undoes it).
+    Auto,
+}
+
+impl ParentCompletion {
+    fn parse(value: &str) -> Option<ParentCompletion> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "false" => Some(ParentCompletion::Off),
+            "ask" => Some(ParentCompletion::Ask),
+            "auto" | "true" => Some(ParentCompletion::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// How many colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum ColorDepth {
+    /// The 16 basic ANSI colors.
+    Ansi16,
+    /// The xterm 256-color palette.
+    #[default]
+    Ansi256,
+    /// 24-bit RGB.
+    TrueColor,
+}
+
+impl ColorDepth {
+    fn parse(value: &str) -> Option<ColorDepth> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "16" => Some(ColorDepth::Ansi16),
+            "256" => Some(ColorDepth::Ansi256),
+            "truecolor" | "24bit" => Some(ColorDepth::TrueColor),
+            _ => None,
+        }
+    }
+
+    /// Guess the depth from `COLORTERM` and `TERM`, assuming 256 colors when
+    /// neither says otherwise.
+    fn detect(colorterm: Option<&str>, term: Option<&str>) -> ColorDepth {
+        if matches!(colorterm, Some("truecolor" | "24bit")) {
+            return ColorDepth::TrueColor;
+        }
+        match term {
+            Some(term) if term == "linux" || term.ends_with("-16color") => ColorDepth::Ansi16,
+            _ => ColorDepth::Ansi256,
+        }
+    }
+}
+
+/// RGB values of the 16 basic colors, as xterm draws them.
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Channel levels of the 6x6x6 cube in the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI16_RGB[index as usize],
+        16..=231 => {
+            let i = (index - 16) as usize;
+            (
+                CUBE_LEVELS[i / 36],
+                CUBE_LEVELS[i / 6 % 6],
+                CUBE_LEVELS[i % 6],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+fn rgb_distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// Nearest entry of the 256-color cube or grayscale ramp.
+fn rgb_to_256(rgb: (u8, u8, u8)) -> u8 {
+    let nearest_level = |c: u8| {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+            .unwrap_or(0)
+    };
+    let cube = 16 + 36 * nearest_level(rgb.0) + 6 * nearest_level(rgb.1) + nearest_level(rgb.2);
+    let gray = (232..=255u8)
+        .min_by_key(|&i| rgb_distance(rgb, palette_rgb(i)))
+        .unwrap_or(232);
+    if rgb_distance(rgb, palette_rgb(gray)) < rgb_distance(rgb, palette_rgb(cube as u8)) {
+        gray
+    } else {
+        cube as u8
+    }
+}
+
+/// Nearest of the 16 basic colors.
+fn rgb_to_16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16u8)
+        .min_by_key(|&i| rgb_distance(rgb, ANSI16_RGB[i as usize]))
+        .unwrap_or(7)
+}
+
+/// Rewrite the 256-color and RGB parameters of an SGR sequence so the
+/// terminal can show them; other parameters pass through unchanged.
+fn fit_color(sgr: &str, depth: ColorDepth) -> String {
+    let Some(params) = sgr.strip_prefix("\x1b[").and_then(|p| p.strip_suffix('m')) else {
+        return sgr.to_string();
+    };
+    let params: Vec<&str> = params.split(';').collect();
+    let mut out: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < params.len() {
+        let extended = matches!(params[i], "38" | "48");
+        let number = |j: usize| params.get(j).and_then(|p| p.parse::<u8>().ok());
+        let color = match (extended, params.get(i + 1).copied()) {
+            (true, Some("5")) => number(i + 2).map(|n| (palette_rgb(n), Some(n), 3)),
+            (true, Some("2")) => match (number(i + 2), number(i + 3), number(i + 4)) {
+                (Some(r), Some(g), Some(b)) => Some(((r, g, b), None, 5)),
+                _ => None,
+            },
+            _ => None,
+        };
+        let Some((rgb, index, len)) = color else {
+            out.push(params[i].to_string());
+            i += 1;
+            continue;
+        };
+        let layer = params[i];
+        out.push(match (depth, index) {
+            (ColorDepth::TrueColor, _) => params[i..i + len].join(";"),
+            (ColorDepth::Ansi256, Some(n)) => format!("{};5;{}", layer, n),
+            (ColorDepth::Ansi256, None) => format!("{};5;{}", layer, rgb_to_256(rgb)),
+            (ColorDepth::Ansi16, _) => {
+                let basic = match index {
+                    Some(n) if n < 16 => n,
+                    _ => rgb_to_16(rgb),
+                };
+                let base = if layer == "38" { 30 } else { 40 };
+                let bright = if basic >= 8 { 60 } else { 0 };
+                (base + bright + basic % 8).to_string()
+            }
+        });
+        i += len;
+    }
+    format!("\x1b[{}m", out.join(";"))
+}
+
+/// 256-color palette entries that read well on both dark and light backgrounds.
+const AGENT_PALETTE: [u8; 12] = [39, 208, 170, 114, 221, 75, 203, 141, 43, 179, 99, 167];
+
+/// A stable color for an agent name. FNV-1a is used rather than the std
+/// hasher so colors never change between builds.
+fn agent_palette_color(agent: &str) -> String {
+    let hash = agent.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!(
+        "\x1b[38;5;{}m",
+        AGENT_PALETTE[(hash % AGENT_PALETTE.len() as u64) as usize]
+    )
+}
+
+fn non_empty(value: Option<&String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// The configuration in effect: the project file's keys, with the layout's
+/// taking precedence.
+fn layer_config(
+    project: BTreeMap<String, String>,
+    layout: BTreeMap<String, String>,
+) -> BTreeMap<String, String> {
+    let mut layered = project;
+    layered.extend(layout);
+    layered
+}
+
+/// Read the TOML subset `.yak-map.toml` uses into layout-style keys:
+/// `[tab]` followed by `alice = "todo wip @alice"` is `tab.alice`. Values
+/// are strings, bare words (numbers, booleans) or one-line arrays, which
+/// become comma-separated lists.
+fn parse_config_file(contents: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut map = BTreeMap::new();
+    let mut table: Option<String> = None;
+    for (number, line) in contents.lines().enumerate() {
+        let error = |what: &str| format!("line {}: {}", number + 1, what);
+        let line = strip_toml_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[') {
+            let name = header
+                .strip_suffix(']')
+                .ok_or_else(|| error("unclosed table header"))?;
+            table = Some(toml_key(name).ok_or_else(|| error("bad table name"))?);
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| error("expected key = value"))?;
+        let key = toml_key(key).ok_or_else(|| error("bad key"))?;
+        let value = toml_value(value.trim()).ok_or_else(|| error("bad value"))?;
+        let key = match &table {
+            Some(table) => format!("{}.{}", table, key),
+            None => key,
+        };
+        map.insert(key, value);
+    }
+    Ok(map)
+}
+
+/// A line with any `#` comment outside a string removed.
+fn strip_toml_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some('"'), '\\') if !escaped => {
+                escaped = true;
+                continue;
+            }
+            (Some(q), c) if c == q && !escaped => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+/// A bare dotted key (`agent_color.alice`) or a quoted one.
+fn toml_key(key: &str) -> Option<String> {
+    let key = key.trim();
+    if key.starts_with('"') || key.starts_with('\'') {
+        return match toml_string(key)? {
+            (name, "") => Some(name),
+            _ => None,
+        };
+    }
+    let bare = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    };
+    key.split('.')
+        .map(str::trim)
+        .all(bare)
+        .then(|| key.replace(' ', ""))
+}
+
+fn toml_value(value: &str) -> Option<String> {
+    if let Some(items) = value.strip_prefix('[') {
+        let mut rest = items.trim_start();
+        let mut list = Vec::new();
+        while !rest.starts_with(']') {
+            let (item, after) = toml_string(rest)?;
+            list.push(item);
+            rest = after.trim_start();
+            rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+        }
+        return (rest == "]").then(|| list.join(","));
+    }
+    if value.starts_with('"') || value.starts_with('\'') {
+        return match toml_string(value)? {
+            (string, "") => Some(string),
+            _ => None,
+        };
+    }
+    let bare = |c: char| c.is_ascii_alphanumeric() || "_-+.:".contains(c);
+    (!value.is_empty() && value.chars().all(bare)).then(|| value.to_string())
+}
+
+/// A leading basic (`"..."`, with escapes) or literal (`'...'`) string, and
+/// the text after it.
+fn toml_string(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let mut string = String::new();
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Some((string, text[i + 1..].trim_start())),
+            '\\' if quote == '"' => string.push(match chars.next()?.1 {
+                'n' => '\n',
+                't' => '\t',
+                other => other,
+            }),
+            c => string.push(c),
+        }
+    }
+    None
+}
+
+fn parse_bool(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "true" | "yes" | "on" | "1"
+    )
+}
+
+/// Characters used for jump labels, home row first.
+const JUMP_LABEL_CHARS: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+/// Characters used for `#` quick-select labels, in row order.
+const QUICK_SELECT_CHARS: &str = "123456789abcdefghijklmnopqrstuvwxyz";
+
+/// Labels for `count` rows from `alphabet`: single characters when they
+/// suffice, otherwise two-character labels so every row gets a label of the
+/// same length.
+fn jump_labels(alphabet: &str, count: usize) -> Vec<String> {
+    let chars: Vec<char> = alphabet.chars().collect();
+    if count <= chars.len() {
+        return chars.iter().take(count).map(|c| c.to_string()).collect();
+    }
+    chars
+        .iter()
+        .flat_map(|&a| chars.iter().map(move |&b| format!("{}{}", a, b)))
+        .take(count)
+        .collect()
+}
+
+/// What the key handler is currently collecting input for.
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Mode {
+    #[default]
+    Normal,
+    /// `f` (or `#`, with `numbered` labels) was pressed; visible rows show
+    /// labels and `typed` holds the label characters entered so far.
+    JumpLabel { typed: String, numbered: bool },
+    /// A list to choose from, drawn in place of the current view.
+    Picker(Picker),
+    /// A line of text to type, drawn under the tree.
+    Prompt(Prompt),
+    /// Every path a delete, move or import will touch, drawn in place of the
+    /// current view until it is confirmed or cancelled.
+    Preview(Preview),
+    /// `/` was pressed; `input` is the filter query being typed and applied
+    /// live. `previous` is the view to return to if the edit is cancelled,
+    /// and `recall` tracks Up/Down through earlier queries.
+    Filter {
+        input: String,
+        previous: FilterView,
+        recall: Recall,
+    },
+    /// `"` was pressed; `typed` is the name prefix to jump to, searched from
+    /// the row after `from`, the selection when typing started.
+    TypeAhead { typed: String, from: usize },
+}
+
+/// Panes at least this wide show extra per-row detail.
+const WIDE_MODE_COLS: usize = 100;
+
+/// Permissions the plugin asks for on load.
+const PERMISSIONS: [PermissionType; 3] = [
+    PermissionType::OpenFiles,
+    PermissionType::RunCommands,
+    PermissionType::ReadCliPipes,
+];
+
+/// The build's date and commit, set by build.rs.
+const VERSION: &str = env!("VERSION");
+
+/// Panes at most this tall show a one-line summary instead of the tree.
+const STATUS_BAR_MAX_ROWS: usize = 3;
+
+/// Panes at least this wide show the preview beside the tree with
+/// `preview_split=auto`.
+const SIDE_PREVIEW_MIN_COLS: usize = 140;
+
+/// Share of the pane width the side preview takes unless `preview_ratio`
+/// says otherwise.
+const DEFAULT_PREVIEW_RATIO: f64 = 0.4;
+
+/// Columns the tree keeps however large `preview_ratio` is.
+const MIN_TREE_COLS: usize = 40;
+
+/// Rows of context kept around the selection unless `scroll_margin` says
+/// otherwise.
+const DEFAULT_SCROLL_MARGIN: usize = 2;
+
+/// Seconds between automatic refreshes unless `refresh_secs` says otherwise.
+const DEFAULT_REFRESH_SECS: f64 = 2.0;
+
+/// Shortest refresh interval accepted, to keep re-reading the tree cheap.
+const MIN_REFRESH_SECS: f64 = 0.5;
+
+/// Writes closer together than this share one refresh, run when the window ends.
+const WRITE_DEBOUNCE_SECS: f64 = 0.25;
+
+/// Debug log entries kept in memory; older ones are dropped first.
+const LOG_CAP: usize = 200;
+
+/// Refreshes slower than this are logged even when nothing changed.
+const SLOW_REFRESH_MS: u128 = 50;
+
+/// Lines of activity shown in the feed panel.
+const FEED_PANEL_ROWS: usize = 5;
+
+/// Lines of the selected task's context.md shown in the preview panel.
+const PREVIEW_PANEL_ROWS: usize = 8;
+/// Bounds of the preview panel's height as resized with `+`/`-`.
+const MIN_PREVIEW_ROWS: usize = 2;
+const MAX_PREVIEW_ROWS: usize = 40;
+
+/// Columns taken by the minimap: a viewport marker and a state cell.
+const MINIMAP_COLS: usize = 2;
+
+/// Number of time buckets in a row's agent-status trail.
+const STATUS_TRAIL_LEN: usize = 8;
+
+/// Seconds covered by each character of the agent-status trail.
+const STATUS_TRAIL_BUCKET_SECS: u64 = 60;
+
+/// Status changes kept per task, oldest dropped first.
+const STATUS_HISTORY_CAP: usize = 32;
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// One-character summary of an agent-status value for the status trail.
+fn status_trail_char(agent_status: Option<&str>) -> char {
+    match agent_status {
+        None => '·',
+        Some(s) if s.starts_with("wip:") => 'w',
+        Some(s) if s.starts_with("blocked:") => 'b',
+        Some(s) if s.starts_with("done:") => 'd',
+        Some(_) => '!',
+    }
+}
+
+/// Render a status trail from `(timestamp, char)` changes: one char per
+/// bucket, oldest on the left, each showing the status at the bucket's end.
+fn status_trail(history: &std::collections::VecDeque<(u64, char)>, now: u64) -> String {
+    (0..STATUS_TRAIL_LEN)
+        .rev()
+        .map(|back| {
+            let at = now.saturating_sub(back as u64 * STATUS_TRAIL_BUCKET_SECS);
+            history
+                .iter()
+                .rev()
+                .find(|(t, _)| *t <= at)
+                .map_or(' ', |(_, c)| *c)
+        })
+        .collect()
+}
+
+/// Name agents pass to `zellij pipe --name` to reach the plugin.
+const PIPE_NAME: &str = "yak-map";
+
+/// Refresh ticks a row stays flashed after a piped update.
+const FLASH_TICKS: u8 = 2;
+
+/// A command received over `zellij pipe --name yak-map -- <command>`.
+#[derive(Debug, Clone, PartialEq)]
+enum PipeCommand {
+    /// `status <yak-id> <text>`: set (or with no text, clear) agent-status.
+    Status { yak_id: String, status: String },
+}
+
+/// Split a pipe payload or command line into words, honouring single and
+/// double quotes.
+fn split_words(input: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    for c in input.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    words
+}
+
+fn parse_pipe_command(payload: &str) -> Result<PipeCommand, String> {
+    let words = split_words(payload);
+    match words.split_first() {
+        Some((command, rest)) if command == "status" => match rest.split_first() {
+            Some((yak_id, status)) => Ok(PipeCommand::Status {
+                yak_id: yak_id.clone(),
+                status: status.join(" "),
+            }),
+            None => Err("usage: status <yak-id> [status]".to_string()),
+        },
+        Some((command, _)) => Err(format!("unknown command: {}", command)),
+        None => Err("empty command".to_string()),
+    }
+}
+
+/// Send a reply to a `zellij pipe` caller and let it exit.
+#[cfg(not(test))]
+fn reply_to_cli_pipe(pipe_id: &str, output: &str) {
+    cli_pipe_output(pipe_id, output);
+    unblock_cli_pipe_input(pipe_id);
+}
+
+/// Native test builds link the plugin's exported `pipe` in place of libc's,
+/// which keeps this path live, so it must not reach the host functions.
+#[cfg(test)]
+fn reply_to_cli_pipe(_pipe_id: &str, _output: &str) {}
+
+/// Arm the timer that flushes a deferred post-write refresh.
+#[cfg(not(test))]
+fn start_write_debounce_timer() {
+    set_timeout(WRITE_DEBOUNCE_SECS);
+}
+
+/// See `reply_to_cli_pipe`: `pipe` calls this too.
+#[cfg(test)]
+fn start_write_debounce_timer() {}
+
+/// Run a command without a pane; its outcome arrives as a
+/// `RunCommandResult` carrying `context`.
+#[cfg(not(test))]
+fn run_in_background(args: &[String], context: BTreeMap<String, String>) {
+    run_command(
+        &args.iter().map(String::as_str).collect::<Vec<_>>(),
+        context,
+    );
+}
+
+#[cfg(test)]
+fn run_in_background(_args: &[String], _context: BTreeMap<String, String>) {}
+
+/// GET a URL; the response arrives as a `WebRequestResult` carrying
+/// `context`.
+#[cfg(not(test))]
+fn fetch_url(url: &str, context: BTreeMap<String, String>) {
+    web_request(url, HttpVerb::Get, BTreeMap::new(), Vec::new(), context);
+}
+
+#[cfg(test)]
+fn fetch_url(_url: &str, _context: BTreeMap<String, String>) {}
+
+/// Run a command in a new pane placed as configured.
+#[cfg(not(test))]
+fn open_command_pane_at(command: CommandToRun, placement: PanePlacement) {
+    match placement {
+        PanePlacement::Floating => open_command_pane_floating(command, None, BTreeMap::new()),
+        PanePlacement::Tiled => open_command_pane(command, BTreeMap::new()),
+        PanePlacement::InPlace => open_command_pane_in_place(command, BTreeMap::new()),
+    }
+}
+
+/// Open a file in Zellij's default editor, in a pane placed as configured.
+#[cfg(not(test))]
+fn open_file_at(file: FileToOpen, placement: PanePlacement) {
+    match placement {
+        PanePlacement::Floating => open_file_floating(file, None, BTreeMap::new()),
+        PanePlacement::Tiled => open_file(file, BTreeMap::new()),
+        PanePlacement::InPlace => open_file_in_place(file, BTreeMap::new()),
+    }
+}
+
+/// Tests drive the picker, whose choices can open panes, so these must not
+/// reach the host functions either.
+#[cfg(test)]
+fn open_command_pane_at(_command: CommandToRun, _placement: PanePlacement) {}
+
+#[cfg(test)]
+fn open_file_at(_file: FileToOpen, _placement: PanePlacement) {}
+
+/// Which tasks a view tab shows, parsed from words such as `wip blocked
+/// @alice`: any of the listed states, only blocked tasks, only the user's
+/// tasks or only an agent's. No words shows everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct TabFilter {
+    states: Vec<TaskState>,
+    blocked: bool,
+    mine: bool,
+    assignee: Option<String>,
+}
+
+impl TabFilter {
+    fn parse(value: &str) -> Option<TabFilter> {
+        let mut filter = TabFilter::default();
+        for word in value.split_whitespace() {
+            match word.to_ascii_lowercase().as_str() {
+                "todo" => filter.states.push(TaskState::Todo),
+                "wip" => filter.states.push(TaskState::Wip),
+                "done" => filter.states.push(TaskState::Done),
+                "blocked" => filter.blocked = true,
+                "mine" => filter.mine = true,
+                _ => filter.assignee = Some(word.strip_prefix('@')?.to_string()),
+            }
+        }
+        Some(filter)
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == TabFilter::default()
+    }
+}
+
+/// The tabs every tree has, before those from `tab.<name>` keys.
+const BUILTIN_TABS: [(&str, &str); 5] = [
+    ("All", ""),
+    ("WIP", "wip"),
+    ("Blocked", "blocked"),
+    ("Done", "done"),
+    ("Mine", "mine"),
+];
+
+/// A task being dragged, and the task it is over.
+#[derive(Debug, Clone, PartialEq)]
+struct Drag {
+    path: String,
+    over: Option<String>,
+}
+
+/// Which screen the plugin is showing.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum View {
+    #[default]
+    Tree,
+    /// Each agent with its wip task and queued todo tasks.
+    AgentQueues,
+    /// Changes seen between refreshes in the last `changes_window_mins`.
+    Changes,
+    /// Tasks with fields that could not be read or understood.
+    Diagnostics,
+    /// Totals by state, plus the tasks that have stalled in wip.
+    Stats,
+    /// The selected task's field files with their values, ages and sizes.
+    Fields,
+    /// The internal debug log.
+    Log,
+    /// Tasks with a due date as bars along a day axis.
+    Timeline,
+    /// The coming week or two as columns of due tasks.
+    Calendar,
+    /// Tasks as a table with a column per field, sortable by any column.
+    Table,
+    /// Version, yx, permissions, configuration and refresh timing, for
+    /// support questions.
+    About,
+    /// Deleted tasks in `.yaks/.trash`, newest first, to restore.
+    Trash,
+}
+
+impl View {
+    /// Views a session is restored into, by their name in the UI state.
+    /// The others are glanced at and left, so a session left in one of them
+    /// comes back to the tree.
+    const SAVED: [(View, &'static str); 7] = [
+        (View::Tree, "tree"),
+        (View::AgentQueues, "agents"),
+        (View::Changes, "changes"),
+        (View::Stats, "stats"),
+        (View::Timeline, "timeline"),
+        (View::Calendar, "calendar"),
+        (View::Table, "table"),
+    ];
+
+    fn saved_name(self) -> Option<&'static str> {
+        View::SAVED
+            .iter()
+            .find(|(view, _)| *view == self)
+            .map(|(_, name)| *name)
+    }
+
+    fn from_saved_name(name: &str) -> Option<View> {
+        View::SAVED
+            .iter()
+            .find(|(_, saved)| *saved == name)
+            .map(|(view, _)| *view)
+    }
+}
+
+/// A column of the table view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TableColumn {
+    Task,
+    State,
+    Assignee,
+    Due,
+    Estimate,
+    Age,
+}
+
+impl TableColumn {
+    const ALL: [TableColumn; 6] = [
+        TableColumn::Task,
+        TableColumn::State,
+        TableColumn::Assignee,
+        TableColumn::Due,
+        TableColumn::Estimate,
+        TableColumn::Age,
+    ];
+
+    fn title(self) -> &'static str {
+        match self {
+            TableColumn::Task => "Task",
+            TableColumn::State => "State",
+            TableColumn::Assignee => "Assignee",
+            TableColumn::Due => "Due",
+            TableColumn::Estimate => "Est",
+            TableColumn::Age => "Age",
+        }
+    }
+
+    /// Width of the column; the task column is sized to its paths.
+    fn width(self, task_width: usize) -> usize {
+        match self {
+            TableColumn::Task => task_width,
+            TableColumn::State => 5,
+            TableColumn::Assignee => 12,
+            TableColumn::Due => 10,
+            TableColumn::Estimate => 4,
+            TableColumn::Age => 9,
+        }
+    }
+
+    fn compare(self, a: &TaskLine, b: &TaskLine) -> std::cmp::Ordering {
+        match self {
+            TableColumn::Task => a.path.cmp(&b.path),
+            TableColumn::State => {
+                let rank = |task: &TaskLine| match task.state {
+                    TaskState::Todo => 0,
+                    TaskState::Wip => 1,
+                    TaskState::Done => 2,
+                };
+                rank(a).cmp(&rank(b))
+            }
+            TableColumn::Assignee => a.assigned_to.cmp(&b.assigned_to),
+            TableColumn::Due => a.due.cmp(&b.due),
+            TableColumn::Estimate => a
+                .estimate
+                .partial_cmp(&b.estimate)
+                .unwrap_or(std::cmp::Ordering::Equal),
+            // Older first: the earlier the state was written, the older it is
+            TableColumn::Age => b.state_since.cmp(&a.state_since),
+        }
+    }
+}
+
+/// The fields of a task that changes are tracked for.
+#[derive(Debug, Clone, PartialEq)]
+struct TaskSnapshot {
+    state: TaskState,
+    assigned_to: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ChangeKind {
+    Created,
+    Removed,
+    State {
+        from: TaskState,
+        to: TaskState,
+    },
+    Assigned {
+        from: Option<String>,
+        to: Option<String>,
+    },
+}
+
+/// Field writes made by the plugin that `u` can revert: each entry is a
+/// task path, a field and the value it had before (`None` if absent).
+#[derive(Debug, Clone, PartialEq)]
+struct Undo {
+    label: String,
+    writes: Vec<(String, String, Option<String>)>,
+}
+
+/// A change to one task, seen at `at` (seconds since the epoch).
+#[derive(Debug, Clone, PartialEq)]
+struct Change {
+    at: u64,
+    path: String,
+    kind: ChangeKind,
+}
+
+/// Most changes kept; older ones are dropped first.
+const CHANGE_LOG_CAP: usize = 500;
+
+fn snapshot_tasks(tasks: &[TaskLine]) -> BTreeMap<String, TaskSnapshot> {
+    tasks
+        .iter()
+        .map(|t| {
+            let snapshot = TaskSnapshot {
+                state: t.state,
+                assigned_to: t.assigned_to.clone(),
+            };
+            (t.path.clone(), snapshot)
+        })
+        .collect()
+}
+
+/// What changed between two snapshots, in path order.
+fn diff_snapshots(
+    old: &BTreeMap<String, TaskSnapshot>,
+    new: &BTreeMap<String, TaskSnapshot>,
+) -> Vec<(String, ChangeKind)> {
+    let mut changes = Vec::new();
+    for (path, after) in new {
+        let Some(before) = old.get(path) else {
+            changes.push((path.clone(), ChangeKind::Created));
+            continue;
+        };
+        if before.state != after.state {
+            changes.push((
+                path.clone(),
+                ChangeKind::State {
+                    from: before.state,
+                    to: after.state,
+                },
+            ));
+        }
+        if before.assigned_to != after.assigned_to {
+            changes.push((
+                path.clone(),
+                ChangeKind::Assigned {
+                    from: before.assigned_to.clone(),
+                    to: after.assigned_to.clone(),
+                },
+            ));
+        }
+    }
+    for path in old.keys().filter(|path| !new.contains_key(*path)) {
+        changes.push((path.clone(), ChangeKind::Removed));
+    }
+    changes
+}
+
+/// Parse a UTC offset such as `+02:00`, `-0530` or `+1` into minutes.
+fn parse_utc_offset(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (sign, rest) = match value.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let digits: String = rest.chars().filter(|c| *c != ':').collect();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i64>().ok()?, 0),
+        3 | 4 => {
+            let split = digits.len() - 2;
+            (
+                digits[..split].parse::<i64>().ok()?,
+                digits[split..].parse::<i64>().ok()?,
+            )
+        }
+        _ => return None,
+    };
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 60 + minutes))
+}
+
+/// `YYYY-MM-DD` calendar date of `secs` (seconds since the epoch).
+fn format_date(secs: u64, utc_offset_mins: i64) -> String {
+    format_day(day_number(secs, utc_offset_mins))
+}
+
+/// Days since 1970-01-01 of a `YYYY-MM-DD` date.
+fn parse_date(value: &str) -> Option<i64> {
+    let mut parts = value.trim().splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: u32 = parts.next()?.parse().ok()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    // Larger years would overflow the day arithmetic
+    if !(0..=9999).contains(&year) {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    // Reject days past the end of the month by round-tripping.
+    (civil_from_days(days) == (year, month, day)).then_some(days)
+}
+
+/// How often a recurring task comes back, from its `repeat` field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Repeat {
+    Days(u32),
+    Months(u32),
+}
+
+impl Repeat {
+    /// `daily`, `weekly`, `monthly`, `yearly`, or `every` with an optional
+    /// count and a unit (`every 3d`, `every 2 weeks`, `every month`).
+    fn parse(value: &str) -> Option<Repeat> {
+        let value = value.trim().to_ascii_lowercase();
+        match value.as_str() {
+            "daily" => return Some(Repeat::Days(1)),
+            "weekly" => return Some(Repeat::Days(7)),
+            "monthly" => return Some(Repeat::Months(1)),
+            "yearly" => return Some(Repeat::Months(12)),
+            _ => {}
+        }
+        let rest = value.strip_prefix("every")?.trim_start();
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let count: u32 = if digits == 0 {
+            1
+        } else {
+            rest[..digits].parse().ok()?
+        };
+        if count == 0 {
+            return None;
+        }
+        match rest[digits..].trim() {
+            "d" | "day" | "days" => Some(Repeat::Days(count)),
+            "w" | "week" | "weeks" => Some(Repeat::Days(count.checked_mul(7)?)),
+            "m" | "month" | "months" => Some(Repeat::Months(count)),
+            "y" | "year" | "years" => Some(Repeat::Months(count.checked_mul(12)?)),
+            _ => None,
+        }
+    }
+
+    /// The occurrence after `day`, clamping to the end of shorter months.
+    fn next(self, day: i64) -> i64 {
+        match self {
+            Repeat::Days(n) => day + i64::from(n),
+            Repeat::Months(n) => {
+                let (year, month, day_of_month) = civil_from_days(day);
+                let months = year * 12 + i64::from(month - 1) + i64::from(n);
+                let (year, month) = (months.div_euclid(12), months.rem_euclid(12) as u32 + 1);
+                (1..=day_of_month)
+                    .rev()
+                    .map(|d| days_from_civil(year, month, d))
+                    .find(|&days| civil_from_days(days).1 == month)
+                    .unwrap_or(day)
+            }
+        }
+    }
+
+    /// The first occurrence after `due` that is not before `today`, so a
+    /// chore finished late is not recreated already overdue.
+    fn next_after(self, due: i64, today: i64) -> i64 {
+        let mut next = self.next(due);
+        while next < today {
+            next = self.next(next);
+        }
+        next
+    }
+}
+
+/// Day count since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Local day number (days since 1970-01-01) of `secs`.
+fn day_number(secs: u64, utc_offset_mins: i64) -> i64 {
+    (secs as i64 + utc_offset_mins * 60).div_euclid(86400)
+}
+
+/// `YYYY-MM-DD` of a day number.
+fn format_day(day: i64) -> String {
+    let (year, month, day) = civil_from_days(day);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Proleptic Gregorian (year, month, day) of a day count since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// `HH:MM` wall-clock time of `secs` (seconds since the epoch).
+fn format_clock(secs: u64, utc_offset_mins: i64) -> String {
+    let minutes = (secs as i64 / 60 + utc_offset_mins).rem_euclid(24 * 60);
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// A short relative age: `just now`, `5m ago`, `2h ago`, `3d ago`.
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// One agent's workload in the agent queue view, as indices into the task list.
+#[derive(Debug, Clone, PartialEq)]
+struct AgentQueue {
+    agent: String,
+    wip: Vec<usize>,
+    queued: Vec<usize>,
+}
+
+/// Group open tasks by assignee. Queued (todo) tasks are ordered by their
+/// `queue-position`, with unpositioned tasks after them in tree order.
+fn agent_queues(tasks: &[TaskLine]) -> Vec<AgentQueue> {
+    let mut queues: BTreeMap<&str, AgentQueue> = BTreeMap::new();
+    for (i, task) in tasks.iter().enumerate() {
+        let Some(agent) = task.assigned_to.as_deref() else {
+            continue;
+        };
+        let queue = queues.entry(agent).or_insert_with(|| AgentQueue {
+            agent: agent.to_string(),
+            wip: Vec::new(),
+            queued: Vec::new(),
+        });
+        match task.state {
+            TaskState::Wip => queue.wip.push(i),
+            TaskState::Todo => queue.queued.push(i),
+            TaskState::Done => {}
+        }
+    }
+    let mut queues: Vec<AgentQueue> = queues.into_values().collect();
+    for queue in &mut queues {
+        queue
+            .queued
+            .sort_by_key(|&i| tasks[i].queue_position.unwrap_or(u32::MAX));
+    }
+    queues
+}
+
+/// A list of choices opened by an action; `purpose` decides what choosing does.
+#[derive(Debug, Clone, PartialEq)]
+struct Picker {
+    title: String,
+    items: Vec<PickerItem>,
+    selected: usize,
+    purpose: PickerPurpose,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct PickerItem {
+    label: String,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PickerPurpose {
+    /// Write the chosen agent to `assigned-to` of the target tasks.
+    Assign,
+    /// Mark the chosen tasks (paths separated by newlines) done.
+    CompleteParents,
+    /// Set the target tasks to the chosen state.
+    SetState,
+    /// Edit the chosen field of the selected task.
+    EditField,
+    /// Write the state on the first line to the paths on the others.
+    CascadeState,
+    /// View the chosen file, a path relative to `.yaks`.
+    OpenFile,
+    /// Snooze the target tasks: a [`parse_snooze`] duration, `date` to type
+    /// one in, or `wake` to clear `defer`.
+    Snooze,
+    /// Run the chosen `action.<name>` for the selected task.
+    Action,
+    /// Run the chosen entry of the right-click menu on the selected task.
+    ContextMenu,
+    /// Create tasks named by all but the first line under the path on the
+    /// first line.
+    Paste,
+}
+
+/// A change listed path by path before it is made.
+#[derive(Debug, Clone, PartialEq)]
+struct Preview {
+    title: String,
+    /// One line per path: `+` created, `>` moved, `-` removed.
+    lines: Vec<String>,
+    /// The first line shown, moved by Up/Down.
+    scroll: usize,
+    change: PendingChange,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PendingChange {
+    /// Delete these tasks and their subtasks.
+    Delete(Vec<String>),
+    /// Move the task at `path` (and its subtasks) under `target`.
+    Move { path: String, target: String },
+    /// Create outline lines, indented by tabs, under `parent`.
+    Create { parent: String, names: Vec<String> },
+}
+
+/// A change to the target tasks that `,` can make again on another selection.
+#[derive(Debug, Clone, PartialEq)]
+enum Repeatable {
+    State(String),
+    /// The agent to assign, or empty to unassign.
+    Assign(String),
+    Snooze(Option<i64>),
+}
+
+/// A line of text being typed, shown under the tree; `purpose` decides what
+/// Enter does with it.
+#[derive(Debug, Clone, PartialEq)]
+struct Prompt {
+    title: String,
+    input: String,
+    purpose: PromptPurpose,
+    /// Up/Down position in the command history.
+    recall: Recall,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum PromptPurpose {
+    /// Snooze the target tasks until the typed date or duration.
+    Snooze,
+    /// Append the text as a timestamped line to the task's context.md.
+    Capture { task_path: String },
+    /// Write the text to a field of a task; empty text removes the field.
+    SetField { task_path: String, field: String },
+    /// Run a `:` command.
+    Command,
+}
+
+/// A snooze end typed by the user: a `YYYY-MM-DD` date, or a duration after
+/// `today` such as `3d`, `2 weeks` or `month`.
+fn parse_snooze(input: &str, today: i64) -> Option<i64> {
+    let input = input.trim();
+    parse_date(input).or_else(|| Some(Repeat::parse(&format!("every {}", input))?.next(today)))
+}
+
+/// Commands the `:` prompt understands, offered by `Tab`.
+const COMMAND_NAMES: [&str; 7] = [
+    "about",
+    "cheatsheet",
+    "import",
+    "set",
+    "trash",
+    "unset",
+    "view",
+];
+
+/// The README, whose Keys table is the source of the `:cheatsheet` key list.
+const README: &str = include_str!("../README.md");
+
+/// The table under `## Keys` in the README.
+fn readme_key_table() -> String {
+    README
+        .split("\n## ")
+        .find_map(|section| section.strip_prefix("Keys\n"))
+        .unwrap_or_default()
+        .lines()
+        .skip_while(|line| !line.starts_with('|'))
+        .take_while(|line| line.starts_with('|'))
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Entries kept per input history; the oldest is dropped first.
+const HISTORY_CAP: usize = 50;
+
+/// Lines entered in an input, oldest first, kept for the session.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct History {
+    entries: Vec<String>,
+}
+
+/// Where Up/Down has got to in a `History`, and the line that was being typed
+/// before recall started.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Recall {
+    index: Option<usize>,
+    draft: String,
+}
+
+impl History {
+    /// Record an entered line, moving a repeat to the newest position.
+    fn push(&mut self, entry: &str) {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > HISTORY_CAP {
+            self.entries.remove(0);
+        }
+    }
+
+    /// Step one entry older or newer from `recall` and return the text to
+    /// show; stepping newer past the last entry gives back the draft.
+    fn step(&self, recall: &mut Recall, input: &str, older: bool) -> Option<String> {
+        let index = match (recall.index, older) {
+            (None, true) if !self.entries.is_empty() => {
+                recall.draft = input.to_string();
+                self.entries.len() - 1
+            }
+            (Some(i), true) => i.saturating_sub(1),
+            (Some(i), false) if i + 1 < self.entries.len() => i + 1,
+            (Some(_), false) => {
+                recall.index = None;
+                return Some(std::mem::take(&mut recall.draft));
+            }
+            _ => return None,
+        };
+        recall.index = Some(index);
+        Some(self.entries[index].clone())
+    }
+}
+
+/// The filter and position to restore when filter input is cancelled.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FilterView {
+    filter: Option<String>,
+    selected_path: Option<String>,
+    scroll_offset: usize,
+}
+
+#[derive(Default)]
+struct State {
+    repository: TaskRepository,
+    config: Config,
+    mode: Mode,
+    view: View,
+    /// Selected row in views other than the tree.
+    view_selected: usize,
+    tasks: Vec<TaskLine>,
+    /// Indices into `tasks` of the rows currently shown, in display order.
+    /// `selected_index` and `scroll_offset` are positions in this list.
+    visible: Vec<usize>,
+    /// Applied `/` filter query.
+    filter: Option<String>,
+    /// Show only tasks assigned to `config.me`.
+    mine_only: bool,
+    /// Index into `tabs()` of the tab shown.
+    tab: usize,
+    /// Selected task and scroll offset each tab was left with.
+    tab_positions: BTreeMap<usize, (Option<String>, usize)>,
+    /// Agent whose current wip task the selection tracks on every refresh.
+    following: Option<String>,
+    /// Tasks as of the last refresh, diffed against the next one. `None`
+    /// until the first refresh so the initial load is not reported as changes.
+    snapshot: Option<BTreeMap<String, TaskSnapshot>>,
+    /// Changes seen between refreshes, oldest first.
+    changes: std::collections::VecDeque<Change>,
+    /// Show the activity feed panel under the tree.
+    show_feed: bool,
+    /// Show the selected task's context.md under the tree.
+    show_preview: bool,
+    /// Height of the preview panel, resized with `+`/`-`;
+    /// `PREVIEW_PANEL_ROWS` when `None`.
+    preview_rows: Option<usize>,
+    /// Views saved by name with `:view save`.
+    views: BTreeMap<String, SavedView>,
+    /// Queries entered with `/`, recalled with Up/Down.
+    filter_history: History,
+    /// Lines entered at the `:` prompt, recalled with Up/Down.
+    command_history: History,
+    /// Show task directory names instead of their `name` fields.
+    raw_names: bool,
+    /// Task on each line the tree last rendered, for mouse clicks.
+    row_tasks: Vec<Option<usize>>,
+    /// The row being dragged with the mouse.
+    drag: Option<Drag>,
+    /// Column the table view is sorted by, and whether descending; tree
+    /// order when `None`.
+    table_sort: Option<(TableColumn, bool)>,
+    /// What the `yx --version` probe at load found.
+    yx: YxProbe,
+    /// Automatic refreshing is paused; `r` and piped updates still refresh.
+    paused: bool,
+    /// Mark the tasks on the critical path.
+    show_critical_path: bool,
+    /// Show the version, yaks directory and last refresh under the tree.
+    show_status_line: bool,
+    /// When the last refresh finished, in seconds since the epoch, and how
+    /// many milliseconds it took.
+    last_refresh: Option<(u64, u128)>,
+    /// The yaks directory as the host sees it, which the plugin reads as
+    /// `/host/.yaks`.
+    host_yaks_dir: Option<PathBuf>,
+    /// The configuration in effect, the project file's layered under the
+    /// layout's.
+    configuration: BTreeMap<String, String>,
+    /// The plugin configuration from the layout, as given.
+    layout_configuration: BTreeMap<String, String>,
+    /// Why the task store could not be read at the last refresh, warned
+    /// about once until it changes.
+    store_error: Option<String>,
+    /// When `.yak-map.toml` was last modified as of the last read of it.
+    config_file_modified: Option<std::time::SystemTime>,
+    /// The answer to the permission request, once there is one.
+    permissions: Option<PermissionStatus>,
+    /// Days the timeline view is scrolled from its default start.
+    timeline_shift: i64,
+    /// The calendar view shows 14 days instead of 7.
+    calendar_fortnight: bool,
+    /// Task indices of the critical path, first to start first.
+    critical_path: Vec<usize>,
+    /// Today's day number as of the last refresh.
+    today: i64,
+    /// Show snoozed tasks, which are otherwise hidden until their `defer` date.
+    show_snoozed: bool,
+    /// Paths of the tasks snoozed at the last refresh, to spot them waking up.
+    snoozed: std::collections::BTreeSet<String>,
+    /// Open tasks whose subtasks were all done at the last refresh, so each
+    /// is only offered once; `None` until the first refresh.
+    completable: Option<std::collections::BTreeSet<String>>,
+    /// The last undoable write, reverted with `u`.
+    undo: Option<Undo>,
+    /// Agents over their WIP limit at the last refresh, so each breach is
+    /// only announced once.
+    over_wip_limit: std::collections::BTreeSet<String>,
+    /// Paths of the open tasks already past their due date, so each is only
+    /// announced once; `None` until the first refresh.
+    overdue: Option<std::collections::BTreeSet<String>>,
+    /// When the last refresh after one of our own writes ran.
+    last_write_refresh: Option<std::time::Instant>,
+    /// A write landed inside the debounce window and still needs a refresh.
+    refresh_pending: bool,
+    /// The debounce timer must be armed once the current event is handled.
+    debounce_timer_needed: bool,
+    /// Debug log as `(seconds since the epoch, message)`, oldest first.
+    log: std::collections::VecDeque<(u64, String)>,
+    /// Task the tree is re-rooted at; only it and its descendants are shown.
+    zoom: Option<String>,
+    /// Number of tasks matching the active filters directly (ancestors shown for context
+    /// are not counted).
+    filter_matches: usize,
+    selected_index: usize,
+    scroll_offset: usize,
+    /// Count typed before a motion (`5j`), vim-style.
+    pending_count: Option<usize>,
+    /// First key of a multi-key sequence (`g` of `gg`, `z` of `zz`).
+    pending_prefix: Option<char>,
+    /// The last `"` type-ahead prefix, repeated by `>` and `<`.
+    type_ahead: Option<String>,
+    /// The last state change, assignment or snooze, repeated by `,`.
+    last_change: Option<Repeatable>,
+    /// Register and keys of the macro being recorded after `q<reg>`.
+    recording: Option<(char, Vec<KeyWithModifier>)>,
+    /// Recorded macros by register, replayed with `Q<reg>`.
+    macros: BTreeMap<char, Vec<KeyWithModifier>>,
+    /// Set while a macro replays, so its keys aren't recorded again and it
+    /// can't replay macros itself.
+    replaying: bool,
+    /// Task rows visible in the last render, used by viewport-relative motions.
+    viewport_rows: usize,
+    /// Done-last sorting toggled at runtime, overriding `config.sort_done_last`.
+    sort_done_last: Option<bool>,
+    /// Paths collapsed by hand with `za`.
+    collapsed: std::collections::BTreeSet<String>,
+    /// Paths expanded by hand that would otherwise be auto-collapsed.
+    expanded: std::collections::BTreeSet<String>,
+    /// Task indices grouped by parent path ("" for root tasks), in tree order.
+    children_by_parent: BTreeMap<String, Vec<usize>>,
+    /// Agent-status changes seen across refreshes, by task path.
+    status_history: std::collections::HashMap<String, std::collections::VecDeque<(u64, char)>>,
+    /// Rows recently updated over the pipe, with the ticks left to flash them.
+    flashed: BTreeMap<String, u8>,
+    /// Pane width in the last render.
+    pane_cols: usize,
+    /// Pane height in the last render.
+    pane_rows: usize,
+    /// While a preview is drawn beside the tree: the tree's width and the
+    /// preview lines still to print, one per emitted line.
+    side_panel: std::cell::RefCell<Option<(usize, std::collections::VecDeque<String>)>>,
+    /// Tasks tagged with Space for bulk actions, by path.
+    tagged: std::collections::BTreeSet<String>,
+    /// What the trash view lists, read when it opens.
+    trash: Vec<TrashEntry>,
+    /// Marks set with `m<letter>`, keyed by letter and holding the task path.
+    marks: BTreeMap<char, String>,
+    /// UI state as last written to disk; saves are skipped while unchanged.
+    saved_ui_state: UiState,
+    error: Option<String>,
+    toast_message: Option<String>,
+    toast_ticks_remaining: u8,
+    /// Notifications raised since the last dispatch.
+    notifications: Vec<Notification>,
+    /// Notification escape sequences to write with the next render.
+    pending_osc: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskLine {
+    path: String,
+    name: String,
+    yak_id: String,
+    depth: usize,
+    state: TaskState,
+    assigned_to: Option<String>,
+    agent_status: Option<String>,
+    /// Order in the assignee's queue, from the `queue-position` field.
+    queue_position: Option<u32>,
+    /// External link (PR, design doc, ticket) from the `url` field.
+    url: Option<String>,
+    /// Git branch created for the task with `B`, from the `branch` field.
+    branch: Option<String>,
+    /// Markdown files in the task's `notes/` directory.
+    note_count: usize,
+    /// The last line of the task's `agent.log`, if it has one.
+    last_log_line: Option<String>,
+    /// Yak ids this task waits on, from the `blocked-by` field.
+    blocked_by: Vec<String>,
+    /// Indices of the tasks named in `blocked_by` that exist.
+    blockers: Vec<usize>,
+    /// Relative effort from the `estimate` field, in whatever unit the team uses.
+    estimate: Option<f64>,
+    /// Due date from the `due` field, in days since the epoch.
+    due: Option<i64>,
+    /// How often the task recurs, from the `repeat` field.
+    repeat: Option<Repeat>,
+    /// Day the task is snoozed until, from the `defer` field.
+    defer: Option<i64>,
+    /// Listed above the tree, from the `pinned` field.
+    pinned: bool,
+    /// When the `state` file was last written, as an estimate of when the
+    /// task entered its current state.
+    state_since: Option<u64>,
+    /// Fields that could not be read or understood, for the diagnostics view.
+    problems: Vec<String>,
+    has_children: bool,
+    /// Number of tasks directly below this one.
+    child_count: usize,
+    /// Number of tasks below this one in the tree.
+    descendant_count: usize,
+    /// This task and every descendant are done.
+    subtree_done: bool,
+    is_last_sibling: bool,
+    ancestor_continuations: Vec<bool>,
+}
+
+impl Default for TaskLine {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            name: String::new(),
+            yak_id: String::new(),
+            depth: 0,
+            state: TaskState::Todo,
+            assigned_to: None,
+            agent_status: None,
+            queue_position: None,
+            url: None,
+            branch: None,
+            note_count: 0,
+            last_log_line: None,
+            blocked_by: Vec::new(),
+            blockers: Vec::new(),
+            estimate: None,
+            due: None,
+            repeat: None,
+            defer: None,
+            pinned: false,
+            state_since: None,
+            problems: Vec::new(),
+            has_children: false,
+            child_count: 0,
+            descendant_count: 0,
+            subtree_done: false,
+            is_last_sibling: false,
+            ancestor_continuations: Vec::new(),
+        }
+    }
+}
+
+/// Escape a string for use inside single-quoted shell literal (replace ' with '\'').
+fn escape_single_quoted(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('\'');
+    for c in s.chars() {
+        if c == '\'' {
+            out.push_str("'\\''");
+        } else {
+            out.push(c);
+        }
+    }
+    out.push('\'');
+    out
+}
+
+/// Copy the yak name (yx-style, space-separated) to the system clipboard.
+/// Tries pbcopy (macOS), then xclip, then xsel (Linux). Name is passed as $1 to avoid escaping.
+fn copy_yak_name_to_clipboard(yx_name: &str) {
+    let script = r#"if command -v pbcopy >/dev/null 2>&1; then printf '%s' "$1" | pbcopy
+elif command -v xclip >/dev/null 2>&1; then printf '%s' "$1" | xclip -selection clipboard
+elif command -v xsel >/dev/null 2>&1; then printf '%s' "$1" | xsel --clipboard
+else echo "No clipboard tool (pbcopy/xclip/xsel) found" >&2; exit 1
+fi"#;
+    let args = ["sh", "-c", script, "sh", yx_name].map(str::to_string);
+    run_in_background(&args, BTreeMap::new());
+}
+
+/// The directory a new task called `name` gets: its slug, or `task`.
+fn task_dir_name(name: &str) -> String {
+    match slugify(name).as_str() {
+        "" => "task".to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+/// `base` under `parent` (`""` for the top level), with a numeric suffix
+/// from 2 up if `taken` says the path is used. `notes` is never a task.
+fn free_child_path(parent: &str, base: &str, taken: impl Fn(&str) -> bool) -> String {
+    let join = |dir: &str| match parent {
+        "" => dir.to_string(),
+        parent => format!("{}/{}", parent, dir),
+    };
+    let mut dir = base.to_string();
+    let mut n = 2;
+    while dir == NOTES_DIR || taken(&join(&dir)) {
+        dir = format!("{}-{}", base, n);
+        n += 1;
+    }
+    join(&dir)
+}
+
+/// `name` lowercased with runs of other characters turned into `-`.
+fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// Task names in pasted or imported text, one per non-blank line, with the
+/// depth its indentation gives it in the outline (a line indented deeper
+/// than the one before is its subtask). Names are trimmed, with list bullets
+/// (`-`, `*`, `+`) and checkboxes (`[ ]`) removed.
+fn outline_lines(text: &str) -> Vec<(usize, String)> {
+    let mut indents: Vec<usize> = Vec::new();
+    let mut lines = Vec::new();
+    for line in text.lines() {
+        let indent: usize = line
+            .chars()
+            .take_while(|c| c.is_whitespace())
+            .map(|c| if c == '\t' { 4 } else { 1 })
+            .sum();
+        let line = line.trim();
+        let line = ["- ", "* ", "+ "]
+            .iter()
+            .find_map(|bullet| line.strip_prefix(bullet))
+            .unwrap_or(line)
+            .trim_start();
+        let line = ["[ ] ", "[x] ", "[X] "]
+            .iter()
+            .find_map(|checkbox| line.strip_prefix(checkbox))
+            .unwrap_or(line)
+            .trim();
+        if line.is_empty() {
+            continue;
+        }
+        while indents.last().is_some_and(|&last| last > indent) {
+            indents.pop();
+        }
+        if indents.last().is_none_or(|&last| last < indent) {
+            indents.push(indent);
+        }
+        lines.push((indents.len() - 1, line.to_string()));
+    }
+    lines
+}
+
+/// A task's branch name from `template`: `{id}` is the yak id, `{slug}` the
+/// name lowercased with runs of other characters turned into `-`, and
+/// `{assignee}` the assignee (or `unassigned`). Whitespace left in the result
+/// becomes `-`, since git refuses it.
+fn branch_name(template: &str, task: &TaskLine) -> String {
+    template
+        .replace("{id}", &task.yak_id)
+        .replace("{slug}", &slugify(&task.name))
+        .replace(
+            "{assignee}",
+            task.assigned_to.as_deref().unwrap_or("unassigned"),
+        )
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Sets of tasks whose `blocked-by` edges form a cycle (strongly connected
+/// components of more than one task, or a task blocking itself), each in
+/// task order.
+fn dependency_cycles(tasks: &[TaskLine]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        tasks: &'a [TaskLine],
+        next_index: usize,
+        index: Vec<Option<usize>>,
+        low_link: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        cycles: Vec<Vec<usize>>,
+    }
+
+    impl Tarjan<'_> {
+        fn visit(&mut self, v: usize) {
+            self.index[v] = Some(self.next_index);
+            self.low_link[v] = self.next_index;
+            self.next_index += 1;
+            self.stack.push(v);
+            self.on_stack[v] = true;
+            for &w in &self.tasks[v].blockers {
+                match self.index[w] {
+                    None => {
+                        self.visit(w);
+                        self.low_link[v] = self.low_link[v].min(self.low_link[w]);
+                    }
+                    Some(w_index) if self.on_stack[w] => {
+                        self.low_link[v] = self.low_link[v].min(w_index);
+                    }
+                    Some(_) => {}
+                }
+            }
+            if Some(self.low_link[v]) == self.index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = self.stack.pop() {
+                    self.on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
+                    }
+                }
+                if component.len() > 1 || self.tasks[v].blockers.contains(&v) {
+                    component.sort_unstable();
+                    self.cycles.push(component);
+                }
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        tasks,
+        next_index: 0,
+        index: vec![None; tasks.len()],
+        low_link: vec![0; tasks.len()],
+        on_stack: vec![false; tasks.len()],
+        stack: Vec::new(),
+        cycles: Vec::new(),
+    };
+    for v in 0..tasks.len() {
+        if tarjan.index[v].is_none() {
+            tarjan.visit(v);
+        }
+    }
+    tarjan.cycles.sort();
+    tarjan.cycles
+}
+
+/// The heaviest chain of incomplete tasks linked by `blocked-by`, weighted
+/// by `estimate` (1 where unset), from the task to start first to the one it
+/// ultimately gates. Edges closing a cycle are ignored.
+fn critical_path(tasks: &[TaskLine]) -> Vec<usize> {
+    /// Weight of the heaviest chain ending at `i`, and the blocker it runs through.
+    fn chain(
+        i: usize,
+        tasks: &[TaskLine],
+        memo: &mut Vec<Option<(f64, Option<usize>)>>,
+        visiting: &mut Vec<bool>,
+    ) -> f64 {
+        if let Some((weight, _)) = memo[i] {
+            return weight;
+        }
+        if visiting[i] {
+            return 0.0;
+        }
+        visiting[i] = true;
+        let mut best: (f64, Option<usize>) = (0.0, None);
+        for &b in &tasks[i].blockers {
+            if tasks[b].state == TaskState::Done || visiting[b] {
+                continue;
+            }
+            let weight = chain(b, tasks, memo, visiting);
+            if best.1.is_none() || weight > best.0 {
+                best = (weight, Some(b));
+            }
+        }
+        visiting[i] = false;
+        let weight = best.0 + tasks[i].estimate.unwrap_or(1.0);
+        memo[i] = Some((weight, best.1));
+        weight
+    }
+
+    let mut memo = vec![None; tasks.len()];
+    let mut visiting = vec![false; tasks.len()];
+    let mut end: Option<(f64, usize)> = None;
+    for (i, task) in tasks.iter().enumerate() {
+        if task.state == TaskState::Done {
+            continue;
+        }
+        let weight = chain(i, tasks, &mut memo, &mut visiting);
+        if end.is_none_or(|(best, _)| weight > best) {
+            end = Some((weight, i));
+        }
+    }
+    let mut path = Vec::new();
+    let mut next = end.map(|(_, i)| i);
+    while let Some(i) = next {
+        if path.contains(&i) {
+            break;
+        }
+        path.push(i);
+        next = memo[i].and_then(|(_, via)| via);
+    }
+    // A lone task is not much of a path.
+    if path.len() < 2 {
+        path.clear();
+    }
+    path.reverse();
+    path
+}
+
+/// Whether `path` is `root` or one of its descendants.
+fn is_in_subtree(path: &str, root: &str) -> bool {
+    path.strip_prefix(root)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Reorder a pre-order task list so that, within each set of siblings, done
+/// tasks come after todo/wip ones. Siblings otherwise keep their name order and
+/// every task stays directly above its own subtree.
+fn sort_done_last(tasks: Vec<TaskLine>) -> Vec<TaskLine> {
+    let mut children: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, task) in tasks.iter().enumerate() {
+        let parent = task.path.rsplit_once('/').map_or("", |(p, _)| p);
+        children.entry(parent.to_string()).or_default().push(i);
+    }
+    for siblings in children.values_mut() {
+        siblings.sort_by_key(|&i| tasks[i].state == TaskState::Done);
+    }
+
+    let mut order = Vec::with_capacity(tasks.len());
+    let mut stack: Vec<usize> = children
+        .get("")
+        .into_iter()
+        .flatten()
+        .rev()
+        .copied()
+        .collect();
+    while let Some(i) = stack.pop() {
+        order.push(i);
+        if let Some(kids) = children.get(&tasks[i].path) {
+            stack.extend(kids.iter().rev());
+        }
+    }
+
+    let mut slots: Vec<Option<TaskLine>> = tasks.into_iter().map(Some).collect();
+    order.into_iter().filter_map(|i| slots[i].take()).collect()
+}
+
+/// Case-insensitive match of `query` against `text`, returning the char
+/// positions that matched. A contiguous substring match is preferred so the
+/// highlight reads naturally; otherwise the query may match as a subsequence.
+fn fuzzy_match(query: &str, text: &str) -> Option<Vec<usize>> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Some(Vec::new());
+    }
+    if let Some(start) = text
+        .windows(query.len())
+        .position(|w| w == query.as_slice())
+    {
+        return Some((start..start + query.len()).collect());
+    }
+    let mut positions = Vec::with_capacity(query.len());
+    let mut wanted = query.iter().peekable();
+    for (i, c) in text.iter().enumerate() {
+        if wanted.peek() == Some(&c) {
+            positions.push(i);
+            wanted.next();
+        }
+    }
+    if wanted.peek().is_none() {
+        Some(positions)
+    } else {
+        None
+    }
+}
+
+/// Render `text` with the chars at `positions` bold and underlined. Only the
+/// bold/underline attributes are reset so surrounding colors carry through.
+fn highlight_matches(text: &str, positions: &[usize]) -> String {
+    let mut out = String::new();
+    for (i, c) in text.chars().enumerate() {
+        if positions.contains(&i) {
+            out.push_str(&format!("\x1b[1;4m{}\x1b[22;24m", c));
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// The typed character for an unmodified (or shift-only) key press.
+fn plain_char(key: &KeyWithModifier) -> Option<char> {
+    let shift_only = key
+        .key_modifiers
+        .iter()
+        .all(|m| matches!(m, KeyModifier::Shift));
+    match key.bare_key {
+        BareKey::Char(c) if shift_only => Some(c),
+        _ => None,
+    }
+}
+
+/// `line` cut or padded to `width` columns, keeping its escape sequences.
+fn fit_ansi(line: &str, width: usize) -> String {
+    let mut result = String::new();
+    let mut shown = 0;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            result.push(c);
+            for inner in chars.by_ref() {
+                result.push(inner);
+                if inner.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else if shown < width {
+            result.push(c);
+            shown += 1;
+        }
+    }
+    result + &" ".repeat(width - shown)
+}
+
+/// A tree line and a preview line printed next to each other.
+fn side_by_side(left: &str, width: usize, right: &str) -> String {
+    format!(
+        "{}\x1b[0m \x1b[90m│\x1b[0m {}",
+        fit_ansi(left, width),
+        right
+    )
+}
+
+/// Strip ANSI escape sequences (CSI sequences like \x1b[...m) from a string,
+/// returning only the visible characters.
+fn strip_ansi(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next(); // consume '['
+            for inner in chars.by_ref() {
+                if inner.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+impl State {
+    /// Take the settings in effect from a layered configuration map.
+    fn apply_configuration(&mut self, configuration: BTreeMap<String, String>) {
+        self.config = Config::from_map(&configuration);
+        if self.config.me.is_none() {
+            self.config.me = std::env::var("USER").ok().filter(|u| !u.is_empty());
+        }
+        if !configuration.contains_key("color_depth") {
+            self.config.color_depth = ColorDepth::detect(
+                std::env::var("COLORTERM").ok().as_deref(),
+                std::env::var("TERM").ok().as_deref(),
+            );
+        }
+        self.repository.set_read_only(self.config.read_only);
+        self.configuration = configuration;
+    }
+
+    /// Re-read `.yak-map.toml` when it has changed since it was last read,
+    /// keeping the previous settings if it no longer parses. Settings that
+    /// are only a starting point, like `follow` or `critical_path`, are left
+    /// as they have been toggled.
+    fn reload_config_if_changed(&mut self) {
+        let modified = self.repository.config_modified();
+        if modified == self.config_file_modified {
+            return;
+        }
+        self.config_file_modified = modified;
+        match self.repository.load_config_file() {
+            Ok(project) => {
+                self.apply_configuration(layer_config(project, self.layout_configuration.clone()))
+            }
+            Err(e) => self.show_warning(e),
+        }
+    }
+
+    fn refresh_tasks(&mut self) {
+        self.refresh_pending = false;
+        self.reload_config_if_changed();
+        // Until it is granted, a web request would go unanswered
+        if self.permissions == Some(PermissionStatus::Granted) {
+            self.repository.fetch();
+        }
+        let started = std::time::Instant::now();
+        let previous_count = self.tasks.len();
+        self.today = day_number(now_secs(), self.config.utc_offset_mins);
+        self.load_tasks();
+        let rolls_over = !self.config.read_only
+            && self.config.backend == WriteBackend::Files
+            && self.repository.keeps_task_dirs();
+        if rolls_over && self.roll_over_recurring(self.today) {
+            self.load_tasks();
+        }
+        self.flash_woken_tasks();
+        self.notify_overdue();
+        self.check_wip_limits();
+        if self.complete_parents() {
+            self.load_tasks();
+        }
+        let elapsed = started.elapsed().as_millis();
+        self.last_refresh = Some((now_secs(), elapsed));
+        if elapsed >= SLOW_REFRESH_MS || self.tasks.len() != previous_count {
+            self.log(format!(
+                "refresh: {} tasks in {}ms",
+                self.tasks.len(),
+                elapsed
+            ));
+        }
+    }
+
+    /// Archive recurring tasks that are done and recreate their next
+    /// occurrence. Returns whether anything was rolled over.
+    fn roll_over_recurring(&mut self, today: i64) -> bool {
+        let finished: Vec<(String, String, Repeat, Option<i64>)> = self
+            .tasks
+            .iter()
+            .filter(|task| task.state == TaskState::Done)
+            .filter_map(|task| Some((task.path.clone(), task.name.clone(), task.repeat?, task.due)))
+            .collect();
+        for (path, name, repeat, due) in &finished {
+            let next_due = repeat.next_after(due.unwrap_or(today), today);
+            let stamp = format_day(due.unwrap_or(today));
+            match self.repository.roll_over(path, &stamp, next_due) {
+                Ok(None) => self.log(format!("repeat: {} is no longer done", path)),
+                Ok(Some(archive)) => {
+                    self.log(format!(
+                        "repeat: archived {} to {}",
+                        path,
+                        archive.display()
+                    ));
+                    self.show_toast(format!("Next {} due {}", name, format_day(next_due)));
+                }
+                Err(e) => self.log(format!("repeat: roll over {}: {}", path, e)),
+            }
+        }
+        !finished.is_empty()
+    }
+
+    fn is_snoozed(&self, task: &TaskLine) -> bool {
+        task.defer.is_some_and(|day| day > self.today)
+    }
+
+    /// Flash tasks whose snooze ended since the last refresh.
+    fn flash_woken_tasks(&mut self) {
+        let snoozed: std::collections::BTreeSet<String> = self
+            .tasks
+            .iter()
+            .filter(|t| self.is_snoozed(t))
+            .map(|t| t.path.clone())
+            .collect();
+        let woken: Vec<(String, String)> = self
+            .tasks
+            .iter()
+            .filter(|t| self.snoozed.contains(&t.path) && !snoozed.contains(&t.path))
+            .map(|t| (t.path.clone(), t.name.clone()))
+            .collect();
+        self.snoozed = snoozed;
+        for (path, _) in &woken {
+            self.flashed.insert(path.clone(), FLASH_TICKS);
+        }
+        match woken.as_slice() {
+            [] => {}
+            [(_, name)] => self.show_toast(format!("{} is back from snooze", name)),
+            _ => self.show_toast(format!("{} tasks are back from snooze", woken.len())),
+        }
+    }
+
+    /// Act on open tasks whose subtasks all became done since the last
+    /// refresh, per `complete_parents`. Returns whether anything was written.
+    fn complete_parents(&mut self) -> bool {
+        if self.config.read_only {
+            return false;
+        }
+        let completable: std::collections::BTreeSet<String> = self
+            .tasks
+            .iter()
+            .filter(|t| t.has_children && t.state != TaskState::Done)
+            .filter(|t| {
+                self.children_by_parent
+                    .get(&t.path)
+                    .is_some_and(|children| children.iter().all(|&c| self.tasks[c].subtree_done))
+            })
+            .map(|t| t.path.clone())
+            .collect();
+        let previous = self.completable.replace(completable.clone());
+        let Some(previous) = previous else {
+            return false;
+        };
+        let ready: Vec<String> = completable.difference(&previous).cloned().collect();
+        if ready.is_empty() {
+            return false;
+        }
+        match self.config.complete_parents {
+            ParentCompletion::Off => false,
+            ParentCompletion::Ask => {
+                if self.mode == Mode::Normal {
+                    self.offer_parent_completion(&ready);
+                }
+                false
+            }
+            ParentCompletion::Auto => {
+                self.mark_done(&ready, "all subtasks are done");
+                true
+            }
+        }
+    }
+
+    fn offer_parent_completion(&mut self, paths: &[String]) {
+        let names: Vec<&str> = paths
+            .iter()
+            .map(|p| p.rsplit('/').next().unwrap_or(p))
+            .collect();
+        let label = match names.as_slice() {
+            [name] => format!("Mark {} done", name),
+            _ => format!("Mark {} done", names.join(", ")),
+        };
+        self.mode = Mode::Picker(Picker {
+            title: "All subtasks are done".to_string(),
+            items: vec![
+                PickerItem {
+                    label,
+                    value: paths.join("\n"),
+                },
+                PickerItem {
+                    label: "Keep open".to_string(),
+                    value: String::new(),
+                },
+            ],
+            selected: 0,
+            purpose: PickerPurpose::CompleteParents,
+        });
+    }
+
+    /// Set `state` to done on `paths`, with `reason` in the toast.
+    fn mark_done(&mut self, paths: &[String], reason: &str) {
+        let message = self.write_state(paths, "done");
+        self.show_toast(format!("{}: {} (u: undo)", message, reason));
+    }
+
+    /// Write `state` on `paths`, remembering the old states for `u`, and
+    /// return a description of what was done.
+    fn write_state(&mut self, paths: &[String], state: &str) -> String {
+        let writes: Vec<_> = paths
+            .iter()
+            .map(|path| (path.clone(), "state".to_string(), Some(state.to_string())))
+            .collect();
+        let what = match paths {
+            [path] => path.rsplit('/').next().unwrap_or(path).to_string(),
+            _ => format!("{} tasks", paths.len()),
+        };
+        let label = format!("marked {} {}", what, state);
+        match self.write_undoable(&label, writes) {
+            0 => format!("Marked {} {}", what, state),
+            failed => format!(
+                "Failed to mark {} of {} tasks {}",
+                failed,
+                paths.len(),
+                state
+            ),
+        }
+    }
+
+    /// Choose a state for the selected (or tagged) tasks.
+    fn open_state_picker(&mut self) {
+        let targets = self.target_paths();
+        if targets.is_empty() {
+            return;
+        }
+        let items = [TaskState::Todo, TaskState::Wip, TaskState::Done]
+            .iter()
+            .map(|state| PickerItem {
+                label: state.as_str().to_string(),
+                value: state.as_str().to_string(),
+            })
+            .collect();
+        let title = match targets.as_slice() {
+            [path] => format!("State of {}", path.rsplit('/').next().unwrap_or(path)),
+            _ => format!("State of {} tasks", targets.len()),
+        };
+        self.mode = Mode::Picker(Picker {
+            title,
+            items,
+            selected: 0,
+            purpose: PickerPurpose::SetState,
+        });
+    }
+
+    /// Set the target tasks' state. Marking tasks done or todo when some of
+    /// their subtasks are in another state first asks whether to change
+    /// those too.
+    fn set_target_state(&mut self, state: &str) {
+        self.last_change = Some(Repeatable::State(state.to_string()));
+        let targets = self.target_paths();
+        let descendants: Vec<String> = self
+            .tasks
+            .iter()
+            .filter(|t| t.state.as_str() != state && !targets.contains(&t.path))
+            .filter(|t| targets.iter().any(|root| is_in_subtree(&t.path, root)))
+            .map(|t| t.path.clone())
+            .collect();
+        if state == "wip" || descendants.is_empty() {
+            self.set_states(&targets, state);
+            return;
+        }
+        let what = match targets.as_slice() {
+            [path] => path.rsplit('/').next().unwrap_or(path).to_string(),
+            _ => format!("{} tasks", targets.len()),
+        };
+        let only = std::iter::once(state.to_string()).chain(targets.iter().cloned());
+        let all = only.clone().chain(descendants.iter().cloned());
+        self.mode = Mode::Picker(Picker {
+            title: format!("Mark {} {}", what, state),
+            items: vec![
+                PickerItem {
+                    label: format!("Only {}", what),
+                    value: only.collect::<Vec<_>>().join("\n"),
+                },
+                PickerItem {
+                    label: format!(
+                        "{} and {} subtask{}",
+                        what,
+                        descendants.len(),
+                        if descendants.len() == 1 { "" } else { "s" }
+                    ),
+                    value: all.collect::<Vec<_>>().join("\n"),
+                },
+            ],
+            selected: 1,
+            purpose: PickerPurpose::CascadeState,
+        });
+    }
+
+    fn set_states(&mut self, paths: &[String], state: &str) {
+        let message = self.write_state(paths, state);
+        self.tagged.clear();
+        self.refresh_after_write();
+        self.show_toast(format!("{} (u: undo)", message));
+    }
+
+    /// Write (or, with `None`, clear) a task's field: to its file, or with
+    /// `backend=yx` by running the matching `yx` command, whose outcome
+    /// `handle_command_result` reports.
+    fn store_field(&mut self, path: &str, field: &str, value: Option<&str>) -> std::io::Result<()> {
+        if self.config.read_only {
+            return Err(read_only_error());
+        }
+        if self.config.backend == WriteBackend::Files {
+            return match value {
+                Some(value) => self.repository.set_field(path, field, value),
+                None => self.repository.clear_field(path, field),
+            };
+        }
+        if let Some(reason) = self.yx_unavailable() {
+            return Err(std::io::Error::other(reason));
+        }
+        let yak_id = self
+            .tasks
+            .iter()
+            .find(|t| t.path == path)
+            .map_or(path, |t| t.yak_id.as_str());
+        let args = self.config.yx_command(yak_id, field, value);
+        if args.is_empty() {
+            return Err(std::io::Error::other(format!(
+                "no yx command for {}",
+                field
+            )));
+        }
+        let context = BTreeMap::from([
+            ("action".to_string(), "yx".to_string()),
+            ("task".to_string(), path.to_string()),
+            ("command".to_string(), args.join(" ")),
+        ]);
+        self.log(format!("run: {}", args.join(" ")));
+        run_in_background(&args, context);
+        Ok(())
+    }
+
+    /// Write (or with `None`, clear) fields, recording their previous values
+    /// so `u` can put them back. Returns how many writes failed.
+    fn write_undoable(
+        &mut self,
+        label: &str,
+        writes: Vec<(String, String, Option<String>)>,
+    ) -> usize {
+        let mut undo = Undo {
+            label: label.to_string(),
+            writes: Vec::new(),
+        };
+        let mut failed = 0;
+        for (path, field, value) in writes {
+            let previous = self.repository.get_field(&path, &field);
+            let written = self.store_field(&path, &field, value.as_deref());
+            match written {
+                Ok(()) => undo.writes.push((path, field, previous)),
+                Err(e) => {
+                    self.log(format!("write {} of {}: {}", field, path, e));
+                    failed += 1;
+                }
+            }
+        }
+        self.undo = Some(undo);
+        failed
+    }
+
+    /// Revert the last undoable write.
+    fn undo_last(&mut self) {
+        let Some(undo) = self.undo.take() else {
+            self.show_toast("Nothing to undo".to_string());
+            return;
+        };
+        // Reopened parents must not be offered for completion again at once
+        if let Some(completable) = &mut self.completable {
+            completable.extend(undo.writes.iter().map(|(path, _, _)| path.clone()));
+        }
+        for (path, field, previous) in undo.writes.iter().rev() {
+            let restored = self.store_field(path, field, previous.as_deref());
+            if let Err(e) = restored {
+                self.log(format!("undo {} of {}: {}", field, path, e));
+            }
+        }
+        self.refresh_after_write();
+        self.show_toast(format!("Undid: {}", undo.label));
+    }
+
+    /// Wip task counts per agent, with `extra` (path, agent) assignments
+    /// applied on top of the current tree.
+    fn wip_counts(&self, extra: &[(String, String)]) -> BTreeMap<String, usize> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for task in self.tasks.iter().filter(|t| t.state == TaskState::Wip) {
+            let agent = extra
+                .iter()
+                .find(|(path, _)| *path == task.path)
+                .map(|(_, agent)| agent)
+                .or(task.assigned_to.as_ref());
+            if let Some(agent) = agent.filter(|agent| !agent.is_empty()) {
+                *counts.entry(agent.clone()).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// Agents with more wip tasks than their limit, with the count and limit.
+    fn wip_breaches(&self, extra: &[(String, String)]) -> Vec<(String, usize, usize)> {
+        self.wip_counts(extra)
+            .into_iter()
+            .filter_map(|(agent, wip)| {
+                let limit = self.config.wip_limit_for(&agent)?;
+                (wip > limit).then_some((agent, wip, limit))
+            })
+            .collect()
+    }
+
+    /// Warn when an agent goes over their WIP limit.
+    fn check_wip_limits(&mut self) {
+        let breaches = self.wip_breaches(&[]);
+        let over: std::collections::BTreeSet<String> =
+            breaches.iter().map(|(agent, _, _)| agent.clone()).collect();
+        for (agent, wip, limit) in &breaches {
+            if !self.over_wip_limit.contains(agent) {
+                self.show_toast(format!(
+                    "{} is over the WIP limit ({}/{})",
+                    agent, wip, limit
+                ));
+            }
+        }
+        self.over_wip_limit = over;
+    }
+
+    /// Queue an `overdue` notification for open tasks whose due date passed
+    /// since the last refresh. Tasks already overdue at startup are not
+    /// announced.
+    fn notify_overdue(&mut self) {
+        let overdue: std::collections::BTreeSet<String> = self
+            .tasks
+            .iter()
+            .filter(|t| t.state != TaskState::Done && t.due.is_some_and(|day| day < self.today))
+            .map(|t| t.path.clone())
+            .collect();
+        if let Some(previous) = &self.overdue {
+            for task in self.tasks.iter().filter(|t| overdue.contains(&t.path)) {
+                if previous.contains(&task.path) {
+                    continue;
+                }
+                let who = task.assigned_to.as_deref().unwrap_or("unassigned");
+                self.notifications.push(Notification {
+                    event: NotifyEvent::Overdue,
+                    title: NotifyEvent::Overdue.title().to_string(),
+                    body: format!(
+                        "{} ({}) was due {}",
+                        task.name,
+                        who,
+                        task.due.map(format_day).unwrap_or_default()
+                    ),
+                });
+            }
+        }
+        self.overdue = Some(overdue);
+    }
+
+    /// Refresh after the plugin wrote to the tree: straight away, unless
+    /// another write refreshed moments ago, in which case one refresh is
+    /// deferred to the end of the debounce window for the whole burst.
+    fn refresh_after_write(&mut self) {
+        self.refresh_after_write_at(std::time::Instant::now());
+    }
+
+    fn refresh_after_write_at(&mut self, now: std::time::Instant) {
+        let window = std::time::Duration::from_secs_f64(WRITE_DEBOUNCE_SECS);
+        let recent = self
+            .last_write_refresh
+            .is_some_and(|at| now.saturating_duration_since(at) < window);
+        if !recent {
+            self.refresh_tasks();
+            self.last_write_refresh = Some(now);
+        } else if !self.refresh_pending {
+            self.refresh_pending = true;
+            self.debounce_timer_needed = true;
+        }
+    }
+
+    /// Run the refresh deferred by `refresh_after_write`, if still needed.
+    fn flush_pending_refresh(&mut self) {
+        if self.refresh_pending {
+            self.refresh_tasks();
+            self.last_write_refresh = Some(std::time::Instant::now());
+        }
+    }
+
+    /// Re-read every task and rebuild the derived tree data.
+    fn load_tasks(&mut self) {
+        let tasks = match self.repository.load_snapshot() {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                if self.store_error.as_ref() != Some(&e) {
+                    self.show_warning(e.clone());
+                }
+                self.store_error = Some(e);
+                return;
+            }
+        };
+        self.store_error = None;
+        self.set_tasks(tasks);
+    }
+
+    /// Rebuild the derived tree data for a fresh list of tasks.
+    fn set_tasks(&mut self, tasks: Vec<TaskLine>) {
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        let mut tasks = if self.sorts_done_last() {
+            sort_done_last(tasks)
+        } else {
+            tasks
+        };
+        self.record_changes(&tasks, now_secs());
+
+        if tasks.is_empty() {
+            self.tasks = tasks;
+            self.children_by_parent.clear();
+            self.visible.clear();
+            self.selected_index = 0;
+            return;
+        }
+
+        let path_to_index: std::collections::HashMap<String, usize> = tasks
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.path.clone(), i))
+            .collect();
+
+        let id_to_index: std::collections::HashMap<String, usize> = tasks
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.yak_id.clone(), i))
+            .collect();
+        for task in &mut tasks {
+            for id in &task.blocked_by {
+                match id_to_index.get(id) {
+                    Some(&blocker) => task.blockers.push(blocker),
+                    None => task
+                        .problems
+                        .push(format!("blocked-by: no yak with id {:?}", id)),
+                }
+            }
+        }
+        for cycle in dependency_cycles(&tasks) {
+            let ids: Vec<&str> = cycle.iter().map(|&i| tasks[i].yak_id.as_str()).collect();
+            let problem = match ids.as_slice() {
+                [_] => "blocked-by: blocks itself".to_string(),
+                _ => format!(
+                    "blocked-by: part of a dependency cycle ({})",
+                    ids.join(", ")
+                ),
+            };
+            for &i in &cycle {
+                tasks[i].problems.push(problem.clone());
+            }
+        }
+
+        for i in 0..tasks.len() {
+            let path = &tasks[i].path;
+            let prefix = format!("{}/", path);
+            tasks[i].has_children = tasks.iter().any(|t| t.path.starts_with(&prefix));
+        }
+
+        // Tasks are in pre-order, so walking backwards visits children before
+        // their parent and subtree totals can be rolled up in one pass.
+        for i in (0..tasks.len()).rev() {
+            let Some((parent, _)) = tasks[i].path.rsplit_once('/') else {
+                continue;
+            };
+            if let Some(&p) = path_to_index.get(parent) {
+                tasks[p].descendant_count += tasks[i].descendant_count + 1;
+                tasks[p].subtree_done &= tasks[i].subtree_done;
+            }
+        }
+
+        let mut by_parent: std::collections::BTreeMap<String, Vec<usize>> =
+            std::collections::BTreeMap::new();
+        for (i, task) in tasks.iter().enumerate() {
+            let parent = match task.path.rfind('/') {
+                Some(pos) => task.path[..pos].to_string(),
+                None => String::new(),
+            };
+            by_parent.entry(parent).or_default().push(i);
+        }
+
+        for (parent, indices) in &by_parent {
+            if let Some(&last) = indices.last() {
+                tasks[last].is_last_sibling = true;
+            }
+            if let Some(&p) = path_to_index.get(parent) {
+                tasks[p].child_count = indices.len();
+            }
+        }
+
+        for i in 0..tasks.len() {
+            let path = &tasks[i].path;
+            let mut continuations = Vec::new();
+
+            // For each ancestor level (parent, grandparent, etc.), check if that ancestor has siblings after it
+            let mut current = if let Some(pos) = path.rfind('/') {
+                Some(path[..pos].to_string())
+            } else {
+                None
+            };
+
+            while let Some(ancestor) = current {
+                // Get the parent's parent (to find siblings of the ancestor)
+                let ancestors_parent = if let Some(pos) = ancestor.rfind('/') {
+                    Some(ancestor[..pos].to_string())
+                } else {
+                    Some(String::new()) // root level
+                };
+
+                if let Some(parent_of_ancestor) = ancestors_parent {
+                    let ancestors_siblings = by_parent
+                        .get(&parent_of_ancestor)
+                        .map(|v| v.as_slice())
+                        .unwrap_or(&[]);
+                    let pos_in_ancestors_siblings = ancestors_siblings.iter().position(|&x| {
+                        x == path_to_index.get(&ancestor).copied().unwrap_or(usize::MAX)
+                    });
+
+                    if let Some(pos) = pos_in_ancestors_siblings {
+                        let has_more_siblings = pos + 1 < ancestors_siblings.len();
+                        continuations.push(has_more_siblings);
+                    }
+                }
+
+                current = if let Some(pos) = ancestor.rfind('/') {
+                    Some(ancestor[..pos].to_string())
+                } else {
+                    None
+                };
+            }
+            tasks[i].ancestor_continuations = continuations;
+        }
+
+        self.critical_path = critical_path(&tasks);
+        self.tasks = tasks;
+        self.children_by_parent = by_parent;
+        self.update_visible(selected_path);
+        self.record_status_history(now_secs());
+        self.follow_agent();
+    }
+
+    /// Append each task's agent-status to its history when it changed, and
+    /// forget tasks that no longer exist.
+    fn record_status_history(&mut self, now: u64) {
+        let paths: std::collections::HashSet<&str> =
+            self.tasks.iter().map(|t| t.path.as_str()).collect();
+        self.status_history
+            .retain(|path, _| paths.contains(path.as_str()));
+        for task in &self.tasks {
+            let c = status_trail_char(task.agent_status.as_deref());
+            let history = self.status_history.entry(task.path.clone()).or_default();
+            let previous = history.back().map(|(_, last)| *last);
+            if previous != Some(c) {
+                // The first sighting of a task is not a change worth announcing.
+                let event = match c {
+                    'b' => Some(NotifyEvent::Blocked),
+                    'd' => Some(NotifyEvent::Done),
+                    _ => None,
+                };
+                if let (Some(event), Some(_)) = (event, previous) {
+                    let title = event.title();
+                    let who = task.assigned_to.as_deref().unwrap_or("unassigned");
+                    self.notifications.push(Notification {
+                        event,
+                        title: title.to_string(),
+                        body: format!(
+                            "{} on {}: {}",
+                            who,
+                            task.name,
+                            task.agent_status.as_deref().unwrap_or_default()
+                        ),
+                    });
+                }
+                history.push_back((now, c));
+                if history.len() > STATUS_HISTORY_CAP {
+                    history.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Diff `tasks` against the previous refresh and log what changed.
+    fn record_changes(&mut self, tasks: &[TaskLine], now: u64) {
+        let snapshot = snapshot_tasks(tasks);
+        if let Some(previous) = &self.snapshot {
+            for (path, kind) in diff_snapshots(previous, &snapshot) {
+                self.changes.push_back(Change {
+                    at: now,
+                    path,
+                    kind,
+                });
+            }
+            while self.changes.len() > CHANGE_LOG_CAP {
+                self.changes.pop_front();
+            }
+        }
+        self.snapshot = Some(snapshot);
+    }
+
+    /// The task `agent` is working on: one whose agent-status is `wip:`, or
+    /// failing that one in the wip state.
+    fn agent_wip_task(&self, agent: &str) -> Option<&TaskLine> {
+        let assigned = || {
+            self.tasks
+                .iter()
+                .filter(move |t| t.assigned_to.as_deref() == Some(agent))
+        };
+        assigned()
+            .find(|t| {
+                t.agent_status
+                    .as_deref()
+                    .is_some_and(|s| s.starts_with("wip:"))
+            })
+            .or_else(|| assigned().find(|t| t.state == TaskState::Wip))
+    }
+
+    /// Move the selection to the followed agent's wip task, if it is visible.
+    fn follow_agent(&mut self) {
+        let Some(agent) = &self.following else {
+            return;
+        };
+        if let Some(row) = self
+            .agent_wip_task(agent)
+            .map(|t| t.path.clone())
+            .and_then(|path| self.row_of_path(&path))
+        {
+            self.selected_index = row;
+        }
+    }
+
+    /// Follow the selected task's assignee, or stop following.
+    fn toggle_follow(&mut self) {
+        if let Some(agent) = self.following.take() {
+            self.show_toast(format!("Stopped following {}", agent));
+            return;
+        }
+        let Some(agent) = self.selected_task().and_then(|t| t.assigned_to.clone()) else {
+            self.show_toast("Select an assigned task to follow its agent".to_string());
+            return;
+        };
+        self.show_toast(format!("Following {}", agent));
+        self.following = Some(agent);
+        self.follow_agent();
+    }
+
+    fn sorts_done_last(&self) -> bool {
+        self.sort_done_last.unwrap_or(self.config.sort_done_last)
+    }
+
+    fn toggle_sort_done_last(&mut self) {
+        let done_last = !self.sorts_done_last();
+        self.sort_done_last = Some(done_last);
+        self.refresh_tasks();
+        self.show_toast(if done_last {
+            "Sorting done tasks last".to_string()
+        } else {
+            "Sorting by name".to_string()
+        });
+    }
+
+    fn selected_task(&self) -> Option<&TaskLine> {
+        self.visible
+            .get(self.selected_index)
+            .and_then(|&i| self.tasks.get(i))
+    }
+
+    /// Row of the task with `path`, if it is currently visible.
+    fn row_of_path(&self, path: &str) -> Option<usize> {
+        self.visible
+            .iter()
+            .position(|&i| self.tasks[i].path == path)
+    }
+
+    fn row_of_task(&self, task_index: usize) -> Option<usize> {
+        self.visible.iter().position(|&i| i == task_index)
+    }
+
+    /// Recompute the visible rows after the tasks or the filter changed,
+    /// keeping `selected_path` selected when it is still shown.
+    fn update_visible(&mut self, selected_path: Option<String>) {
+        if self.is_filtering() {
+            let (visible, matches) = self.filtered_rows();
+            self.visible = visible;
+            self.filter_matches = matches;
+        } else {
+            self.visible = self.unfolded_rows();
+            self.filter_matches = 0;
+        }
+        if !self.show_snoozed {
+            let snoozed: Vec<&str> = self
+                .tasks
+                .iter()
+                .filter(|t| self.is_snoozed(t))
+                .map(|t| t.path.as_str())
+                .collect();
+            if !snoozed.is_empty() {
+                let tasks = &self.tasks;
+                self.visible.retain(|&i| {
+                    !snoozed
+                        .iter()
+                        .any(|root| is_in_subtree(&tasks[i].path, root))
+                });
+            }
+        }
+        if let Some(root) = &self.zoom {
+            if self.tasks.iter().any(|t| &t.path == root) {
+                let tasks = &self.tasks;
+                self.visible
+                    .retain(|&i| is_in_subtree(&tasks[i].path, root));
+            } else {
+                self.zoom = None;
+            }
+        }
+
+        // A selection hidden by a fold moves up to its nearest visible ancestor
+        let mut selected_path = selected_path;
+        while let Some(path) = selected_path {
+            if let Some(row) = self.row_of_path(&path) {
+                self.selected_index = row;
+                return;
+            }
+            selected_path = path.rsplit_once('/').map(|(parent, _)| parent.to_string());
+        }
+        if self.selected_index >= self.visible.len() {
+            self.selected_index = self.visible.len().saturating_sub(1);
+        }
+    }
+
+    /// Whether any filter is active. Folds are ignored while filtering so
+    /// matches inside collapsed subtrees stay reachable.
+    fn is_filtering(&self) -> bool {
+        self.filter.is_some() || self.mine_only || !self.tab_filter().is_empty()
+    }
+
+    /// The built-in tabs followed by the configured ones.
+    fn tabs(&self) -> Vec<(String, TabFilter)> {
+        BUILTIN_TABS
+            .iter()
+            .map(|(name, words)| {
+                (
+                    name.to_string(),
+                    TabFilter::parse(words).unwrap_or_default(),
+                )
+            })
+            .chain(self.config.tabs.iter().cloned())
+            .collect()
+    }
+
+    fn tab_filter(&self) -> TabFilter {
+        self.tabs()
+            .into_iter()
+            .nth(self.tab)
+            .map(|(_, filter)| filter)
+            .unwrap_or_default()
+    }
+
+    /// Whether the task waits on a blocker or its agent reports `blocked:`.
+    fn is_blocked(&self, task: &TaskLine) -> bool {
+        !self.open_blockers(task).is_empty()
+            || task
+                .agent_status
+                .as_deref()
+                .is_some_and(|s| s.starts_with("blocked:"))
+    }
+
+    fn task_matches_tab(&self, task: &TaskLine, filter: &TabFilter) -> bool {
+        (filter.states.is_empty() || filter.states.contains(&task.state))
+            && (!filter.blocked || self.is_blocked(task))
+            && (!filter.mine || (self.config.me.is_some() && task.assigned_to == self.config.me))
+            && filter
+                .assignee
+                .as_ref()
+                .is_none_or(|agent| task.assigned_to.as_ref() == Some(agent))
+    }
+
+    /// Switch to the tab `delta` places along, wrapping around, and return to
+    /// the selection and scroll position it was left with.
+    fn switch_tab(&mut self, delta: isize) {
+        let count = self.tabs().len() as isize;
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        self.tab_positions
+            .insert(self.tab, (selected_path, self.scroll_offset));
+        self.tab = (self.tab as isize + delta).rem_euclid(count) as usize;
+        let (path, scroll) = self
+            .tab_positions
+            .get(&self.tab)
+            .cloned()
+            .unwrap_or_default();
+        self.selected_index = 0;
+        self.update_visible(path);
+        self.scroll_offset = scroll.min(self.visible.len().saturating_sub(1));
+    }
+
+    /// The tab strip above the tree, the current tab highlighted.
+    fn tab_strip(&self) -> String {
+        self.tabs()
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| {
+                if i != self.tab {
+                    format!("\x1b[90m {} \x1b[0m", name)
+                } else if self.config.plain {
+                    format!("[{}]", name)
+                } else {
+                    format!("\x1b[1;7m {} \x1b[0m", name)
+                }
+            })
+            .collect()
+    }
+
+    /// Tasks matching the active filters plus their ancestors, so matches stay
+    /// in their tree context. Also returns the number of direct matches.
+    fn filtered_rows(&self) -> (Vec<usize>, usize) {
+        let mut shown = std::collections::HashSet::new();
+        let mut matches = 0;
+        for task in self.tasks.iter().filter(|t| self.task_matches_filters(t)) {
+            matches += 1;
+            let mut path = task.path.as_str();
+            shown.insert(path);
+            while let Some((parent, _)) = path.rsplit_once('/') {
+                shown.insert(parent);
+                path = parent;
+            }
+        }
+        let rows = (0..self.tasks.len())
+            .filter(|&i| shown.contains(self.tasks[i].path.as_str()))
+            .collect();
+        (rows, matches)
+    }
+
+    /// All tasks except those below a collapsed task.
+    fn unfolded_rows(&self) -> Vec<usize> {
+        let mut visible = Vec::new();
+        let mut hidden_prefix: Option<String> = None;
+        for (i, task) in self.tasks.iter().enumerate() {
+            if let Some(prefix) = &hidden_prefix {
+                if task.path.starts_with(prefix.as_str()) {
+                    continue;
+                }
+            }
+            visible.push(i);
+            hidden_prefix = self.is_collapsed(task).then(|| format!("{}/", task.path));
+        }
+        visible
+    }
+
+    fn task_matches_filters(&self, task: &TaskLine) -> bool {
+        if let Some(query) = &self.filter {
+            if fuzzy_match(query, &task.name).is_none() {
+                return false;
+            }
+        }
+        if self.mine_only && (self.config.me.is_none() || task.assigned_to != self.config.me) {
+            return false;
+        }
+        self.task_matches_tab(task, &self.tab_filter())
+    }
+
+    fn toggle_mine_only(&mut self) {
+        if self.config.me.is_none() {
+            self.show_toast(
+                "Set `me` in the plugin configuration to filter by assignee".to_string(),
+            );
+            return;
+        }
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        self.mine_only = !self.mine_only;
+        self.update_visible(selected_path);
+    }
+
+    /// Whether the task's children are hidden, either by hand or because the
+    /// whole subtree is done and `auto_collapse_done` is on.
+    fn is_collapsed(&self, task: &TaskLine) -> bool {
+        if !task.has_children {
+            return false;
+        }
+        self.collapsed.contains(&task.path)
+            || (self.is_auto_collapsed(task) && !self.expanded.contains(&task.path))
+    }
+
+    fn is_auto_collapsed(&self, task: &TaskLine) -> bool {
+        self.config.auto_collapse_done && task.has_children && task.subtree_done
+    }
+
+    /// Depth of the zoom root, which is drawn as if it were top-level.
+    fn zoom_depth(&self) -> usize {
+        self.zoom
+            .as_deref()
+            .map_or(0, |root| root.matches('/').count())
+    }
+
+    /// Re-root the tree at the selected task.
+    fn zoom_in(&mut self) {
+        let Some(path) = self.selected_task().map(|t| t.path.clone()) else {
+            return;
+        };
+        self.zoom = Some(path.clone());
+        self.update_visible(Some(path));
+    }
+
+    /// Re-root the tree one level up, or show the whole tree again when
+    /// zoomed into a top-level task.
+    fn zoom_out(&mut self) {
+        let Some(root) = self.zoom.take() else {
+            return;
+        };
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        self.zoom = root.rsplit_once('/').map(|(parent, _)| parent.to_string());
+        self.update_visible(selected_path);
+    }
+
+    /// Fold or unfold the selected task.
+    fn toggle_fold(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        if !task.has_children {
+            return;
+        }
+        let path = task.path.clone();
+        let auto_collapsed = self.is_auto_collapsed(task);
+        if self.is_collapsed(task) {
+            self.collapsed.remove(&path);
+            if auto_collapsed {
+                self.expanded.insert(path.clone());
+            }
+        } else {
+            self.expanded.remove(&path);
+            self.collapsed.insert(path.clone());
+        }
+        self.update_visible(Some(path));
+    }
+
+    /// Fold the tree so that `level` levels are shown, or unfold everything
+    /// when `level` is `None`. Levels count from the zoom root when zoomed,
+    /// and folds outside the zoomed subtree are left alone.
+    fn fold_to_level(&mut self, level: Option<usize>) {
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        let zoom_depth = self.zoom_depth();
+        for task in &self.tasks {
+            let outside_zoom = self
+                .zoom
+                .as_deref()
+                .is_some_and(|root| !is_in_subtree(&task.path, root));
+            if !task.has_children || outside_zoom {
+                continue;
+            }
+            let fold = level.is_some_and(|level| task.depth - zoom_depth + 1 >= level);
+            if fold {
+                self.expanded.remove(&task.path);
+                self.collapsed.insert(task.path.clone());
+            } else {
+                self.collapsed.remove(&task.path);
+                if self.is_auto_collapsed(task) {
+                    self.expanded.insert(task.path.clone());
+                }
+            }
+        }
+        self.update_visible(selected_path);
+    }
+
+    fn apply_filter(&mut self, query: Option<String>) {
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        self.filter = query.filter(|q| !q.is_empty());
+        self.update_visible(selected_path);
+    }
+
+    fn start_filter_input(&mut self) {
+        self.mode = Mode::Filter {
+            input: self.filter.clone().unwrap_or_default(),
+            previous: FilterView {
+                filter: self.filter.clone(),
+                selected_path: self.selected_task().map(|t| t.path.clone()),
+                scroll_offset: self.scroll_offset,
+            },
+            recall: Recall::default(),
+        };
+    }
+
+    /// Filter input is applied on every keystroke; Enter keeps the result and
+    /// Esc puts back the view from before `/` was pressed. Up/Down recall
+    /// earlier queries.
+    fn handle_filter_key(&mut self, key: &KeyWithModifier) -> bool {
+        let Mode::Filter {
+            input,
+            previous,
+            recall,
+        } = &mut self.mode
+        else {
+            return false;
+        };
+        match key.bare_key {
+            BareKey::Enter => {
+                self.filter_history.push(input);
+                self.mode = Mode::Normal;
+            }
+            BareKey::Up | BareKey::Down => {
+                let older = key.bare_key == BareKey::Up;
+                if let Some(query) = self.filter_history.step(recall, input, older) {
+                    *input = query.clone();
+                    self.apply_filter(Some(query));
+                }
+            }
+            BareKey::Esc => {
+                let previous = std::mem::take(previous);
+                self.mode = Mode::Normal;
+                self.filter = previous.filter;
+                self.update_visible(previous.selected_path);
+                self.scroll_offset = previous.scroll_offset;
+            }
+            BareKey::Backspace => {
+                input.pop();
+                let query = input.clone();
+                self.apply_filter(Some(query));
+            }
+            _ => {
+                if let Some(c) = plain_char(key) {
+                    input.push(c);
+                    let query = input.clone();
+                    self.apply_filter(Some(query));
+                }
+            }
+        }
+        true
+    }
+
+    /// Move the selection by `delta` rows, clamped to the task list.
+    fn move_selection(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() - 1;
+        let target = self.selected_index as isize + delta;
+        self.selected_index = target.clamp(0, last as isize) as usize;
+    }
+
+    /// Append a digit to the pending count. A leading `0` is not a count and is
+    /// left unhandled.
+    fn push_count_digit(&mut self, digit: u32) -> bool {
+        if digit == 0 && self.pending_count.is_none() {
+            return false;
+        }
+        let count = self
+            .pending_count
+            .unwrap_or(0)
+            .saturating_mul(10)
+            .saturating_add(digit as usize);
+        self.pending_count = Some(count.min(99_999));
+        true
+    }
+
+    /// Grow or shrink the preview panel by `delta` rows.
+    fn resize_preview(&mut self, delta: isize) {
+        let rows = self.preview_rows.unwrap_or(PREVIEW_PANEL_ROWS);
+        let rows = rows
+            .saturating_add_signed(delta)
+            .clamp(MIN_PREVIEW_ROWS, MAX_PREVIEW_ROWS);
+        self.preview_rows = Some(rows);
+        self.show_toast(format!("Preview: {} rows", rows));
+    }
+
+    /// Consume the pending count, defaulting to 1.
+    fn take_count(&mut self) -> usize {
+        self.pending_count.take().unwrap_or(1).max(1)
+    }
+
+    /// Select the row at `index`, clamped to the task list.
+    fn jump_to(&mut self, index: usize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        self.selected_index = index.min(self.visible.len() - 1);
+    }
+
+    fn ui_state(&self) -> UiState {
+        UiState {
+            selected: self.selected_task().map(|t| t.path.clone()),
+            scroll_offset: self.scroll_offset,
+            marks: self.marks.clone(),
+            collapsed: self.collapsed.clone(),
+            expanded: self.expanded.clone(),
+            filter: self.filter.clone(),
+            view: self.view.saved_name().map(str::to_string),
+            tab: self.tabs().into_iter().nth(self.tab).map(|(name, _)| name),
+            sort_done_last: self.sort_done_last,
+            mine_only: self.mine_only,
+            zoom: self.zoom.clone(),
+            show_feed: self.show_feed,
+            show_preview: self.show_preview,
+            preview_rows: self.preview_rows,
+            views: self.views.clone(),
+        }
+    }
+
+    /// zellij-tile has no hook for handing serialized state back to a plugin, so
+    /// when a session is resurrected the plugin is simply loaded again; this
+    /// restores the view from the state file so it doesn't start cold.
+    fn restore_ui_state(&mut self, ui_state: UiState) {
+        self.collapsed = ui_state.collapsed.clone();
+        self.expanded = ui_state.expanded.clone();
+        self.filter = ui_state.filter.clone();
+        if let Some(view) = ui_state.view.as_deref().and_then(View::from_saved_name) {
+            self.open_view(view);
+        }
+        self.tab = self
+            .tabs()
+            .iter()
+            .position(|(tab, _)| Some(tab) == ui_state.tab.as_ref())
+            .unwrap_or(0);
+        self.mine_only = ui_state.mine_only && self.config.me.is_some();
+        self.zoom = ui_state.zoom.clone();
+        self.show_feed = ui_state.show_feed;
+        self.show_preview = ui_state.show_preview;
+        self.preview_rows = ui_state.preview_rows;
+        self.views = ui_state.views.clone();
+        if ui_state.sort_done_last.is_some() && ui_state.sort_done_last != self.sort_done_last {
+            self.sort_done_last = ui_state.sort_done_last;
+            self.refresh_tasks();
+        }
+        self.update_visible(None);
+        self.scroll_offset = ui_state
+            .scroll_offset
+            .min(self.visible.len().saturating_sub(1));
+        if let Some(index) = ui_state
+            .selected
+            .as_ref()
+            .and_then(|path| self.row_of_path(path))
+        {
+            self.selected_index = index;
+        }
+        self.marks = ui_state.marks.clone();
+        self.saved_ui_state = ui_state;
+    }
+
+    /// Write the UI state if it changed since the last save. Called from the
+    /// refresh timer, which debounces bursts of key presses into one write.
+    fn save_ui_state_if_changed(&mut self) {
+        // Someone else's pane: leave their saved state alone
+        if self.config.read_only {
+            return;
+        }
+        let ui_state = self.ui_state();
+        if ui_state == self.saved_ui_state {
+            return;
+        }
+        match self.repository.save_ui_state(&ui_state) {
+            Ok(()) => self.saved_ui_state = ui_state,
+            Err(e) => self.log(format!("ui state: {}", e)),
+        }
+    }
+
+    /// Add a debug log entry, also appending it to the log file when
+    /// `log_file` is on.
+    fn log(&mut self, message: String) {
+        let now = now_secs();
+        if self.config.log_file {
+            let line = format!(
+                "{} {}",
+                format_clock(now, self.config.utc_offset_mins),
+                message
+            );
+            // Nowhere better to report a failing log write than the log itself.
+            if let Err(e) = self.repository.append_log(&line) {
+                self.log.push_back((now, format!("log file: {}", e)));
+            }
+        }
+        self.log.push_back((now, message));
+        while self.log.len() > LOG_CAP {
+            self.log.pop_front();
+        }
+    }
+
+    fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.show_toast(if self.paused {
+            "Auto-refresh paused".to_string()
+        } else {
+            "Auto-refresh resumed".to_string()
+        });
+    }
+
+    /// With `read_only` on, say that `what` is off; returns whether it is.
+    fn refuse_when_read_only(&mut self, what: &str) -> bool {
+        if self.config.read_only {
+            self.show_toast(format!("Read-only: {} disabled", what));
+        }
+        self.config.read_only
+    }
+
+    /// With `backend=yx`, say that `what` is off, as it has no `yx` command
+    /// and would write `.yaks` behind yx's back; returns whether it is.
+    fn refuse_under_yx(&mut self, what: &str) -> bool {
+        let refused = self.config.backend == WriteBackend::Yx;
+        if refused {
+            self.show_toast(format!("backend=yx: {} disabled", what));
+        }
+        refused
+    }
+
+    fn show_toast(&mut self, message: String) {
+        self.toast_message = Some(message);
+        self.toast_ticks_remaining = 1;
+    }
+
+    /// A toast that stays up until the next key press.
+    fn show_warning(&mut self, message: String) {
+        self.toast_message = Some(message);
+        self.toast_ticks_remaining = 0;
+    }
+
+    fn set_mark(&mut self, letter: char) {
+        if let Some(task) = self.selected_task() {
+            let message = format!("Mark '{}' set on {}", letter, task.name);
+            self.marks.insert(letter, task.path.clone());
+            self.show_toast(message);
+        }
+    }
+
+    fn jump_to_mark(&mut self, letter: char) {
+        let Some(path) = self.marks.get(&letter) else {
+            self.show_toast(format!("Mark '{}' not set", letter));
+            return;
+        };
+        if let Some(row) = self.row_of_path(path) {
+            self.selected_index = row;
+        } else if self.tasks.iter().any(|t| &t.path == path) {
+            self.show_toast(format!("Mark '{}' is hidden by the current filter", letter));
+        } else {
+            self.show_toast(format!(
+                "Mark '{}' points to a missing task: {}",
+                letter, path
+            ));
+        }
+    }
+
+    fn select_parent(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        if let Some(row) = task
+            .path
+            .rsplit_once('/')
+            .and_then(|(parent, _)| self.row_of_path(parent))
+        {
+            self.selected_index = row;
+        }
+    }
+
+    /// Move `delta` siblings forward or backward, stopping at the first/last sibling.
+    fn select_sibling(&mut self, delta: isize) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let parent = task.path.rsplit_once('/').map_or("", |(p, _)| p);
+        let Some(siblings) = self.children_by_parent.get(parent) else {
+            return;
+        };
+        let rows: Vec<usize> = siblings
+            .iter()
+            .filter_map(|&i| self.row_of_task(i))
+            .collect();
+        if let Some(pos) = rows.iter().position(|&row| row == self.selected_index) {
+            let target = (pos as isize + delta).clamp(0, rows.len() as isize - 1);
+            self.selected_index = rows[target as usize];
+        }
+    }
+
+    fn select_first_child(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        if let Some(row) = self
+            .children_by_parent
+            .get(&task.path)
+            .and_then(|children| children.iter().find_map(|&i| self.row_of_task(i)))
+        {
+            self.selected_index = row;
+        }
+    }
+
+    /// Scroll the viewport and the selection together by `delta` rows, keeping
+    /// the selection's position on screen where possible.
+    fn scroll_by(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let max_offset = self.visible.len().saturating_sub(self.viewport_rows.max(1));
+        let offset = (self.scroll_offset as isize + delta).clamp(0, max_offset as isize);
+        self.scroll_offset = offset as usize;
+        self.move_selection(delta);
+    }
+
+    fn page_size(&self) -> usize {
+        self.viewport_rows.max(1)
+    }
+
+    fn half_page_size(&self) -> usize {
+        (self.viewport_rows / 2).max(1)
+    }
+
+    /// Scroll just enough that the selection is on screen with `scroll_margin`
+    /// rows around it, or as many as fit in half of `body_rows`.
+    fn keep_selection_in_view(&mut self, body_rows: usize) {
+        if body_rows == 0 {
+            return;
+        }
+        let margin = self.config.scroll_margin.min((body_rows - 1) / 2);
+        let selected = self.selected_index;
+        if selected < self.scroll_offset + margin {
+            self.scroll_offset = selected.saturating_sub(margin);
+        } else if selected + margin >= self.scroll_offset + body_rows {
+            // Near the end there are no rows left to show below the selection
+            let last_offset = self.visible.len().saturating_sub(body_rows);
+            let lowest = (selected + 1).saturating_sub(body_rows);
+            self.scroll_offset = (selected + margin + 1 - body_rows).min(last_offset.max(lowest));
+        }
+    }
+
+    /// Scroll so the selection sits in the middle of the viewport.
+    fn center_selection(&mut self) {
+        self.scroll_offset = self.selected_index.saturating_sub(self.viewport_rows / 2);
+    }
+
+    /// Complete a multi-key sequence started by `prefix`. Unknown sequences are
+    /// swallowed so a stray `g` never triggers the following key's action.
+    fn handle_sequence(&mut self, prefix: char, key: &KeyWithModifier) -> bool {
+        let count = self.pending_count.take();
+        match (prefix, plain_char(key)) {
+            ('g', Some('g')) => {
+                self.jump_to(count.map_or(0, |n| n.saturating_sub(1)));
+            }
+            ('z', Some('z')) => self.center_selection(),
+            ('z', Some('a')) => self.toggle_fold(),
+            ('z', Some('A')) => self.fold_to_level(None),
+            ('z', Some('C')) => self.fold_to_level(Some(1)),
+            ('z', Some(c @ '1'..='9')) => self.fold_to_level(c.to_digit(10).map(|n| n as usize)),
+            ('z', Some('i')) => self.zoom_in(),
+            ('z', Some('u')) => self.zoom_out(),
+            ('z', Some('s')) => self.open_snooze_picker(),
+            ('z', Some('S')) => self.toggle_show_snoozed(),
+            ('m', Some(c)) if c.is_ascii_alphabetic() => self.set_mark(c),
+            ('\'', Some(c)) if c.is_ascii_alphabetic() => self.jump_to_mark(c),
+            ('q', Some(c)) if c.is_ascii_alphanumeric() => {
+                self.recording = Some((c, Vec::new()));
+            }
+            ('Q', Some(c)) => self.replay_macro(c, count.unwrap_or(1)),
+            _ => {}
+        }
+        true
+    }
+
+    /// Keep the keys recorded since `q<reg>`, without the `q` that ended it.
+    fn stop_recording(&mut self) {
+        let Some((register, mut keys)) = self.recording.take() else {
+            return;
+        };
+        keys.pop();
+        self.show_toast(format!("Recorded {} keys into {}", keys.len(), register));
+        self.macros.insert(register, keys);
+    }
+
+    /// Replay the keys recorded into `register`, `count` times.
+    fn replay_macro(&mut self, register: char, count: usize) {
+        if self.replaying {
+            return;
+        }
+        let Some(keys) = self.macros.get(&register).cloned() else {
+            self.show_toast(format!("No macro in {}", register));
+            return;
+        };
+        self.replaying = true;
+        for _ in 0..count {
+            for key in &keys {
+                self.handle_key(key.clone());
+            }
+        }
+        self.replaying = false;
+    }
+
+    /// Rows of the top row's ancestors that have scrolled off-screen, outermost
+    /// first, keeping at most `limit` of the innermost.
+    fn sticky_ancestor_rows(&self, limit: usize) -> Vec<usize> {
+        if !self.config.sticky_ancestors {
+            return Vec::new();
+        }
+        let Some(&top) = self.visible.get(self.scroll_offset) else {
+            return Vec::new();
+        };
+        let mut rows = Vec::new();
+        let mut path = self.tasks[top].path.as_str();
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            if let Some(row) = self
+                .row_of_path(parent)
+                .filter(|&row| row < self.scroll_offset)
+            {
+                rows.push(row);
+            }
+            path = parent;
+        }
+        rows.truncate(limit);
+        rows.reverse();
+        rows
+    }
+
+    /// One minimap cell per screen row, each summarizing an equal chunk of the
+    /// visible rows: whether the chunk overlaps the viewport (`body_rows` from
+    /// `scroll_offset`) and the color of its most common state.
+    fn minimap_cells(&self, cells: usize, body_rows: usize) -> Vec<(bool, String)> {
+        if cells == 0 || self.visible.is_empty() {
+            return Vec::new();
+        }
+        let total = self.visible.len();
+        (0..cells)
+            .map(|cell| {
+                let start = cell * total / cells;
+                let end = ((cell + 1) * total / cells).max(start + 1).min(total);
+                let mut counts: Vec<(String, usize)> = Vec::new();
+                for &i in &self.visible[start.min(total - 1)..end] {
+                    let color = self.task_color(&self.tasks[i]);
+                    match counts.iter_mut().find(|(c, _)| *c == color) {
+                        Some((_, n)) => *n += 1,
+                        None => counts.push((color, 1)),
+                    }
+                }
+                // Ties go to the first color seen, i.e. the one nearest the top.
+                let dominant = counts
+                    .iter()
+                    .rev()
+                    .max_by_key(|(_, n)| *n)
+                    .map(|(c, _)| c.clone())
+                    .unwrap_or_default();
+                let in_viewport =
+                    start < self.scroll_offset + body_rows && end > self.scroll_offset;
+                (in_viewport, dominant)
+            })
+            .collect()
+    }
+
+    /// Labels for the rows currently on screen, paired with their task index.
+    fn visible_jump_labels(&self) -> Vec<(usize, String)> {
+        let end = (self.scroll_offset + self.viewport_rows).min(self.visible.len());
+        let start = self.scroll_offset.min(end);
+        let numbered = matches!(self.mode, Mode::JumpLabel { numbered: true, .. });
+        let alphabet = if numbered {
+            QUICK_SELECT_CHARS
+        } else {
+            JUMP_LABEL_CHARS
+        };
+        (start..end)
+            .zip(jump_labels(alphabet, end - start))
+            .collect()
+    }
+
+    fn handle_jump_label_key(&mut self, key: &KeyWithModifier) -> bool {
+        let Mode::JumpLabel { typed, numbered } = self.mode.clone() else {
+            return false;
+        };
+        let Some(c) = plain_char(key) else {
+            self.mode = Mode::Normal;
+            return true;
+        };
+        let typed = format!("{}{}", typed, c);
+        let labels = self.visible_jump_labels();
+        if let Some((index, _)) = labels.iter().find(|(_, label)| *label == typed) {
+            self.selected_index = *index;
+            self.mode = Mode::Normal;
+        } else if labels.iter().any(|(_, label)| label.starts_with(&typed)) {
+            self.mode = Mode::JumpLabel { typed, numbered };
+        } else {
+            self.mode = Mode::Normal;
+        }
+        true
+    }
+
+    /// The first visible row after `from` (before it, going backwards),
+    /// wrapping around, whose task name starts with `prefix`, ignoring case.
+    fn type_ahead_match(&self, prefix: &str, from: usize, forward: bool) -> Option<usize> {
+        let len = self.visible.len();
+        let prefix = prefix.to_lowercase();
+        (1..=len)
+            .map(|step| {
+                (if forward {
+                    from + step
+                } else {
+                    from + len - step
+                }) % len
+            })
+            .find(|&row| {
+                self.tasks[self.visible[row]]
+                    .name
+                    .to_lowercase()
+                    .starts_with(&prefix)
+            })
+    }
+
+    /// Typed characters narrow the prefix and jump live; Enter keeps the
+    /// selection and Esc goes back to where typing started.
+    fn handle_type_ahead_key(&mut self, key: &KeyWithModifier) -> bool {
+        let Mode::TypeAhead { mut typed, from } = self.mode.clone() else {
+            return false;
+        };
+        match key.bare_key {
+            BareKey::Esc => {
+                self.selected_index = from;
+                self.mode = Mode::Normal;
+                return true;
+            }
+            BareKey::Backspace => {
+                typed.pop();
+            }
+            _ => match plain_char(key) {
+                Some(c) => typed.push(c),
+                None => {
+                    self.mode = Mode::Normal;
+                    return true;
+                }
+            },
+        }
+        if !typed.is_empty() {
+            match self.type_ahead_match(&typed, from, true) {
+                Some(row) => self.selected_index = row,
+                None => self.show_toast(format!("No task starts with {:?}", typed)),
+            }
+        }
+        self.type_ahead = Some(typed.clone()).filter(|t| !t.is_empty());
+        self.mode = Mode::TypeAhead { typed, from };
+        true
+    }
+
+    /// Jump to the next (or previous) task starting with the last `"` prefix.
+    fn repeat_type_ahead(&mut self, forward: bool) {
+        let Some(prefix) = self.type_ahead.clone() else {
+            return;
+        };
+        match self.type_ahead_match(&prefix, self.selected_index, forward) {
+            Some(row) => self.selected_index = row,
+            None => self.show_toast(format!("No task starts with {:?}", prefix)),
+        }
+    }
+
+    /// Gutter for a visible row: the distance from the selection, or the
+    /// absolute (1-based) line number on the selected row itself.
+    fn line_number_gutter(&self, index: usize, width: usize) -> String {
+        let number = if index == self.selected_index {
+            index + 1
+        } else {
+            index.abs_diff(self.selected_index)
+        };
+        if index == self.selected_index {
+            format!("\x1b[33m{:<width$}\x1b[0m ", number, width = width)
+        } else {
+            format!("\x1b[90m{:>width$}\x1b[0m ", number, width = width)
+        }
+    }
+
+    /// Color for an agent name: a configured override, else a hashed color.
+    fn agent_color(&self, agent: &str) -> String {
+        let color = self
+            .config
+            .agent_colors
+            .get(agent)
+            .cloned()
+            .unwrap_or_else(|| agent_palette_color(agent));
+        fit_color(&color, self.config.color_depth)
+    }
+
+    fn task_color(&self, task: &TaskLine) -> String {
+        fit_color(self.theme_color(task), self.config.color_depth)
+    }
+
+    fn theme_color(&self, task: &TaskLine) -> &'static str {
+        let theme = &self.config.theme;
+        if let Some(status) = &task.agent_status {
+            if status.starts_with("blocked:") {
+                return theme.blocked;
+            }
+            if status.starts_with("done:") {
+                return theme.agent_done;
+            }
+            if status.starts_with("wip:") {
+                return theme.wip;
+            }
+        }
+        match task.state {
+            TaskState::Wip => theme.wip,
+            TaskState::Done => theme.done,
+            TaskState::Todo => theme.todo,
+        }
+    }
+
+    fn status_symbol(&self, task: &TaskLine) -> char {
+        if let Some(status) = &task.agent_status {
+            if status.starts_with("done:") {
+                return '●';
+            }
+            if status.starts_with("wip:") || status.starts_with("blocked:") {
+                return '●';
+            }
+        }
+        match task.state {
+            TaskState::Wip | TaskState::Done => '●',
+            TaskState::Todo => '○',
+        }
+    }
+
+    fn tree_prefix(&self, task: &TaskLine) -> String {
+        let depth = task.depth.saturating_sub(self.zoom_depth());
+        if depth == 0 {
+            return String::new();
+        }
+
+        let mut prefix = String::new();
+        let line_color = "\x1b[90m";
+        let reset = "\x1b[0m";
+
+        // Show continuation columns for each ancestor level (from root-most to parent).
+        // ancestor_continuations is ordered [parent, grandparent, ...], so we take
+        // the first depth-1 entries (excluding the root-most) and reverse them to
+        // render columns from left (root-most) to right (parent-most).
+        let col_count = depth.saturating_sub(1);
+        let cols = &task.ancestor_continuations[..col_count.min(task.ancestor_continuations.len())];
+        for &has_continuation in cols.iter().rev() {
+            if has_continuation {
+                prefix.push_str(&format!("{}│ {}", line_color, reset));
+            } else {
+                prefix.push_str("  ");
+            }
+        }
+
+        if task.is_last_sibling {
+            prefix.push_str(&format!("{}╰─{}", line_color, reset));
+        } else {
+            prefix.push_str(&format!("{}├─{}", line_color, reset));
+        }
+
+        prefix
+    }
+
+    fn highlight_line(&self, line: &str, padding: &str) -> String {
+        let bg = match self.config.selection {
+            SelectionStyle::Reverse => "\x1b[7m".to_string(),
+            SelectionStyle::Bold => "\x1b[1m".to_string(),
+            _ => fit_color(
+                self.config
+                    .selection_bg
+                    .as_deref()
+                    .unwrap_or(self.config.theme.selection_bg),
+                self.config.color_depth,
+            ),
+        };
+        let highlighted = line.replace("\x1b[0m", &format!("\x1b[0m{bg}"));
+        format!("{bg}{}{}\x1b[0m", highlighted, padding)
+    }
+
+    /// Text label for a task's state, preferring its agent-status.
+    fn plain_label(&self, task: &TaskLine) -> &'static str {
+        let status = task.agent_status.as_deref().unwrap_or_default();
+        if status.starts_with("blocked:") {
+            "[BLOCKED]"
+        } else if status.starts_with("done:") || task.state == TaskState::Done {
+            "[DONE]"
+        } else if status.starts_with("wip:") || task.state == TaskState::Wip {
+            "[WIP]"
+        } else {
+            "[TODO]"
+        }
+    }
+
+    /// The task's name, or its directory name while raw names are shown.
+    fn display_name<'a>(&self, task: &'a TaskLine) -> &'a str {
+        if self.raw_names {
+            task.path.rsplit('/').next().unwrap_or(&task.path)
+        } else {
+            &task.name
+        }
+    }
+
+    /// A task row for plain mode: state as a text prefix, depth as spaces.
+    fn render_plain_task(&self, task: &TaskLine) -> String {
+        let depth = task.depth.saturating_sub(self.zoom_depth());
+        let name = self.display_name(task);
+        let mut line = format!("{}{} {}", "  ".repeat(depth), self.plain_label(task), name);
+        if !self.is_filtering() && self.is_collapsed(task) {
+            line.push_str(&format!(
+                " (collapsed, {} child{}, {} task{})",
+                task.child_count,
+                if task.child_count == 1 { "" } else { "ren" },
+                task.descendant_count,
+                if task.descendant_count == 1 { "" } else { "s" }
+            ));
+        }
+        if self.tagged.contains(&task.path) {
+            line.push_str(" (tagged)");
+        }
+        match self.open_blockers(task).len() {
+            0 => {}
+            n => line.push_str(&format!(" (waiting on {})", n)),
+        }
+        if self.is_on_critical_path(task) {
+            line.push_str(" (critical path)");
+        }
+        if let Some(age) = self.stalled_for(task, now_secs()) {
+            line.push_str(&format!(" (stalled, wip {})", format_age(age)));
+        }
+        if task.url.is_some() {
+            line.push_str(" (link)");
+        }
+        if let Some(day) = task.defer.filter(|_| self.is_snoozed(task)) {
+            line.push_str(&format!(" (snoozed until {})", format_day(day)));
+        }
+        if task.note_count > 0 {
+            let plural = if task.note_count == 1 { "" } else { "s" };
+            line.push_str(&format!(" ({} note{})", task.note_count, plural));
+        }
+        if !task.problems.is_empty() {
+            line.push_str(" (has problems)");
+        }
+        if let Some(agent) = &task.assigned_to {
+            line.push_str(&format!(" [{}]", agent));
+        }
+        if let Some((_, detail)) = task.agent_status.as_deref().and_then(|s| s.split_once(':')) {
+            if !detail.trim().is_empty() {
+                line.push_str(&format!(": {}", detail.trim()));
+            }
+        }
+        line
+    }
+
+    /// Print a line, dropping its escape sequences in plain mode.
+    fn emit(&self, line: &str) {
+        let side_line = self.side_panel.borrow_mut().as_mut().map(|(width, lines)| {
+            side_by_side(line, *width, &lines.pop_front().unwrap_or_default())
+        });
+        let line = side_line.as_deref().unwrap_or(line);
+        if self.config.plain {
+            println!("{}", strip_ansi(line));
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    /// Print a list row, marking the selection in the configured style.
+    /// Plain mode always uses the `>` marker.
+    fn emit_row(&self, line: &str, cols: usize, selected: bool) {
+        let marker = self.config.plain || self.config.selection == SelectionStyle::Marker;
+        match (marker, selected) {
+            (true, true) => self.emit(&format!("\x1b[1m>\x1b[0m {}", line)),
+            (true, false) => self.emit(&format!("  {}", line)),
+            (false, true) if self.config.selection == SelectionStyle::Bold => {
+                self.emit(&self.highlight_line(line, ""))
+            }
+            (false, true) => {
+                let padding = " ".repeat(cols.saturating_sub(strip_ansi(line).chars().count()));
+                self.emit(&self.highlight_line(line, &padding));
+            }
+            (false, false) => self.emit(line),
+        }
+    }
+
+    fn render_task(&self, task: &TaskLine) -> String {
+        if self.config.plain {
+            return self.render_plain_task(task);
+        }
+        let prefix = self.tree_prefix(task);
+        let status = self.status_symbol(task);
+
+        let color = self.task_color(task);
+
+        let display_name = self.display_name(task);
+        let name = match self
+            .filter
+            .as_deref()
+            .and_then(|q| fuzzy_match(q, display_name))
+        {
+            Some(positions) => highlight_matches(display_name, &positions),
+            None => display_name.to_string(),
+        };
+        let name = if matches!(task.state, TaskState::Done) {
+            format!("\x1b[9m{}\x1b[0m", name)
+        } else {
+            name
+        };
+        let name = if !self.is_filtering() && self.is_collapsed(task) {
+            if task.subtree_done {
+                format!(
+                    "▸ {} \x1b[90m(done, {} task{})\x1b[0m",
+                    name,
+                    task.descendant_count,
+                    if task.descendant_count == 1 { "" } else { "s" }
+                )
+            } else {
+                format!(
+                    "▸ {} \x1b[90m({}/{})\x1b[0m",
+                    name, task.child_count, task.descendant_count
+                )
+            }
+        } else {
+            name
+        };
+
+        let assignment = if let Some(agent) = &task.assigned_to {
+            format!(" [{}{}\x1b[0m]", self.agent_color(agent), agent)
+        } else {
+            String::new()
+        };
+
+        let status_color = if matches!(task.state, TaskState::Done) {
+            "\x1b[90m".to_string()
+        } else {
+            color
+        };
+
+        let trail = match self.status_history.get(&task.path) {
+            Some(history)
+                if self.pane_cols >= WIDE_MODE_COLS && history.iter().any(|(_, c)| *c != '·') =>
+            {
+                format!(" \x1b[90m{}\x1b[0m", status_trail(history, now_secs()))
+            }
+            _ => String::new(),
+        };
+
+        let trail = match &task.last_log_line {
+            Some(line) if self.pane_cols >= WIDE_MODE_COLS => {
+                let line: String = line.chars().take(LOG_LINE_MAX_CHARS).collect();
+                format!("{} \x1b[2m{}\x1b[22m", trail, line)
+            }
+            _ => trail,
+        };
+
+        let name = if self.flashed.contains_key(&task.path) {
+            format!("\x1b[1;7m{}\x1b[27;22m", name)
+        } else {
+            name
+        };
+
+        let tag = if self.tagged.contains(&task.path) {
+            "\x1b[35m+\x1b[0m"
+        } else {
+            ""
+        };
+
+        let link = if task.url.is_some() {
+            " \x1b[36m⎘\x1b[0m"
+        } else {
+            ""
+        };
+
+        let dependency = match self.open_blockers(task).len() {
+            _ if task.blockers.is_empty() => "",
+            0 => " \x1b[90m⇠\x1b[0m",
+            _ => " \x1b[33m⇠\x1b[0m",
+        };
+        let name = if self.open_blockers(task).is_empty() {
+            name
+        } else {
+            format!("\x1b[2m{}\x1b[22m", name)
+        };
+
+        let critical = if self.is_on_critical_path(task) {
+            " \x1b[35m◆\x1b[0m"
+        } else {
+            ""
+        };
+
+        let critical = match self.stalled_for(task, now_secs()) {
+            Some(age) => format!("{} \x1b[33mwip {}\x1b[0m", critical, format_age(age)),
+            None => critical.to_string(),
+        };
+
+        let notes = if task.note_count > 0 {
+            format!(" \x1b[90m✎{}\x1b[0m", task.note_count)
+        } else {
+            String::new()
+        };
+
+        let notes = match task.defer {
+            Some(day) if self.is_snoozed(task) => {
+                format!("{} \x1b[90m☾ {}\x1b[0m", notes, format_day(day))
+            }
+            _ => notes,
+        };
+
+        let badge = if task.problems.is_empty() {
+            ""
+        } else {
+            " \x1b[1;31m!\x1b[0m"
+        };
+
+        format!(
+            "{}{}{}{} {}{}{}{}{}{}{}{}\x1b[0m",
+            prefix,
+            tag,
+            status_color,
+            status,
+            name,
+            dependency,
+            critical,
+            link,
+            notes,
+            badge,
+            assignment,
+            trail
+        )
+    }
+
+    fn is_on_critical_path(&self, task: &TaskLine) -> bool {
+        self.show_critical_path
+            && self
+                .critical_path
+                .iter()
+                .any(|&i| self.tasks[i].path == task.path)
+    }
+
+    /// The tasks `task` is blocked by that are not done yet.
+    fn open_blockers(&self, task: &TaskLine) -> Vec<&TaskLine> {
+        task.blockers
+            .iter()
+            .map(|&i| &self.tasks[i])
+            .filter(|blocker| blocker.state != TaskState::Done)
+            .collect()
+    }
+
+    fn toggle_tag(&mut self) {
+        let Some(path) = self.selected_task().map(|t| t.path.clone()) else {
+            return;
+        };
+        if !self.tagged.remove(&path) {
+            self.tagged.insert(path);
+        }
+        self.move_selection(1);
+    }
+
+    /// Paths a bulk action applies to: the tagged tasks, or else the selection.
+    fn target_paths(&self) -> Vec<String> {
+        if self.tagged.is_empty() {
+            self.selected_task()
+                .map(|t| t.path.clone())
+                .into_iter()
+                .collect()
+        } else {
+            self.tagged.iter().cloned().collect()
+        }
+    }
+
+    /// Known agents with their number of open (not done) assigned tasks,
+    /// least loaded first.
+    fn agent_loads(&self) -> Vec<(String, usize)> {
+        let mut loads: BTreeMap<String, usize> = BTreeMap::new();
+        for agent in &self.config.agents {
+            loads.entry(agent.clone()).or_default();
+        }
+        for task in &self.tasks {
+            if let Some(agent) = &task.assigned_to {
+                let load = loads.entry(agent.clone()).or_default();
+                if task.state != TaskState::Done {
+                    *load += 1;
+                }
+            }
+        }
+        let mut loads: Vec<_> = loads.into_iter().collect();
+        loads.sort_by_key(|(_, load)| *load);
+        loads
+    }
+
+    fn open_assign_picker(&mut self) {
+        let targets = self.target_paths();
+        if targets.is_empty() {
+            return;
+        }
+        let loads = self.agent_loads();
+        let mut items = loads
+            .iter()
+            .enumerate()
+            .map(|(i, (agent, load))| PickerItem {
+                label: format!(
+                    "{}{}\x1b[0m \x1b[90m({} open){}\x1b[0m",
+                    self.agent_color(agent),
+                    agent,
+                    load,
+                    if i == 0 { " ← least loaded" } else { "" }
+                ),
+                value: agent.clone(),
+            })
+            .collect::<Vec<_>>();
+        let assigned = self
+            .tasks
+            .iter()
+            .any(|t| t.assigned_to.is_some() && targets.contains(&t.path));
+        if assigned {
+            items.push(PickerItem {
+                label: "\x1b[90m(unassign)\x1b[0m".to_string(),
+                value: String::new(),
+            });
+        }
+        let title = if targets.len() == 1 {
+            "Assign to".to_string()
+        } else {
+            format!("Assign {} tasks to (r: round-robin)", targets.len())
+        };
+        self.mode = Mode::Picker(Picker {
+            title,
+            items,
+            selected: 0,
+            purpose: PickerPurpose::Assign,
+        });
+    }
+
+    /// Pick one of the selected task's extra files to view or edit.
+    fn open_files_picker(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let files = self.repository.task_files(&task.path);
+        if files.is_empty() {
+            let message = format!("No files in {}", task.name);
+            self.show_toast(message);
+            return;
+        }
+        let items = files
+            .iter()
+            .map(|name| PickerItem {
+                label: name.clone(),
+                value: format!("{}/{}", task.path, name),
+            })
+            .collect();
+        self.mode = Mode::Picker(Picker {
+            title: format!("Files in {} (e: edit)", task.name),
+            items,
+            selected: 0,
+            purpose: PickerPurpose::OpenFile,
+        });
+    }
+
+    /// Choose one of the configured `action.<name>` commands to run for the
+    /// selected task.
+    fn open_action_picker(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        if self.config.actions.is_empty() {
+            let message = "No actions: add action.<name> keys to the plugin configuration";
+            self.show_toast(message.to_string());
+            return;
+        }
+        let items = self
+            .config
+            .actions
+            .iter()
+            .map(|(name, words)| PickerItem {
+                label: format!("{}: {}", name, words.join(" ")),
+                value: name.clone(),
+            })
+            .collect();
+        self.mode = Mode::Picker(Picker {
+            title: format!("Actions for {}", task.name),
+            items,
+            selected: 0,
+            purpose: PickerPurpose::Action,
+        });
+    }
+
+    /// Run the named action for the selected task in a floating pane.
+    fn run_action(&mut self, name: &str) {
+        if self.refuse_when_read_only("actions") {
+            return;
+        }
+        let template = self.config.actions.get(name);
+        let (Some(task), Some(template)) = (self.selected_task(), template) else {
+            return;
+        };
+        let dir = self.repository.yaks_dir().join(&task.path);
+        let host_dir = dir.strip_prefix("/host").unwrap_or(&dir).to_path_buf();
+        let command = action_command(template, task, &host_dir);
+        self.log(format!(
+            "run: {} {}",
+            command.path.display(),
+            command.args.join(" ")
+        ));
+        open_command_pane_at(command, PanePlacement::Floating);
+    }
+
+    /// Choose how long to snooze the selected (or tagged) tasks.
+    fn open_snooze_picker(&mut self) {
+        let targets = self.target_paths();
+        if targets.is_empty() {
+            return;
+        }
+        let mut items: Vec<PickerItem> = [("1 day", "1d"), ("1 week", "1w"), ("Until…", "date")]
+            .iter()
+            .map(|(label, value)| PickerItem {
+                label: label.to_string(),
+                value: value.to_string(),
+            })
+            .collect();
+        let any_snoozed = self
+            .tasks
+            .iter()
+            .any(|t| targets.contains(&t.path) && self.is_snoozed(t));
+        if any_snoozed {
+            items.push(PickerItem {
+                label: "Wake now".to_string(),
+                value: "wake".to_string(),
+            });
+        }
+        let title = if targets.len() == 1 {
+            "Snooze".to_string()
+        } else {
+            format!("Snooze {} tasks", targets.len())
+        };
+        self.mode = Mode::Picker(Picker {
+            title,
+            items,
+            selected: 0,
+            purpose: PickerPurpose::Snooze,
+        });
+    }
+
+    /// Write `defer` for the target tasks, or clear it when `until` is None.
+    fn snooze(&mut self, until: Option<i64>) {
+        self.last_change = Some(Repeatable::Snooze(until));
+        let targets = self.target_paths();
+        let mut failed = 0;
+        for path in &targets {
+            let day = until.map(format_day);
+            let written = self.store_field(path, "defer", day.as_deref());
+            if let Err(e) = written {
+                self.log(format!("snooze {}: {}", path, e));
+                failed += 1;
+            }
+        }
+        self.tagged.clear();
+        self.refresh_after_write();
+        let what = match targets.as_slice() {
+            [path] => path.rsplit('/').next().unwrap_or(path).to_string(),
+            _ => format!("{} tasks", targets.len()),
+        };
+        self.show_toast(match (until, failed) {
+            (_, 0) if until.is_some_and(|day| day <= self.today) => format!("Woke {}", what),
+            (Some(day), 0) => format!("Snoozed {} until {}", what, format_day(day)),
+            (None, 0) => format!("Woke {}", what),
+            (_, failed) => format!("Failed to snooze {} of {} tasks", failed, targets.len()),
+        });
+    }
+
+    /// Pin the selected (or tagged) tasks above the tree, or unpin them when
+    /// the first is pinned already.
+    fn toggle_pinned(&mut self) {
+        let targets = self.target_paths();
+        let Some(first) = targets.first() else {
+            return;
+        };
+        let pin = !self.tasks.iter().any(|t| &t.path == first && t.pinned);
+        let value = pin.then(|| "true".to_string());
+        let writes = targets
+            .iter()
+            .map(|path| (path.clone(), "pinned".to_string(), value.clone()))
+            .collect();
+        let what = match targets.as_slice() {
+            [path] => path.rsplit('/').next().unwrap_or(path).to_string(),
+            _ => format!("{} tasks", targets.len()),
+        };
+        let verb = if pin { "Pinned" } else { "Unpinned" };
+        let failed = self.write_undoable(&format!("{} {}", verb.to_lowercase(), what), writes);
+        self.tagged.clear();
+        self.refresh_after_write();
+        self.show_toast(match failed {
+            0 => format!("{} {}", verb, what),
+            failed => format!("Failed to pin {} of {} tasks", failed, targets.len()),
+        });
+    }
+
+    /// Lines of the pinned section above the tree: each pinned task's status
+    /// and name, with its path as a subtitle. Filters don't apply.
+    fn pinned_lines(&self) -> Vec<(usize, String)> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| task.pinned)
+            .map(|(i, task)| {
+                let name = self.display_name(task);
+                if self.config.plain {
+                    return (i, format!("* {}  ({})", name, task.path));
+                }
+                let line = format!(
+                    "{}{}\x1b[0m {}  \x1b[90m{}\x1b[0m",
+                    self.task_color(task),
+                    self.status_symbol(task),
+                    name,
+                    task.path
+                );
+                (i, line)
+            })
+            .collect()
+    }
+
+    /// Pick a field of the selected task to edit inline.
+    fn open_field_picker(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let items = self
+            .repository
+            .field_names(&task.path)
+            .into_iter()
+            .map(|field| {
+                let value = self.repository.get_field(&task.path, &field);
+                PickerItem {
+                    label: match &value {
+                        Some(value) => {
+                            format!("{} \x1b[90m{}\x1b[0m", field, value.replace('\n', " ⏎ "))
+                        }
+                        None => format!("\x1b[90m{}\x1b[0m", field),
+                    },
+                    value: field,
+                }
+            })
+            .collect();
+        self.mode = Mode::Picker(Picker {
+            title: format!("Edit a field of {}", task.name),
+            items,
+            selected: 0,
+            purpose: PickerPurpose::EditField,
+        });
+    }
+
+    /// Ask for a new value of `field`, starting from its current one.
+    fn start_field_edit(&mut self, task_path: &str, field: &str) {
+        self.mode = Mode::Prompt(Prompt {
+            title: format!("{} (empty to remove): ", field),
+            input: self
+                .repository
+                .get_field(task_path, field)
+                .unwrap_or_default(),
+            purpose: PromptPurpose::SetField {
+                task_path: task_path.to_string(),
+                field: field.to_string(),
+            },
+            recall: Recall::default(),
+        });
+    }
+
+    /// Write (or with an empty value, remove) a field; `u` undoes it.
+    fn set_task_field(&mut self, task_path: &str, field: &str, value: &str) {
+        if !is_field_name(field) {
+            self.show_toast(format!("Not a field name: {:?}", field));
+            return;
+        }
+        let name = task_path.rsplit('/').next().unwrap_or(task_path);
+        let (label, write) = if value.is_empty() {
+            (format!("removed {} of {}", field, name), None)
+        } else {
+            (
+                format!("set {} of {}", field, name),
+                Some(value.to_string()),
+            )
+        };
+        let writes = vec![(task_path.to_string(), field.to_string(), write)];
+        if self.write_undoable(&label, writes) > 0 {
+            self.show_toast(format!("Could not write {} of {}", field, name));
+            return;
+        }
+        self.refresh_after_write();
+        let message = format!("{}{} (u: undo)", label[..1].to_uppercase(), &label[1..]);
+        self.show_toast(message);
+    }
+
+    /// Run a `:` command against the selected task: `set <field> <value>`
+    /// or `unset <field>`.
+    fn run_command_line(&mut self, line: &str) {
+        let line = line.trim();
+        if let Some(shell) = line.strip_prefix('!') {
+            self.run_shell_command(shell.trim());
+            return;
+        }
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        if command == "about" {
+            self.open_view(View::About);
+            return;
+        }
+        if command == "cheatsheet" {
+            self.write_cheatsheet();
+            return;
+        }
+        if command == "trash" {
+            self.trash = self.repository.trash_entries();
+            self.open_view(View::Trash);
+            return;
+        }
+        if command == "view" {
+            // Views don't need a selected task, and may be what brings one back
+            self.run_view_command(rest.trim());
+            return;
+        }
+        let (field, value) = rest.trim().split_once(' ').unwrap_or((rest.trim(), ""));
+        let Some(task_path) = self.selected_task().map(|t| t.path.clone()) else {
+            return;
+        };
+        match command {
+            "" => {}
+            "set" if field.is_empty() => self.show_toast("Usage: :set <field> <value>".to_string()),
+            "unset" if field.is_empty() => self.show_toast("Usage: :unset <field>".to_string()),
+            "import" if rest.trim().is_empty() => {
+                self.show_toast("Usage: :import <file>".to_string())
+            }
+            "import" => self.import_outline(rest.trim()),
+            "set" => self.set_task_field(&task_path, field, value.trim()),
+            "unset" => self.set_task_field(&task_path, field, ""),
+            _ => self.show_toast(format!("Unknown command: {}", command)),
+        }
+    }
+
+    /// The keys, plus the configured `.` actions and view tabs, as markdown.
+    fn cheatsheet(&self) -> String {
+        let mut out = String::from("# yak-map keys\n\n");
+        out.push_str(&readme_key_table());
+        if !self.config.actions.is_empty() {
+            out.push_str("\n## Actions (`.`)\n\n| Action | Command |\n| --- | --- |\n");
+            for (name, template) in &self.config.actions {
+                let command = template.join(" ").replace('|', "\\|");
+                out.push_str(&format!("| {} | `{}` |\n", name, command));
+            }
+        }
+        out.push_str("\n## Tabs (`Tab` / `Shift+Tab`)\n\n");
+        for (name, _) in self.tabs() {
+            out.push_str(&format!("- {}\n", name));
+        }
+        out
+    }
+
+    /// Write the cheatsheet to `.yaks/.yak-map-keys.md` and open it.
+    fn write_cheatsheet(&mut self) {
+        if self.refuse_when_read_only(":cheatsheet") {
+            return;
+        }
+        let path = self.repository.cheatsheet_path();
+        match std::fs::write(&path, self.cheatsheet()) {
+            Ok(()) => self.open_in_editor(&path),
+            Err(e) => {
+                self.log(format!("cheatsheet: {}", e));
+                self.show_toast(format!("Failed to write {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    /// `:view save <name>` saves the current view, `:view delete <name>`
+    /// forgets one and `:view <name>` recalls one.
+    fn run_view_command(&mut self, args: &str) {
+        let (action, name) = args.split_once(' ').unwrap_or(("", args));
+        let name = name.trim();
+        if name.is_empty() || name.contains(['.', '=']) {
+            self.show_toast("Usage: :view [save|delete] <name>".to_string());
+            return;
+        }
+        match action {
+            // Views live in the UI state, which a read-only pane never writes
+            "save" | "delete" if self.refuse_when_read_only("saving views") => {}
+            "save" => {
+                let view = SavedView {
+                    filter: self.filter.clone(),
+                    sort_done_last: self.sorts_done_last(),
+                    mine_only: self.mine_only,
+                    tab: self.tabs().into_iter().nth(self.tab).map(|(name, _)| name),
+                    zoom: self.zoom.clone(),
+                    collapsed: self.collapsed.clone(),
+                };
+                self.views.insert(name.to_string(), view);
+                self.save_ui_state_if_changed();
+                self.show_toast(format!("Saved view {}", name));
+            }
+            "delete" => {
+                if self.views.remove(name).is_some() {
+                    self.save_ui_state_if_changed();
+                    self.show_toast(format!("Deleted view {}", name));
+                } else {
+                    self.show_toast(format!("No view named {}", name));
+                }
+            }
+            "" => self.recall_view(name),
+            _ => self.show_toast(format!("Unknown view command: {}", action)),
+        }
+    }
+
+    fn recall_view(&mut self, name: &str) {
+        let Some(view) = self.views.get(name).cloned() else {
+            self.show_toast(format!("No view named {}", name));
+            return;
+        };
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        self.filter = view.filter;
+        self.mine_only = view.mine_only && self.config.me.is_some();
+        self.tab = self
+            .tabs()
+            .iter()
+            .position(|(tab, _)| Some(tab) == view.tab.as_ref())
+            .unwrap_or(0);
+        self.zoom = view.zoom;
+        self.collapsed = view.collapsed;
+        if view.sort_done_last != self.sorts_done_last() {
+            self.sort_done_last = Some(view.sort_done_last);
+            self.refresh_tasks();
+        }
+        self.update_visible(selected_path);
+        self.show_toast(format!("View {}", name));
+    }
+
+    /// Run a `:!` shell command in a floating command pane, with the selected
+    /// task in `YAK_ID`, `YAK_PATH` and `YAK_DIR`.
+    fn run_shell_command(&mut self, shell: &str) {
+        if self.refuse_when_read_only("shell commands") {
+            return;
+        }
+        if shell.is_empty() {
+            self.show_toast("Usage: :!<shell command>".to_string());
+            return;
+        }
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let dir = self.repository.yaks_dir().join(&task.path);
+        let host_dir = dir.strip_prefix("/host").unwrap_or(&dir).to_path_buf();
+        let command = shell_command(shell, &task.yak_id, &task.path, &host_dir);
+        self.log(format!("run: {} (for {})", shell, task.path));
+        open_command_pane_at(command, PanePlacement::Floating);
+    }
+
+    /// Complete the field name of a `set` or `unset` command line to the
+    /// longest prefix shared by the selected task's matching fields.
+    fn complete_command_line(&self, input: &str) -> Option<String> {
+        let (done, partial) = match input.rfind([' ', ',']) {
+            Some(at) => input.split_at(at + 1),
+            None => ("", input),
+        };
+        let candidates: Vec<String> = self
+            .command_candidates(done)
+            .into_iter()
+            .filter(|c| c.starts_with(partial))
+            .collect();
+        let first = candidates.first()?;
+        let common = candidates.iter().fold(first.len(), |len, c| {
+            first
+                .chars()
+                .zip(c.chars())
+                .take(len)
+                .take_while(|(a, b)| a == b)
+                .count()
+        });
+        let common: String = first.chars().take(common).collect();
+        let suffix = if candidates.len() == 1 { " " } else { "" };
+        Some(format!("{}{}{}", done, common, suffix))
+    }
+
+    /// What may follow the words already typed at the `:` prompt: command
+    /// names, field names, assignees, yak ids and saved view names.
+    fn command_candidates(&self, done: &str) -> Vec<String> {
+        let words: Vec<&str> = done.split_whitespace().collect();
+        let mut candidates: Vec<String> = match words.as_slice() {
+            [] => COMMAND_NAMES.iter().map(|c| c.to_string()).collect(),
+            ["set" | "unset"] => match self.selected_task() {
+                Some(task) => self.repository.field_names(&task.path),
+                None => Vec::new(),
+            },
+            ["set", "assigned-to"] => {
+                let assignees = self.tasks.iter().filter_map(|t| t.assigned_to.clone());
+                let agents = self.config.agents.iter().cloned();
+                assignees
+                    .chain(agents)
+                    .chain(self.config.me.clone())
+                    .collect()
+            }
+            ["set", "blocked-by", ..] => self.tasks.iter().map(|t| t.yak_id.clone()).collect(),
+            ["view"] => ["save", "delete"]
+                .iter()
+                .map(|c| c.to_string())
+                .chain(self.views.keys().cloned())
+                .collect(),
+            ["view", "delete"] => self.views.keys().cloned().collect(),
+            _ => Vec::new(),
+        };
+        candidates.sort();
+        candidates.dedup();
+        candidates
+    }
+
+    /// Ask for a line to add to the selected task's context.md.
+    fn start_capture(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        self.mode = Mode::Prompt(Prompt {
+            title: format!("Note for {}: ", task.name),
+            input: String::new(),
+            purpose: PromptPurpose::Capture {
+                task_path: task.path.clone(),
+            },
+            recall: Recall::default(),
+        });
+    }
+
+    /// Append `- [YYYY-MM-DD HH:MM] text` to the task's context.md.
+    fn capture_note(&mut self, task_path: &str, text: &str) {
+        let text = text.trim();
+        if text.is_empty() || self.refuse_under_yx("notes") {
+            return;
+        }
+        let now = now_secs();
+        let offset = self.config.utc_offset_mins;
+        let stamp = format!("{} {}", format_date(now, offset), format_clock(now, offset));
+        let line = format!("- [{}] {}", stamp, text);
+        match self.repository.append_context(task_path, &line) {
+            Ok(()) => {
+                self.refresh_after_write();
+                self.show_toast(format!("Noted in {}/context.md", task_path));
+            }
+            Err(e) => {
+                self.log(format!("capture {}: {}", task_path, e));
+                self.show_toast(format!("Could not write context.md: {}", e));
+            }
+        }
+    }
+
+    fn toggle_show_snoozed(&mut self) {
+        let selected_path = self.selected_task().map(|t| t.path.clone());
+        self.show_snoozed = !self.show_snoozed;
+        self.update_visible(selected_path);
+    }
+
+    fn handle_prompt_key(&mut self, key: &KeyWithModifier) -> bool {
+        let Mode::Prompt(prompt) = &mut self.mode else {
+            return false;
+        };
+        match key.bare_key {
+            BareKey::Enter => {
+                let prompt = prompt.clone();
+                self.mode = Mode::Normal;
+                self.apply_prompt(&prompt.purpose, &prompt.input);
+            }
+            BareKey::Esc => self.mode = Mode::Normal,
+            BareKey::Backspace => {
+                prompt.input.pop();
+            }
+            BareKey::Up | BareKey::Down if prompt.purpose == PromptPurpose::Command => {
+                let older = key.bare_key == BareKey::Up;
+                let history = &self.command_history;
+                if let Some(line) = history.step(&mut prompt.recall, &prompt.input, older) {
+                    prompt.input = line;
+                }
+            }
+            BareKey::Tab if prompt.purpose == PromptPurpose::Command => {
+                let input = prompt.input.clone();
+                if let Some(completed) = self.complete_command_line(&input) {
+                    if let Mode::Prompt(prompt) = &mut self.mode {
+                        prompt.input = completed;
+                    }
+                }
+            }
+            _ => {
+                if let Some(c) = plain_char(key) {
+                    prompt.input.push(c);
+                }
+            }
+        }
+        true
+    }
+
+    fn apply_prompt(&mut self, purpose: &PromptPurpose, input: &str) {
+        match purpose {
+            PromptPurpose::Capture { task_path } => self.capture_note(task_path, input),
+            PromptPurpose::SetField { task_path, field } => {
+                let (task_path, field) = (task_path.clone(), field.clone());
+                self.set_task_field(&task_path, &field, input.trim());
+            }
+            PromptPurpose::Command => {
+                self.command_history.push(input);
+                self.run_command_line(input);
+            }
+            PromptPurpose::Snooze => match parse_snooze(input, self.today) {
+                Some(day) => self.snooze(Some(day)),
+                None => {
+                    let message = format!("Not a date or duration: {:?}", input);
+                    self.show_toast(message);
+                }
+            },
+        }
+    }
+
+    fn handle_picker_key(&mut self, key: &KeyWithModifier) -> bool {
+        let Mode::Picker(picker) = &mut self.mode else {
+            return false;
+        };
+        match key.bare_key {
+            BareKey::Down | BareKey::Char('j') if key.has_no_modifiers() => {
+                picker.selected = (picker.selected + 1).min(picker.items.len().saturating_sub(1));
+            }
+            BareKey::Up | BareKey::Char('k') if key.has_no_modifiers() => {
+                picker.selected = picker.selected.saturating_sub(1);
+            }
+            BareKey::Enter if key.has_no_modifiers() => {
+                let picker = picker.clone();
+                self.mode = Mode::Normal;
+                if let Some(item) = picker.items.get(picker.selected) {
+                    self.apply_picker(picker.purpose, &item.value);
+                }
+            }
+            BareKey::Char('r')
+                if key.has_no_modifiers() && picker.purpose == PickerPurpose::Assign =>
+            {
+                self.mode = Mode::Normal;
+                self.assign_round_robin();
+            }
+            BareKey::Char('e')
+                if key.has_no_modifiers() && picker.purpose == PickerPurpose::OpenFile =>
+            {
+                let value = picker
+                    .items
+                    .get(picker.selected)
+                    .map(|item| item.value.clone());
+                self.mode = Mode::Normal;
+                if let Some(value) = value {
+                    let path = self.repository.yaks_dir().join(value);
+                    self.open_in_editor(&path);
+                }
+            }
+            BareKey::Esc | BareKey::Char('q') if key.has_no_modifiers() => self.mode = Mode::Normal,
+            _ => {}
+        }
+        true
+    }
+
+    fn apply_picker(&mut self, purpose: PickerPurpose, value: &str) {
+        match purpose {
+            PickerPurpose::Assign => {
+                self.last_change = Some(Repeatable::Assign(value.to_string()));
+                let targets = self.target_paths();
+                let assignments: Vec<_> = targets
+                    .iter()
+                    .map(|p| (p.clone(), value.to_string()))
+                    .collect();
+                self.assign(&assignments);
+            }
+            PickerPurpose::OpenFile => {
+                let path = self.repository.yaks_dir().join(value);
+                self.open_in_viewer(&path);
+            }
+            PickerPurpose::CompleteParents => {
+                if !value.is_empty() {
+                    let paths: Vec<String> = value.lines().map(str::to_string).collect();
+                    self.mark_done(&paths, "all subtasks are done");
+                    self.refresh_after_write();
+                }
+            }
+            PickerPurpose::SetState => self.set_target_state(value),
+            PickerPurpose::Action => self.run_action(value),
+            PickerPurpose::ContextMenu => match value {
+                "open" => self.open_selected_task_context(),
+                "edit" => self.edit_selected_task_context(),
+                "done" => self.set_target_state("done"),
+                "assign" => self.open_assign_picker(),
+                "copy" => self.copy_selected_yak_id(),
+                "delete" => self.confirm_delete(),
+                _ => {}
+            },
+            PickerPurpose::Paste => {
+                let mut lines = value.lines();
+                let parent = lines.next().unwrap_or_default().to_string();
+                let names: Vec<String> = lines.map(str::to_string).collect();
+                self.preview_create(&parent, names);
+            }
+            PickerPurpose::EditField => {
+                if let Some(task_path) = self.selected_task().map(|t| t.path.clone()) {
+                    self.start_field_edit(&task_path, value);
+                }
+            }
+            PickerPurpose::CascadeState => {
+                let mut lines = value.lines();
+                let state = lines.next().unwrap_or_default().to_string();
+                let paths: Vec<String> = lines.map(str::to_string).collect();
+                self.set_states(&paths, &state);
+            }
+            PickerPurpose::Snooze => match value {
+                "wake" => self.snooze(None),
+                "date" => {
+                    self.mode = Mode::Prompt(Prompt {
+                        title: "Snooze until (YYYY-MM-DD, 3d, 2w): ".to_string(),
+                        input: String::new(),
+                        purpose: PromptPurpose::Snooze,
+                        recall: Recall::default(),
+                    });
+                }
+                _ => {
+                    let until = parse_snooze(value, self.today);
+                    self.snooze(until);
+                }
+            },
+        }
+    }
+
+    /// Make the last state change, assignment or snooze again, on the
+    /// selected (or tagged) tasks.
+    fn repeat_last_change(&mut self) {
+        match self.last_change.clone() {
+            Some(Repeatable::State(state)) => self.set_target_state(&state),
+            Some(Repeatable::Assign(agent)) => {
+                let targets = self.target_paths();
+                let assignments: Vec<_> = targets.into_iter().map(|p| (p, agent.clone())).collect();
+                self.assign(&assignments);
+            }
+            Some(Repeatable::Snooze(until)) => self.snooze(until),
+            None => self.show_toast("Nothing to repeat yet".to_string()),
+        }
+    }
+
+    /// Spread the target tasks over the known agents, least loaded first.
+    fn assign_round_robin(&mut self) {
+        let agents: Vec<String> = self.agent_loads().into_iter().map(|(a, _)| a).collect();
+        if agents.is_empty() {
+            return;
+        }
+        let assignments: Vec<_> = self
+            .target_paths()
+            .into_iter()
+            .zip(agents.iter().cycle())
+            .map(|(path, agent)| (path, agent.clone()))
+            .collect();
+        self.assign(&assignments);
+    }
+
+    /// Write `assigned-to` for each (path, agent) pair and refresh once.
+    fn assign(&mut self, assignments: &[(String, String)]) {
+        let before = self.wip_breaches(&[]);
+        let breach = self
+            .wip_breaches(assignments)
+            .into_iter()
+            .find(|breach| !before.contains(breach));
+        let mut failed = 0;
+        for (path, agent) in assignments {
+            let agent_value = Some(agent.as_str()).filter(|agent| !agent.is_empty());
+            let written = self.store_field(path, "assigned-to", agent_value);
+            if let Err(e) = written {
+                self.log(format!("assign {} to {}: {}", path, agent, e));
+                failed += 1;
+            }
+        }
+        self.tagged.clear();
+        self.refresh_after_write();
+        let message = match (assignments, failed) {
+            (_, 0) if assignments.iter().all(|(_, agent)| agent.is_empty()) => {
+                match assignments.len() {
+                    1 => "Unassigned".to_string(),
+                    n => format!("Unassigned {} tasks", n),
+                }
+            }
+            (_, 0) if assignments.len() == 1 => format!("Assigned to {}", assignments[0].1),
+            (_, 0) => format!("Assigned {} tasks", assignments.len()),
+            (_, n) => format!("Failed to assign {} of {} tasks", n, assignments.len()),
+        };
+        self.show_toast(match breach {
+            Some((agent, wip, limit)) => {
+                format!(
+                    "{}; {} is over the WIP limit ({}/{})",
+                    message, agent, wip, limit
+                )
+            }
+            None => message,
+        });
+    }
+
+    fn render_picker(&self, picker: &Picker, rows: usize, cols: usize) {
+        self.emit(&format!(
+            "\x1b[1m{}\x1b[0m \x1b[90m(Enter: choose, Esc: cancel)\x1b[0m",
+            picker.title
+        ));
+        if picker.items.is_empty() {
+            self.emit("\x1b[90mNothing to choose from.\x1b[0m");
+            return;
+        }
+        let max_rows = rows.saturating_sub(2).max(1);
+        let offset = (picker.selected + 1).saturating_sub(max_rows);
+        for (i, item) in picker.items.iter().enumerate().skip(offset).take(max_rows) {
+            self.emit_row(&format!("  {}", item.label), cols, i == picker.selected);
+        }
+    }
+
+    fn render_preview(&self, preview: &Preview, rows: usize, cols: usize) {
+        self.emit(&format!(
+            "\x1b[1m{}\x1b[0m \x1b[90m(Enter: confirm, Esc: cancel)\x1b[0m",
+            preview.title
+        ));
+        let max_rows = rows.saturating_sub(2).max(1);
+        let offset = preview
+            .scroll
+            .min(preview.lines.len().saturating_sub(max_rows));
+        for line in preview.lines.iter().skip(offset).take(max_rows) {
+            self.emit_row(&format!("  {}", line), cols, false);
+        }
+    }
+
+    fn handle_preview_key(&mut self, key: &KeyWithModifier) -> bool {
+        let Mode::Preview(preview) = &mut self.mode else {
+            return false;
+        };
+        if !key.has_no_modifiers() {
+            return true;
+        }
+        match key.bare_key {
+            BareKey::Down | BareKey::Char('j') => {
+                preview.scroll = (preview.scroll + 1).min(preview.lines.len().saturating_sub(1));
+            }
+            BareKey::Up | BareKey::Char('k') => preview.scroll = preview.scroll.saturating_sub(1),
+            BareKey::Enter | BareKey::Char('y') => self.confirm_preview(),
+            BareKey::Esc | BareKey::Char('q') | BareKey::Char('n') => self.mode = Mode::Normal,
+            _ => {}
+        }
+        true
+    }
+
+    /// Make the change being previewed.
+    fn confirm_preview(&mut self) {
+        let Mode::Preview(preview) = std::mem::take(&mut self.mode) else {
+            return;
+        };
+        match preview.change {
+            PendingChange::Delete(paths) => self.delete_tasks(&paths),
+            PendingChange::Move { path, target } => self.move_task(&path, &target),
+            PendingChange::Create { parent, names } => {
+                let names: Vec<&str> = names.iter().map(String::as_str).collect();
+                self.create_tasks(&parent, &names);
+            }
+        }
+    }
+
+    /// List the paths `create_tasks` would make for `names` under `parent`.
+    fn preview_create(&mut self, parent: &str, names: Vec<String>) {
+        let mut taken: std::collections::BTreeSet<String> =
+            self.tasks.iter().map(|t| t.path.clone()).collect();
+        let mut ancestors: Vec<String> = Vec::new();
+        let mut lines = Vec::new();
+        for line in &names {
+            let name = line.trim_start_matches('\t');
+            let depth = (line.len() - name.len()).min(ancestors.len());
+            ancestors.truncate(depth);
+            let under = ancestors.last().map_or(parent, String::as_str);
+            let path = free_child_path(under, &task_dir_name(name), |p| taken.contains(p));
+            lines.push(format!("\x1b[32m+\x1b[0m {}", path));
+            taken.insert(path.clone());
+            ancestors.push(path);
+        }
+        let plural = if names.len() == 1 { "" } else { "s" };
+        self.mode = Mode::Preview(Preview {
+            title: format!("Create {} task{}?", names.len(), plural),
+            lines,
+            scroll: 0,
+            change: PendingChange::Create {
+                parent: parent.to_string(),
+                names,
+            },
+        });
+    }
+
+    fn open_view(&mut self, view: View) {
+        self.view = view;
+        self.view_selected = 0;
+    }
+
+    /// Rows of the agent queue view: display text, plus the task index for
+    /// rows that can be selected.
+    fn agent_queue_rows(&self) -> Vec<(String, Option<usize>)> {
+        let mut rows = Vec::new();
+        for queue in agent_queues(&self.tasks) {
+            let limit = self.config.wip_limit_for(&queue.agent);
+            let over = limit.is_some_and(|limit| queue.wip.len() > limit);
+            let (color, warning) = match (over, self.config.plain) {
+                (true, true) => (String::new(), " (over WIP limit)"),
+                (true, false) => ("\x1b[1;31m".to_string(), ""),
+                (false, _) => (self.agent_color(&queue.agent), ""),
+            };
+            let wip = match limit {
+                Some(limit) => format!("{}/{}", queue.wip.len(), limit),
+                None => queue.wip.len().to_string(),
+            };
+            rows.push((
+                format!(
+                    "{}{}\x1b[0m \x1b[90m({} wip, {} queued)\x1b[0m{}",
+                    color,
+                    queue.agent,
+                    wip,
+                    queue.queued.len(),
+                    warning
+                ),
+                None,
+            ));
+            for &i in &queue.wip {
+                let task = &self.tasks[i];
+                let status = task
+                    .agent_status
+                    .as_deref()
+                    .map(|s| format!(" \x1b[90m{}\x1b[0m", s))
+                    .unwrap_or_default();
+                rows.push((
+                    match self.config.plain {
+                        true => format!("  [WIP] {}{}", task.name, status),
+                        false => format!(
+                            "  {}●\x1b[0m {}{}",
+                            self.task_color(task),
+                            task.name,
+                            status
+                        ),
+                    },
+                    Some(i),
+                ));
+            }
+            for (n, &i) in queue.queued.iter().enumerate() {
+                rows.push((
+                    format!("  \x1b[90m{}.\x1b[0m {}", n + 1, self.tasks[i].name),
+                    Some(i),
+                ));
+            }
+        }
+        rows
+    }
+
+    /// Up to `count` lines of the selected task's context.md, rendered as
+    /// markdown unless in plain mode, ending with its blockers, notes and
+    /// other files.
+    fn preview_lines(&self, count: usize) -> Vec<String> {
+        let Some(task) = self.selected_task() else {
+            return Vec::new();
+        };
+        let files = self.repository.task_files(&task.path);
+        let notes = self.repository.note_files(&task.path);
+        let mut lines = match std::fs::read_to_string(self.repository.context_path(&task.path)) {
+            Ok(source) if self.config.plain => source.lines().map(str::to_string).collect(),
+            Ok(source) => markdown::render(&source),
+            Err(_) => vec!["\x1b[90mNo context.md.\x1b[0m".to_string()],
+        };
+        let mut footer = Vec::new();
+        if !task.blockers.is_empty() {
+            let names: Vec<String> = task
+                .blockers
+                .iter()
+                .map(|&i| &self.tasks[i])
+                .map(|blocker| match blocker.state {
+                    TaskState::Done => format!("\x1b[9m{}\x1b[0m (done)", blocker.name),
+                    _ => blocker.name.clone(),
+                })
+                .collect();
+            footer.push(format!("\x1b[90mBlocked by:\x1b[0m {}", names.join(", ")));
+        }
+        if !notes.is_empty() {
+            footer.push(format!(
+                "\x1b[90mNotes (n: new):\x1b[0m {}",
+                notes.join(", ")
+            ));
+        }
+        if !files.is_empty() {
+            footer.push(format!(
+                "\x1b[90mFiles (o: open):\x1b[0m {}",
+                files.join(", ")
+            ));
+        }
+        lines.truncate(count.saturating_sub(footer.len()));
+        lines.extend(footer);
+        lines
+    }
+
+    /// The newest `count` changes as activity feed lines, oldest first.
+    fn feed_lines(&self, count: usize) -> Vec<String> {
+        let skip = self.changes.len().saturating_sub(count);
+        self.changes
+            .iter()
+            .skip(skip)
+            .map(|change| {
+                let event = match &change.kind {
+                    ChangeKind::Created => format!("{} created", change.path),
+                    ChangeKind::Removed => format!("{} removed", change.path),
+                    ChangeKind::State { to, .. } => format!("{} → {}", change.path, to.as_str()),
+                    ChangeKind::Assigned {
+                        to: Some(agent), ..
+                    } => format!(
+                        "{}{}\x1b[0m assigned {}",
+                        self.agent_color(agent),
+                        agent,
+                        change.path
+                    ),
+                    ChangeKind::Assigned { from, to: None } => format!(
+                        "{} unassigned (was {})",
+                        change.path,
+                        from.as_deref().unwrap_or("nobody")
+                    ),
+                };
+                format!(
+                    "\x1b[90m{}\x1b[0m {}",
+                    format_clock(change.at, self.config.utc_offset_mins),
+                    event
+                )
+            })
+            .collect()
+    }
+
+    /// Rows of the changes view, newest first.
+    fn change_rows(&self, now: u64) -> Vec<(String, Option<usize>)> {
+        let since = now.saturating_sub(self.config.changes_window_mins * 60);
+        self.changes
+            .iter()
+            .rev()
+            .take_while(|change| change.at >= since)
+            .map(|change| {
+                let what = match &change.kind {
+                    ChangeKind::Created => "created".to_string(),
+                    ChangeKind::Removed => "removed".to_string(),
+                    ChangeKind::State { from, to } => {
+                        format!("{} → {}", from.as_str(), to.as_str())
+                    }
+                    ChangeKind::Assigned { from, to } => format!(
+                        "assigned {} → {}",
+                        from.as_deref().unwrap_or("nobody"),
+                        to.as_deref().unwrap_or("nobody")
+                    ),
+                };
+                let line = format!(
+                    "\x1b[90m{:>8}\x1b[0m  {}  \x1b[90m{}\x1b[0m",
+                    format_age(now.saturating_sub(change.at)),
+                    change.path,
+                    what
+                );
+                (line, self.tasks.iter().position(|t| t.path == change.path))
+            })
+            .collect()
+    }
+
+    /// Rows of the log overlay, newest first.
+    fn log_rows(&self) -> Vec<(String, Option<usize>)> {
+        self.log
+            .iter()
+            .rev()
+            .map(|(at, message)| {
+                let clock = format_clock(*at, self.config.utc_offset_mins);
+                (format!("\x1b[90m{}\x1b[0m {}", clock, message), None)
+            })
+            .collect()
+    }
+
+    /// Rows of the diagnostics view: one per problem, in tree order.
+    fn diagnostic_rows(&self) -> Vec<(String, Option<usize>)> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .flat_map(|(i, task)| {
+                task.problems.iter().map(move |problem| {
+                    (
+                        format!("{}  \x1b[31m{}\x1b[0m", task.path, problem),
+                        Some(i),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// How long a wip task has been wip, once that passes `stale_wip_hours`.
+    fn stalled_for(&self, task: &TaskLine, now: u64) -> Option<u64> {
+        if task.state != TaskState::Wip || self.config.stale_wip_hours == 0 {
+            return None;
+        }
+        let age = now.saturating_sub(task.state_since?);
+        (age >= self.config.stale_wip_hours * 3600).then_some(age)
+    }
+
+    /// Rows of the stats view: totals by state, then stalled wip tasks,
+    /// longest stalled first.
+    fn stats_rows(&self, now: u64) -> Vec<(String, Option<usize>)> {
+        let count = |state: TaskState| self.tasks.iter().filter(|t| t.state == state).count();
+        let (todo, wip) = (count(TaskState::Todo), count(TaskState::Wip));
+        let done = count(TaskState::Done);
+        let percent = (done * 100).checked_div(self.tasks.len()).unwrap_or(0);
+        let overdue = self.overdue.as_ref().map_or(0, |paths| paths.len());
+        let waiting = self
+            .tasks
+            .iter()
+            .filter(|t| !self.open_blockers(t).is_empty())
+            .count();
+        let mut rows = vec![
+            (
+                format!(
+                    "{} todo · {} wip · {} done ({}% done)",
+                    todo, wip, done, percent
+                ),
+                None,
+            ),
+            (
+                format!(
+                    "{} overdue · {} waiting on blockers · {} snoozed",
+                    overdue,
+                    waiting,
+                    self.snoozed.len()
+                ),
+                None,
+            ),
+            (String::new(), None),
+        ];
+
+        let mut stalled: Vec<(usize, u64)> = self
+            .tasks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, task)| Some((i, self.stalled_for(task, now)?)))
+            .collect();
+        stalled.sort_by_key(|&(_, age)| std::cmp::Reverse(age));
+        rows.push((
+            format!(
+                "\x1b[1mStalled\x1b[0m \x1b[90m(wip for over {}h)\x1b[0m",
+                self.config.stale_wip_hours
+            ),
+            None,
+        ));
+        if stalled.is_empty() {
+            rows.push(("  \x1b[90mnone\x1b[0m".to_string(), None));
+        }
+        for (i, age) in stalled {
+            let task = &self.tasks[i];
+            let who = task.assigned_to.as_deref().unwrap_or("unassigned");
+            let age = format_age(age);
+            rows.push((
+                format!(
+                    "  {}  \x1b[33mwip {}\x1b[0m  \x1b[90m{}\x1b[0m",
+                    task.path, age, who
+                ),
+                Some(i),
+            ));
+        }
+        rows
+    }
+
+    /// Hand a `task_url` response to the store and show what it brought.
+    fn receive_task_snapshot(&mut self, status: u16, body: &[u8]) -> bool {
+        if self.repository.web_response(status, body) {
+            self.load_tasks();
+        }
+        true
+    }
+
+    /// Permissions to ask for: web access only to browse a `task_url`.
+    fn requested_permissions(&self) -> Vec<PermissionType> {
+        let mut permissions = PERMISSIONS.to_vec();
+        if self.config.task_url.is_some() {
+            permissions.push(PermissionType::WebAccess);
+        }
+        permissions
+    }
+
+    /// Rows of the about view: what a bug report needs to know about this
+    /// pane.
+    fn about_rows(&self) -> Vec<(String, Option<usize>)> {
+        let dir = self
+            .host_yaks_dir
+            .as_ref()
+            .unwrap_or(self.repository.yaks_dir());
+        let refreshed = match self.last_refresh {
+            Some((at, ms)) => {
+                format!(
+                    "{} ago, took {}ms",
+                    format_age(now_secs().saturating_sub(at)),
+                    ms
+                )
+            }
+            None => "not yet".to_string(),
+        };
+        let permissions = match self.permissions {
+            Some(PermissionStatus::Granted) => "granted",
+            Some(PermissionStatus::Denied) => "denied",
+            None => "not answered yet",
+        };
+        let requested = self.requested_permissions();
+        let requested: Vec<String> = requested.iter().map(|p| format!("{:?}", p)).collect();
+        let mut lines = vec![
+            format!("\x1b[1myak-map\x1b[0m {}", VERSION),
+            self.yx_summary(),
+            format!("Yaks directory: {}", dir.display()),
+            format!("Tasks: {} ({} shown)", self.tasks.len(), self.visible.len()),
+            format!("Last refresh: {}", refreshed),
+            format!("Permissions: {} ({})", permissions, requested.join(", ")),
+            String::new(),
+            "\x1b[1mConfiguration\x1b[0m \x1b[90m(anything not listed is at its default)\x1b[0m"
+                .to_string(),
+        ];
+        if self.configuration.is_empty() {
+            lines.push("  \x1b[90mnone\x1b[0m".to_string());
+        }
+        for (key, value) in &self.configuration {
+            lines.push(format!("  {} = {}", key, value));
+        }
+        lines.into_iter().map(|line| (line, None)).collect()
+    }
+
+    /// Rows of the field inspector: each field file of the selected task
+    /// with its value (first line), when it was written and its size.
+    fn field_rows(&self, now: u64) -> Vec<(String, Option<usize>)> {
+        let Some(task) = self.selected_task() else {
+            return Vec::new();
+        };
+        let dir = self.repository.yaks_dir().join(&task.path);
+        let fields: Vec<(String, std::fs::Metadata)> = self
+            .repository
+            .field_names(&task.path)
+            .into_iter()
+            .filter_map(|name| Some((name.clone(), std::fs::metadata(dir.join(&name)).ok()?)))
+            .collect();
+        let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        fields
+            .into_iter()
+            .map(|(name, metadata)| {
+                let value = match self.repository.read_field(&task.path, &name) {
+                    Ok(Some(value)) => {
+                        let mut lines = value.lines();
+                        let first = lines.next().unwrap_or_default().to_string();
+                        match lines.count() {
+                            0 => first,
+                            more => format!("{} \x1b[90m(+{} lines)\x1b[0m", first, more),
+                        }
+                    }
+                    Ok(None) => "\x1b[90m(empty)\x1b[0m".to_string(),
+                    Err(e) => format!("\x1b[31m{}\x1b[0m", e),
+                };
+                let written = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or("?".to_string(), |t| {
+                        format_age(now.saturating_sub(t.as_secs()))
+                    });
+                (
+                    format!(
+                        "{:<width$}  {}  \x1b[90m{} · {}B\x1b[0m",
+                        name,
+                        value,
+                        written,
+                        metadata.len(),
+                        width = width
+                    ),
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    /// Task indices of the selectable rows in the current non-tree view.
+    fn view_task_rows(&self) -> Vec<usize> {
+        let rows = match self.view {
+            View::Tree => Vec::new(),
+            View::AgentQueues => self.agent_queue_rows(),
+            View::Changes => self.change_rows(now_secs()),
+            View::Diagnostics => self.diagnostic_rows(),
+            View::Stats => self.stats_rows(now_secs()),
+            View::Fields => self.field_rows(now_secs()),
+            View::Log => self.log_rows(),
+            View::Timeline => {
+                self.timeline_rows(day_number(now_secs(), self.config.utc_offset_mins), 80)
+            }
+            View::Calendar => {
+                let today = day_number(now_secs(), self.config.utc_offset_mins);
+                let columns = self.calendar_columns(today);
+                return columns.into_iter().flat_map(|(_, tasks)| tasks).collect();
+            }
+            View::Table => return self.table_tasks(),
+            View::About => self.about_rows(),
+            View::Trash => self.trash_rows(),
+        };
+        rows.into_iter().filter_map(|(_, i)| i).collect()
+    }
+
+    /// Leave the current view and select `task_index` in the tree, clearing
+    /// filters if they hide it.
+    fn jump_to_task_in_tree(&mut self, task_index: usize) {
+        self.view = View::Tree;
+        let path = self.tasks[task_index].path.clone();
+        if self.row_of_path(&path).is_none() && self.is_filtering() {
+            self.filter = None;
+            self.mine_only = false;
+        }
+        // Unfold ancestors so the task is reachable
+        let mut ancestor = path.as_str();
+        while let Some((parent, _)) = ancestor.rsplit_once('/') {
+            self.collapsed.remove(parent);
+            if self
+                .tasks
+                .iter()
+                .any(|t| t.path == parent && self.is_auto_collapsed(t))
+            {
+                self.expanded.insert(parent.to_string());
+            }
+            ancestor = parent;
+        }
+        self.update_visible(Some(path));
+    }
+
+    /// Keys shared by list views: move with j/k, Enter jumps to the task in
+    /// the tree, Esc or q goes back.
+    fn handle_view_key(&mut self, key: &KeyWithModifier) -> bool {
+        let rows = self.view_task_rows();
+        match key.bare_key {
+            BareKey::Down | BareKey::Char('j') if key.has_no_modifiers() => {
+                if self.view_selected + 1 < rows.len() {
+                    self.view_selected += 1;
+                }
+            }
+            BareKey::Up | BareKey::Char('k') if key.has_no_modifiers() => {
+                self.view_selected = self.view_selected.saturating_sub(1);
+            }
+            BareKey::Enter if key.has_no_modifiers() && self.view == View::Trash => {
+                self.restore_selected_trash();
+            }
+            BareKey::Enter if key.has_no_modifiers() => {
+                if let Some(&task_index) = rows.get(self.view_selected) {
+                    self.jump_to_task_in_tree(task_index);
+                }
+            }
+            BareKey::Left | BareKey::Char('h')
+                if key.has_no_modifiers() && self.view == View::Timeline =>
+            {
+                self.timeline_shift -= 7;
+            }
+            BareKey::Right | BareKey::Char('l')
+                if key.has_no_modifiers() && self.view == View::Timeline =>
+            {
+                self.timeline_shift += 7;
+            }
+            BareKey::Char(c @ '1'..='6') if key.has_no_modifiers() && self.view == View::Table => {
+                self.cycle_table_sort(TableColumn::ALL[c as usize - '1' as usize]);
+            }
+            BareKey::Char('w') if key.has_no_modifiers() && self.view == View::Calendar => {
+                self.calendar_fortnight = !self.calendar_fortnight;
+                self.view_selected = 0;
+            }
+            BareKey::Esc | BareKey::Char('q') if key.has_no_modifiers() => self.view = View::Tree,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Width of a preview drawn beside the tree in a pane `cols` wide, or
+    /// `None` when it goes under the tree.
+    fn side_preview_cols(&self, cols: usize) -> Option<usize> {
+        let beside = match self.config.preview_split {
+            PreviewSplit::Bottom => false,
+            PreviewSplit::Right => true,
+            PreviewSplit::Auto => cols >= SIDE_PREVIEW_MIN_COLS,
+        };
+        let preview_cols = (cols as f64 * self.config.preview_ratio).round() as usize;
+        let preview_cols = preview_cols.min(cols.saturating_sub(MIN_TREE_COLS + 3));
+        Some(preview_cols).filter(|&preview_cols| beside && preview_cols >= 10)
+    }
+
+    fn render_tree(&mut self, rows: usize, cols: usize) {
+        let resized = (rows, cols) != (self.pane_rows, self.pane_cols);
+        self.pane_rows = rows;
+        self.pane_cols = cols;
+        let side_preview = self.side_preview_cols(cols);
+        // The tree keeps what the side preview and its " │ " border leave
+        let cols = side_preview.map_or(cols, |preview_cols| cols - preview_cols - 3);
+        let show_preview = self.show_preview && side_preview.is_none();
+        let header = self.header_line();
+        let header_rows = if header.is_some() { 1 } else { 0 };
+        let breadcrumb_rows = if self.config.breadcrumb { 1 } else { 0 };
+        let feed_rows = if self.show_feed {
+            FEED_PANEL_ROWS + 1
+        } else {
+            0
+        };
+        // Leave the tree at least a few rows however large the preview is set
+        let preview_height = self
+            .preview_rows
+            .unwrap_or(PREVIEW_PANEL_ROWS)
+            .min(rows.saturating_sub(8))
+            .max(1);
+        let preview_rows = if show_preview { preview_height + 1 } else { 0 };
+        let pinned = self.pinned_lines();
+        let pinned_rows = if pinned.is_empty() {
+            0
+        } else {
+            pinned.len() + 1
+        };
+        let status_rows = if self.show_status_line { 1 } else { 0 };
+        let panel_rows = feed_rows + preview_rows + pinned_rows + status_rows;
+        let max_rows = rows.saturating_sub(4 + header_rows + breadcrumb_rows + panel_rows);
+
+        // Keep scroll_offset in sync with selected_index. Pinned ancestors take
+        // rows from the body, which can scroll further and pin more, so repeat
+        // until the two agree.
+        let mut sticky: Vec<usize> = Vec::new();
+        for _ in 0..4 {
+            let body_rows = max_rows.saturating_sub(sticky.len());
+            if resized {
+                // Don't leave blank rows under the tree after the pane grows
+                let last_offset = self.visible.len().saturating_sub(body_rows);
+                self.scroll_offset = self.scroll_offset.min(last_offset);
+            }
+            self.keep_selection_in_view(body_rows);
+            let next = self.sticky_ancestor_rows(max_rows / 2);
+            if next == sticky {
+                break;
+            }
+            sticky = next;
+        }
+        let body_rows = max_rows.saturating_sub(sticky.len());
+        self.viewport_rows = body_rows;
+
+        if side_preview.is_some() {
+            let lines = self.preview_lines(rows.saturating_sub(1));
+            *self.side_panel.borrow_mut() = Some((cols, lines.into()));
+        }
+        self.emit(&self.tab_strip());
+        let mut row_tasks = vec![None];
+        if let Some(header) = header {
+            self.emit(&header);
+            row_tasks.push(None);
+        }
+        if !pinned.is_empty() {
+            for (i, line) in &pinned {
+                self.emit_row(line, cols, false);
+                row_tasks.push(Some(*i));
+            }
+            self.emit(&format!("\x1b[90m{}\x1b[0m", "─".repeat(cols.min(40))));
+            row_tasks.push(None);
+        }
+
+        let gutter_width = self.visible.len().to_string().len();
+        let labels: BTreeMap<usize, String> = match &self.mode {
+            Mode::JumpLabel { typed, .. } => self
+                .visible_jump_labels()
+                .into_iter()
+                .filter(|(_, label)| label.starts_with(typed.as_str()))
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+        let label_width = labels.values().map(|l| l.len()).max().unwrap_or(0);
+        let task_line = |index: usize| {
+            let task = &self.tasks[self.visible[index]];
+            let mut line = self.render_task(task) + self.drop_marker(task);
+            if self.config.relative_numbers {
+                line = format!("{}{}", self.line_number_gutter(index, gutter_width), line);
+            }
+            if let Mode::JumpLabel { .. } = self.mode {
+                let label = match labels.get(&index) {
+                    Some(label) => format!("\x1b[1;30;43m{}\x1b[0m", label),
+                    None => " ".repeat(label_width),
+                };
+                line = format!("{} {}", label, line);
+            }
+            line
+        };
+        let show_minimap = self.config.minimap
+            && !self.config.plain
+            && cols >= WIDE_MODE_COLS
+            && self.visible.len() > body_rows;
+        let minimap = if show_minimap {
+            self.minimap_cells(max_rows, body_rows)
+        } else {
+            Vec::new()
+        };
+        let mut minimap_cells = minimap.iter();
+        let mut row_line = |index: usize| {
+            let line = task_line(index);
+            let Some((in_viewport, color)) = minimap_cells.next() else {
+                return line;
+            };
+            let width = cols.saturating_sub(MINIMAP_COLS);
+            let padding = " ".repeat(width.saturating_sub(strip_ansi(&line).chars().count()));
+            let marker = if *in_viewport { "\x1b[1m▐" } else { " " };
+            format!("{}{}{}\x1b[0m{}█\x1b[0m", line, padding, marker, color)
+        };
+        for (n, &index) in sticky.iter().enumerate() {
+            row_tasks.push(Some(self.visible[index]));
+            let line = row_line(index);
+            if n + 1 == sticky.len() && !self.config.plain {
+                // Underline the last pinned row to separate it from the body.
+                let underlined = line.replace("\x1b[0m", "\x1b[0m\x1b[4m");
+                self.emit(&format!("\x1b[4m{}\x1b[0m", underlined));
+            } else {
+                self.emit_row(&line, cols, false);
+            }
+        }
+        let body_end = (self.scroll_offset + body_rows).min(self.visible.len());
+        for index in self.scroll_offset.min(body_end)..body_end {
+            self.emit_row(&row_line(index), cols, index == self.selected_index);
+            row_tasks.push(Some(self.visible[index]));
+        }
+        self.row_tasks = row_tasks;
+
+        if self.visible.is_empty() {
+            self.emit("\x1b[90mNo matching tasks.\x1b[0m");
+        } else if self.config.breadcrumb {
+            self.emit(&self.breadcrumb(cols).unwrap_or_default());
+        }
+
+        if show_preview {
+            self.emit(&format!("\x1b[90m{}\x1b[0m", "─".repeat(cols.min(40))));
+            for line in self.preview_lines(preview_height) {
+                self.emit(&line);
+            }
+        }
+
+        if self.show_feed {
+            self.emit(&format!("\x1b[90m{}\x1b[0m", "─".repeat(cols.min(40))));
+            let lines = self.feed_lines(FEED_PANEL_ROWS);
+            if lines.is_empty() {
+                self.emit("\x1b[90mNo activity yet.\x1b[0m");
+            }
+            for line in lines {
+                self.emit(&line);
+            }
+        }
+
+        if let Some(line) = self.input_line() {
+            self.emit(&line);
+        }
+        if self.show_status_line {
+            self.emit(&self.status_line());
+        }
+        // Finish a side preview that is longer than the tree
+        while self
+            .side_panel
+            .borrow()
+            .as_ref()
+            .is_some_and(|(_, lines)| !lines.is_empty())
+        {
+            self.emit("");
+        }
+        self.side_panel.replace(None);
+    }
+
+    /// The build, the yaks directory and when the tree was last read, for bug
+    /// reports and for telling panes on different repositories apart.
+    fn status_line(&self) -> String {
+        let dir = self
+            .host_yaks_dir
+            .as_ref()
+            .unwrap_or(self.repository.yaks_dir());
+        let refreshed = match self.last_refresh {
+            Some((at, ms)) => {
+                let clock = format_clock(at, self.config.utc_offset_mins);
+                format!("refreshed {}:{:02} in {}ms", clock, at % 60, ms)
+            }
+            None => "not refreshed yet".to_string(),
+        };
+        format!(
+            "\x1b[90myak-map {} · {} · {}\x1b[0m",
+            VERSION,
+            dir.display(),
+            refreshed
+        )
+    }
+
+    /// The line being typed (filter, prompt or type-ahead), or the active
+    /// filter, shown under the tree.
+    fn input_line(&self) -> Option<String> {
+        Some(match (&self.mode, &self.filter) {
+            (Mode::Filter { input, .. }, _) => {
+                let count = if input.is_empty() {
+                    String::new()
+                } else {
+                    format!(
+                        "  \x1b[90m\"{}\" — {} match{}\x1b[0m",
+                        input,
+                        self.filter_matches,
+                        if self.filter_matches == 1 { "" } else { "es" }
+                    )
+                };
+                format!("/{}\x1b[7m \x1b[0m{}", input, count)
+            }
+            (Mode::Prompt(prompt), _) => format!("{}{}\x1b[7m \x1b[0m", prompt.title, prompt.input),
+            (Mode::TypeAhead { typed, .. }, _) => {
+                let hint = "\x1b[90m(>/<: next/previous)\x1b[0m";
+                format!("\"{}\x1b[7m \x1b[0m  {}", typed, hint)
+            }
+            (_, Some(query)) => format!("\x1b[90mfilter: {}  (Esc to clear)\x1b[0m", query),
+            _ => return None,
+        })
+    }
+
+    /// The one-line summary drawn in panes too short for the tree: wip and
+    /// blocked counts, then the selected task with its assignee and agent
+    /// status. A line being typed takes its place.
+    fn status_bar_line(&self) -> String {
+        if let Some(line) = self.input_line().filter(|_| self.mode != Mode::Normal) {
+            return line;
+        }
+        if let Some(toast) = &self.toast_message {
+            return format!("\x1b[1m{}\x1b[0m", toast);
+        }
+        let open = self.tasks.iter().filter(|t| t.state != TaskState::Done);
+        let blocked = open.filter(|t| self.is_blocked(t)).count();
+        let wip = self
+            .tasks
+            .iter()
+            .filter(|t| t.state == TaskState::Wip)
+            .count();
+        let color = |sgr| fit_color(sgr, self.config.color_depth);
+        let theme = &self.config.theme;
+        let mut line = format!(
+            "{}{} wip\x1b[0m · {}{} blocked\x1b[0m",
+            color(theme.wip),
+            wip,
+            color(theme.blocked),
+            blocked
+        );
+        if let Some(task) = self.selected_task() {
+            line.push_str(&format!(" \x1b[90m│\x1b[0m ▶ \x1b[1m{}\x1b[0m", task.path));
+            if let Some(agent) = &task.assigned_to {
+                line.push_str(&format!(" [{}{}\x1b[0m]", self.agent_color(agent), agent));
+            }
+            if let Some(status) = &task.agent_status {
+                line.push_str(&format!(" \x1b[90m{}\x1b[0m", status));
+            }
+        }
+        line
+    }
+
+    fn render_agent_queues(&mut self, rows: usize, cols: usize) {
+        let lines = self.agent_queue_rows();
+        self.render_list_view("Agent queues", "No assigned tasks.", &lines, rows, cols);
+    }
+
+    /// First day shown by the timeline: a couple of days before today, moved
+    /// by `h`/`l`.
+    fn timeline_start(&self, today: i64) -> i64 {
+        today - 2 + self.timeline_shift
+    }
+
+    /// Rows of the timeline view: a date ruler, then one row per task with a
+    /// due date (soonest first) whose bar covers its `estimate` in days (at
+    /// least one) up to the due date, colored by state. `│` marks today.
+    fn timeline_rows(&self, today: i64, cols: usize) -> Vec<(String, Option<usize>)> {
+        const LABEL_COLS: usize = 20;
+        let start = self.timeline_start(today);
+        let days = cols.saturating_sub(LABEL_COLS + 2).max(7) as i64;
+
+        let mut ruler = " ".repeat(LABEL_COLS + 1);
+        let mut day = start;
+        while day < start + days {
+            // Label each week with its month and day, if it fits.
+            let label = format_day(day)[5..].to_string();
+            if day + 6 <= start + days {
+                ruler.push_str(&format!("\x1b[90m|{:<6}\x1b[0m", label));
+            }
+            day += 7;
+        }
+        let mut rows = vec![(ruler, None)];
+
+        let mut dated: Vec<usize> = (0..self.tasks.len())
+            .filter(|&i| self.tasks[i].due.is_some())
+            .collect();
+        dated.sort_by_key(|&i| self.tasks[i].due);
+        for i in dated {
+            let task = &self.tasks[i];
+            let due = task.due.unwrap_or(today);
+            let length = task.estimate.unwrap_or(1.0).ceil().max(1.0) as i64;
+            let color = match task.state {
+                TaskState::Done => "\x1b[90m".to_string(),
+                _ => self.task_color(task),
+            };
+            let mut bar = String::new();
+            for day in start..start + days {
+                if day > due - length && day <= due {
+                    bar.push_str(&format!("{}█\x1b[0m", color));
+                } else if day == today {
+                    bar.push_str("\x1b[33m│\x1b[0m");
+                } else {
+                    bar.push(' ');
+                }
+            }
+            let name: String = task.name.chars().take(LABEL_COLS).collect();
+            rows.push((
+                format!("{:<width$} {}", name, bar, width = LABEL_COLS),
+                Some(i),
+            ));
+        }
+        rows
+    }
+
+    fn render_timeline(&mut self, rows: usize, cols: usize) {
+        let today = day_number(now_secs(), self.config.utc_offset_mins);
+        let title = format!(
+            "Timeline from {} (h/l: a week earlier/later)",
+            format_day(self.timeline_start(today))
+        );
+        let lines = self.timeline_rows(today, cols);
+        let empty = "No tasks have a due date (YYYY-MM-DD in a `due` field).";
+        if lines.len() == 1 {
+            self.render_list_view(&title, empty, &[], rows, cols);
+        } else {
+            self.render_list_view(&title, empty, &lines, rows, cols);
+        }
+    }
+
+    /// Tasks due in the next 7 (or 14) days by day, starting today; open
+    /// tasks that are overdue are listed first under today.
+    fn calendar_columns(&self, today: i64) -> Vec<(i64, Vec<usize>)> {
+        let days = if self.calendar_fortnight { 14 } else { 7 };
+        let mut columns: Vec<(i64, Vec<usize>)> =
+            (today..today + days).map(|day| (day, Vec::new())).collect();
+        let mut overdue = Vec::new();
+        for (i, task) in self.tasks.iter().enumerate() {
+            match task.due {
+                Some(due) if due < today && task.state != TaskState::Done => overdue.push(i),
+                Some(due) if due >= today && due < today + days => {
+                    columns[(due - today) as usize].1.push(i)
+                }
+                _ => {}
+            }
+        }
+        overdue.append(&mut columns[0].1);
+        columns[0].1 = overdue;
+        columns
+    }
+
+    fn render_calendar(&self, rows: usize, cols: usize) {
+        const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+        let today = day_number(now_secs(), self.config.utc_offset_mins);
+        let columns = self.calendar_columns(today);
+        self.emit(&format!(
+            "\x1b[1mDue in the next {} days\x1b[0m \x1b[90m({})\x1b[0m",
+            columns.len(),
+            "w: 7/14 days, Enter: go to task, Esc: back"
+        ));
+        let width = (cols / columns.len()).max(4);
+        let cell = |text: &str, style: &str| {
+            let text: String = text.chars().take(width - 1).collect();
+            format!("{}{:<w$}\x1b[0m ", style, text, w = width - 1)
+        };
+
+        let mut header = String::new();
+        for &(day, _) in &columns {
+            let (_, _, day_of_month) = civil_from_days(day);
+            let label = format!(
+                "{} {}",
+                WEEKDAYS[(day + 3).rem_euclid(7) as usize],
+                day_of_month
+            );
+            header.push_str(&cell(
+                &label,
+                if day == today { "\x1b[1;4m" } else { "\x1b[1m" },
+            ));
+        }
+        self.emit(&header);
+
+        let selected = self.view_task_rows().get(self.view_selected).copied();
+        let depth = columns
+            .iter()
+            .map(|(_, tasks)| tasks.len())
+            .max()
+            .unwrap_or(0);
+        if depth == 0 {
+            self.emit("\x1b[90mNothing due.\x1b[0m");
+        }
+        for row in 0..depth.min(rows.saturating_sub(3)) {
+            let mut line = String::new();
+            for (day, tasks) in &columns {
+                let Some(&i) = tasks.get(row) else {
+                    line.push_str(&" ".repeat(width));
+                    continue;
+                };
+                let task = &self.tasks[i];
+                let overdue = task.due.is_some_and(|due| due < *day);
+                let mut name = if overdue {
+                    format!("!{}", task.name)
+                } else {
+                    task.name.clone()
+                };
+                let mut style = if overdue {
+                    "\x1b[31m".to_string()
+                } else {
+                    self.task_color(task)
+                };
+                if Some(i) == selected {
+                    style.push_str("\x1b[7m");
+                    if self.config.plain {
+                        name.insert(0, '>');
+                    }
+                }
+                line.push_str(&cell(&name, &style));
+            }
+            self.emit(&line);
+        }
+    }
+
+    /// Visible tasks in the table view's order.
+    fn table_tasks(&self) -> Vec<usize> {
+        let mut tasks = self.visible.clone();
+        if let Some((column, descending)) = self.table_sort {
+            tasks.sort_by(|&a, &b| {
+                let order = column.compare(&self.tasks[a], &self.tasks[b]);
+                if descending {
+                    order.reverse()
+                } else {
+                    order
+                }
+            });
+        }
+        tasks
+    }
+
+    /// Sort the table by `column`: ascending first, then toggling direction.
+    fn cycle_table_sort(&mut self, column: TableColumn) {
+        self.table_sort = match self.table_sort {
+            Some((current, descending)) if current == column => Some((column, !descending)),
+            _ => Some((column, false)),
+        };
+        self.view_selected = 0;
+    }
+
+    fn table_task_width(&self) -> usize {
+        let longest = self
+            .visible
+            .iter()
+            .map(|&i| self.tasks[i].path.chars().count())
+            .max();
+        longest.unwrap_or(0).clamp(4, 40)
+    }
+
+    /// The table column under screen column `col` of the header row.
+    fn table_column_at(&self, col: usize) -> Option<TableColumn> {
+        let marker = self.config.plain || self.config.selection == SelectionStyle::Marker;
+        let mut x = if marker { 2 } else { 0 };
+        let task_width = self.table_task_width();
+        TableColumn::ALL.into_iter().find(|column| {
+            x += column.width(task_width) + 1;
+            col < x
+        })
+    }
+
+    fn render_table(&self, rows: usize, cols: usize) {
+        self.emit(&format!(
+            "\x1b[1mTable\x1b[0m \x1b[90m({})\x1b[0m",
+            "1-6 or click a header: sort, Enter: go to task, Esc: back"
+        ));
+        let task_width = self.table_task_width();
+        let cell = |text: &str, width: usize| {
+            let text: String = text.chars().take(width).collect();
+            format!("{:<w$} ", text, w = width)
+        };
+        let mut header = String::new();
+        for (n, column) in TableColumn::ALL.into_iter().enumerate() {
+            let arrow = match self.table_sort {
+                Some((sorted, false)) if sorted == column => "▲",
+                Some((sorted, true)) if sorted == column => "▼",
+                _ => "",
+            };
+            let title = format!("{}{}{}", n + 1, column.title(), arrow);
+            header.push_str(&cell(&title, column.width(task_width)));
+        }
+        self.emit_row(
+            &format!("\x1b[1;4m{}\x1b[0m", header.trim_end()),
+            cols,
+            false,
+        );
+
+        let now = now_secs();
+        let tasks = self.table_tasks();
+        let max_rows = rows.saturating_sub(3).max(1);
+        let offset = (self.view_selected + 1).saturating_sub(max_rows);
+        for (n, &i) in tasks.iter().enumerate().skip(offset).take(max_rows) {
+            let task = &self.tasks[i];
+            let values = [
+                task.path.clone(),
+                task.state.as_str().to_string(),
+                task.assigned_to.clone().unwrap_or_default(),
+                task.due.map(format_day).unwrap_or_default(),
+                task.estimate.map(|e| e.to_string()).unwrap_or_default(),
+                task.state_since
+                    .map(|since| format_age(now.saturating_sub(since)))
+                    .unwrap_or_default(),
+            ];
+            let mut line = self.task_color(task);
+            for (column, value) in TableColumn::ALL.into_iter().zip(values) {
+                line.push_str(&cell(&value, column.width(task_width)));
+            }
+            let line = format!("{}\x1b[0m", line.trim_end());
+            self.emit_row(&line, cols, n == self.view_selected);
+        }
+    }
+
+    fn render_diagnostics(&mut self, rows: usize, cols: usize) {
+        let lines = self.diagnostic_rows();
+        self.render_list_view("Diagnostics", "No problems found.", &lines, rows, cols);
+    }
+
+    fn render_changes(&mut self, rows: usize, cols: usize) {
+        let title = format!("Changes in the last {}m", self.config.changes_window_mins);
+        let lines = self.change_rows(now_secs());
+        self.render_list_view(&title, "No changes seen yet.", &lines, rows, cols);
+    }
+
+    /// Draw a list view whose selectable lines carry a task index; the
+    /// `view_selected`-th selectable line is highlighted.
+    fn render_list_view(
+        &self,
+        title: &str,
+        empty: &str,
+        lines: &[(String, Option<usize>)],
+        rows: usize,
+        cols: usize,
+    ) {
+        let enter = if self.view == View::Trash {
+            "restore"
+        } else {
+            "go to task"
+        };
+        self.emit(&format!(
+            "\x1b[1m{}\x1b[0m \x1b[90m(Enter: {}, Esc: back)\x1b[0m",
+            title, enter
+        ));
+        if lines.is_empty() {
+            self.emit(&format!("\x1b[90m{}\x1b[0m", empty));
+            return;
+        }
+        let selected_line = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, i))| i.is_some())
+            .nth(self.view_selected)
+            .map(|(n, _)| n);
+        let max_rows = rows.saturating_sub(2).max(1);
+        let offset = (selected_line.unwrap_or(0) + 1).saturating_sub(max_rows);
+        for (n, (line, _)) in lines.iter().enumerate().skip(offset).take(max_rows) {
+            self.emit_row(line, cols, Some(n) == selected_line);
+        }
+    }
+
+    /// Carry out a piped command, returning the reply for the sender.
+    fn handle_pipe_command(&mut self, command: PipeCommand) -> Result<String, String> {
+        match command {
+            PipeCommand::Status { yak_id, status } => {
+                let path = self
+                    .tasks
+                    .iter()
+                    .find(|t| t.yak_id == yak_id || t.path == yak_id)
+                    .map(|t| t.path.clone())
+                    .ok_or_else(|| format!("no such yak: {}", yak_id))?;
+                let status_value = Some(status.as_str()).filter(|status| !status.is_empty());
+                let written = self.store_field(&path, "agent-status", status_value);
+                written
+                    .map_err(|e| format!("failed to write agent-status for {}: {}", yak_id, e))?;
+                self.flashed.insert(path, FLASH_TICKS);
+                self.refresh_after_write();
+                Ok(format!("{}: {}", yak_id, status))
+            }
+        }
+    }
+
+    /// Deliver queued notifications through each event's configured backends.
+    fn dispatch_notifications(&mut self) {
+        for notification in std::mem::take(&mut self.notifications) {
+            for backend in self.config.notify_backends(notification.event) {
+                match backend {
+                    NotifyBackend::Toast => {
+                        let message = format!("{}: {}", notification.title, notification.body);
+                        if notification.event == NotifyEvent::Overdue {
+                            self.show_warning(message);
+                        } else {
+                            self.show_toast(message);
+                        }
+                    }
+                    NotifyBackend::Bell => self.pending_osc.push('\x07'),
+                    NotifyBackend::Osc777 | NotifyBackend::Osc9 => {
+                        if let Some(osc) = osc_notification(backend, &notification) {
+                            self.pending_osc.push_str(&osc);
+                        }
+                    }
+                    // Commands are off in someone else's pane
+                    NotifyBackend::Command if self.config.read_only => {}
+                    NotifyBackend::Command => {
+                        let args = notify_command_args(&self.config.notify_command, &notification);
+                        let command = &self.config.notify_command;
+                        self.log(format!("run: {} ({})", command, notification.title));
+                        run_in_background(&args, BTreeMap::new());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Age row flashes by one refresh tick.
+    fn tick_flashes(&mut self) {
+        self.flashed.retain(|_, ticks| {
+            *ticks = ticks.saturating_sub(1);
+            *ticks > 0
+        });
+    }
+
+    /// The selected task's ancestry (`infra ▸ api ▸ retries`), dropping the
+    /// outermost names when it is wider than `cols`.
+    fn breadcrumb(&self, cols: usize) -> Option<String> {
+        let task = self.selected_task()?;
+        let mut names: Vec<&str> = Vec::new();
+        let mut path = task.path.as_str();
+        loop {
+            let fallback = path.rsplit('/').next().unwrap_or(path);
+            let name = self
+                .tasks
+                .iter()
+                .find(|t| t.path == path)
+                .map_or(fallback, |t| self.display_name(t));
+            names.push(name);
+            match path.rsplit_once('/') {
+                Some((parent, _)) => path = parent,
+                None => break,
+            }
+        }
+        names.reverse();
+
+        let separator = if self.config.plain { " > " } else { " ▸ " };
+        let width = |names: &[&str]| {
+            names.iter().map(|n| n.chars().count()).sum::<usize>()
+                + separator.chars().count() * names.len().saturating_sub(1)
+        };
+        let mut start = 0;
+        while start + 1 < names.len() && width(&names[start..]) + 4 > cols {
+            start += 1;
+        }
+        let mut crumbs: Vec<&str> = names[start..].to_vec();
+        if start > 0 {
+            crumbs.insert(0, "…");
+        }
+        Some(crumbs.join(&format!("\x1b[90m{}\x1b[0m", separator)))
+    }
+
+    /// Header summarising the active view modes, if any are on.
+    fn header_line(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.mine_only {
+            let me = self.config.me.as_deref().unwrap_or_default();
+            let mine = self
+                .tasks
+                .iter()
+                .filter(|t| t.assigned_to.as_deref() == Some(me))
+                .count();
+            parts.push(format!(
+                "\x1b[1mMy tasks\x1b[0m {}{}\x1b[0m \x1b[90m({})\x1b[0m",
+                self.agent_color(me),
+                me,
+                mine
+            ));
+        }
+        if self.config.read_only {
+            parts.push("\x1b[1;35mRead-only\x1b[0m".to_string());
+        }
+        if self.paused {
+            parts.push("\x1b[1;33mPaused\x1b[0m \x1b[90m(P: resume)\x1b[0m".to_string());
+        }
+        if let Some((register, _)) = &self.recording {
+            parts.push(format!(
+                "\x1b[1;31mRecording {}\x1b[0m \x1b[90m(q: stop)\x1b[0m",
+                register
+            ));
+        }
+        if self.raw_names {
+            parts.push("\x1b[1mDirectory names\x1b[0m \x1b[90m(R: names)\x1b[0m".to_string());
+        }
+        if self.show_critical_path && !self.critical_path.is_empty() {
+            let total: f64 = self
+                .critical_path
+                .iter()
+                .map(|&i| self.tasks[i].estimate.unwrap_or(1.0))
+                .sum();
+            let last = &self.tasks[*self.critical_path.last().unwrap()];
+            parts.push(format!(
+                "\x1b[1;35mCritical path\x1b[0m {} tasks, {} to {}",
+                self.critical_path.len(),
+                total,
+                last.name
+            ));
+        }
+        let hidden = if self.show_snoozed {
+            0
+        } else {
+            self.snoozed.len()
+        };
+        if hidden > 0 {
+            parts.push(format!("\x1b[90m{} snoozed (zS: show)\x1b[0m", hidden));
+        }
+        if let Some(root) = &self.zoom {
+            let crumbs = root.split('/').collect::<Vec<_>>().join(" ▸ ");
+            parts.push(format!(
+                "\x1b[1mZoomed\x1b[0m {} \x1b[90m(zu: out)\x1b[0m",
+                crumbs
+            ));
+        }
+        if let Some(agent) = &self.following {
+            let target = match self.agent_wip_task(agent) {
+                Some(task) => task.name.clone(),
+                None => "idle".to_string(),
+            };
+            parts.push(format!(
+                "\x1b[1mFollowing\x1b[0m {}{}\x1b[0m → {}",
+                self.agent_color(agent),
+                agent,
+                target
+            ));
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join("  \x1b[90m│\x1b[0m  "))
+        }
+    }
+
+    /// Start a timestamped note for the selected task and open it for editing.
+    fn new_note(&mut self) {
+        if self.refuse_under_yx("notes") {
+            return;
+        }
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let now = now_secs();
+        let offset = self.config.utc_offset_mins;
+        let (date, clock) = (format_date(now, offset), format_clock(now, offset));
+        let stamp = format!("{}-{}", date, clock.replace(':', ""));
+        let title = format!("{} — {} {}", task.name, date, clock);
+        let path = task.path.clone();
+        match self.repository.create_note(&path, &stamp, &title) {
+            Ok(note) => {
+                self.refresh_after_write();
+                self.open_in_editor(&note);
+            }
+            Err(e) => {
+                self.log(format!("create note for {}: {}", path, e));
+                self.show_toast(format!("Could not create note: {}", e));
+            }
+        }
+    }
+
+    /// Open the selected task's `url` with the configured opener.
+    fn open_selected_task_url(&mut self) {
+        if self.refuse_when_read_only("the opener") {
+            return;
+        }
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let Some(url) = task.url.clone() else {
+            let message = format!("No url for {}", task.name);
+            self.show_toast(message);
+            return;
+        };
+        let mut args = self.config.opener.clone();
+        args.push(url.clone());
+        self.log(format!("run: {}", args.join(" ")));
+        run_in_background(&args, BTreeMap::new());
+        self.show_toast(format!("Opening {}", url));
+    }
+
+    /// Open `file_manager` in a floating pane rooted at the selected task's
+    /// directory.
+    fn open_task_dir_in_file_manager(&mut self) {
+        if self.refuse_when_read_only("the file manager") {
+            return;
+        }
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let Some(words) = &self.config.file_manager else {
+            self.show_toast("Set file_manager (e.g. yazi) in the plugin configuration".to_string());
+            return;
+        };
+        let dir = self.repository.yaks_dir().join(&task.path);
+        let host_dir = dir.strip_prefix("/host").unwrap_or(&dir).to_path_buf();
+        let command = file_manager_command(words, &host_dir);
+        self.log(format!(
+            "run: {} (in {})",
+            words.join(" "),
+            host_dir.display()
+        ));
+        open_command_pane_at(command, PanePlacement::Floating);
+    }
+
+    /// Switch the host repository to the selected task's branch, creating it
+    /// from `branch_template` the first time. The outcome arrives as a
+    /// `RunCommandResult` handled by `handle_command_result`.
+    fn switch_to_task_branch(&mut self) {
+        if self.refuse_when_read_only("branch switching") {
+            return;
+        }
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let (branch, mut args) = match &task.branch {
+            Some(branch) => (branch.clone(), vec!["git", "switch"]),
+            None => (
+                branch_name(&self.config.branch_template, task),
+                vec!["git", "switch", "-c"],
+            ),
+        };
+        let context = BTreeMap::from([
+            ("action".to_string(), "switch-branch".to_string()),
+            ("task".to_string(), task.path.clone()),
+            ("branch".to_string(), branch.clone()),
+        ]);
+        args.push(&branch);
+        self.log(format!("run: {}", args.join(" ")));
+        let args: Vec<String> = args.into_iter().map(String::from).collect();
+        run_in_background(&args, context);
+    }
+
+    /// Finish a command started by the plugin. Returns whether to re-render.
+    fn handle_command_result(
+        &mut self,
+        exit_code: Option<i32>,
+        stderr: &[u8],
+        context: &BTreeMap<String, String>,
+    ) -> bool {
+        if context.get("action").map(String::as_str) == Some("yx") {
+            return self.handle_yx_result(exit_code, stderr, context);
+        }
+        if context.get("action").map(String::as_str) != Some("switch-branch") {
+            return false;
+        }
+        let (Some(path), Some(branch)) = (context.get("task"), context.get("branch")) else {
+            return false;
+        };
+        if exit_code != Some(0) {
+            let stderr = String::from_utf8_lossy(stderr);
+            let reason = stderr
+                .lines()
+                .next()
+                .unwrap_or("unknown error")
+                .trim()
+                .to_string();
+            self.log(format!("git switch {} failed: {}", branch, reason));
+            self.show_toast(format!("git switch failed: {}", reason));
+            return true;
+        }
+        if let Err(e) = self.store_field(path, "branch", Some(branch)) {
+            self.log(format!("write branch of {}: {}", path, e));
+        }
+        self.refresh_after_write();
+        self.show_toast(format!("Switched to {}", branch));
+        true
+    }
+
+    /// Record the outcome of the `yx --version` probe, warning when
+    /// `backend=yx` can't use what was found.
+    fn record_yx_version(&mut self, exit_code: Option<i32>, stdout: &[u8]) -> bool {
+        self.yx = match exit_code {
+            Some(0) => {
+                let output = String::from_utf8_lossy(stdout);
+                YxProbe::Found(parse_yx_version(&output).unwrap_or_else(|| "unknown".to_string()))
+            }
+            _ => YxProbe::Missing,
+        };
+        self.log(self.yx_summary());
+        if let Some(reason) = self.yx_unavailable() {
+            self.show_warning(reason);
+        }
+        true
+    }
+
+    /// Why `backend=yx` writes are disabled, if they are.
+    fn yx_unavailable(&self) -> Option<String> {
+        if self.config.backend != WriteBackend::Yx {
+            return None;
+        }
+        match &self.yx {
+            YxProbe::Pending => None,
+            YxProbe::Missing => {
+                Some("yx not found on PATH: install it or set backend=files".to_string())
+            }
+            YxProbe::Found(version) => {
+                let minimum = self.config.yx_min_version.as_deref()?;
+                version_older_than(version, minimum).then(|| {
+                    format!(
+                        "yx {} is older than yx_min_version {}: upgrade it",
+                        version, minimum
+                    )
+                })
+            }
+        }
+    }
+
+    /// One line describing the probed `yx`.
+    fn yx_summary(&self) -> String {
+        match &self.yx {
+            YxProbe::Pending => "yx: checking…".to_string(),
+            YxProbe::Missing => "yx: not found".to_string(),
+            YxProbe::Found(version) => format!("yx: {}", version),
+        }
+    }
+
+    /// Read the clipboard to paste its lines as subtasks of the selected
+    /// task; `receive_paste` takes the output.
+    fn paste_from_clipboard(&mut self) {
+        if self.refuse_when_read_only("pasting tasks") {
+            return;
+        }
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let script = "pbpaste 2>/dev/null || xclip -o -selection clipboard 2>/dev/null \
+            || xsel --clipboard --output";
+        let args = match &self.config.paste_command {
+            Some(words) => words.clone(),
+            None => ["sh", "-c", script].map(str::to_string).to_vec(),
+        };
+        let context = BTreeMap::from([
+            ("action".to_string(), "paste".to_string()),
+            ("task".to_string(), task.path.clone()),
+        ]);
+        self.log(format!("run: {}", args.join(" ")));
+        run_in_background(&args, context);
+    }
+
+    /// Offer to create the pasted lines as subtasks: all of them, or one.
+    fn receive_paste(
+        &mut self,
+        exit_code: Option<i32>,
+        stdout: &[u8],
+        stderr: &[u8],
+        context: &BTreeMap<String, String>,
+    ) -> bool {
+        let Some(parent) = context.get("task") else {
+            return false;
+        };
+        if exit_code != Some(0) {
+            let stderr = String::from_utf8_lossy(stderr);
+            let reason = stderr
+                .lines()
+                .next()
+                .unwrap_or("no clipboard tool found")
+                .trim();
+            self.log(format!("paste failed: {}", reason));
+            self.show_toast(format!("Couldn't read the clipboard: {}", reason));
+            return true;
+        }
+        let parent = parent.clone();
+        self.offer_outline(&parent, &String::from_utf8_lossy(stdout), "Paste");
+        true
+    }
+
+    /// Offer to create the outline in `text` under `parent`: all of it, or
+    /// just one line.
+    fn offer_outline(&mut self, parent: &str, text: &str, verb: &str) {
+        let lines = outline_lines(text);
+        if lines.is_empty() {
+            self.show_toast(format!("Nothing to {}", verb.to_lowercase()));
+            return;
+        }
+        // Picker values: the parent, then one line per task, indented by tabs.
+        let value = |lines: &[(usize, String)]| {
+            let lines: Vec<String> = lines
+                .iter()
+                .map(|(depth, name)| "\t".repeat(*depth) + name)
+                .collect();
+            format!("{}\n{}", parent, lines.join("\n"))
+        };
+        let plural = if lines.len() == 1 { "" } else { "s" };
+        let mut items = vec![PickerItem {
+            label: format!("Create {} task{}", lines.len(), plural),
+            value: value(&lines),
+        }];
+        if lines.len() > 1 {
+            items.extend(lines.iter().map(|(depth, name)| PickerItem {
+                label: format!("  only {}{}", "  ".repeat(*depth), name),
+                value: value(&[(0, name.clone())]),
+            }));
+        }
+        self.mode = Mode::Picker(Picker {
+            title: format!("{} under {}", verb, parent),
+            items,
+            selected: 0,
+            purpose: PickerPurpose::Paste,
+        });
+    }
+
+    /// Offer to create the outline in a host file under the selected task;
+    /// relative paths start from the directory holding `.yaks`.
+    fn import_outline(&mut self, file: &str) {
+        let Some(parent) = self.selected_task().map(|t| t.path.clone()) else {
+            return;
+        };
+        let path = match self.repository.yaks_dir().parent() {
+            Some(root) => root.join(file),
+            None => PathBuf::from(file),
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => self.offer_outline(&parent, &text, "Import"),
+            Err(e) => self.show_toast(format!("Can't read {}: {}", file, e)),
+        }
+    }
+
+    /// Create tasks under `parent` from outline lines indented by tabs, each
+    /// under the nearest less indented line before it, and select the first.
+    fn create_tasks(&mut self, parent: &str, names: &[&str]) {
+        if self.refuse_under_yx("creating tasks") {
+            return;
+        }
+        let mut created = Vec::new();
+        // The task created at each depth so far, for the lines below it
+        let mut ancestors: Vec<String> = Vec::new();
+        for line in names {
+            let name = line.trim_start_matches('\t');
+            let depth = (line.len() - name.len()).min(ancestors.len());
+            ancestors.truncate(depth);
+            let under = ancestors.last().map_or(parent, String::as_str);
+            match self.repository.add_task(under, name) {
+                Ok(path) => {
+                    created.push(path.clone());
+                    ancestors.push(path);
+                }
+                Err(e) => self.log(format!("create {} under {}: {}", name, under, e)),
+            }
+        }
+        for path in &created {
+            self.log(format!("created {}", path));
+        }
+        self.collapsed.remove(parent);
+        self.refresh_tasks();
+        if let Some(row) = created.first().and_then(|path| self.row_of_path(path)) {
+            self.selected_index = row;
+        }
+        let failed = names.len() - created.len();
+        let plural = if created.len() == 1 { "" } else { "s" };
+        self.show_toast(match failed {
+            0 => format!("Created {} task{}", created.len(), plural),
+            failed => format!("Failed to create {} of {} tasks", failed, names.len()),
+        });
+    }
+
+    /// Report a failed `backend=yx` write and refresh to show its effect.
+    fn handle_yx_result(
+        &mut self,
+        exit_code: Option<i32>,
+        stderr: &[u8],
+        context: &BTreeMap<String, String>,
+    ) -> bool {
+        let command = context.get("command").map(String::as_str).unwrap_or("yx");
+        if exit_code != Some(0) {
+            let stderr = String::from_utf8_lossy(stderr);
+            let reason = stderr
+                .lines()
+                .next()
+                .unwrap_or("unknown error")
+                .trim()
+                .to_string();
+            self.log(format!("{} failed: {}", command, reason));
+            self.show_warning(format!("yx failed: {}", reason));
+        }
+        self.refresh_after_write();
+        true
+    }
+
+    /// Open the selected task's context.md in the viewer.
+    fn open_selected_task_context(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let context_path = self.repository.context_path(&task.path);
+        if context_path.exists() {
+            self.open_in_viewer(&context_path);
+        }
+    }
+
+    /// Open a file in the configured viewer, or $PAGER (or less), placed per
+    /// `pager_pane`.
+    fn open_in_viewer(&mut self, path: &std::path::Path) {
+        let host_path = path.strip_prefix("/host").unwrap_or(path);
+        let path_str = host_path.to_string_lossy().to_string();
+        let command = match &self.config.pager {
+            Some(template) => command_from_template(template, &path_str, 1),
+            None => CommandToRun {
+                path: PathBuf::from(std::env::var("PAGER").unwrap_or_else(|_| "less".to_string())),
+                args: vec![path_str],
+                cwd: None,
+            },
+        };
+        self.log(format!(
+            "run: {} {}",
+            command.path.display(),
+            command.args.join(" ")
+        ));
+        open_command_pane_at(command, self.config.pager_pane);
+    }
+
+    /// Open the selected task's context.md (created if missing) in the
+    /// configured editor, or Zellij's default editor.
+    fn edit_selected_task_context(&mut self) {
+        if self.refuse_when_read_only("editing") {
+            return;
+        }
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let context_path = self.repository.context_path(&task.path);
+        if let Some(parent) = context_path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        if !context_path.exists() {
+            let _ = std::fs::write(&context_path, "");
+        }
+        self.open_in_editor(&context_path);
+    }
+
+    fn copy_selected_yak_id(&mut self) {
+        if let Some(yak_id) = self.selected_task().map(|t| t.yak_id.clone()) {
+            copy_yak_name_to_clipboard(&yak_id);
+            self.log(format!("run: clipboard copy of {}", yak_id));
+            self.show_toast(format!("Copied: {}", yak_id));
+        }
+    }
+
+    /// Select the task under a click in the tree; a right-click then opens
+    /// the context menu for it.
+    /// Dragging a row (click, hold, release) onto another task moves it there.
+    fn handle_mouse(&mut self, mouse: Mouse) -> bool {
+        if let (View::Table, Mouse::LeftClick(1, col)) = (self.view, mouse) {
+            let Some(column) = self.table_column_at(col) else {
+                return false;
+            };
+            self.cycle_table_sort(column);
+            return true;
+        }
+        if self.view != View::Tree || self.mode != Mode::Normal {
+            self.drag = None;
+            return false;
+        }
+        let task_at = |line: isize| {
+            let row = usize::try_from(line).ok()?;
+            let task_index = (*self.row_tasks.get(row)?)?;
+            Some((task_index, self.tasks[task_index].path.clone()))
+        };
+        match mouse {
+            Mouse::LeftClick(line, _) | Mouse::RightClick(line, _) => {
+                let Some((task_index, path)) = task_at(line) else {
+                    return false;
+                };
+                self.jump_to_task_in_tree(task_index);
+                if matches!(mouse, Mouse::RightClick(..)) {
+                    self.open_context_menu();
+                } else {
+                    self.drag = Some(Drag { path, over: None });
+                }
+                true
+            }
+            Mouse::Hold(line, _) => {
+                let over = task_at(line).map(|(_, path)| path);
+                let Some(drag) = &mut self.drag else {
+                    return false;
+                };
+                let changed = drag.over != over;
+                drag.over = over;
+                changed
+            }
+            Mouse::Release(line, _) => {
+                let Some(drag) = self.drag.take() else {
+                    return false;
+                };
+                if let Some((_, target)) = task_at(line) {
+                    if target != drag.path {
+                        self.preview_move(&drag.path, &target);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether a dragged task can be dropped on `target`: not on itself, its
+    /// own subtree or its current parent.
+    fn can_drop(&self, path: &str, target: &str) -> bool {
+        let parent = path.rsplit_once('/').map_or("", |(parent, _)| parent);
+        !is_in_subtree(target, path) && target != parent
+    }
+
+    /// Marker for the row a dragged task is over: where it would land, or a
+    /// cross if it can't go there.
+    fn drop_marker(&self, task: &TaskLine) -> &'static str {
+        let Some(Drag {
+            path,
+            over: Some(over),
+        }) = &self.drag
+        else {
+            return "";
+        };
+        if *over != task.path || *over == *path {
+            ""
+        } else if self.can_drop(path, over) {
+            " \x1b[1;36m⇐ drop here\x1b[0m"
+        } else {
+            " \x1b[31m✗\x1b[0m"
+        }
+    }
+
+    /// List where a task and its subtasks would end up under `target`.
+    fn preview_move(&mut self, path: &str, target: &str) {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if !self.can_drop(path, target) {
+            self.show_toast(format!("Can't move {} under {}", name, target));
+            return;
+        }
+        let moved = free_child_path(target, name, |p| self.tasks.iter().any(|t| t.path == p));
+        let lines: Vec<String> = self
+            .tasks
+            .iter()
+            .filter(|t| is_in_subtree(&t.path, path))
+            .map(|t| {
+                let new = format!("{}{}", moved, &t.path[path.len()..]);
+                format!("\x1b[33m>\x1b[0m {} \x1b[90m→\x1b[0m {}", t.path, new)
+            })
+            .collect();
+        self.mode = Mode::Preview(Preview {
+            title: format!("Move {} under {}?", name, target),
+            lines,
+            scroll: 0,
+            change: PendingChange::Move {
+                path: path.to_string(),
+                target: target.to_string(),
+            },
+        });
+    }
+
+    /// Move a task (and its subtasks) under `target`.
+    fn move_task(&mut self, path: &str, target: &str) {
+        if self.refuse_under_yx("moving tasks") {
+            return;
+        }
+        let name = path.rsplit('/').next().unwrap_or(path).to_string();
+        if !self.can_drop(path, target) {
+            self.show_toast(format!("Can't move {} under {}", name, target));
+            return;
+        }
+        match self.repository.move_task(path, target) {
+            Ok(moved) => {
+                self.log(format!("moved {} to {}", path, moved));
+                self.collapsed.remove(target);
+                self.refresh_tasks();
+                if let Some(row) = self.row_of_path(&moved) {
+                    self.selected_index = row;
+                }
+                self.show_toast(format!("Moved {} under {}", name, target));
+            }
+            Err(e) => {
+                self.log(format!("move {} to {}: {}", path, target, e));
+                self.show_toast(format!("Failed to move {}: {}", name, e));
+            }
+        }
+    }
+
+    /// The right-click menu: the common actions on the selected task.
+    fn open_context_menu(&mut self) {
+        let Some(task) = self.selected_task() else {
+            return;
+        };
+        let entries = [
+            ("Open context", "open"),
+            ("Edit context", "edit"),
+            ("Mark done", "done"),
+            ("Assign…", "assign"),
+            ("Copy id", "copy"),
+            ("Delete…", "delete"),
+        ];
+        self.mode = Mode::Picker(Picker {
+            title: task.name.clone(),
+            items: entries
+                .iter()
+                .map(|(label, value)| PickerItem {
+                    label: label.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            selected: 0,
+            purpose: PickerPurpose::ContextMenu,
+        });
+    }
+
+    /// List the target tasks and their subtasks before deleting them.
+    fn confirm_delete(&mut self) {
+        let targets = self.target_paths();
+        // Tagged tasks inside another target's subtree go with it
+        let roots: Vec<String> = targets
+            .iter()
+            .filter(|path| !targets.iter().any(|t| t != *path && is_in_subtree(path, t)))
+            .cloned()
+            .collect();
+        if roots.is_empty() {
+            return;
+        }
+        let lines: Vec<String> = self
+            .tasks
+            .iter()
+            .filter(|task| roots.iter().any(|root| is_in_subtree(&task.path, root)))
+            .map(|task| format!("\x1b[31m-\x1b[0m {}", task.path))
+            .collect();
+        let plural = if lines.len() == 1 { "" } else { "s" };
+        self.mode = Mode::Preview(Preview {
+            title: format!("Delete {} task{}?", lines.len(), plural),
+            lines,
+            scroll: 0,
+            change: PendingChange::Delete(roots),
+        });
+    }
+
+    /// Remove a task's directory, subtasks and all, or with `backend=yx`
+    /// run `yx.rm`.
+    fn remove_task(&mut self, path: &str) -> std::io::Result<()> {
+        if self.config.read_only {
+            return Err(read_only_error());
+        }
+        if self.config.backend == WriteBackend::Files {
+            return self.repository.remove_task(path);
+        }
+        if let Some(reason) = self.yx_unavailable() {
+            return Err(std::io::Error::other(reason));
+        }
+        let yak_id = self
+            .tasks
+            .iter()
+            .find(|t| t.path == path)
+            .map_or(path, |t| t.yak_id.as_str());
+        let args: Vec<String> = self
+            .config
+            .yx_template("rm")
+            .unwrap_or_default()
+            .iter()
+            .map(|word| word.replace("{id}", yak_id))
+            .collect();
+        if args.is_empty() {
+            return Err(std::io::Error::other("no yx command for rm"));
+        }
+        let context = BTreeMap::from([
+            ("action".to_string(), "yx".to_string()),
+            ("task".to_string(), path.to_string()),
+            ("command".to_string(), args.join(" ")),
+        ]);
+        self.log(format!("run: {}", args.join(" ")));
+        run_in_background(&args, context);
+        Ok(())
+    }
+
+    /// Rows of the trash view: when and from where each task was deleted.
+    fn trash_rows(&self) -> Vec<(String, Option<usize>)> {
+        self.trash
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                (
+                    format!("\x1b[90m{}\x1b[0m  {}", entry.stamp, entry.path),
+                    Some(i),
+                )
+            })
+            .collect()
+    }
+
+    /// Put the task selected in the trash view back in the tree.
+    fn restore_selected_trash(&mut self) {
+        let Some(entry) = self.trash.get(self.view_selected).cloned() else {
+            return;
+        };
+        match self.repository.restore_from_trash(&entry) {
+            Ok(path) => {
+                self.log(format!("restored {} to {}", entry.path, path));
+                self.trash = self.repository.trash_entries();
+                self.view_selected = self.view_selected.min(self.trash.len().saturating_sub(1));
+                self.refresh_after_write();
+                self.show_toast(format!("Restored {}", path));
+            }
+            Err(e) => {
+                self.log(format!("restore {}: {}", entry.path, e));
+                self.show_toast(format!("Failed to restore {}: {}", entry.path, e));
+            }
+        }
+    }
+
+    /// Permanently remove tasks deleted more than `trash_days` ago.
+    fn empty_old_trash(&mut self) {
+        if self.config.read_only || self.config.trash_days == 0 {
+            return;
+        }
+        let today = day_number(now_secs(), 0);
+        match self
+            .repository
+            .empty_trash(today - self.config.trash_days as i64)
+        {
+            Ok(0) => {}
+            Ok(emptied) => self.log(format!("emptied {} old deletions from the trash", emptied)),
+            Err(e) => self.log(format!("empty trash: {}", e)),
+        }
+    }
+
+    /// Delete tasks and their subtasks, then refresh once.
+    fn delete_tasks(&mut self, paths: &[String]) {
+        let mut failure = None;
+        let mut failed = 0;
+        for path in paths {
+            match self.remove_task(path) {
+                Ok(()) => self.log(format!("deleted {}", path)),
+                Err(e) => {
+                    self.log(format!("delete {}: {}", path, e));
+                    failure.get_or_insert(e);
+                    failed += 1;
+                }
+            }
+        }
+        self.tagged
+            .retain(|tag| !paths.iter().any(|path| is_in_subtree(tag, path)));
+        self.refresh_after_write();
+        let name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+        self.show_toast(match (paths, failure) {
+            ([path], None) => format!("Deleted {}", name(path)),
+            ([path], Some(e)) => format!("Failed to delete {}: {}", name(path), e),
+            (_, None) => format!("Deleted {} tasks", paths.len()),
+            (_, Some(e)) => format!(
+                "Failed to delete {} of {} tasks: {}",
+                failed,
+                paths.len(),
+                e
+            ),
+        });
+    }
+
+    /// Open a file in the configured editor, or Zellij's default editor.
+    fn open_in_editor(&mut self, path: &std::path::Path) {
+        if self.refuse_when_read_only("editing") {
+            return;
+        }
+        let host_path = path.strip_prefix("/host").unwrap_or(path).to_path_buf();
+        let placement = if self.config.editor_tiled {
+            PanePlacement::Tiled
+        } else {
+            PanePlacement::Floating
+        };
+        let Some(template) = &self.config.editor else {
+            self.log(format!("open: {}", host_path.display()));
+            open_file_at(FileToOpen::new(host_path), placement);
+            return;
+        };
+        let last_line = std::fs::read_to_string(path)
+            .map(|contents| contents.lines().count().max(1))
+            .unwrap_or(1);
+        let command = command_from_template(template, &host_path.to_string_lossy(), last_line);
+        self.log(format!(
+            "run: {} {}",
+            command.path.display(),
+            command.args.join(" ")
+        ));
+        open_command_pane_at(command, placement);
+    }
+
+    fn handle_key(&mut self, key: KeyWithModifier) -> bool {
+        if self.toast_ticks_remaining == 0 {
+            self.toast_message = None;
+        }
+        if let Some((_, keys)) = &mut self.recording {
+            if !self.replaying {
+                keys.push(key.clone());
+            }
+        }
+        match self.mode {
+            Mode::JumpLabel { .. } => return self.handle_jump_label_key(&key),
+            Mode::Filter { .. } => return self.handle_filter_key(&key),
+            Mode::Picker(_) => return self.handle_picker_key(&key),
+            Mode::Prompt(_) => return self.handle_prompt_key(&key),
+            Mode::Preview(_) => return self.handle_preview_key(&key),
+            Mode::TypeAhead { .. } => return self.handle_type_ahead_key(&key),
+            Mode::Normal => {}
+        }
+
+        if self.view != View::Tree {
+            return self.handle_view_key(&key);
+        }
+
+        if let Some(prefix) = self.pending_prefix.take() {
+            return self.handle_sequence(prefix, &key);
+        }
+
+        if key.has_no_modifiers() {
+            if let BareKey::Char(c) = key.bare_key {
+                if let Some(digit) = c.to_digit(10) {
+                    if self.push_count_digit(digit) {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        match key.bare_key {
+            BareKey::Up | BareKey::Char('k') if key.has_no_modifiers() => {
+                let count = self.take_count();
+                self.move_selection(-(count as isize));
+                true
+            }
+            BareKey::Down | BareKey::Char('j') if key.has_no_modifiers() => {
+                let count = self.take_count();
+                self.move_selection(count as isize);
+                true
+            }
+            BareKey::Char('q') if key.has_no_modifiers() && self.recording.is_some() => {
+                self.pending_count = None;
+                self.stop_recording();
+                true
+            }
+            BareKey::Char('g')
+            | BareKey::Char('z')
+            | BareKey::Char('m')
+            | BareKey::Char('\'')
+            | BareKey::Char('q')
+                if key.has_no_modifiers() =>
+            {
+                if let BareKey::Char(c) = key.bare_key {
+                    self.pending_prefix = Some(c);
+                }
+                true
+            }
+            BareKey::Char('p') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.select_parent();
+                true
+            }
+            BareKey::Char('{') | BareKey::Char('}') if plain_char(&key).is_some() => {
+                let count = self.take_count() as isize;
+                let delta = if key.bare_key == BareKey::Char('{') {
+                    -count
+                } else {
+                    count
+                };
+                self.select_sibling(delta);
+                true
+            }
+            BareKey::PageDown | BareKey::PageUp if key.has_no_modifiers() => {
+                let rows = (self.take_count() * self.page_size()) as isize;
+                self.scroll_by(if key.bare_key == BareKey::PageUp {
+                    -rows
+                } else {
+                    rows
+                });
+                true
+            }
+            BareKey::Tab if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.switch_tab(1);
+                true
+            }
+            BareKey::Tab if key.has_modifiers(&[KeyModifier::Shift]) => {
+                self.pending_count = None;
+                self.switch_tab(-1);
+                true
+            }
+            BareKey::Char('v') if key.has_modifiers(&[KeyModifier::Ctrl]) => {
+                self.pending_count = None;
+                self.paste_from_clipboard();
+                true
+            }
+            BareKey::Char('l') if key.has_modifiers(&[KeyModifier::Ctrl]) => {
+                self.pending_count = None;
+                self.open_view(View::Log);
+                true
+            }
+            BareKey::Char('d') | BareKey::Char('u') if key.has_modifiers(&[KeyModifier::Ctrl]) => {
+                let rows = (self.take_count() * self.half_page_size()) as isize;
+                self.scroll_by(if key.bare_key == BareKey::Char('u') {
+                    -rows
+                } else {
+                    rows
+                });
+                true
+            }
+            BareKey::Home if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.jump_to(0);
+                true
+            }
+            BareKey::End if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.jump_to(self.visible.len().saturating_sub(1));
+                true
+            }
+            BareKey::Right if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.select_first_child();
+                true
+            }
+            BareKey::Char('f') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.mode = Mode::JumpLabel {
+                    typed: String::new(),
+                    numbered: false,
+                };
+                true
+            }
+            BareKey::Char('"') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                if !self.visible.is_empty() {
+                    self.mode = Mode::TypeAhead {
+                        typed: String::new(),
+                        from: self.selected_index,
+                    };
+                }
+                true
+            }
+            BareKey::Char('>') | BareKey::Char('<') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.repeat_type_ahead(key.bare_key == BareKey::Char('>'));
+                true
+            }
+            BareKey::Char('#') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.mode = Mode::JumpLabel {
+                    typed: String::new(),
+                    numbered: true,
+                };
+                true
+            }
+            BareKey::Char('G') if plain_char(&key).is_some() => {
+                let index = match self.pending_count.take() {
+                    Some(n) => n.saturating_sub(1),
+                    None => self.visible.len().saturating_sub(1),
+                };
+                self.jump_to(index);
+                true
+            }
+            BareKey::Char(' ') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.toggle_tag();
+                true
+            }
+            BareKey::Char('Q') if plain_char(&key).is_some() => {
+                self.pending_prefix = Some('Q');
+                true
+            }
+            BareKey::Char('@') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_assign_picker();
+                true
+            }
+            BareKey::Char('A') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_view(View::AgentQueues);
+                true
+            }
+            BareKey::Char('T') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.timeline_shift = 0;
+                self.open_view(View::Timeline);
+                true
+            }
+            BareKey::Char('W') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_view(View::Calendar);
+                true
+            }
+            BareKey::Char('D') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_view(View::Changes);
+                true
+            }
+            BareKey::Char('!') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_view(View::Diagnostics);
+                true
+            }
+            BareKey::Char('I') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_view(View::Stats);
+                true
+            }
+            BareKey::Char('P') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.toggle_pause();
+                true
+            }
+            BareKey::Char('n') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.new_note();
+                true
+            }
+            BareKey::Char('t') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.toggle_pinned();
+                true
+            }
+            BareKey::Char('.') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.open_action_picker();
+                true
+            }
+            BareKey::Char('o') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.open_files_picker();
+                true
+            }
+            BareKey::Char('v') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.show_preview = !self.show_preview;
+                true
+            }
+            BareKey::Char(c @ ('+' | '=' | '-')) if key.has_no_modifiers() && self.show_preview => {
+                let delta = self.take_count() as isize;
+                self.resize_preview(if c == '-' { -delta } else { delta });
+                true
+            }
+            BareKey::Char('B') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.switch_to_task_branch();
+                true
+            }
+            BareKey::Char('H') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.show_critical_path = !self.show_critical_path;
+                true
+            }
+            BareKey::Char('V') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.show_status_line = !self.show_status_line;
+                true
+            }
+            BareKey::Char('U') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_selected_task_url();
+                true
+            }
+            BareKey::Char('K') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_view(View::Table);
+                true
+            }
+            BareKey::Char('O') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_task_dir_in_file_manager();
+                true
+            }
+            BareKey::Char('R') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.raw_names = !self.raw_names;
+                true
+            }
+            BareKey::Char('i') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.open_view(View::Fields);
+                true
+            }
+            BareKey::Char('E') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.open_field_picker();
+                true
+            }
+            BareKey::Char(':') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.mode = Mode::Prompt(Prompt {
+                    title: ":".to_string(),
+                    input: String::new(),
+                    purpose: PromptPurpose::Command,
+                    recall: Recall::default(),
+                });
+                true
+            }
+            BareKey::Char('c') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.start_capture();
+                true
+            }
+            BareKey::Char('s') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.open_state_picker();
+                true
+            }
+            BareKey::Char(',') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.repeat_last_change();
+                true
+            }
+            BareKey::Char('u') if key.has_no_modifiers() => {
+                self.pending_count = None;
+                self.undo_last();
+                true
+            }
+            BareKey::Char('L') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.show_feed = !self.show_feed;
+                true
+            }
+            BareKey::Char('F') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.toggle_follow();
+                true
+            }
+            BareKey::Char('M') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.toggle_mine_only();
+                true
+            }
+            BareKey::Char('S') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.toggle_sort_done_last();
+                true
+            }
+            BareKey::Char('/') if plain_char(&key).is_some() => {
+                self.pending_count = None;
+                self.start_filter_input();
+                true
+            }
+            BareKey::Esc if self.pending_count.is_some() => {
+                self.pending_count = None;
+                true
+            }
+            BareKey::Esc if self.filter.is_some() => {
+                self.apply_filter(None);
+                true
+            }
+            _ => {
+                self.pending_count = None;
+                self.handle_action_key(&key)
+            }
+        }
+    }
+
+    fn handle_action_key(&mut self, key: &KeyWithModifier) -> bool {
+        match key.bare_key {
+            BareKey::Char('r') if key.has_no_modifiers() => {
+                self.refresh_tasks();
+                true
+            }
+            BareKey::Char('e') if key.has_no_modifiers() => {
+                self.edit_selected_task_context();
+                true
+            }
+            BareKey::Char('y') if key.has_no_modifiers() => {
+                self.copy_selected_yak_id();
+                true
+            }
+            BareKey::Char('Y') if plain_char(key).is_some() => {
+                if let Some(task) = self.selected_task() {
+                    let branch = branch_name(&self.config.branch_template, task);
+                    copy_yak_name_to_clipboard(&branch);
+                    self.log(format!("run: clipboard copy of {}", branch));
+                    self.show_toast(format!("Copied: {}", branch));
+                }
+                true
+            }
+            BareKey::Enter if key.has_no_modifiers() => {
+                self.open_selected_task_context();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl ZellijPlugin for State {
+    fn load(&mut self, configuration: BTreeMap<String, String>) {
+        subscribe(&[
+            EventType::Timer,
+            EventType::Key,
+            EventType::Mouse,
+            EventType::RunCommandResult,
+            EventType::PermissionRequestResult,
+            EventType::WebRequestResult,
+        ]);
+        let yaks_dir = PathBuf::from("/host/.yaks");
+        let project_repository = TaskRepository::new(yaks_dir.clone());
+        let project = project_repository.load_config_file();
+        self.config_file_modified = project_repository.config_modified();
+        self.layout_configuration = configuration.clone();
+        self.apply_configuration(layer_config(
+            project.clone().unwrap_or_default(),
+            configuration,
+        ));
+        request_permission(&self.requested_permissions());
+        set_timeout(self.config.refresh_secs);
+        self.following = self.config.follow.clone();
+        self.show_critical_path = self.config.critical_path;
+        self.show_status_line = self.config.status_line;
+        self.host_yaks_dir = Some(get_plugin_ids().initial_cwd.join(".yaks"));
+        let probe = ["yx".to_string(), "--version".to_string()];
+        let context = BTreeMap::from([("action".to_string(), "yx-version".to_string())]);
+        run_in_background(&probe, context);
+        if let Err(e) = project {
+            self.show_warning(e);
+        }
+
+        if let Some(url) = &self.config.task_url {
+            let store = Box::new(HttpStore::new(url.clone()));
+            self.repository = TaskRepository::with_store(yaks_dir, store);
+        } else if let Some(file) = &self.config.task_file {
+            let store = Box::new(TaskFile::new(PathBuf::from("/host").join(file)));
+            self.repository = TaskRepository::with_store(yaks_dir, store);
+        } else if !yaks_dir.exists() {
+            self.error = Some(format!(
+                "Yaks directory not found: {}\nRun `yx add <name>` to create a task.",
+                yaks_dir.display()
+            ));
+            return;
+        } else if self.config.index_cache {
+            let store = Box::new(DirectoryStore::indexed(yaks_dir.clone()));
+            self.repository = TaskRepository::with_store(yaks_dir, store);
+        } else {
+            self.repository = TaskRepository::new(yaks_dir);
+        }
+        self.repository.set_read_only(self.config.read_only);
+        self.empty_old_trash();
+        match self.repository.cached_snapshot() {
+            // Show the last session's tree now and read the real one next
+            Some(tasks) => {
+                self.set_tasks(tasks);
+                self.snapshot = None;
+                self.refresh_pending = true;
+                start_write_debounce_timer();
+            }
+            None => self.refresh_tasks(),
+        }
+        let ui_state = self.repository.load_ui_state();
+        self.restore_ui_state(ui_state);
+    }
+
+    fn update(&mut self, event: Event) -> bool {
+        let should_render = match event {
+            // Only the write debounce timer is shorter than any refresh interval.
+            Event::Timer(secs) if secs < MIN_REFRESH_SECS => {
+                self.flush_pending_refresh();
+                true
+            }
+            Event::Timer(_) => {
+                set_timeout(self.config.refresh_secs);
+                if !self.paused {
+                    self.refresh_tasks();
+                }
+                self.dispatch_notifications();
+                self.save_ui_state_if_changed();
+                self.tick_flashes();
+                if self.toast_ticks_remaining > 0 {
+                    self.toast_ticks_remaining -= 1;
+                    if self.toast_ticks_remaining == 0 {
+                        self.toast_message = None;
+                    }
+                }
+                true
+            }
+            Event::Key(key) => self.handle_key(key),
+            Event::Mouse(mouse) => self.handle_mouse(mouse),
+            Event::PermissionRequestResult(status) => {
+                self.permissions = Some(status);
+                if status == PermissionStatus::Granted {
+                    self.repository.fetch();
+                }
+                self.view == View::About
+            }
+            Event::WebRequestResult(status, _headers, body, context) => {
+                match context.get("action").map(String::as_str) {
+                    Some("task-snapshot") => self.receive_task_snapshot(status, &body),
+                    _ => false,
+                }
+            }
+            Event::RunCommandResult(exit_code, stdout, stderr, context) => {
+                match context.get("action").map(String::as_str) {
+                    Some("yx-version") => self.record_yx_version(exit_code, &stdout),
+                    Some("paste") => self.receive_paste(exit_code, &stdout, &stderr, &context),
+                    _ => self.handle_command_result(exit_code, &stderr, &context),
+                }
+            }
+            _ => false,
+        };
+        if std::mem::take(&mut self.debounce_timer_needed) {
+            start_write_debounce_timer();
+        }
+        should_render
+    }
+
+    fn pipe(&mut self, pipe_message: PipeMessage) -> bool {
+        if pipe_message.name != PIPE_NAME {
+            return false;
+        }
+        let Some(payload) = pipe_message.payload else {
+            return false;
+        };
+        let reply =
+            parse_pipe_command(&payload).and_then(|command| self.handle_pipe_command(command));
+        self.log(match &reply {
+            Ok(_) => format!("pipe: {}", payload),
+            Err(error) => format!("pipe: {} failed: {}", payload, error),
+        });
+        if let PipeSource::Cli(pipe_id) = &pipe_message.source {
+            let output = match &reply {
+                Ok(message) => format!("{}\n", message),
+                Err(error) => format!("error: {}\n", error),
+            };
+            reply_to_cli_pipe(pipe_id, &output);
+        }
+        if let Err(error) = reply {
+            self.show_toast(format!("Pipe: {}", error));
+        }
+        if std::mem::take(&mut self.debounce_timer_needed) {
+            start_write_debounce_timer();
+        }
+        true
+    }
+
+    fn render(&mut self, rows: usize, cols: usize) {
+        if !self.pending_osc.is_empty() {
+            print!("{}", std::mem::take(&mut self.pending_osc));
+        }
+
+        if let Some(error) = &self.error {
+            self.emit(&format!("\x1b[31mError: {}\x1b[0m", error));
+            return;
+        }
+
+        if self.tasks.is_empty() {
+            println!("No tasks. Run `yx add <name>` to create one.");
+            println!("(Refresh interval: {}s)", self.config.refresh_secs);
+            return;
+        }
+
+        if rows <= STATUS_BAR_MAX_ROWS && self.view == View::Tree {
+            self.viewport_rows = 1;
+            self.emit(&fit_ansi(&self.status_bar_line(), cols));
+            return;
+        }
+
+        let toast_rows = if self.toast_message.is_some() { 2 } else { 0 };
+        if let Mode::Picker(picker) = &self.mode {
+            self.render_picker(picker, rows.saturating_sub(toast_rows), cols);
+        } else if let Mode::Preview(preview) = &self.mode {
+            self.render_preview(preview, rows.saturating_sub(toast_rows), cols);
+        } else {
+            match self.view {
+                View::Tree => self.render_tree(rows.saturating_sub(toast_rows), cols),
+                View::AgentQueues => {
+                    self.render_agent_queues(rows.saturating_sub(toast_rows), cols)
+                }
+                View::Changes => self.render_changes(rows.saturating_sub(toast_rows), cols),
+                View::Diagnostics => self.render_diagnostics(rows.saturating_sub(toast_rows), cols),
+                View::Fields => {
+                    let title = match self.selected_task() {
+                        Some(task) => format!("Fields of {}", task.path),
+                        None => "Fields".to_string(),
+                    };
+                    let lines = self.field_rows(now_secs());
+                    let rows = rows.saturating_sub(toast_rows);
+                    self.render_list_view(&title, "No field files.", &lines, rows, cols);
+                }
+                View::Stats => {
+                    let lines = self.stats_rows(now_secs());
+                    let rows = rows.saturating_sub(toast_rows);
+                    self.render_list_view("Stats", "No tasks.", &lines, rows, cols);
+                }
+                View::About => {
+                    let lines = self.about_rows();
+                    let rows = rows.saturating_sub(toast_rows);
+                    self.render_list_view("About", "", &lines, rows, cols);
+                }
+                View::Trash => {
+                    let lines = self.trash_rows();
+                    let rows = rows.saturating_sub(toast_rows);
+                    self.render_list_view("Trash", "The trash is empty.", &lines, rows, cols);
+                }
+                View::Timeline => self.render_timeline(rows.saturating_sub(toast_rows), cols),
+                View::Calendar => self.render_calendar(rows.saturating_sub(toast_rows), cols),
+                View::Table => self.render_table(rows.saturating_sub(toast_rows), cols),
+                View::Log => {
+                    let lines = self.log_rows();
+                    let rows = rows.saturating_sub(toast_rows);
+                    self.render_list_view("Debug log", "Nothing logged yet.", &lines, rows, cols);
+                }
+            }
+        }
+
+        if let Some(msg) = &self.toast_message.clone() {
+            println!();
+            let toast = format!(" {} ", msg);
+            let warning = if self.toast_ticks_remaining == 0 {
+                "\x1b[33m"
+            } else {
+                ""
+            };
+            self.emit(&format!("{}\x1b[7m\x1b[1m{}\x1b[0m", warning, toast));
+        }
+    }
+}
+
+register_plugin!(State);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::TempDir;
+
+    fn mock_yaks() -> (TempDir, PathBuf) {
+        let temp = TempDir::new().unwrap();
+        let yaks = temp.path().join(".yaks");
+        fs::create_dir_all(&yaks).unwrap();
+        (temp, yaks)
+    }
+
+    fn create_task(yaks: &Path, path: &str) {
+        fs::create_dir_all(yaks.join(path)).unwrap();
+    }
+
+    fn set_field(yaks: &Path, task_path: &str, field: &str, value: &str) {
+        fs::write(yaks.join(task_path).join(field), value).unwrap();
+    }
+
+    fn snapshot_task(repo: &TaskRepository, path: &str) -> TaskLine {
+        let tasks = repo.load_snapshot().unwrap();
+        tasks.into_iter().find(|task| task.path == path).unwrap()
+    }
+
+    fn snapshot_paths(repo: &TaskRepository) -> Vec<(String, usize)> {
+        let tasks = repo.load_snapshot().unwrap();
+        tasks
+            .into_iter()
+            .map(|task| (task.path, task.depth))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_uses_name_file_when_present() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-hyphenated-slug");
+        set_field(&yaks, "my-hyphenated-slug", "name", "my hyphenated slug");
+
+        let repo = TaskRepository::new(yaks);
+        let task = snapshot_task(&repo, "my-hyphenated-slug");
+
+        assert_eq!(task.name, "my hyphenated slug");
+    }
+
+    #[test]
+    fn snapshot_falls_back_to_slug_when_name_file_absent() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-hyphenated-slug");
+
+        let repo = TaskRepository::new(yaks);
+        let task = snapshot_task(&repo, "my-hyphenated-slug");
+
+        assert_eq!(task.name, "my-hyphenated-slug");
+    }
+
+    #[test]
+    fn snapshot_returns_empty_for_empty_directory() {
+        let (_temp, yaks) = mock_yaks();
+        let repo = TaskRepository::new(yaks);
+        let tasks = snapshot_paths(&repo);
+        assert!(tasks.is_empty());
+    }
+
+    #[test]
+    fn snapshot_finds_root_level_task() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+
+        let repo = TaskRepository::new(yaks);
+        let tasks = snapshot_paths(&repo);
+
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0], ("my-task".to_string(), 0));
+    }
+
+    #[test]
+    fn snapshot_finds_nested_task() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "parent/child/grandchild");
+
+        let repo = TaskRepository::new(yaks);
+        let tasks = snapshot_paths(&repo);
+
+        // Should find all three levels (parent, child, grandchild)
+        assert_eq!(tasks.len(), 3);
+        let paths: Vec<_> = tasks.iter().map(|(p, _)| p.as_str()).collect();
+        assert!(paths.contains(&"parent"));
+        assert!(paths.contains(&"parent/child"));
+        assert!(paths.contains(&"parent/child/grandchild"));
+    }
+
+    #[test]
+    fn snapshot_finds_multiple_tasks_at_different_depths() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "task-a");
+        create_task(&yaks, "parent/task-b");
+        create_task(&yaks, "parent/child/task-c");
+
+        let repo = TaskRepository::new(yaks);
+        let tasks = snapshot_paths(&repo);
+
+        // Should find all 5 tasks (task-a, parent, parent/task-b, parent/child, parent/child/task-c)
+        assert_eq!(tasks.len(), 5);
+        let paths: Vec<_> = tasks.iter().map(|(p, _)| p.as_str()).collect();
+        assert!(paths.contains(&"task-a"));
+        assert!(paths.contains(&"parent"));
+        assert!(paths.contains(&"parent/task-b"));
+        assert!(paths.contains(&"parent/child"));
+        assert!(paths.contains(&"parent/child/task-c"));
+    }
+
+    #[test]
+    fn get_field_returns_none_for_missing_field() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+
+        let repo = TaskRepository::new(yaks);
+        assert!(repo.get_field("my-task", "state").is_none());
+    }
+
+    #[test]
+    fn get_field_returns_value_for_present_field() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+        set_field(&yaks, "my-task", "state", "wip");
+
+        let repo = TaskRepository::new(yaks);
+        assert_eq!(repo.get_field("my-task", "state"), Some("wip".to_string()));
+    }
+
+    #[test]
+    fn get_field_trims_whitespace() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+        set_field(&yaks, "my-task", "assigned-to", "  alice  \n");
+
+        let repo = TaskRepository::new(yaks);
+        assert_eq!(
+            repo.get_field("my-task", "assigned-to"),
+            Some("alice".to_string())
+        );
+    }
+
+    #[test]
+    fn snapshot_assembles_all_fields() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+        set_field(&yaks, "my-task", "state", "wip");
+        set_field(&yaks, "my-task", "assigned-to", "bob");
+        set_field(&yaks, "my-task", "agent-status", "wip: implementing");
+
+        let repo = TaskRepository::new(yaks);
+        let task = snapshot_task(&repo, "my-task");
+
+        assert_eq!(task.name, "my-task");
+        assert_eq!(task.depth, 0);
+        assert_eq!(task.state, TaskState::Wip);
+        assert_eq!(task.assigned_to, Some("bob".to_string()));
+        assert_eq!(task.agent_status, Some("wip: implementing".to_string()));
+    }
+
+    #[test]
+    fn snapshot_defaults_to_todo_when_no_state() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+
+        let repo = TaskRepository::new(yaks);
+        let task = snapshot_task(&repo, "my-task");
+
+        assert_eq!(task.state, TaskState::Todo);
+    }
+
+    #[test]
+    fn task_color_red_for_blocked() {
+        let state = State::default();
+        let task = TaskLine {
+            agent_status: Some("blocked: waiting".to_string()),
+            ..TaskLine::default()
+        };
+        assert_eq!(state.task_color(&task), "\x1b[31m");
+    }
+
+    #[test]
+    fn task_color_green_for_done() {
+        let state = State::default();
+        let task = TaskLine {
+            agent_status: Some("done: finished".to_string()),
+            ..TaskLine::default()
+        };
+        assert_eq!(state.task_color(&task), "\x1b[32m");
+    }
+
+    #[test]
+    fn task_color_yellow_for_wip() {
+        let state = State::default();
+        let task = TaskLine {
+            agent_status: Some("wip: working".to_string()),
+            ..TaskLine::default()
+        };
+        assert_eq!(state.task_color(&task), "\x1b[33m");
+    }
+
+    #[test]
+    fn task_color_yellow_when_state_is_wip() {
+        let state = State::default();
+        let task = TaskLine {
+            state: TaskState::Wip,
+            agent_status: None,
+            ..TaskLine::default()
+        };
+        assert_eq!(state.task_color(&task), "\x1b[33m");
+    }
+
+    #[test]
+    fn task_color_white_for_todo() {
+        let state = State::default();
+        let task = TaskLine {
+            state: TaskState::Todo,
+            agent_status: None,
+            ..TaskLine::default()
+        };
+        assert_eq!(state.task_color(&task), "\x1b[37m");
+    }
+
+    #[test]
+    fn theme_changes_state_colors_and_selection() {
+        let mut map = BTreeMap::new();
+        map.insert("theme".to_string(), "deuteranopia-safe".to_string());
+        let state = State {
+            config: Config::from_map(&map),
+            ..State::default()
+        };
+        let task = TaskLine {
+            agent_status: Some("blocked: waiting".to_string()),
+            ..TaskLine::default()
+        };
+        assert_eq!(state.task_color(&task), "\x1b[38;5;208m");
+
+        map.insert("theme".to_string(), "high-contrast".to_string());
+        let state = State {
+            config: Config::from_map(&map),
+            ..State::default()
+        };
+        assert!(state.highlight_line("hi", "").starts_with("\x1b[48;5;24m"));
+
+        map.insert("theme".to_string(), "neon".to_string());
+        assert_eq!(Config::from_map(&map).theme, Theme::DEFAULT);
+    }
+
+    #[test]
+    fn selection_style_is_configurable() {
+        let state_with = |key: &str, value: &str| {
+            let mut map = BTreeMap::new();
+            map.insert(key.to_string(), value.to_string());
+            State {
+                config: Config::from_map(&map),
+                ..State::default()
+            }
+        };
+        let reverse = state_with("selection", "reverse").highlight_line("a\x1b[0mb", "");
+        assert_eq!(reverse, "\x1b[7ma\x1b[0m\x1b[7mb\x1b[0m");
+        assert!(state_with("selection", "bold")
+            .highlight_line("a", "")
+            .starts_with("\x1b[1m"));
+        assert_eq!(
+            state_with("selection", "marker").config.selection,
+            SelectionStyle::Marker
+        );
+        let custom = state_with("selection_bg", "blue");
+        assert!(custom.highlight_line("a", "").starts_with("\x1b[44m"));
+        assert_eq!(
+            state_with("selection", "sparkles").config.selection,
+            SelectionStyle::Background
+        );
+    }
+
+    #[test]
+    fn to_background_maps_foreground_sequences() {
+        assert_eq!(to_background("\x1b[31m"), "\x1b[41m");
+        assert_eq!(to_background("\x1b[96m"), "\x1b[106m");
+        assert_eq!(to_background("\x1b[38;5;24m"), "\x1b[48;5;24m");
+        assert_eq!(to_background("\x1b[38;2;1;2;3m"), "\x1b[48;2;1;2;3m");
+    }
+
+    #[test]
+    fn task_name_extracts_last_path_component() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "parent/child/grandchild");
+
+        let repo = TaskRepository::new(yaks);
+        let task = snapshot_task(&repo, "parent/child/grandchild");
+        assert_eq!(task.name, "grandchild");
+    }
+
+    #[test]
+    fn handles_special_characters_in_task_name() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "task-with-dashes_and_underscores");
+        set_field(&yaks, "task-with-dashes_and_underscores", "state", "done");
+
+        let repo = TaskRepository::new(yaks);
+        let task = snapshot_task(&repo, "task-with-dashes_and_underscores");
+
+        assert_eq!(task.name, "task-with-dashes_and_underscores");
+        assert_eq!(task.state, TaskState::Done);
+    }
+
+    #[test]
+    fn handles_empty_field_file() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+        set_field(&yaks, "my-task", "assigned-to", "");
+
+        let repo = TaskRepository::new(yaks);
+        assert_eq!(repo.get_field("my-task", "assigned-to"), None);
+    }
+
+    #[test]
+    fn debug_continuation() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "task-a/child1");
+        create_task(&yaks, "task-a/child2");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        for task in &state.tasks {
+            eprintln!(
+                "{}: depth={}, ancestors={:?}, is_last={}",
+                task.path, task.depth, task.ancestor_continuations, task.is_last_sibling
+            );
+        }
+    }
+
+    #[test]
+    fn tree_prefix_depth_2_parent_has_sibling_shows_continuation() {
+        let (_temp, yaks) = mock_yaks();
+        // parent "child" has sibling "child2" under "parent"
+        create_task(&yaks, "parent/child/grandchild");
+        create_task(&yaks, "parent/child2");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let grandchild = state.tasks.iter().find(|t| t.name == "grandchild").unwrap();
+        // parent "child" has sibling "child2", so continuation line shows
+        let prefix = state.tree_prefix(grandchild);
+        assert_eq!(prefix, "\x1b[90m│ \x1b[0m\x1b[90m╰─\x1b[0m");
+    }
+
+    #[test]
+    fn tree_prefix_depth_2_last_child_has_continuation() {
+        let (_temp, yaks) = mock_yaks();
+        // Only task-a, no sibling - children have no continuation needed
+        create_task(&yaks, "task-a/child1");
+        create_task(&yaks, "task-a/child2");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        // task-a has no sibling at depth 0, so no continuation
+        let child2 = state.tasks.iter().find(|t| t.name == "child2").unwrap();
+        let prefix = state.tree_prefix(child2);
+        assert_eq!(prefix, "\x1b[90m╰─\x1b[0m");
+    }
+
+    #[test]
+    fn tree_prefix_depth_2_no_continuation_when_parent_is_last() {
+        let (_temp, yaks) = mock_yaks();
+        // "child" is the only child of "parent", so no continuation column
+        create_task(&yaks, "parent/child/grandchild");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let grandchild = state.tasks.iter().find(|t| t.name == "grandchild").unwrap();
+        // parent "child" has no siblings, so empty continuation column + connector
+        let prefix = state.tree_prefix(grandchild);
+        assert_eq!(prefix, "  \x1b[90m╰─\x1b[0m");
+    }
+
+    #[test]
+    fn tree_prefix_depth_3_shows_two_continuation_columns() {
+        let (_temp, yaks) = mock_yaks();
+        // a/b/c/d at depth 3; b has sibling b2, c has no sibling
+        create_task(&yaks, "a/b/c/d");
+        create_task(&yaks, "a/b2");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let d = state.tasks.iter().find(|t| t.name == "d").unwrap();
+        // Columns: [grandparent b has siblings → │ ] [parent c has no siblings → "  "] then ╰─
+        let prefix = state.tree_prefix(d);
+        assert_eq!(prefix, "\x1b[90m│ \x1b[0m  \x1b[90m╰─\x1b[0m");
+    }
+
+    #[test]
+    fn render_task_wip_shows_green_bullet() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+        set_field(&yaks, "my-task", "state", "wip");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
+        let rendered = state.render_task(task);
+
+        assert!(rendered.contains("●"), "rendered: {:?}", rendered);
+    }
+
+    #[test]
+    fn render_task_done_shows_strikethrough() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+        set_field(&yaks, "my-task", "state", "done");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
+        let rendered = state.render_task(task);
+
+        assert!(rendered.contains("\x1b[9m"));
+        assert!(rendered.contains("my-task"));
+        assert!(rendered.contains("\x1b[0m"));
+    }
+
+    #[test]
+    fn render_task_todo_shows_white() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
+        let rendered = state.render_task(task);
+
+        assert!(rendered.contains("○"));
+        assert!(rendered.contains("\x1b[37m"));
+    }
+
+    #[test]
+    fn refresh_tasks_sets_is_last_sibling() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "task-a");
+        create_task(&yaks, "task-b");
+        create_task(&yaks, "task-c");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let task_a = state.tasks.iter().find(|t| t.name == "task-a").unwrap();
+        let task_b = state.tasks.iter().find(|t| t.name == "task-b").unwrap();
+        let task_c = state.tasks.iter().find(|t| t.name == "task-c").unwrap();
+
+        assert!(!task_a.is_last_sibling);
+        assert!(!task_b.is_last_sibling);
+        assert!(task_c.is_last_sibling);
+    }
+
+    #[test]
+    fn render_task_with_assignment_shows_agent() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "my-task");
+        set_field(&yaks, "my-task", "assigned-to", "bob");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
+        assert!(
+            task.assigned_to.is_some(),
+            "assigned_to: {:?}",
+            task.assigned_to
+        );
+
+        let rendered = state.render_task(task);
+        assert!(rendered.contains("bob"), "rendered: {:?}", rendered);
+    }
+
+    #[test]
+    fn refresh_tasks_handles_empty_directory() {
+        let (_temp, yaks) = mock_yaks();
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            selected_index: 5,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        assert!(state.tasks.is_empty());
+        assert_eq!(state.selected_index, 0);
+    }
+
+    #[test]
+    fn escape_single_quoted_empty() {
+        assert_eq!(escape_single_quoted(""), "''");
+    }
+
+    #[test]
+    fn escape_single_quoted_no_special() {
+        assert_eq!(escape_single_quoted("foo-bar"), "'foo-bar'");
+    }
+
+    #[test]
+    fn escape_single_quoted_with_single_quote() {
+        assert_eq!(escape_single_quoted("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn snapshot_uses_id_file_when_present() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "parent/my-task");
+        set_field(&yaks, "parent/my-task", "id", "my-task-a1b2");
 
-    #[test]
-    fn list_tasks_returns_empty_for_empty_directory() {
-        let (_temp, yaks) = mock_yaks();
         let repo = TaskRepository::new(yaks);
-        let tasks = repo.list_tasks();
-        assert!(tasks.is_empty());
+        let task = snapshot_task(&repo, "parent/my-task");
+
+        assert_eq!(task.yak_id, "my-task-a1b2");
+    }
+
+    #[test]
+    fn snapshot_falls_back_to_leaf_slug_for_id_when_id_file_absent() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "parent/my-task");
+
+        let repo = TaskRepository::new(yaks);
+        let task = snapshot_task(&repo, "parent/my-task");
+
+        assert_eq!(task.yak_id, "my-task");
+    }
+
+    #[test]
+    fn refresh_tasks_selected_index_bounded() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "task-a");
+
+        let repo = TaskRepository::new(yaks.clone());
+        let mut state = State {
+            repository: repo,
+            selected_index: 10,
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        assert_eq!(state.selected_index, 0);
+    }
+
+    #[test]
+    fn highlight_line_uses_explicit_bg_not_reverse_video() {
+        let state = State::default();
+        let result = state.highlight_line("hello", "   ");
+        assert!(
+            result.starts_with("\x1b[48;5;237m"),
+            "should start with explicit bg: {:?}",
+            result
+        );
+        assert!(
+            !result.contains("\x1b[7m"),
+            "should not use reverse video: {:?}",
+            result
+        );
+        assert!(
+            result.ends_with("\x1b[0m"),
+            "should end with reset: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn highlight_line_reestablishes_bg_after_reset() {
+        let state = State::default();
+        // A line that contains a reset mid-way (e.g. from colored text)
+        let line = "\x1b[32mfoo\x1b[0mbar";
+        let result = state.highlight_line(line, "");
+        // After each \x1b[0m the bg color should be re-established
+        assert!(
+            result.contains("\x1b[0m\x1b[48;5;237m"),
+            "bg not re-established after reset: {:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn highlight_line_padding_uses_same_bg() {
+        let state = State::default();
+        let result = state.highlight_line("hi", "     ");
+        // The bg is set at start before both the text and the padding
+        let bg = "\x1b[48;5;237m";
+        assert!(result.starts_with(bg));
+        // padding is inside the bg scope (before the final reset)
+        let reset_pos = result.rfind("\x1b[0m").unwrap();
+        assert!(
+            reset_pos == result.len() - "\x1b[0m".len(),
+            "final reset should be at end: {:?}",
+            result
+        );
+    }
+    fn state_with_tasks(count: usize) -> State {
+        State {
+            tasks: (0..count)
+                .map(|i| TaskLine {
+                    path: format!("task-{}", i),
+                    name: format!("task-{}", i),
+                    ..TaskLine::default()
+                })
+                .collect(),
+            visible: (0..count).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn count_prefix_accumulates_digits() {
+        let mut state = state_with_tasks(20);
+        assert!(state.push_count_digit(1));
+        assert!(state.push_count_digit(0));
+        assert_eq!(state.take_count(), 10);
+        assert_eq!(state.pending_count, None);
+    }
+
+    #[test]
+    fn count_prefix_leading_zero_is_not_a_count() {
+        let mut state = state_with_tasks(5);
+        assert!(!state.push_count_digit(0));
+        assert_eq!(state.take_count(), 1);
+    }
+
+    #[test]
+    fn move_selection_clamps_to_bounds() {
+        let mut state = state_with_tasks(5);
+        state.move_selection(10);
+        assert_eq!(state.selected_index, 4);
+        state.move_selection(-3);
+        assert_eq!(state.selected_index, 1);
+        state.move_selection(-10);
+        assert_eq!(state.selected_index, 0);
+    }
+
+    #[test]
+    fn line_number_gutter_shows_relative_distance() {
+        let state = State {
+            selected_index: 3,
+            ..state_with_tasks(12)
+        };
+        assert_eq!(state.line_number_gutter(0, 2), "\x1b[90m 3\x1b[0m ");
+        assert_eq!(state.line_number_gutter(5, 2), "\x1b[90m 2\x1b[0m ");
+        assert_eq!(state.line_number_gutter(3, 2), "\x1b[33m4 \x1b[0m ");
+    }
+
+    #[test]
+    fn config_reads_relative_numbers() {
+        let mut map = BTreeMap::new();
+        map.insert("relative_numbers".to_string(), "true".to_string());
+        assert!(Config::from_map(&map).relative_numbers);
+        assert!(!Config::from_map(&BTreeMap::new()).relative_numbers);
+    }
+
+    #[test]
+    fn sequence_gg_jumps_to_first_task() {
+        let mut state = State {
+            selected_index: 7,
+            ..state_with_tasks(10)
+        };
+        state.handle_sequence('g', &KeyWithModifier::new(BareKey::Char('g')));
+        assert_eq!(state.selected_index, 0);
+    }
+
+    #[test]
+    fn sequence_gg_with_count_jumps_to_line() {
+        let mut state = state_with_tasks(10);
+        state.pending_count = Some(4);
+        state.handle_sequence('g', &KeyWithModifier::new(BareKey::Char('g')));
+        assert_eq!(state.selected_index, 3);
+    }
+
+    #[test]
+    fn sequence_zz_centers_selection() {
+        let mut state = State {
+            selected_index: 30,
+            viewport_rows: 10,
+            ..state_with_tasks(50)
+        };
+        state.handle_sequence('z', &KeyWithModifier::new(BareKey::Char('z')));
+        assert_eq!(state.scroll_offset, 25);
+    }
+
+    #[test]
+    fn unknown_sequence_is_swallowed() {
+        let mut state = State {
+            selected_index: 2,
+            ..state_with_tasks(5)
+        };
+        assert!(state.handle_sequence('g', &KeyWithModifier::new(BareKey::Char('x'))));
+        assert_eq!(state.selected_index, 2);
+    }
+
+    #[test]
+    fn plain_char_accepts_shift_only() {
+        let shifted = KeyWithModifier::new(BareKey::Char('G')).with_shift_modifier();
+        let ctrl = KeyWithModifier::new(BareKey::Char('g')).with_ctrl_modifier();
+        assert_eq!(plain_char(&shifted), Some('G'));
+        assert_eq!(plain_char(&ctrl), None);
+    }
+
+    #[test]
+    fn jump_labels_use_single_letters_when_they_fit() {
+        assert_eq!(jump_labels(JUMP_LABEL_CHARS, 3), vec!["a", "s", "d"]);
+    }
+
+    #[test]
+    fn jump_labels_switch_to_two_letters_for_many_rows() {
+        let labels = jump_labels(JUMP_LABEL_CHARS, 30);
+        assert_eq!(labels.len(), 30);
+        assert!(labels.iter().all(|l| l.len() == 2));
+        assert_eq!(labels[0], "aa");
+        assert_eq!(labels[1], "as");
+    }
+
+    #[test]
+    fn jump_label_key_selects_labelled_row() {
+        let mut state = State {
+            scroll_offset: 2,
+            viewport_rows: 5,
+            mode: Mode::JumpLabel {
+                typed: String::new(),
+                numbered: false,
+            },
+            ..state_with_tasks(10)
+        };
+        // Visible rows 2..7 are labelled a, s, d, f, g
+        state.handle_jump_label_key(&KeyWithModifier::new(BareKey::Char('d')));
+        assert_eq!(state.selected_index, 4);
+        assert_eq!(state.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn quick_select_numbers_rows_and_jumps_to_the_typed_one() {
+        let mut state = State {
+            viewport_rows: 12,
+            ..state_with_tasks(12)
+        };
+        state.handle_key(KeyWithModifier::new(BareKey::Char('#')));
+        let labels: Vec<String> = state
+            .visible_jump_labels()
+            .into_iter()
+            .map(|(_, l)| l)
+            .collect();
+        assert_eq!(labels[..3], ["1", "2", "3"]);
+        assert_eq!(labels[9..], ["a", "b", "c"]);
+        state.handle_key(KeyWithModifier::new(BareKey::Char('b')));
+        assert_eq!(state.selected_index, 10);
+        assert_eq!(state.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn type_ahead_jumps_to_names_by_prefix_and_repeats() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["alpha", "apple", "banana", "apricot"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "banana");
+        let press = |state: &mut State, keys: &str| {
+            for c in keys.chars() {
+                state.handle_key(KeyWithModifier::new(BareKey::Char(c)));
+            }
+        };
+        press(&mut state, "\"ap");
+        assert_eq!(selected_path(&state), "apple");
+        press(&mut state, "r");
+        assert_eq!(selected_path(&state), "apricot");
+        state.handle_key(KeyWithModifier::new(BareKey::Backspace));
+        state.handle_key(KeyWithModifier::new(BareKey::Enter));
+        assert_eq!(state.mode, Mode::Normal);
+
+        press(&mut state, ">");
+        assert_eq!(selected_path(&state), "apricot");
+        press(&mut state, ">");
+        assert_eq!(selected_path(&state), "apple");
+        press(&mut state, "<");
+        assert_eq!(selected_path(&state), "apricot");
+
+        press(&mut state, "\"zz");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("No task starts with \"zz\"")
+        );
+        state.handle_key(KeyWithModifier::new(BareKey::Esc));
+        assert_eq!(selected_path(&state), "apricot");
+    }
+
+    #[test]
+    fn scroll_margin_keeps_rows_around_the_selection() {
+        let mut state = state_with_tasks(20);
+        state.config.scroll_margin = 2;
+        state.selected_index = 7;
+        state.keep_selection_in_view(10);
+        assert_eq!(state.scroll_offset, 0);
+        state.selected_index = 8;
+        state.keep_selection_in_view(10);
+        assert_eq!(state.scroll_offset, 1);
+        state.selected_index = 19;
+        state.keep_selection_in_view(10);
+        assert_eq!(state.scroll_offset, 10);
+        state.selected_index = 11;
+        state.keep_selection_in_view(10);
+        assert_eq!(state.scroll_offset, 9);
+        // A margin larger than half the viewport keeps the selection centered
+        state.config.scroll_margin = 50;
+        state.keep_selection_in_view(5);
+        assert_eq!(state.scroll_offset, 9);
+        state.selected_index = 14;
+        state.keep_selection_in_view(5);
+        assert_eq!(state.scroll_offset, 12);
+    }
+
+    #[test]
+    fn resizing_keeps_the_selection_visible_and_the_tree_filled() {
+        let mut state = state_with_tasks(30);
+        state.render_tree(20, 80);
+        state.selected_index = 29;
+        state.render_tree(20, 80);
+        let offset = state.scroll_offset;
+        assert!(offset > 0 && offset + state.viewport_rows == 30);
+
+        state.render_tree(12, 80);
+        assert!(state.scroll_offset + state.viewport_rows > 29);
+        state.selected_index = 20;
+        state.render_tree(12, 80);
+        state.render_tree(60, 80);
+        assert_eq!(state.scroll_offset, 0);
+        assert_eq!(state.selected_index, 20);
+    }
+
+    #[test]
+    fn jump_label_key_cancels_on_unknown_label() {
+        let mut state = State {
+            viewport_rows: 3,
+            mode: Mode::JumpLabel {
+                typed: String::new(),
+                numbered: false,
+            },
+            ..state_with_tasks(3)
+        };
+        state.handle_jump_label_key(&KeyWithModifier::new(BareKey::Char('z')));
+        assert_eq!(state.selected_index, 0);
+        assert_eq!(state.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn marks_jump_back_to_task_by_path() {
+        let mut state = State {
+            selected_index: 3,
+            ..state_with_tasks(6)
+        };
+        state.handle_sequence('m', &KeyWithModifier::new(BareKey::Char('a')));
+        state.selected_index = 0;
+        state.handle_sequence('\'', &KeyWithModifier::new(BareKey::Char('a')));
+        assert_eq!(state.selected_index, 3);
+    }
+
+    #[test]
+    fn marks_follow_task_when_list_changes() {
+        let mut state = State {
+            selected_index: 2,
+            ..state_with_tasks(4)
+        };
+        state.set_mark('b');
+        state.tasks.remove(0);
+        state.selected_index = 0;
+        state.jump_to_mark('b');
+        assert_eq!(state.tasks[state.selected_index].path, "task-2");
+    }
+
+    #[test]
+    fn jump_to_unset_mark_shows_toast() {
+        let mut state = state_with_tasks(2);
+        state.jump_to_mark('z');
+        assert_eq!(state.selected_index, 0);
+        assert_eq!(state.toast_message.as_deref(), Some("Mark 'z' not set"));
+    }
+
+    fn refreshed_state(yaks: &Path) -> State {
+        let mut state = State {
+            repository: TaskRepository::new(yaks.to_path_buf()),
+            ..Default::default()
+        };
+        state.refresh_tasks();
+        state
+    }
+
+    fn select_path(state: &mut State, path: &str) {
+        state.selected_index = state.row_of_path(path).unwrap();
+    }
+
+    fn selected_path(state: &State) -> &str {
+        &state.selected_task().unwrap().path
+    }
+
+    #[test]
+    fn select_parent_moves_to_parent_task() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/story/leaf");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "epic/story/leaf");
+
+        state.select_parent();
+        assert_eq!(selected_path(&state), "epic/story");
+    }
+
+    #[test]
+    fn breadcrumb_shows_ancestry_and_truncates_from_the_left() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "infra/api/retries");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "infra/api/retries");
+
+        assert_eq!(
+            strip_ansi(&state.breadcrumb(80).unwrap()),
+            "infra ▸ api ▸ retries"
+        );
+        assert_eq!(
+            strip_ansi(&state.breadcrumb(18).unwrap()),
+            "… ▸ api ▸ retries"
+        );
+        assert_eq!(strip_ansi(&state.breadcrumb(2).unwrap()), "… ▸ retries");
+    }
+
+    #[test]
+    fn select_sibling_skips_over_descendants() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "a/deep/deeper");
+        create_task(&yaks, "b");
+        create_task(&yaks, "c");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "a");
+
+        state.select_sibling(1);
+        assert_eq!(selected_path(&state), "b");
+        state.select_sibling(5);
+        assert_eq!(selected_path(&state), "c");
+        state.select_sibling(-1);
+        assert_eq!(selected_path(&state), "b");
+    }
+
+    #[test]
+    fn select_first_child_descends_one_level() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/first");
+        create_task(&yaks, "epic/second");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "epic");
+
+        state.select_first_child();
+        assert_eq!(selected_path(&state), "epic/first");
+        // Leaf tasks have no child to move to
+        state.select_first_child();
+        assert_eq!(selected_path(&state), "epic/first");
+    }
+
+    #[test]
+    fn scroll_by_moves_viewport_and_selection_together() {
+        let mut state = State {
+            selected_index: 2,
+            viewport_rows: 10,
+            ..state_with_tasks(50)
+        };
+        state.scroll_by(5);
+        assert_eq!(state.scroll_offset, 5);
+        assert_eq!(state.selected_index, 7);
+    }
+
+    #[test]
+    fn scroll_by_clamps_at_the_edges() {
+        let mut state = State {
+            selected_index: 45,
+            scroll_offset: 38,
+            viewport_rows: 10,
+            ..state_with_tasks(50)
+        };
+        state.scroll_by(20);
+        assert_eq!(state.scroll_offset, 40);
+        assert_eq!(state.selected_index, 49);
+
+        state.scroll_by(-100);
+        assert_eq!(state.scroll_offset, 0);
+        assert_eq!(state.selected_index, 0);
+    }
+
+    #[test]
+    fn half_page_size_is_never_zero() {
+        let state = State {
+            viewport_rows: 1,
+            ..state_with_tasks(3)
+        };
+        assert_eq!(state.half_page_size(), 1);
+    }
+
+    #[test]
+    fn ui_state_round_trips_through_serialize() {
+        let mut marks = BTreeMap::new();
+        marks.insert('a', "epic/story".to_string());
+        let ui_state = UiState {
+            selected: Some("epic".to_string()),
+            scroll_offset: 12,
+            marks,
+            collapsed: ["epic/done".to_string()].into_iter().collect(),
+            expanded: ["epic".to_string()].into_iter().collect(),
+            filter: Some("retry budget".to_string()),
+            view: Some("table".to_string()),
+            tab: Some("WIP".to_string()),
+            sort_done_last: Some(true),
+            mine_only: true,
+            zoom: Some("epic/story".to_string()),
+            show_feed: true,
+            show_preview: true,
+            preview_rows: Some(14),
+            views: BTreeMap::from([(
+                "triage".to_string(),
+                SavedView {
+                    filter: Some("api".to_string()),
+                    sort_done_last: true,
+                    mine_only: true,
+                    tab: Some("WIP".to_string()),
+                    zoom: Some("epic".to_string()),
+                    collapsed: ["epic/done".to_string()].into_iter().collect(),
+                },
+            )]),
+        };
+        assert_eq!(UiState::parse(&ui_state.serialize()), ui_state);
+    }
+
+    #[test]
+    fn preview_is_resized_within_bounds_and_remembered() {
+        let mut state = state_with_tasks(2);
+        state.resize_preview(3);
+        assert_eq!(state.preview_rows, Some(PREVIEW_PANEL_ROWS + 3));
+        assert_eq!(state.toast_message.as_deref(), Some("Preview: 11 rows"));
+        state.resize_preview(-100);
+        assert_eq!(state.preview_rows, Some(MIN_PREVIEW_ROWS));
+        assert_eq!(state.ui_state().preview_rows, Some(MIN_PREVIEW_ROWS));
+        assert!(state.ui_state().serialize().contains("preview_rows=2\n"));
+    }
+
+    #[test]
+    fn views_are_saved_and_recalled_by_name() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["api/retries", "docs"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        state.filter = Some("retries".to_string());
+        state.collapsed.insert("docs".to_string());
+        state.switch_tab(3);
+        state.run_command_line("view save triage");
+        assert_eq!(state.toast_message.as_deref(), Some("Saved view triage"));
+        let saved = fs::read_to_string(yaks.join(".yak-map-state")).unwrap();
+        assert!(saved.contains("view.triage.tab=Done\n"));
+
+        let mut state = refreshed_state(&yaks);
+        let ui_state = state.repository.load_ui_state();
+        state.restore_ui_state(ui_state);
+        state.run_command_line("view triage");
+        assert_eq!(state.filter.as_deref(), Some("retries"));
+        assert_eq!(state.tab, 3);
+        assert!(state.collapsed.contains("docs"));
+
+        state.run_command_line("view delete triage");
+        state.run_command_line("view triage");
+        assert_eq!(state.toast_message.as_deref(), Some("No view named triage"));
+    }
+
+    #[test]
+    fn cheatsheet_lists_keys_actions_and_tabs() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        state
+            .config
+            .actions
+            .insert("lint".to_string(), vec!["cargo".into(), "clippy".into()]);
+        state.run_command_line("cheatsheet");
+
+        let sheet = fs::read_to_string(yaks.join(".yak-map-keys.md")).unwrap();
+        assert!(sheet.starts_with("# yak-map keys\n\n| Key | Action |\n| --- | --- |\n"));
+        assert!(sheet.contains("| `p` | Jump to the parent task |\n"));
+        assert!(sheet.contains("| `Ctrl+l` | Debug log"));
+        assert!(sheet.contains("| lint | `cargo clippy` |\n"));
+        assert!(sheet.ends_with("- Done\n- Mine\n"));
+    }
+
+    #[test]
+    fn ui_state_parse_ignores_unknown_and_malformed_lines() {
+        let ui_state = UiState::parse("future=1\ngarbage\nmark.ab=x\nselected=task-a\n");
+        assert_eq!(ui_state.selected.as_deref(), Some("task-a"));
+        assert!(ui_state.marks.is_empty());
+    }
+
+    #[test]
+    fn ui_state_is_saved_and_restored_from_yaks_dir() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "task-a");
+        create_task(&yaks, "task-b");
+        set_field(&yaks, "task-b", "state", "wip");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "task-b");
+        state.set_mark('q');
+        state.apply_filter(Some("task".to_string()));
+        state.switch_tab(1);
+        state.open_view(View::Table);
+        state.save_ui_state_if_changed();
+
+        let mut restored = refreshed_state(&yaks);
+        let ui_state = restored.repository.load_ui_state();
+        restored.restore_ui_state(ui_state);
+        assert_eq!(restored.filter.as_deref(), Some("task"));
+        assert_eq!((restored.view, restored.tab), (View::Table, 1));
+        assert_eq!(selected_path(&restored), "task-b");
+        assert_eq!(restored.marks.get(&'q').map(String::as_str), Some("task-b"));
+    }
+
+    #[test]
+    fn restore_ui_state_clamps_scroll_offset() {
+        let mut state = state_with_tasks(5);
+        state.restore_ui_state(UiState {
+            selected: Some("task-3".to_string()),
+            scroll_offset: 40,
+            ..UiState::default()
+        });
+        assert_eq!(state.selected_index, 3);
+        assert_eq!(state.scroll_offset, 4);
+    }
+
+    #[test]
+    fn fuzzy_match_prefers_contiguous_substring() {
+        assert_eq!(fuzzy_match("API", "fix-api-retries"), Some(vec![4, 5, 6]));
+    }
+
+    #[test]
+    fn fuzzy_match_falls_back_to_subsequence() {
+        assert_eq!(fuzzy_match("frt", "fix-retries"), Some(vec![0, 4, 6]));
+        assert_eq!(fuzzy_match("xyz", "fix-retries"), None);
+    }
+
+    #[test]
+    fn highlight_matches_wraps_matched_chars() {
+        assert_eq!(highlight_matches("abc", &[1]), "a\x1b[1;4mb\x1b[22;24mc");
+    }
+
+    #[test]
+    fn filter_keeps_matches_and_their_ancestors() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "infra/api/retries");
+        create_task(&yaks, "infra/dns");
+        create_task(&yaks, "docs");
+        let mut state = refreshed_state(&yaks);
+
+        state.apply_filter(Some("retr".to_string()));
+        let shown: Vec<_> = state
+            .visible
+            .iter()
+            .map(|&i| state.tasks[i].path.as_str())
+            .collect();
+        assert_eq!(shown, vec!["infra", "infra/api", "infra/api/retries"]);
+    }
+
+    #[test]
+    fn filter_keeps_selected_task_when_still_visible() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "alpha");
+        create_task(&yaks, "beta");
+        create_task(&yaks, "gamma");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "gamma");
+
+        state.apply_filter(Some("a".to_string()));
+        assert_eq!(selected_path(&state), "gamma");
+        state.apply_filter(None);
+        assert_eq!(state.visible.len(), 3);
+        assert_eq!(selected_path(&state), "gamma");
+    }
+
+    #[test]
+    fn render_task_highlights_filter_matches() {
+        let state = State {
+            filter: Some("ap".to_string()),
+            ..State::default()
+        };
+        let task = TaskLine {
+            name: "api".to_string(),
+            ..TaskLine::default()
+        };
+        assert!(state.render_task(&task).contains("\x1b[1;4ma\x1b[22;24m"));
+    }
+
+    fn type_keys(state: &mut State, text: &str) {
+        for c in text.chars() {
+            state.handle_filter_key(&KeyWithModifier::new(BareKey::Char(c)));
+        }
+    }
+
+    #[test]
+    fn filter_input_applies_on_every_keystroke() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api-client");
+        create_task(&yaks, "api-server");
+        create_task(&yaks, "docs");
+        let mut state = refreshed_state(&yaks);
+        state.start_filter_input();
+
+        type_keys(&mut state, "api");
+        assert_eq!(state.filter_matches, 2);
+        assert_eq!(state.visible.len(), 2);
+        type_keys(&mut state, "-s");
+        assert_eq!(state.filter_matches, 1);
+    }
+
+    #[test]
+    fn filter_input_esc_restores_previous_view() {
+        let (_temp, yaks) = mock_yaks();
+        for i in 0..10 {
+            create_task(&yaks, &format!("task-{}", i));
+        }
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "task-7");
+        state.scroll_offset = 4;
+        state.start_filter_input();
+
+        type_keys(&mut state, "task-1");
+        assert_eq!(state.visible.len(), 1);
+        state.handle_filter_key(&KeyWithModifier::new(BareKey::Esc));
+
+        assert_eq!(state.mode, Mode::Normal);
+        assert_eq!(state.filter, None);
+        assert_eq!(state.visible.len(), 10);
+        assert_eq!(selected_path(&state), "task-7");
+        assert_eq!(state.scroll_offset, 4);
+    }
+
+    #[test]
+    fn filter_input_enter_keeps_filter() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "alpha");
+        create_task(&yaks, "beta");
+        let mut state = refreshed_state(&yaks);
+        state.start_filter_input();
+
+        type_keys(&mut state, "bet");
+        state.handle_filter_key(&KeyWithModifier::new(BareKey::Enter));
+        assert_eq!(state.mode, Mode::Normal);
+        assert_eq!(state.filter.as_deref(), Some("bet"));
+    }
+
+    #[test]
+    fn macros_record_keys_and_replay_with_a_count() {
+        let (_temp, yaks) = mock_yaks();
+        for i in 0..8 {
+            create_task(&yaks, &format!("task-{}", i));
+        }
+        let mut state = refreshed_state(&yaks);
+        let press = |state: &mut State, keys: &str| {
+            for c in keys.chars() {
+                state.handle_key(KeyWithModifier::new(BareKey::Char(c)));
+            }
+        };
+        press(&mut state, "qa");
+        assert!(state.header_line().unwrap().contains("Recording a"));
+        press(&mut state, "jjq");
+        assert_eq!(state.macros[&'a'].len(), 2);
+        assert_eq!(selected_path(&state), "task-2");
+
+        press(&mut state, "2Qa");
+        assert_eq!(selected_path(&state), "task-6");
+        press(&mut state, "Qb");
+        assert_eq!(state.toast_message.as_deref(), Some("No macro in b"));
+    }
+
+    #[test]
+    fn filter_input_recalls_earlier_queries() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "alpha");
+        create_task(&yaks, "beta");
+        let mut state = refreshed_state(&yaks);
+        for query in ["alp", "bet", "alp"] {
+            state.apply_filter(None);
+            state.start_filter_input();
+            type_keys(&mut state, query);
+            state.handle_filter_key(&KeyWithModifier::new(BareKey::Enter));
+        }
+        assert_eq!(state.filter_history.entries, ["bet", "alp"]);
+
+        state.start_filter_input();
+        type_keys(&mut state, "x");
+        let up = KeyWithModifier::new(BareKey::Up);
+        state.handle_filter_key(&up);
+        state.handle_filter_key(&up);
+        state.handle_filter_key(&up);
+        assert_eq!(state.filter.as_deref(), Some("bet"));
+        assert_eq!(state.visible.len(), 1);
+        let down = KeyWithModifier::new(BareKey::Down);
+        state.handle_filter_key(&down);
+        state.handle_filter_key(&down);
+        assert!(matches!(&state.mode, Mode::Filter { input, .. } if input == "alpx"));
+    }
+
+    #[test]
+    fn refresh_tasks_rolls_up_subtree_counts_and_done() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/a/a1");
+        create_task(&yaks, "epic/b");
+        for path in ["epic", "epic/a", "epic/a/a1", "epic/b"] {
+            set_field(&yaks, path, "state", "done");
+        }
+        create_task(&yaks, "open/child");
+        set_field(&yaks, "open", "state", "done");
+        let state = refreshed_state(&yaks);
+
+        let epic = state.tasks.iter().find(|t| t.path == "epic").unwrap();
+        assert_eq!(epic.descendant_count, 3);
+        assert!(epic.subtree_done);
+        let open = state.tasks.iter().find(|t| t.path == "open").unwrap();
+        assert!(!open.subtree_done);
+    }
+
+    #[test]
+    fn auto_collapse_hides_fully_done_subtrees() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "old-migration/step");
+        set_field(&yaks, "old-migration", "state", "done");
+        set_field(&yaks, "old-migration/step", "state", "done");
+        create_task(&yaks, "current/step");
+        let mut state = State {
+            repository: TaskRepository::new(yaks.clone()),
+            config: Config {
+                auto_collapse_done: true,
+                ..Config::default()
+            },
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let shown: Vec<_> = state
+            .visible
+            .iter()
+            .map(|&i| state.tasks[i].path.as_str())
+            .collect();
+        assert_eq!(shown, vec!["current", "current/step", "old-migration"]);
+        let migration = state
+            .tasks
+            .iter()
+            .find(|t| t.path == "old-migration")
+            .unwrap();
+        assert!(state.render_task(migration).contains("(done, 1 task)"));
+    }
+
+    #[test]
+    fn collapsed_rows_count_direct_children_and_descendants() {
+        let (_temp, yaks) = mock_yaks();
+        for path in [
+            "api/auth/tokens",
+            "api/auth/sessions",
+            "api/retries",
+            "docs",
+        ] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "api");
+        state.toggle_fold();
+
+        let api = state.tasks.iter().find(|t| t.path == "api").unwrap();
+        assert_eq!((api.child_count, api.descendant_count), (2, 4));
+        assert!(strip_ansi(&state.render_task(api)).contains("▸ api (2/4)"));
+        state.config.plain = true;
+        assert!(state
+            .render_task(api)
+            .ends_with("api (collapsed, 2 children, 4 tasks)"));
+    }
+
+    #[test]
+    fn toggle_fold_expands_auto_collapsed_subtree() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "done-epic/step");
+        set_field(&yaks, "done-epic", "state", "done");
+        set_field(&yaks, "done-epic/step", "state", "done");
+        let mut state = State {
+            repository: TaskRepository::new(yaks.clone()),
+            config: Config {
+                auto_collapse_done: true,
+                ..Config::default()
+            },
+            ..Default::default()
+        };
+        state.refresh_tasks();
+        assert_eq!(state.visible.len(), 1);
+
+        state.toggle_fold();
+        assert_eq!(state.visible.len(), 2);
+        state.toggle_fold();
+        assert_eq!(state.visible.len(), 1);
+    }
+
+    #[test]
+    fn fold_to_level_shows_that_many_levels() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/story/leaf");
+        create_task(&yaks, "other/story");
+        let mut state = refreshed_state(&yaks);
+
+        state.fold_to_level(Some(2));
+        let shown: Vec<&str> = state
+            .visible
+            .iter()
+            .map(|&i| state.tasks[i].path.as_str())
+            .collect();
+        assert_eq!(shown, vec!["epic", "epic/story", "other", "other/story"]);
+
+        state.handle_sequence('z', &KeyWithModifier::new(BareKey::Char('C')));
+        assert_eq!(state.visible.len(), 2);
+        state.handle_sequence('z', &KeyWithModifier::new(BareKey::Char('A')));
+        assert_eq!(state.visible.len(), 5);
+        assert!(state.collapsed.is_empty());
+    }
+
+    #[test]
+    fn sticky_ancestor_rows_pins_offscreen_ancestors() {
+        let (_temp, yaks) = mock_yaks();
+        for i in 0..5 {
+            create_task(&yaks, &format!("epic/story/leaf-{}", i));
+        }
+        let mut state = refreshed_state(&yaks);
+        state.config.sticky_ancestors = true;
+
+        state.scroll_offset = 0;
+        assert!(state.sticky_ancestor_rows(5).is_empty());
+        state.scroll_offset = 4;
+        assert_eq!(state.sticky_ancestor_rows(5), vec![0, 1]);
+        assert_eq!(state.sticky_ancestor_rows(1), vec![1]);
+
+        state.config.sticky_ancestors = false;
+        assert!(state.sticky_ancestor_rows(5).is_empty());
+    }
+
+    #[test]
+    fn minimap_cells_summarize_chunks_and_mark_the_viewport() {
+        let mut state = state_with_tasks(8);
+        for task in &mut state.tasks[4..] {
+            task.agent_status = Some("blocked: x".to_string());
+        }
+        state.tasks[0].state = TaskState::Done;
+        state.scroll_offset = 4;
+
+        let cells = state.minimap_cells(4, 2);
+        let todo = state.task_color(&state.tasks[1]);
+        let blocked = state.task_color(&state.tasks[4]);
+        assert_eq!(
+            cells,
+            vec![
+                (false, state.task_color(&state.tasks[0])),
+                (false, todo),
+                (true, blocked.clone()),
+                (false, blocked),
+            ]
+        );
+    }
+
+    #[test]
+    fn collapsing_moves_hidden_selection_to_ancestor() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/story/leaf");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "epic/story/leaf");
+
+        state.collapsed.insert("epic".to_string());
+        let selected = state.selected_task().map(|t| t.path.clone());
+        state.update_visible(selected);
+        assert_eq!(selected_path(&state), "epic");
+    }
+
+    #[test]
+    fn sort_done_last_moves_done_siblings_after_open_ones() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "a-done/child");
+        set_field(&yaks, "a-done", "state", "done");
+        create_task(&yaks, "b-open");
+        create_task(&yaks, "c-parent/x-done");
+        set_field(&yaks, "c-parent/x-done", "state", "done");
+        create_task(&yaks, "c-parent/y-open");
+        let mut state = State {
+            repository: TaskRepository::new(yaks.clone()),
+            sort_done_last: Some(true),
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        let paths: Vec<_> = state.tasks.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "b-open",
+                "c-parent",
+                "c-parent/y-open",
+                "c-parent/x-done",
+                "a-done",
+                "a-done/child",
+            ]
+        );
+        let x_done = state
+            .tasks
+            .iter()
+            .find(|t| t.path == "c-parent/x-done")
+            .unwrap();
+        assert!(x_done.is_last_sibling);
+    }
+
+    #[test]
+    fn toggle_sort_done_last_keeps_selection() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "a");
+        set_field(&yaks, "a", "state", "done");
+        create_task(&yaks, "b");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "a");
+
+        state.toggle_sort_done_last();
+        assert_eq!(state.tasks[0].path, "b");
+        assert_eq!(selected_path(&state), "a");
+        assert_eq!(state.ui_state().sort_done_last, Some(true));
+    }
+
+    #[test]
+    fn mine_only_shows_my_tasks_with_ancestors() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/mine");
+        set_field(&yaks, "epic/mine", "assigned-to", "alice");
+        create_task(&yaks, "epic/theirs");
+        set_field(&yaks, "epic/theirs", "assigned-to", "bob");
+        create_task(&yaks, "other");
+        let mut state = State {
+            repository: TaskRepository::new(yaks.clone()),
+            config: Config {
+                me: Some("alice".to_string()),
+                ..Config::default()
+            },
+            ..Default::default()
+        };
+        state.refresh_tasks();
+
+        state.toggle_mine_only();
+        let shown: Vec<_> = state
+            .visible
+            .iter()
+            .map(|&i| state.tasks[i].path.as_str())
+            .collect();
+        assert_eq!(shown, vec!["epic", "epic/mine"]);
+        assert_eq!(state.filter_matches, 1);
+    }
+
+    #[test]
+    fn mine_only_requires_identity() {
+        let mut state = state_with_tasks(2);
+        state.toggle_mine_only();
+        assert!(!state.mine_only);
+        assert!(state.toast_message.is_some());
+    }
+
+    #[test]
+    fn config_reads_me() {
+        let mut map = BTreeMap::new();
+        map.insert("me".to_string(), " alice ".to_string());
+        assert_eq!(Config::from_map(&map).me.as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn follow_mode_tracks_agent_wip_task_across_refreshes() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "first");
+        set_field(&yaks, "first", "assigned-to", "bot");
+        set_field(&yaks, "first", "agent-status", "wip: building");
+        create_task(&yaks, "second");
+        set_field(&yaks, "second", "assigned-to", "bot");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "first");
+
+        state.toggle_follow();
+        assert_eq!(state.following.as_deref(), Some("bot"));
+
+        set_field(&yaks, "first", "agent-status", "done: built");
+        set_field(&yaks, "second", "agent-status", "wip: testing");
+        select_path(&mut state, "first");
+        state.refresh_tasks();
+        assert_eq!(selected_path(&state), "second");
+    }
+
+    #[test]
+    fn agent_wip_task_falls_back_to_wip_state() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "task");
+        set_field(&yaks, "task", "assigned-to", "bot");
+        set_field(&yaks, "task", "state", "wip");
+        let state = refreshed_state(&yaks);
+        assert_eq!(
+            state.agent_wip_task("bot").map(|t| t.path.as_str()),
+            Some("task")
+        );
+        assert!(state.agent_wip_task("nobody").is_none());
+    }
+
+    #[test]
+    fn toggle_follow_needs_an_assignee() {
+        let mut state = state_with_tasks(1);
+        state.toggle_follow();
+        assert_eq!(state.following, None);
+    }
+
+    #[test]
+    fn agent_palette_color_is_stable_and_varies_by_name() {
+        assert_eq!(agent_palette_color("alice"), agent_palette_color("alice"));
+        let colors: std::collections::HashSet<_> = ["alice", "bob", "carol", "dave", "erin"]
+            .iter()
+            .map(|a| agent_palette_color(a))
+            .collect();
+        assert!(colors.len() > 1);
+    }
+
+    #[test]
+    fn parse_color_accepts_names_and_palette_indices() {
+        assert_eq!(parse_color("red").as_deref(), Some("\x1b[31m"));
+        assert_eq!(parse_color("bright-cyan").as_deref(), Some("\x1b[96m"));
+        assert_eq!(parse_color("202").as_deref(), Some("\x1b[38;5;202m"));
+        assert_eq!(
+            parse_color("#FF8800").as_deref(),
+            Some("\x1b[38;2;255;136;0m")
+        );
+        assert_eq!(parse_color("#ff88"), None);
+        assert_eq!(parse_color("chartreuse"), None);
+    }
+
+    #[test]
+    fn agent_color_prefers_configured_override() {
+        let mut map = BTreeMap::new();
+        map.insert("agent_color.bob".to_string(), "green".to_string());
+        let state = State {
+            config: Config::from_map(&map),
+            ..State::default()
+        };
+        assert_eq!(state.agent_color("bob"), "\x1b[32m");
+        assert_eq!(state.agent_color("alice"), agent_palette_color("alice"));
+
+        let task = TaskLine {
+            assigned_to: Some("bob".to_string()),
+            ..TaskLine::default()
+        };
+        assert!(state.render_task(&task).contains("[\x1b[32mbob\x1b[0m]"));
+    }
+
+    #[test]
+    fn agent_queues_group_by_assignee_in_queue_order() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "a-late");
+        set_field(&yaks, "a-late", "assigned-to", "bot");
+        set_field(&yaks, "a-late", "queue-position", "2");
+        create_task(&yaks, "b-unordered");
+        set_field(&yaks, "b-unordered", "assigned-to", "bot");
+        create_task(&yaks, "c-first");
+        set_field(&yaks, "c-first", "assigned-to", "bot");
+        set_field(&yaks, "c-first", "queue-position", "1");
+        create_task(&yaks, "d-current");
+        set_field(&yaks, "d-current", "assigned-to", "bot");
+        set_field(&yaks, "d-current", "state", "wip");
+        create_task(&yaks, "e-finished");
+        set_field(&yaks, "e-finished", "assigned-to", "bot");
+        set_field(&yaks, "e-finished", "state", "done");
+        let state = refreshed_state(&yaks);
+
+        let queues = agent_queues(&state.tasks);
+        assert_eq!(queues.len(), 1);
+        let names = |indices: &[usize]| -> Vec<String> {
+            indices
+                .iter()
+                .map(|&i| state.tasks[i].path.clone())
+                .collect()
+        };
+        assert_eq!(names(&queues[0].wip), vec!["d-current"]);
+        assert_eq!(
+            names(&queues[0].queued),
+            vec!["c-first", "a-late", "b-unordered"]
+        );
+    }
+
+    #[test]
+    fn agent_queue_view_enter_jumps_to_task_in_tree() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/queued");
+        set_field(&yaks, "epic/queued", "assigned-to", "bot");
+        create_task(&yaks, "other");
+        let mut state = refreshed_state(&yaks);
+        state.collapsed.insert("epic".to_string());
+        state.update_visible(None);
+
+        state.open_view(View::AgentQueues);
+        assert_eq!(state.view_task_rows().len(), 1);
+        state.handle_view_key(&KeyWithModifier::new(BareKey::Enter));
+
+        assert_eq!(state.view, View::Tree);
+        assert_eq!(selected_path(&state), "epic/queued");
+    }
+
+    #[test]
+    fn refresh_records_changes_after_the_first_snapshot() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        create_task(&yaks, "old");
+        let mut state = refreshed_state(&yaks);
+        assert!(state.changes.is_empty());
+
+        set_field(&yaks, "api", "state", "wip");
+        set_field(&yaks, "api", "assigned-to", "alice");
+        create_task(&yaks, "new");
+        fs::remove_dir_all(yaks.join("old")).unwrap();
+        state.refresh_tasks();
+
+        let kinds: Vec<(&str, &ChangeKind)> = state
+            .changes
+            .iter()
+            .map(|c| (c.path.as_str(), &c.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    "api",
+                    &ChangeKind::State {
+                        from: TaskState::Todo,
+                        to: TaskState::Wip
+                    }
+                ),
+                (
+                    "api",
+                    &ChangeKind::Assigned {
+                        from: None,
+                        to: Some("alice".to_string())
+                    }
+                ),
+                ("new", &ChangeKind::Created),
+                ("old", &ChangeKind::Removed),
+            ]
+        );
+    }
+
+    #[test]
+    fn change_rows_are_newest_first_within_the_window() {
+        let mut state = state_with_tasks(2);
+        state.config.changes_window_mins = 10;
+        for (at, path) in [(0, "task-0"), (1000, "task-1"), (1100, "task-0")] {
+            state.changes.push_back(Change {
+                at,
+                path: path.to_string(),
+                kind: ChangeKind::Created,
+            });
+        }
+        let rows = state.change_rows(1200);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].1, Some(0));
+        assert_eq!(strip_ansi(&rows[1].0), "  3m ago  task-1  created");
+    }
+
+    #[test]
+    fn wide_rows_show_the_last_line_of_agent_log() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let log = format!(
+            "{}\nrunning tests\n\x1b[32m42 passed\x1b[0m\n\n",
+            "x".repeat(8000)
+        );
+        set_field(&yaks, "api", AGENT_LOG, &log);
+        let mut state = refreshed_state(&yaks);
+        assert_eq!(state.tasks[0].last_log_line.as_deref(), Some("42 passed"));
+        assert_eq!(
+            snapshot_task(&state.repository, "api").last_log_line,
+            state.tasks[0].last_log_line
+        );
+
+        let task = state.tasks[0].clone();
+        state.pane_cols = 80;
+        assert!(!strip_ansi(&state.render_task(&task)).contains("42 passed"));
+        state.pane_cols = WIDE_MODE_COLS;
+        assert!(strip_ansi(&state.render_task(&task)).ends_with(" 42 passed"));
+    }
+
+    #[test]
+    fn status_line_names_the_build_directory_and_last_refresh() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        let line = strip_ansi(&state.status_line());
+        let expected = format!("yak-map {} · {} · refreshed ", VERSION, yaks.display());
+        assert!(line.starts_with(&expected));
+        assert!(line.ends_with("ms"));
+
+        state.host_yaks_dir = Some(PathBuf::from("/home/me/project/.yaks"));
+        state.last_refresh = Some((3600 * 10 + 61, 7));
+        let line = strip_ansi(&state.status_line());
+        assert!(line.ends_with(" · /home/me/project/.yaks · refreshed 10:01:01 in 7ms"));
+    }
+
+    #[test]
+    fn tiny_panes_show_a_one_line_summary() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["api/retries", "api/auth", "docs"] {
+            create_task(&yaks, path);
+        }
+        set_field(&yaks, "api/retries", "state", "wip");
+        set_field(&yaks, "api/retries", "assigned-to", "alice");
+        set_field(&yaks, "api/retries", "agent-status", "wip: tests");
+        set_field(&yaks, "docs", "agent-status", "blocked: needs review");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "api/retries");
+        assert_eq!(
+            strip_ansi(&state.status_bar_line()),
+            "1 wip · 1 blocked │ ▶ api/retries [alice] wip: tests"
+        );
+        state.run_command_line("nope");
+        assert_eq!(
+            strip_ansi(&state.status_bar_line()),
+            "Unknown command: nope"
+        );
+        state.toast_message = None;
+        state.start_filter_input();
+        assert!(strip_ansi(&state.status_bar_line()).starts_with('/'));
+    }
+
+    #[test]
+    fn wide_panes_put_the_preview_beside_the_tree() {
+        let mut state = state_with_tasks(3);
+        state.config.preview_ratio = DEFAULT_PREVIEW_RATIO;
+        assert_eq!(state.side_preview_cols(120), None);
+        assert_eq!(state.side_preview_cols(150), Some(60));
+        state.config.preview_split = PreviewSplit::Bottom;
+        assert_eq!(state.side_preview_cols(200), None);
+        state.config.preview_split = PreviewSplit::Right;
+        state.config.preview_ratio = 0.8;
+        assert_eq!(state.side_preview_cols(100), Some(57));
+        assert_eq!(state.side_preview_cols(50), None);
+        state.render_tree(10, 150);
+        assert!(state.side_panel.borrow().is_none());
+
+        assert_eq!(fit_ansi("\x1b[1mtoolong\x1b[0m", 4), "\x1b[1mtool\x1b[0m");
+        assert_eq!(fit_ansi("ab", 4), "ab  ");
+        let line = side_by_side("\x1b[32mtask\x1b[0m", 6, "# Context");
+        assert_eq!(strip_ansi(&line), "task   │ # Context");
+    }
+
+    #[test]
+    fn preview_lines_render_the_selected_context() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        create_task(&yaks, "docs");
+        set_field(
+            &yaks,
+            "api",
+            "context.md",
+            "# Retries\n- [ ] backoff\nmore\n",
+        );
+        let mut state = refreshed_state(&yaks);
+
+        assert_eq!(
+            state.preview_lines(2),
+            vec![
+                "\x1b[1m\x1b[4mRetries\x1b[0m".to_string(),
+                "☐ backoff".to_string()
+            ]
+        );
+        state.config.plain = true;
+        assert_eq!(state.preview_lines(1), vec!["# Retries".to_string()]);
+        select_path(&mut state, "docs");
+        assert_eq!(
+            strip_ansi(&state.preview_lines(8).join("")),
+            "No context.md."
+        );
+    }
+
+    #[test]
+    fn branch_name_fills_the_template() {
+        let task = TaskLine {
+            name: "Fix the API: retries!".to_string(),
+            yak_id: "api-7".to_string(),
+            assigned_to: Some("alice".to_string()),
+            ..TaskLine::default()
+        };
+        assert_eq!(branch_name("yak/{id}", &task), "yak/api-7");
+        assert_eq!(
+            branch_name("{assignee}/{id}-{slug}", &task),
+            "alice/api-7-fix-the-api-retries"
+        );
+        assert_eq!(branch_name("feature/{id} wip", &task), "feature/api-7-wip");
+        assert_eq!(
+            Config::from_map(&BTreeMap::new()).branch_template,
+            "yak/{id}"
+        );
+    }
+
+    #[test]
+    fn switch_branch_result_records_the_branch_or_reports_failure() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        let context = BTreeMap::from([
+            ("action".to_string(), "switch-branch".to_string()),
+            ("task".to_string(), "api".to_string()),
+            ("branch".to_string(), "yak/api".to_string()),
+        ]);
+
+        let stderr = b"fatal: a branch named 'yak/api' already exists\n";
+        assert!(state.handle_command_result(Some(128), stderr, &context));
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("git switch failed: fatal: a branch named 'yak/api' already exists")
+        );
+        assert_eq!(state.repository.get_field("api", "branch"), None);
+
+        assert!(state.handle_command_result(Some(0), b"", &context));
+        assert_eq!(
+            state.repository.get_field("api", "branch").as_deref(),
+            Some("yak/api")
+        );
+        assert_eq!(state.toast_message.as_deref(), Some("Switched to yak/api"));
+        assert!(!state.handle_command_result(Some(0), b"", &BTreeMap::new()));
+    }
+
+    #[test]
+    fn notes_are_counted_listed_and_not_subtasks() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api/child/.hidden");
+        let repo = TaskRepository::new(yaks.clone());
+        repo.create_note("api", "2026-10-16-1032", "api — first")
+            .unwrap();
+        let second = repo
+            .create_note("api", "2026-10-16-1032", "api — again")
+            .unwrap();
+        set_field(&yaks, "api/notes", "scratch.txt", "not a note");
+        let mut state = refreshed_state(&yaks);
+
+        assert!(second.ends_with("notes/2026-10-16-1032-2.md"));
+        assert_eq!(fs::read_to_string(&second).unwrap(), "# api — again\n\n");
+        assert_eq!(
+            snapshot_paths(&repo),
+            vec![("api".to_string(), 0), ("api/child".to_string(), 1)]
+        );
+        assert_eq!(state.tasks.len(), 2);
+        assert_eq!(state.tasks[0].note_count, 2);
+        assert_eq!(snapshot_task(&repo, "api").note_count, 2);
+        assert!(strip_ansi(&state.render_task(&state.tasks[0])).contains("api ✎2"));
+        assert_eq!(
+            strip_ansi(&state.preview_lines(8).join("\n")),
+            "No context.md.\nNotes (n: new): 2026-10-16-1032-2.md, 2026-10-16-1032.md"
+        );
+        state.config.plain = true;
+        assert!(state
+            .render_task(&state.tasks[0])
+            .ends_with("api (2 notes)"));
+    }
+
+    #[test]
+    fn format_date_handles_offsets_and_leap_years() {
+        assert_eq!(format_date(0, 0), "1970-01-01");
+        assert_eq!(format_date(0, -60), "1969-12-31");
+        assert_eq!(format_date(951_782_400, 0), "2000-02-29");
+        assert_eq!(format_date(1_792_108_800, 0), "2026-10-16");
     }
 
     #[test]
-    fn list_tasks_finds_root_level_task() {
+    fn blocked_by_resolves_ids_and_dims_waiting_tasks() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
+        for (path, id) in [("api", "api-1"), ("db", "db-2"), ("ui", "ui-3")] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "id", id);
+        }
+        set_field(&yaks, "ui", "blocked-by", "api-1, db-2\nghost");
+        set_field(&yaks, "db", "state", "done");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "ui");
+        let ui = state.selected_task().unwrap();
+
+        assert_eq!(ui.blocked_by, vec!["api-1", "db-2", "ghost"]);
+        assert_eq!(ui.blockers.len(), 2);
+        assert_eq!(ui.problems, vec!["blocked-by: no yak with id \"ghost\""]);
+        assert_eq!(state.open_blockers(ui).len(), 1);
+        let row = state.render_task(ui);
+        assert!(row.contains("\x1b[2mui\x1b[22m"));
+        assert!(strip_ansi(&row).contains("ui ⇠"));
+        let preview = strip_ansi(&state.preview_lines(8).join("\n"));
+        assert!(preview.ends_with("Blocked by: api, db (done)"));
+
+        set_field(&yaks, "api", "state", "done");
+        state.refresh_tasks();
+        let ui = state.selected_task().unwrap();
+        assert!(state.open_blockers(ui).is_empty());
+        assert!(!state.render_task(ui).contains("\x1b[2mui"));
+    }
 
-        let repo = TaskRepository::new(yaks);
-        let tasks = repo.list_tasks();
+    #[test]
+    fn dependency_cycles_are_reported_as_problems() {
+        let (_temp, yaks) = mock_yaks();
+        for (path, blocked_by) in [("a", "c"), ("b", "a"), ("c", "b"), ("d", "a"), ("e", "e")] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "blocked-by", blocked_by);
+        }
+        let state = refreshed_state(&yaks);
 
-        assert_eq!(tasks.len(), 1);
-        assert_eq!(tasks[0], ("my-task".to_string(), 0));
+        assert_eq!(
+            dependency_cycles(&state.tasks),
+            vec![vec![0, 1, 2], vec![4]]
+        );
+        for task in &state.tasks[..3] {
+            assert_eq!(
+                task.problems,
+                vec!["blocked-by: part of a dependency cycle (a, b, c)"]
+            );
+        }
+        assert!(state.tasks[3].problems.is_empty());
+        assert_eq!(state.tasks[4].problems, vec!["blocked-by: blocks itself"]);
+        assert!(strip_ansi(&state.render_task(&state.tasks[0])).contains('!'));
     }
 
     #[test]
-    fn list_tasks_finds_nested_task() {
+    fn critical_path_follows_the_heaviest_open_chain() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "parent/child/grandchild");
+        // design -> api -> release, design -> docs -> release; api is heavier.
+        for (path, blocked_by, estimate) in [
+            ("api", "design", "5"),
+            ("design", "", "2"),
+            ("docs", "design", "1"),
+            ("old", "", "9"),
+            ("release", "api docs", ""),
+        ] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "blocked-by", blocked_by);
+            set_field(&yaks, path, "estimate", estimate);
+        }
+        set_field(&yaks, "old", "state", "done");
+        let mut state = refreshed_state(&yaks);
+        let names = |state: &State| -> Vec<String> {
+            state
+                .critical_path
+                .iter()
+                .map(|&i| state.tasks[i].name.clone())
+                .collect()
+        };
 
-        let repo = TaskRepository::new(yaks);
-        let tasks = repo.list_tasks();
+        assert_eq!(names(&state), vec!["design", "api", "release"]);
+        assert!(state.header_line().is_none());
+        state.show_critical_path = true;
+        assert_eq!(
+            strip_ansi(&state.header_line().unwrap()),
+            "Critical path 3 tasks, 8 to release"
+        );
+        assert!(strip_ansi(&state.render_task(&state.tasks[0])).contains("api ⇠ ◆"));
+        assert!(!strip_ansi(&state.render_task(&state.tasks[2])).contains('◆'));
 
-        // Should find all three levels (parent, child, grandchild)
-        assert_eq!(tasks.len(), 3);
-        let paths: Vec<_> = tasks.iter().map(|(p, _)| p.as_str()).collect();
-        assert!(paths.contains(&"parent"));
-        assert!(paths.contains(&"parent/child"));
-        assert!(paths.contains(&"parent/child/grandchild"));
+        set_field(&yaks, "design", "state", "done");
+        set_field(&yaks, "api", "state", "done");
+        state.refresh_tasks();
+        assert_eq!(names(&state), vec!["docs", "release"]);
+        set_field(&yaks, "docs", "estimate", "lots");
+        state.refresh_tasks();
+        let problem = "estimate: \"lots\" is not a non-negative number";
+        assert_eq!(state.tasks[2].problems, vec![problem]);
+    }
+
+    #[test]
+    fn parse_date_round_trips_and_rejects_bad_days() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2026-10-16"), Some(20_742));
+        assert_eq!(format_day(20_742), "2026-10-16");
+        assert_eq!(
+            parse_date("2024-02-29").map(format_day).as_deref(),
+            Some("2024-02-29")
+        );
+        assert_eq!(parse_date("2025-02-29"), None);
+        assert_eq!(parse_date("next week"), None);
+        assert_eq!(
+            parse_date("9999-12-31").map(format_day).as_deref(),
+            Some("9999-12-31")
+        );
+        assert_eq!(parse_date("10000-01-01"), None);
+        assert_eq!(parse_date("9223372036854775807-01-01"), None);
     }
 
     #[test]
-    fn list_tasks_finds_multiple_tasks_at_different_depths() {
+    fn timeline_rows_draw_bars_up_to_the_due_date() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "task-a");
-        create_task(&yaks, "parent/task-b");
-        create_task(&yaks, "parent/child/task-c");
+        for (path, due, estimate) in [("late", "2026-10-20", "3"), ("soon", "2026-10-17", "")] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "due", due);
+            set_field(&yaks, path, "estimate", estimate);
+        }
+        create_task(&yaks, "undated");
+        set_field(&yaks, "undated", "due", "someday");
+        let mut state = refreshed_state(&yaks);
+        let today = parse_date("2026-10-16").unwrap();
+
+        let rows = state.timeline_rows(today, 36);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(strip_ansi(&rows[0].0).trim(), "|10-14 |10-21");
+        assert_eq!(
+            rows[1].1,
+            state.row_of_path("soon").map(|r| state.visible[r])
+        );
+        // Days from 10-14: today (10-16) is the third column.
+        let bars: Vec<String> = rows[1..].iter().map(|(line, _)| strip_ansi(line)).collect();
+        assert_eq!(bars[0], format!("{:<20}   │█{}", "soon", " ".repeat(10)));
+        assert_eq!(bars[1], format!("{:<20}   │ ███{}", "late", " ".repeat(7)));
+        assert_eq!(
+            state.tasks[2].problems,
+            vec!["due: \"someday\" is not a YYYY-MM-DD date"]
+        );
 
-        let repo = TaskRepository::new(yaks);
-        let tasks = repo.list_tasks();
+        state.timeline_shift = 7;
+        assert_eq!(
+            state.timeline_start(today),
+            parse_date("2026-10-21").unwrap()
+        );
+    }
 
-        // Should find all 5 tasks (task-a, parent, parent/task-b, parent/child, parent/child/task-c)
-        assert_eq!(tasks.len(), 5);
-        let paths: Vec<_> = tasks.iter().map(|(p, _)| p.as_str()).collect();
-        assert!(paths.contains(&"task-a"));
-        assert!(paths.contains(&"parent"));
-        assert!(paths.contains(&"parent/task-b"));
-        assert!(paths.contains(&"parent/child"));
-        assert!(paths.contains(&"parent/child/task-c"));
+    #[test]
+    fn calendar_columns_place_due_tasks_by_day() {
+        let (_temp, yaks) = mock_yaks();
+        for (path, due) in [
+            ("late", "2026-10-10"),
+            ("shipped", "2026-10-11"),
+            ("today", "2026-10-16"),
+            ("tuesday", "2026-10-20"),
+            ("next-week", "2026-10-27"),
+        ] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "due", due);
+        }
+        set_field(&yaks, "shipped", "state", "done");
+        let mut state = refreshed_state(&yaks);
+        let today = parse_date("2026-10-16").unwrap();
+        let names = |state: &State| -> Vec<Vec<String>> {
+            state
+                .calendar_columns(today)
+                .into_iter()
+                .map(|(_, tasks)| tasks.iter().map(|&i| state.tasks[i].name.clone()).collect())
+                .collect()
+        };
+
+        let week = names(&state);
+        assert_eq!(week.len(), 7);
+        assert_eq!(week[0], vec!["late", "today"]);
+        assert_eq!(week[4], vec!["tuesday"]);
+        state.calendar_fortnight = true;
+        let fortnight = names(&state);
+        assert_eq!(fortnight.len(), 14);
+        assert_eq!(fortnight[11], vec!["next-week"]);
     }
 
     #[test]
-    fn get_field_returns_none_for_missing_field() {
+    fn repeat_parses_and_steps_over_month_ends() {
+        assert_eq!(Repeat::parse("Weekly"), Some(Repeat::Days(7)));
+        assert_eq!(Repeat::parse("every 3d"), Some(Repeat::Days(3)));
+        assert_eq!(Repeat::parse("every 2 weeks"), Some(Repeat::Days(14)));
+        assert_eq!(Repeat::parse("every month"), Some(Repeat::Months(1)));
+        assert_eq!(Repeat::parse("every 0d"), None);
+        assert_eq!(Repeat::parse("fortnightly"), None);
+
+        let day = |s: &str| parse_date(s).unwrap();
+        assert_eq!(Repeat::Months(1).next(day("2026-01-31")), day("2026-02-28"));
+        assert_eq!(
+            Repeat::Months(12).next(day("2024-02-29")),
+            day("2025-02-28")
+        );
+        let next = Repeat::Days(7).next_after(day("2026-10-01"), day("2026-10-16"));
+        assert_eq!(next, day("2026-10-22"));
+    }
+
+    #[test]
+    fn done_recurring_tasks_are_archived_and_recreated() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
+        create_task(&yaks, "ops/backups/check");
+        for (field, value) in [
+            ("state", "done"),
+            ("repeat", "weekly"),
+            ("due", "2099-01-01"),
+            ("assigned-to", "bob"),
+            ("agent-status", "done: verified"),
+            ("context.md", "Run the restore drill."),
+        ] {
+            set_field(&yaks, "ops/backups", field, value);
+        }
+        let state = refreshed_state(&yaks);
 
-        let repo = TaskRepository::new(yaks);
-        assert!(repo.get_field("my-task", "state").is_none());
+        let archived = yaks.join(".archive/ops/backups@2099-01-01");
+        assert_eq!(fs::read_to_string(archived.join("state")).unwrap(), "done");
+        assert!(archived.join("check").is_dir());
+        let task = state
+            .tasks
+            .iter()
+            .find(|t| t.path == "ops/backups")
+            .unwrap();
+        assert_eq!(task.state, TaskState::Todo);
+        assert_eq!(task.due, parse_date("2099-01-08"));
+        assert_eq!(task.assigned_to.as_deref(), Some("bob"));
+        assert_eq!(task.agent_status, None);
+        assert!(!task.has_children);
+        assert_eq!(
+            fs::read_to_string(yaks.join("ops/backups/context.md")).unwrap(),
+            "Run the restore drill."
+        );
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Next backups due 2099-01-08")
+        );
+        assert!(!yaks
+            .join(".archive/ops/backups@2099-01-01.partial")
+            .exists());
+
+        // A pane that read the tree before the roll-over leaves it alone
+        let rolled = state
+            .repository
+            .roll_over("ops/backups", "2099-01-01", 0)
+            .unwrap();
+        assert_eq!(rolled, None);
+        assert!(!yaks.join(".archive/ops/backups@2099-01-01-2").exists());
+        assert_eq!(
+            state.repository.get_field("ops/backups", "due").as_deref(),
+            Some("2099-01-08")
+        );
     }
 
     #[test]
-    fn get_field_returns_value_for_present_field() {
+    fn snoozed_tasks_are_hidden_until_their_defer_date() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-        set_field(&yaks, "my-task", "state", "wip");
+        create_task(&yaks, "infra/api/retries");
+        create_task(&yaks, "docs");
+        set_field(&yaks, "infra/api", "defer", "2099-01-01");
+        let mut state = refreshed_state(&yaks);
+        assert_eq!(state.row_of_path("infra/api"), None);
+        assert_eq!(state.row_of_path("infra/api/retries"), None);
+        assert!(state.header_line().unwrap().contains("1 snoozed"));
+
+        state.handle_sequence('z', &KeyWithModifier::new(BareKey::Char('S')));
+        assert!(state.row_of_path("infra/api/retries").is_some());
+
+        set_field(&yaks, "infra/api", "defer", "2000-01-01");
+        state.refresh_tasks();
+        assert!(state.flashed.contains_key("infra/api"));
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("api is back from snooze")
+        );
+        assert!(state.snoozed.is_empty());
+    }
+
+    #[test]
+    fn snooze_picker_writes_defer_from_a_typed_date() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "docs");
+        let mut state = refreshed_state(&yaks);
+        state.handle_sequence('z', &KeyWithModifier::new(BareKey::Char('s')));
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Down));
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Down));
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Enter));
+        assert!(matches!(state.mode, Mode::Prompt(_)));
+        for c in "2099-03-01".chars() {
+            state.handle_prompt_key(&KeyWithModifier::new(BareKey::Char(c)));
+        }
+        state.handle_prompt_key(&KeyWithModifier::new(BareKey::Enter));
+
+        assert_eq!(
+            fs::read_to_string(yaks.join("docs/defer")).unwrap(),
+            "2099-03-01\n"
+        );
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Snoozed docs until 2099-03-01")
+        );
+        assert_eq!(parse_snooze("2 weeks", 100), Some(114));
+        assert_eq!(parse_snooze("soon", 100), None);
+    }
+
+    #[test]
+    fn overdue_tasks_are_announced_once() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "old");
+        create_task(&yaks, "api");
+        set_field(&yaks, "old", "due", "2000-01-01");
+        let mut state = refreshed_state(&yaks);
+        assert!(state.notifications.is_empty());
+
+        set_field(&yaks, "api", "due", "2001-02-03");
+        set_field(&yaks, "api", "assigned-to", "alice");
+        state.refresh_tasks();
+        state.refresh_tasks();
+        assert_eq!(state.notifications.len(), 1);
+        assert_eq!(
+            state.notifications[0].body,
+            "api (alice) was due 2001-02-03"
+        );
+        assert_eq!(state.notifications[0].event, NotifyEvent::Overdue);
+    }
+
+    #[test]
+    fn stale_wip_tasks_are_flagged_and_listed_as_stalled() {
+        let (_temp, yaks) = mock_yaks();
+        for (path, state) in [
+            ("api", "wip"),
+            ("docs", "wip"),
+            ("old", "done"),
+            ("new", "todo"),
+        ] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "state", state);
+        }
+        set_field(&yaks, "api", "assigned-to", "alice");
+        let mut state = refreshed_state(&yaks);
+        assert!(state.tasks.iter().all(|t| t.state_since.is_some()));
+
+        let now = 1_000_000;
+        state.config.stale_wip_hours = 24;
+        state.tasks[0].state_since = Some(now - 3 * 86400);
+        state.tasks[1].state_since = Some(now - 3600);
+        let api = &state.tasks[0];
+        assert_eq!(state.stalled_for(api, now), Some(3 * 86400));
+        assert_eq!(state.stalled_for(&state.tasks[1], now), None);
+
+        let rows: Vec<_> = state
+            .stats_rows(now)
+            .into_iter()
+            .map(|(line, task)| (strip_ansi(&line), task))
+            .collect();
+        assert_eq!(rows[0].0, "1 todo · 2 wip · 1 done (25% done)");
+        assert_eq!(rows[3].0, "Stalled (wip for over 24h)");
+        assert_eq!(rows[4], ("  api  wip 3d ago  alice".to_string(), Some(0)));
+        assert_eq!(rows.len(), 5);
+    }
+
+    #[test]
+    fn about_view_lists_counts_permissions_and_configuration() {
+        let mut state = state_with_tasks(3);
+        state.visible = vec![0, 2];
+        state.run_command_line("about");
+        assert!(state.view == View::About);
+
+        let lines: Vec<String> = state
+            .about_rows()
+            .into_iter()
+            .map(|(line, _)| strip_ansi(&line))
+            .collect();
+        assert!(lines.contains(&"Tasks: 3 (2 shown)".to_string()));
+        assert!(lines.contains(&"Last refresh: not yet".to_string()));
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("Permissions: not answered yet")));
+        assert_eq!(lines.last().map(String::as_str), Some("  none"));
+
+        state.permissions = Some(PermissionStatus::Granted);
+        state
+            .configuration
+            .insert("scroll_margin".to_string(), "4".to_string());
+        let lines: Vec<String> = state
+            .about_rows()
+            .into_iter()
+            .map(|(line, _)| strip_ansi(&line))
+            .collect();
+        assert!(lines.iter().any(|l| l.starts_with("Permissions: granted")));
+        assert_eq!(
+            lines.last().map(String::as_str),
+            Some("  scroll_margin = 4")
+        );
+    }
+
+    #[test]
+    fn project_config_file_is_flattened_and_layered_under_the_layout() {
+        let (_temp, yaks) = mock_yaks();
+        let repository = TaskRepository::new(yaks.clone());
+        assert_eq!(repository.load_config_file(), Ok(BTreeMap::new()));
+
+        fs::write(
+            repository.config_path(),
+            "# project defaults\n\
+             theme = \"high-contrast\"\n\
+             scroll_margin = 4 # rows\n\
+             fields = [\"priority\", \"owner\"]\n\
+             \n\
+             [tab]\n\
+             alice = \"todo wip @alice\"\n\
+             [agent_color]\n\
+             \"bob\" = '#ff8800'\n",
+        )
+        .unwrap();
+        let project = repository.load_config_file().unwrap();
+        assert_eq!(project["fields"], "priority,owner");
+        assert_eq!(project["tab.alice"], "todo wip @alice");
+        assert_eq!(project["agent_color.bob"], "#ff8800");
+
+        let layout = BTreeMap::from([("theme".to_string(), "default".to_string())]);
+        let config = Config::from_map(&layer_config(project, layout));
+        assert_eq!(config.scroll_margin, 4);
+        assert_eq!(config.theme, Theme::DEFAULT);
+
+        fs::write(
+            repository.config_path(),
+            "theme = \"default\"\nscroll_margin\n",
+        )
+        .unwrap();
+        let error = repository.load_config_file().unwrap_err();
+        assert!(
+            error.ends_with(".yak-map.toml: line 2: expected key = value"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn project_config_changes_are_picked_up_on_refresh() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        state
+            .layout_configuration
+            .insert("theme".to_string(), "default".to_string());
+
+        let path = state.repository.config_path();
+        fs::write(&path, "scroll_margin = 5\ntheme = \"high-contrast\"\n").unwrap();
+        state.refresh_tasks();
+        assert_eq!(state.config.scroll_margin, 5);
+        assert_eq!(state.config.theme, Theme::DEFAULT);
+        assert_eq!(state.configuration["scroll_margin"], "5");
+
+        fs::write(&path, "scroll_margin = [\n").unwrap();
+        let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(1);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        state.refresh_tasks();
+        assert_eq!(state.config.scroll_margin, 5);
+        assert!(state
+            .toast_message
+            .as_deref()
+            .unwrap()
+            .contains("line 1: bad value"));
+    }
+
+    #[test]
+    fn index_cache_serves_the_first_read_and_skips_unchanged_tasks() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api/retries");
+        set_field(&yaks, "api", "state", "wip");
+        set_field(&yaks, "api", "name", "API\twith a tab");
+        let hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        let touch = |path: &str, time| {
+            fs::File::open(yaks.join(path))
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        for path in ["api/state", "api/name", "api", "api/retries"] {
+            touch(path, hour_ago);
+        }
+
+        let store = DirectoryStore::indexed(yaks.clone());
+        assert!(store.cached_snapshot().is_none());
+        let tasks = store.load_snapshot().unwrap();
+        assert_eq!(tasks[0].name, "API\twith a tab");
+        assert!(yaks.join(".cache/index").exists());
+
+        // A new session starts from the saved index
+        let store = DirectoryStore::indexed(yaks.clone());
+        let cached = store.cached_snapshot().unwrap();
+        assert_eq!(format!("{:?}", cached), format!("{:?}", tasks));
+
+        // A task whose files look untouched is not read again...
+        set_field(&yaks, "api", "state", "done");
+        touch("api/state", hour_ago);
+        assert_eq!(store.load_snapshot().unwrap()[0].state, TaskState::Wip);
+        // ...while a change shows up
+        touch("api/state", hour_ago + std::time::Duration::from_secs(60));
+        assert_eq!(store.load_snapshot().unwrap()[0].state, TaskState::Done);
+        let fresh = DirectoryStore {
+            yaks_dir: yaks,
+            index: None,
+        }
+        .load_snapshot()
+        .unwrap();
+        assert_eq!(
+            format!("{:?}", store.load_snapshot()),
+            format!("{:?}", Ok::<_, String>(fresh))
+        );
+    }
+
+    #[test]
+    fn http_store_shows_the_fetched_snapshot_read_only() {
+        let url = "https://yaks.example.com/snapshot";
+        let store = Box::new(HttpStore::new(url.to_string()));
+        let mut state = State {
+            repository: TaskRepository::with_store(PathBuf::new(), store),
+            ..Default::default()
+        };
+        let body = br#"{"tasks": [
+            {"path": "api/retries", "fields": {"state": "wip", "queue-position": 2}},
+            {"path": "api", "fields": {"name": "API", "assigned-to": null}}
+        ]}"#;
+
+        assert!(state.receive_task_snapshot(200, body));
+        let paths: Vec<_> = state
+            .tasks
+            .iter()
+            .map(|t| (t.path.as_str(), t.depth))
+            .collect();
+        assert_eq!(paths, [("api", 0), ("api/retries", 1)]);
+        assert_eq!(state.tasks[0].name, "API");
+        assert_eq!(state.tasks[0].assigned_to, None);
+        assert_eq!(state.tasks[1].state, TaskState::Wip);
+        assert_eq!(state.tasks[1].queue_position, Some(2));
+        let error = state
+            .repository
+            .set_field("api", "state", "done")
+            .unwrap_err();
+        assert_eq!(error.to_string(), format!("{} is read-only", url));
+
+        // A failed fetch keeps what was shown and says why
+        state.receive_task_snapshot(503, b"");
+        assert_eq!(state.tasks.len(), 2);
+        assert_eq!(state.toast_message, Some(format!("{}: HTTP 503", url)));
+
+        // Paths that could reach outside .yaks fail the whole snapshot
+        for path in ["../x", "/etc", "", "api/./x", "api//x"] {
+            let body = format!(r#"{{"tasks": [{{"path": {:?}}}]}}"#, path);
+            assert!(parse_task_snapshot(body.as_bytes()).is_err(), "{:?}", path);
+        }
+        let config = Config::from_map(&BTreeMap::from([("task_url".to_string(), url.to_string())]));
+        assert!(config.read_only);
+    }
+
+    #[test]
+    fn yaml_task_file_is_read_and_rewritten() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("tasks.yaml");
+        fs::write(
+            &path,
+            "# Small project\n\
+             - name: Ship the API\n\
+             \x20 state: wip\n\
+             \x20 tasks:\n\
+             \x20   - name: Write the docs\n\
+             \x20     assigned-to: alice # for now\n\
+             \x20 url: \"https://example.com/pr/1\"\n\
+             - name: Release\n",
+        )
+        .unwrap();
+        let store = Box::new(TaskFile::new(path.clone()));
+        let repo = TaskRepository::with_store(PathBuf::new(), store);
+
+        let tasks = repo.load_snapshot().unwrap();
+        let paths: Vec<_> = tasks.iter().map(|t| (t.path.as_str(), t.depth)).collect();
+        assert_eq!(
+            paths,
+            [
+                ("ship-the-api", 0),
+                ("ship-the-api/write-the-docs", 1),
+                ("release", 0)
+            ]
+        );
+        assert_eq!(tasks[0].state, TaskState::Wip);
+        assert_eq!(tasks[0].url.as_deref(), Some("https://example.com/pr/1"));
+        assert_eq!(tasks[1].assigned_to.as_deref(), Some("alice"));
+
+        assert_eq!(
+            repo.add_task("release", "Tag: v1").unwrap(),
+            "release/tag-v1"
+        );
+        repo.set_field("ship-the-api/write-the-docs", "state", "done")
+            .unwrap();
+        let moved = repo
+            .move_task("ship-the-api/write-the-docs", "release")
+            .unwrap();
+        assert_eq!(moved, "release/write-the-docs");
+        repo.remove_task("ship-the-api").unwrap();
+        assert!(repo.set_field("ship-the-api", "state", "done").is_err());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "- name: Release\n\
+             \x20 tasks:\n\
+             \x20   - name: \"Tag: v1\"\n\
+             \x20     state: todo\n\
+             \x20   - name: Write the docs\n\
+             \x20     assigned-to: alice\n\
+             \x20     state: done\n"
+        );
+        let assignee = repo.get_field("release/write-the-docs", "assigned-to");
+        assert_eq!(assignee.as_deref(), Some("alice"));
+
+        fs::write(&path, "- name: A\n  notes: |\n").unwrap();
+        let error = repo.load_snapshot().unwrap_err();
+        assert!(
+            error.ends_with("tasks.yaml: line 2: unsupported value"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn checklist_task_file_keeps_other_lines() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("TASKS.md");
+        fs::write(
+            &path,
+            "# Tasks\n\n- [ ] Ship\n  Needs a review first.\n  - [x] Build\n  - [/] Test\n",
+        )
+        .unwrap();
+        let store = Box::new(TaskFile::new(path.clone()));
+        let repo = TaskRepository::with_store(PathBuf::new(), store);
+
+        let tasks = repo.load_snapshot().unwrap();
+        let states: Vec<_> = tasks.iter().map(|t| (t.path.as_str(), t.state)).collect();
+        assert_eq!(
+            states,
+            [
+                ("ship", TaskState::Todo),
+                ("ship/build", TaskState::Done),
+                ("ship/test", TaskState::Wip)
+            ]
+        );
+
+        repo.set_field("ship/test", "state", "done").unwrap();
+        let error = repo.set_field("ship", "assigned-to", "alice").unwrap_err();
+        assert!(error
+            .to_string()
+            .ends_with("TASKS.md only keeps names and states"));
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Tasks\n\n- [ ] Ship\n  Needs a review first.\n  - [x] Build\n  - [x] Test\n"
+        );
+    }
+
+    #[test]
+    fn url_field_is_read_and_marked() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        set_field(&yaks, "api", "url", "https://example.com/pr/1\n");
+        let mut state = refreshed_state(&yaks);
+
+        assert_eq!(
+            state.tasks[0].url.as_deref(),
+            Some("https://example.com/pr/1")
+        );
+        assert!(strip_ansi(&state.render_task(&state.tasks[0])).contains("api ⎘"));
+        assert!(state.repository.task_files("api").is_empty());
+        state.config.plain = true;
+        assert!(state.render_task(&state.tasks[0]).ends_with("api (link)"));
+
+        assert_eq!(Config::from_map(&BTreeMap::new()).opener, vec!["xdg-open"]);
+        let map = BTreeMap::from([("opener".to_string(), "open -g".to_string())]);
+        assert_eq!(Config::from_map(&map).opener, vec!["open", "-g"]);
+    }
+
+    #[test]
+    fn task_files_are_listed_in_the_preview_and_picker() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api/child");
+        set_field(&yaks, "api", "state", "wip");
+        set_field(&yaks, "api", "context.md", "notes");
+        set_field(&yaks, "api", "design.svg", "<svg/>");
+        set_field(&yaks, "api", "run.log", "ok");
+        set_field(&yaks, "api", ".swp", "");
+        fs::create_dir_all(yaks.join("api/.attachments")).unwrap();
+        set_field(&yaks, "api/.attachments", "spec.pdf", "%PDF");
+        set_field(&yaks, "api/child", "notes.txt", "child's own");
+        let mut state = refreshed_state(&yaks);
+
+        assert_eq!(
+            state.repository.task_files("api"),
+            vec![".attachments/spec.pdf", "design.svg", "run.log"]
+        );
+        assert_eq!(
+            strip_ansi(&state.preview_lines(1).join("\n")),
+            "Files (o: open): .attachments/spec.pdf, design.svg, run.log"
+        );
+        state.open_files_picker();
+        let Mode::Picker(picker) = &state.mode else {
+            panic!("expected the files picker");
+        };
+        assert_eq!(picker.purpose, PickerPurpose::OpenFile);
+        assert_eq!(picker.items[2].value, "api/run.log");
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Char('e')));
+        assert_eq!(state.mode, Mode::Normal);
+
+        assert_eq!(state.repository.task_files("api/child"), vec!["notes.txt"]);
+        select_path(&mut state, "api/child");
+        fs::remove_file(yaks.join("api/child/notes.txt")).unwrap();
+        state.open_files_picker();
+        assert_eq!(state.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn feed_lines_show_clock_time_and_event() {
+        let mut state = state_with_tasks(0);
+        state.config.utc_offset_mins = 120;
+        let changes = [
+            (
+                ChangeKind::State {
+                    from: TaskState::Wip,
+                    to: TaskState::Done,
+                },
+                "api/retries",
+            ),
+            (
+                ChangeKind::Assigned {
+                    from: None,
+                    to: Some("alice".to_string()),
+                },
+                "infra/tls",
+            ),
+            (ChangeKind::Created, "docs"),
+        ];
+        for (n, (kind, path)) in changes.into_iter().enumerate() {
+            state.changes.push_back(Change {
+                at: 8 * 3600 + 32 * 60 + n as u64 * 180,
+                path: path.to_string(),
+                kind,
+            });
+        }
+        let lines: Vec<String> = state.feed_lines(2).iter().map(|l| strip_ansi(l)).collect();
+        assert_eq!(
+            lines,
+            vec!["10:35 alice assigned infra/tls", "10:38 docs created"]
+        );
+    }
+
+    #[test]
+    fn parse_utc_offset_accepts_common_forms() {
+        assert_eq!(parse_utc_offset("+02:00"), Some(120));
+        assert_eq!(parse_utc_offset("-0530"), Some(-330));
+        assert_eq!(parse_utc_offset("1"), Some(60));
+        assert_eq!(parse_utc_offset("+25"), None);
+        assert_eq!(parse_utc_offset("soon"), None);
+        assert_eq!(format_clock(30 * 60, -60), "23:30");
+    }
+
+    #[test]
+    fn snapshot_reports_unrecognized_state_and_non_utf8_fields() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "odd");
+        set_field(&yaks, "odd", "state", "blocked");
+        fs::write(yaks.join("odd").join("assigned-to"), [0xff, 0xfe]).unwrap();
+
+        let task = snapshot_task(&TaskRepository::new(yaks), "odd");
+        assert_eq!(task.state, TaskState::Todo);
+        assert_eq!(task.assigned_to, None);
+        assert_eq!(
+            task.problems,
+            vec![
+                "assigned-to: not valid UTF-8".to_string(),
+                "state: unrecognized value \"blocked\", shown as todo".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn load_snapshot_reads_every_task_in_tree_order() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/story/leaf");
+        create_task(&yaks, "another");
+        create_task(&yaks, ".hidden");
+        set_field(&yaks, "epic", "state", "wip");
+        set_field(&yaks, "epic/story", "assigned-to", "alice");
+        set_field(&yaks, "epic/story/leaf", "queue-position", "two");
+        set_field(&yaks, "epic/story/leaf", "context.md", "# notes");
 
         let repo = TaskRepository::new(yaks);
-        assert_eq!(repo.get_field("my-task", "state"), Some("wip".to_string()));
+        let snapshot = repo.load_snapshot().unwrap();
+        let paths: Vec<_> = snapshot
+            .iter()
+            .map(|t| (t.path.as_str(), t.depth))
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                ("another", 0),
+                ("epic", 0),
+                ("epic/story", 1),
+                ("epic/story/leaf", 2)
+            ]
+        );
+        assert_eq!(snapshot[1].state, TaskState::Wip);
+        assert_eq!(snapshot[2].assigned_to.as_deref(), Some("alice"));
+        assert_eq!(
+            snapshot[3].problems,
+            vec!["queue-position: \"two\" is not a number"]
+        );
+    }
+
+    #[test]
+    fn diagnostics_view_lists_problems_and_rows_show_a_badge() {
+        let mut state = state_with_tasks(2);
+        state.tasks[1].problems = vec!["state: bad".to_string()];
+        assert!(strip_ansi(&state.render_task(&state.tasks[1])).contains("task-1 !"));
+        assert!(!strip_ansi(&state.render_task(&state.tasks[0])).contains('!'));
+
+        state.open_view(View::Diagnostics);
+        assert_eq!(state.view_task_rows(), vec![1]);
+        assert_eq!(
+            strip_ansi(&state.diagnostic_rows()[0].0),
+            "task-1  state: bad"
+        );
+    }
+
+    #[test]
+    fn log_keeps_a_bounded_ring_and_optionally_writes_a_file() {
+        let (_temp, yaks) = mock_yaks();
+        let mut state = State {
+            repository: TaskRepository::new(yaks.clone()),
+            config: Config {
+                log_file: true,
+                ..Config::default()
+            },
+            ..State::default()
+        };
+        for n in 0..LOG_CAP + 5 {
+            state.log(format!("entry {}", n));
+        }
+        assert_eq!(state.log.len(), LOG_CAP);
+        assert_eq!(state.log.front().unwrap().1, "entry 5");
+
+        let written = fs::read_to_string(yaks.join(".log").join("yak-map.log")).unwrap();
+        assert_eq!(written.lines().count(), LOG_CAP + 5);
+        assert!(written
+            .lines()
+            .last()
+            .unwrap()
+            .ends_with(&format!("entry {}", LOG_CAP + 4)));
+
+        create_task(&yaks, "api");
+        state.refresh_tasks();
+        assert!(state
+            .log
+            .back()
+            .unwrap()
+            .1
+            .starts_with("refresh: 1 tasks in "));
+    }
+
+    #[test]
+    fn config_reads_refresh_secs_with_a_floor() {
+        let refresh_secs = |value: &str| {
+            let mut map = BTreeMap::new();
+            map.insert("refresh_secs".to_string(), value.to_string());
+            Config::from_map(&map).refresh_secs
+        };
+        assert_eq!(Config::from_map(&BTreeMap::new()).refresh_secs, 2.0);
+        assert_eq!(refresh_secs("10"), 10.0);
+        assert_eq!(refresh_secs("0.1"), MIN_REFRESH_SECS);
+        assert_eq!(refresh_secs("often"), 2.0);
+    }
+
+    #[test]
+    fn toggle_pause_shows_indicator_in_header() {
+        let mut state = state_with_tasks(1);
+        assert!(state.header_line().is_none());
+        state.toggle_pause();
+        assert!(state.paused);
+        assert!(strip_ansi(&state.header_line().unwrap()).starts_with("Paused"));
+        state.toggle_pause();
+        assert!(!state.paused);
+    }
+
+    #[test]
+    fn writes_in_a_burst_share_one_deferred_refresh() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "a");
+        let mut state = refreshed_state(&yaks);
+        let start = std::time::Instant::now();
+
+        state.refresh_after_write_at(start);
+        assert!(!state.refresh_pending);
+
+        create_task(&yaks, "b");
+        state.refresh_after_write_at(start + std::time::Duration::from_millis(50));
+        state.refresh_after_write_at(start + std::time::Duration::from_millis(100));
+        assert!(state.refresh_pending);
+        assert!(state.debounce_timer_needed);
+        assert_eq!(state.tasks.len(), 1);
+
+        state.flush_pending_refresh();
+        assert_eq!(state.tasks.len(), 2);
+        assert!(!state.refresh_pending);
+
+        state.refresh_after_write_at(start + std::time::Duration::from_secs(5));
+        assert!(!state.refresh_pending);
+    }
+
+    fn assigned_state(yaks: &Path) -> State {
+        create_task(yaks, "busy-1");
+        set_field(yaks, "busy-1", "assigned-to", "busy");
+        create_task(yaks, "busy-2");
+        set_field(yaks, "busy-2", "assigned-to", "busy");
+        create_task(yaks, "idle-1");
+        set_field(yaks, "idle-1", "assigned-to", "idle");
+        set_field(yaks, "idle-1", "state", "done");
+        create_task(yaks, "new-1");
+        create_task(yaks, "new-2");
+        create_task(yaks, "new-3");
+        refreshed_state(yaks)
     }
 
     #[test]
-    fn get_field_trims_whitespace() {
+    fn agent_loads_count_open_tasks_least_loaded_first() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-        set_field(&yaks, "my-task", "assigned-to", "  alice  \n");
-
-        let repo = TaskRepository::new(yaks);
+        let state = assigned_state(&yaks);
         assert_eq!(
-            repo.get_field("my-task", "assigned-to"),
-            Some("alice".to_string())
+            state.agent_loads(),
+            vec![("idle".to_string(), 0), ("busy".to_string(), 2)]
         );
     }
 
     #[test]
-    fn get_task_assembles_all_fields() {
+    fn assign_picker_suggests_least_loaded_and_writes_field() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-        set_field(&yaks, "my-task", "state", "wip");
-        set_field(&yaks, "my-task", "assigned-to", "bob");
-        set_field(&yaks, "my-task", "agent-status", "wip: implementing");
+        let mut state = assigned_state(&yaks);
+        select_path(&mut state, "new-1");
 
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("my-task", 0);
+        state.open_assign_picker();
+        let Mode::Picker(picker) = &state.mode else {
+            panic!("picker not open");
+        };
+        assert!(picker.items[0].label.contains("least loaded"));
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Enter));
 
-        assert_eq!(task.name, "my-task");
-        assert_eq!(task.depth, 0);
-        assert_eq!(task.state, TaskState::Wip);
-        assert_eq!(task.assigned_to, Some("bob".to_string()));
-        assert_eq!(task.agent_status, Some("wip: implementing".to_string()));
+        assert_eq!(state.mode, Mode::Normal);
+        assert_eq!(
+            state
+                .repository
+                .get_field("new-1", "assigned-to")
+                .as_deref(),
+            Some("idle")
+        );
     }
 
     #[test]
-    fn get_task_defaults_to_todo_when_no_state() {
+    fn assign_picker_offers_configured_agents_and_unassigning() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("my-task", 0);
+        let mut state = assigned_state(&yaks);
+        state.config.agents = vec!["fresh".to_string(), "busy".to_string()];
+        assert_eq!(
+            state.agent_loads(),
+            vec![
+                ("fresh".to_string(), 0),
+                ("idle".to_string(), 0),
+                ("busy".to_string(), 2)
+            ]
+        );
 
-        assert_eq!(task.state, TaskState::Todo);
+        select_path(&mut state, "busy-1");
+        state.open_assign_picker();
+        let Mode::Picker(picker) = &state.mode else {
+            panic!("picker not open");
+        };
+        assert_eq!(picker.items.len(), 4);
+        assert_eq!(picker.items[3].value, "");
+        for _ in 0..3 {
+            state.handle_picker_key(&KeyWithModifier::new(BareKey::Down));
+        }
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Enter));
+        assert_eq!(state.repository.get_field("busy-1", "assigned-to"), None);
+        assert_eq!(state.toast_message.as_deref(), Some("Unassigned"));
     }
 
     #[test]
-    fn task_color_red_for_blocked() {
-        let state = State::default();
-        let task = TaskLine {
-            agent_status: Some("blocked: waiting".to_string()),
-            ..TaskLine::default()
-        };
-        assert_eq!(state.task_color(&task), "\x1b[31m");
+    fn round_robin_spreads_tagged_tasks_over_agents() {
+        let (_temp, yaks) = mock_yaks();
+        let mut state = assigned_state(&yaks);
+        for path in ["new-1", "new-2", "new-3"] {
+            select_path(&mut state, path);
+            state.toggle_tag();
+        }
+
+        state.open_assign_picker();
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Char('r')));
+
+        let agent = |p: &str| state.repository.get_field(p, "assigned-to").unwrap();
+        assert_eq!(agent("new-1"), "idle");
+        assert_eq!(agent("new-2"), "busy");
+        assert_eq!(agent("new-3"), "idle");
+        assert!(state.tagged.is_empty());
     }
 
     #[test]
-    fn task_color_green_for_done() {
-        let state = State::default();
-        let task = TaskLine {
-            agent_status: Some("done: finished".to_string()),
-            ..TaskLine::default()
+    fn wip_limits_warn_on_assignment_and_mark_the_agent() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["api", "docs"] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "state", "wip");
+        }
+        set_field(&yaks, "api", "assigned-to", "alice");
+        let mut map = BTreeMap::new();
+        map.insert("wip_limit".to_string(), "1".to_string());
+        map.insert("wip_limit.bob".to_string(), "0".to_string());
+        let mut state = State {
+            repository: TaskRepository::new(yaks.clone()),
+            config: Config::from_map(&map),
+            ..Default::default()
         };
-        assert_eq!(state.task_color(&task), "\x1b[32m");
+        state.refresh_tasks();
+        assert_eq!(state.config.wip_limit_for("bob"), None);
+        assert!(state.over_wip_limit.is_empty());
+
+        select_path(&mut state, "docs");
+        state.assign(&[("docs".to_string(), "alice".to_string())]);
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Assigned to alice; alice is over the WIP limit (2/1)")
+        );
+        state.refresh_tasks();
+        assert!(state.over_wip_limit.contains("alice"));
+        let header = &state.agent_queue_rows()[0].0;
+        assert!(header.starts_with("\x1b[1;31malice"));
+        assert!(strip_ansi(header).contains("(2/1 wip, 0 queued)"));
     }
 
     #[test]
-    fn task_color_yellow_for_wip() {
-        let state = State::default();
-        let task = TaskLine {
-            agent_status: Some("wip: working".to_string()),
-            ..TaskLine::default()
+    fn parents_are_completed_when_their_last_subtask_is_done_and_undone_with_u() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["epic/a", "epic/b/c"] {
+            create_task(&yaks, path);
+        }
+        set_field(&yaks, "epic", "state", "wip");
+        set_field(&yaks, "epic/a", "state", "done");
+        let mut state = State {
+            repository: TaskRepository::new(yaks.clone()),
+            config: Config {
+                complete_parents: ParentCompletion::Auto,
+                ..Config::default()
+            },
+            ..Default::default()
         };
-        assert_eq!(state.task_color(&task), "\x1b[33m");
+        state.refresh_tasks();
+        assert_eq!(
+            state.repository.get_field("epic", "state").as_deref(),
+            Some("wip")
+        );
+
+        set_field(&yaks, "epic/b", "state", "done");
+        set_field(&yaks, "epic/b/c", "state", "done");
+        state.refresh_tasks();
+        assert_eq!(
+            state.repository.get_field("epic", "state").as_deref(),
+            Some("done")
+        );
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Marked epic done: all subtasks are done (u: undo)")
+        );
+
+        state.undo_last();
+        state.refresh_tasks();
+        assert_eq!(
+            state.repository.get_field("epic", "state").as_deref(),
+            Some("wip")
+        );
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Undid: marked epic done")
+        );
+        assert!(state.undo.is_none());
     }
 
     #[test]
-    fn task_color_yellow_when_state_is_wip() {
-        let state = State::default();
-        let task = TaskLine {
-            state: TaskState::Wip,
-            agent_status: None,
-            ..TaskLine::default()
-        };
-        assert_eq!(state.task_color(&task), "\x1b[33m");
+    fn yx_backend_runs_configured_commands_instead_of_writing_files() {
+        let mut map = BTreeMap::new();
+        map.insert("backend".to_string(), "yx".to_string());
+        map.insert("yx.wip".to_string(), "yx start {id}".to_string());
+        let config = Config::from_map(&map);
+        assert_eq!(config.backend, WriteBackend::Yx);
+        assert_eq!(
+            config.yx_command("api 1", "state", Some("done")),
+            vec!["yx", "done", "api 1"]
+        );
+        assert_eq!(
+            config.yx_command("api", "state", Some("wip")),
+            vec!["yx", "start", "api"]
+        );
+        assert_eq!(
+            config.yx_command("api", "state", Some("todo")),
+            vec!["yx", "field", "api", "state", "todo"]
+        );
+        assert_eq!(
+            config.yx_command("api", "due", None),
+            vec!["yx", "field", "api", "due", "--clear"]
+        );
+
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        state.config = config;
+        state.set_states(&["api".to_string()], "done");
+        assert_eq!(state.repository.get_field("api", "state"), None);
+
+        let context = BTreeMap::from([
+            ("action".to_string(), "yx".to_string()),
+            ("command".to_string(), "yx done api".to_string()),
+        ]);
+        assert!(state.handle_command_result(Some(1), b"no such yak\n", &context));
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("yx failed: no such yak")
+        );
+
+        // Writes with no yx command are refused rather than made behind its back
+        state.create_tasks("", &["web"]);
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("backend=yx: creating tasks disabled")
+        );
+        assert!(!yaks.join("web").exists());
+        create_task(&yaks, "web");
+        state.refresh_tasks();
+        state.move_task("web", "api");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("backend=yx: moving tasks disabled")
+        );
+        assert!(yaks.join("web").is_dir());
+        state.capture_note("api", "retry budget");
+        assert!(!state.repository.context_path("api").exists());
+        state.new_note();
+        assert!(state.repository.note_files("api").is_empty());
+
+        // Nor are recurring tasks rolled over
+        set_field(&yaks, "web", "state", "done");
+        set_field(&yaks, "web", "repeat", "weekly");
+        state.refresh_tasks();
+        assert!(!yaks.join(".archive").exists());
     }
 
     #[test]
-    fn task_color_white_for_todo() {
-        let state = State::default();
-        let task = TaskLine {
-            state: TaskState::Todo,
-            agent_status: None,
-            ..TaskLine::default()
-        };
-        assert_eq!(state.task_color(&task), "\x1b[37m");
+    fn yx_probe_disables_the_yx_backend_when_missing_or_too_old() {
+        assert_eq!(parse_yx_version("yx 0.4.2\n").as_deref(), Some("0.4.2"));
+        assert_eq!(parse_yx_version("yx version v1.0").as_deref(), Some("1.0"));
+        assert!(version_older_than("0.4.2", "0.10"));
+        assert!(!version_older_than("1.0.0", "1.0"));
+
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        state.record_yx_version(None, b"");
+        assert_eq!(state.yx, YxProbe::Missing);
+        assert_eq!(state.toast_message, None);
+
+        state.config.backend = WriteBackend::Yx;
+        state.config.yx_min_version = Some("0.5".to_string());
+        state.record_yx_version(Some(0), b"yx 0.4.2\n");
+        assert_eq!(state.yx_summary(), "yx: 0.4.2");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("yx 0.4.2 is older than yx_min_version 0.5: upgrade it")
+        );
+        assert!(state.store_field("api", "state", Some("done")).is_err());
     }
 
     #[test]
-    fn task_name_extracts_last_path_component() {
+    fn comma_repeats_the_last_change_on_the_selection() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "parent/child/grandchild");
+        for path in ["a", "b", "c"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        state.handle_key(KeyWithModifier::new(BareKey::Char(',')));
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Nothing to repeat yet")
+        );
 
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("parent/child/grandchild", 2);
-        assert_eq!(task.name, "grandchild");
+        state.apply_picker(PickerPurpose::SetState, "wip");
+        state.apply_picker(PickerPurpose::Assign, "alice");
+        select_path(&mut state, "b");
+        state.handle_key(KeyWithModifier::new(BareKey::Char(',')));
+        assert_eq!(
+            state.repository.get_field("b", "assigned-to").as_deref(),
+            Some("alice")
+        );
+        assert_eq!(state.repository.get_field("b", "state"), None);
+
+        state.apply_picker(PickerPurpose::SetState, "wip");
+        select_path(&mut state, "c");
+        state.handle_key(KeyWithModifier::new(BareKey::Char(',')));
+        assert_eq!(
+            state.repository.get_field("c", "state").as_deref(),
+            Some("wip")
+        );
     }
 
     #[test]
-    fn handles_special_characters_in_task_name() {
+    fn marking_a_parent_done_offers_to_cascade_to_subtasks() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "task-with-dashes_and_underscores");
-        set_field(&yaks, "task-with-dashes_and_underscores", "state", "done");
+        for path in ["epic/a", "epic/b/c", "other"] {
+            create_task(&yaks, path);
+        }
+        set_field(&yaks, "epic/a", "state", "done");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "epic");
 
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("task-with-dashes_and_underscores", 0);
+        state.open_state_picker();
+        for _ in 0..2 {
+            state.handle_picker_key(&KeyWithModifier::new(BareKey::Down));
+        }
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Enter));
+        let Mode::Picker(picker) = &state.mode else {
+            panic!("cascade not offered");
+        };
+        assert_eq!(picker.title, "Mark epic done");
+        assert_eq!(picker.items[1].label, "epic and 2 subtasks");
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Enter));
+
+        let repository = TaskRepository::new(yaks.clone());
+        let field = |path: &str| repository.get_field(path, "state");
+        for path in ["epic", "epic/a", "epic/b", "epic/b/c"] {
+            assert_eq!(field(path).as_deref(), Some("done"), "{}", path);
+        }
+        assert_eq!(field("other"), None);
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Marked 3 tasks done (u: undo)")
+        );
 
-        assert_eq!(task.name, "task-with-dashes_and_underscores");
-        assert_eq!(task.state, TaskState::Done);
+        state.undo_last();
+        assert_eq!(field("epic"), None);
+        assert_eq!(field("epic/a").as_deref(), Some("done"));
     }
 
     #[test]
-    fn handles_empty_field_file() {
+    fn fields_are_edited_inline_and_from_the_command_line() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-        set_field(&yaks, "my-task", "assigned-to", "");
+        create_task(&yaks, "api");
+        set_field(&yaks, "api", "priority", "low");
+        set_field(&yaks, "api", "context.md", "# API");
+        let mut state = refreshed_state(&yaks);
+        let fields = state.repository.field_names("api");
+        assert!(fields.contains(&"priority".to_string()) && fields.contains(&"state".to_string()));
+        assert!(!fields.contains(&"context.md".to_string()));
+
+        state.start_field_edit("api", "priority");
+        let Mode::Prompt(prompt) = &state.mode else {
+            panic!("prompt not open");
+        };
+        assert_eq!(prompt.input, "low");
+        for _ in 0..3 {
+            state.handle_prompt_key(&KeyWithModifier::new(BareKey::Backspace));
+        }
+        for c in "high".chars() {
+            state.handle_prompt_key(&KeyWithModifier::new(BareKey::Char(c)));
+        }
+        state.handle_prompt_key(&KeyWithModifier::new(BareKey::Enter));
+        assert_eq!(
+            state.repository.get_field("api", "priority").as_deref(),
+            Some("high")
+        );
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Set priority of api (u: undo)")
+        );
 
-        let repo = TaskRepository::new(yaks);
-        assert_eq!(repo.get_field("my-task", "assigned-to"), None);
+        assert_eq!(
+            state.complete_command_line("set pri").as_deref(),
+            Some("set priority ")
+        );
+        let completed = state.complete_command_line("unset q");
+        assert_eq!(completed.as_deref(), Some("unset queue-position "));
+        assert_eq!(
+            state.complete_command_line("set a").as_deref(),
+            Some("set a")
+        );
+        state.run_command_line("set owner  team infra ");
+        assert_eq!(
+            state.repository.get_field("api", "owner").as_deref(),
+            Some("team infra")
+        );
+        state.run_command_line("unset priority");
+        assert_eq!(state.repository.get_field("api", "priority"), None);
+        state.run_command_line("set ../state done");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Not a field name: \"../state\"")
+        );
+        state.undo_last();
+        assert_eq!(
+            state.repository.get_field("api", "priority").as_deref(),
+            Some("high")
+        );
     }
 
     #[test]
-    fn debug_continuation() {
+    fn command_line_completes_and_recalls_history() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "task-a/child1");
-        create_task(&yaks, "task-a/child2");
-
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
+        create_task(&yaks, "api");
+        create_task(&yaks, "docs");
+        set_field(&yaks, "api", "assigned-to", "alice");
+        set_field(&yaks, "api", "id", "api-1");
+        let mut state = refreshed_state(&yaks);
+        state
+            .views
+            .insert("triage".to_string(), SavedView::default());
+        state.config.agents = vec!["bob".to_string()];
+
+        let complete = |input: &str| state.complete_command_line(input).unwrap_or_default();
+        assert_eq!(complete("u"), "unset ");
+        assert_eq!(complete("set assigned-to a"), "set assigned-to alice ");
+        assert_eq!(complete("set assigned-to b"), "set assigned-to bob ");
+        assert_eq!(
+            complete("set blocked-by docs,ap"),
+            "set blocked-by docs,api-1 "
+        );
+        assert_eq!(complete("view delete t"), "view delete triage ");
+        assert_eq!(complete("import x"), "");
+
+        let command_prompt = |input: &str| {
+            Mode::Prompt(Prompt {
+                title: ":".to_string(),
+                input: input.to_string(),
+                purpose: PromptPurpose::Command,
+                recall: Recall::default(),
+            })
         };
-        state.refresh_tasks();
-
-        for task in &state.tasks {
-            eprintln!(
-                "{}: depth={}, ancestors={:?}, is_last={}",
-                task.path, task.depth, task.ancestor_continuations, task.is_last_sibling
-            );
+        for line in ["set owner infra", "view triage"] {
+            state.mode = command_prompt(line);
+            state.handle_prompt_key(&KeyWithModifier::new(BareKey::Enter));
+        }
+        state.mode = command_prompt("");
+        for key in [BareKey::Up, BareKey::Up, BareKey::Down] {
+            state.handle_prompt_key(&KeyWithModifier::new(key));
         }
+        assert!(matches!(&state.mode, Mode::Prompt(p) if p.input == "view triage"));
     }
 
     #[test]
-    fn tree_prefix_depth_2_parent_has_sibling_shows_continuation() {
+    fn raw_names_show_directory_names() {
         let (_temp, yaks) = mock_yaks();
-        // parent "child" has sibling "child2" under "parent"
-        create_task(&yaks, "parent/child/grandchild");
-        create_task(&yaks, "parent/child2");
-
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
-        };
-        state.refresh_tasks();
-
-        let grandchild = state.tasks.iter().find(|t| t.name == "grandchild").unwrap();
-        // parent "child" has sibling "child2", so continuation line shows
-        let prefix = state.tree_prefix(grandchild);
-        assert_eq!(prefix, "\x1b[90m│ \x1b[0m\x1b[90m╰─\x1b[0m");
+        create_task(&yaks, "infra/api-v2");
+        set_field(&yaks, "infra/api-v2", "name", "API rewrite");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "infra/api-v2");
+        let task = state.selected_task().unwrap().clone();
+        assert!(strip_ansi(&state.render_task(&task)).contains("API rewrite"));
+
+        state.raw_names = true;
+        let row = strip_ansi(&state.render_task(&task));
+        assert!(row.contains("api-v2") && !row.contains("API rewrite"));
+        assert_eq!(strip_ansi(&state.breadcrumb(80).unwrap()), "infra ▸ api-v2");
+        assert!(state.header_line().unwrap().contains("Directory names"));
     }
 
     #[test]
-    fn tree_prefix_depth_2_last_child_has_continuation() {
+    fn field_inspector_lists_values_ages_and_sizes() {
         let (_temp, yaks) = mock_yaks();
-        // Only task-a, no sibling - children have no continuation needed
-        create_task(&yaks, "task-a/child1");
-        create_task(&yaks, "task-a/child2");
-
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
-        };
-        state.refresh_tasks();
-
-        // task-a has no sibling at depth 0, so no continuation
-        let child2 = state.tasks.iter().find(|t| t.name == "child2").unwrap();
-        let prefix = state.tree_prefix(child2);
-        assert_eq!(prefix, "\x1b[90m╰─\x1b[0m");
+        create_task(&yaks, "api");
+        set_field(&yaks, "api", "state", "wip");
+        set_field(&yaks, "api", "agent-status", "wip: tests\nstill running");
+        set_field(&yaks, "api", "owner", "");
+        fs::write(yaks.join("api/blocked-by"), [0xff, 0xfe]).unwrap();
+        let state = refreshed_state(&yaks);
+
+        let rows: Vec<String> = state
+            .field_rows(now_secs())
+            .into_iter()
+            .map(|(line, _)| strip_ansi(&line))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                "agent-status  wip: tests (+1 lines)  just now · 24B",
+                "blocked-by    blocked-by: not valid UTF-8  just now · 2B",
+                "owner         (empty)  just now · 0B",
+                "state         wip  just now · 3B",
+            ]
+        );
     }
 
     #[test]
-    fn tree_prefix_depth_2_no_continuation_when_parent_is_last() {
+    fn capture_appends_timestamped_lines_to_context() {
         let (_temp, yaks) = mock_yaks();
-        // "child" is the only child of "parent", so no continuation column
-        create_task(&yaks, "parent/child/grandchild");
+        create_task(&yaks, "api");
+        set_field(&yaks, "api", "context.md", "# API");
+        let mut state = refreshed_state(&yaks);
+        state.start_capture();
+        for c in "retries flaky".chars() {
+            state.handle_prompt_key(&KeyWithModifier::new(BareKey::Char(c)));
+        }
+        state.handle_prompt_key(&KeyWithModifier::new(BareKey::Enter));
+        state.capture_note("api", "  ");
+
+        let context = fs::read_to_string(yaks.join("api/context.md")).unwrap();
+        let lines: Vec<&str> = context.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "# API");
+        assert!(lines[1].starts_with("- [20"));
+        assert!(lines[1].ends_with("] retries flaky"));
+        assert_eq!(state.mode, Mode::Normal);
+    }
 
-        let repo = TaskRepository::new(yaks.clone());
+    #[test]
+    fn parent_completion_can_be_offered_instead() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/a");
         let mut state = State {
-            repository: repo,
+            repository: TaskRepository::new(yaks.clone()),
+            config: Config {
+                complete_parents: ParentCompletion::Ask,
+                ..Config::default()
+            },
             ..Default::default()
         };
         state.refresh_tasks();
+        set_field(&yaks, "epic/a", "state", "done");
+        state.refresh_tasks();
+        let Mode::Picker(picker) = &state.mode else {
+            panic!("completion not offered");
+        };
+        assert_eq!(picker.items[0].label, "Mark epic done");
+        state.handle_picker_key(&KeyWithModifier::new(BareKey::Enter));
+        assert_eq!(
+            state.repository.get_field("epic", "state").as_deref(),
+            Some("done")
+        );
+        assert_eq!(ParentCompletion::parse("ASK"), Some(ParentCompletion::Ask));
+    }
 
-        let grandchild = state.tasks.iter().find(|t| t.name == "grandchild").unwrap();
-        // parent "child" has no siblings, so empty continuation column + connector
-        let prefix = state.tree_prefix(grandchild);
-        assert_eq!(prefix, "  \x1b[90m╰─\x1b[0m");
+    #[test]
+    fn status_trail_shows_one_char_per_bucket() {
+        let now = 10_000;
+        let history: std::collections::VecDeque<_> =
+            vec![(now - 500, '·'), (now - 250, 'w'), (now - 90, 'b')]
+                .into_iter()
+                .collect();
+        assert_eq!(status_trail(&history, now), "···wwwbb");
     }
 
     #[test]
-    fn tree_prefix_depth_3_shows_two_continuation_columns() {
-        let (_temp, yaks) = mock_yaks();
-        // a/b/c/d at depth 3; b has sibling b2, c has no sibling
-        create_task(&yaks, "a/b/c/d");
-        create_task(&yaks, "a/b2");
+    fn status_trail_is_blank_before_first_sample() {
+        let history: std::collections::VecDeque<_> = vec![(1_000, 'w')].into_iter().collect();
+        assert_eq!(
+            status_trail(&history, 1_000 + 2 * STATUS_TRAIL_BUCKET_SECS),
+            "     www"
+        );
+    }
 
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
-        };
-        state.refresh_tasks();
+    #[test]
+    fn record_status_history_only_appends_changes() {
+        let mut state = state_with_tasks(1);
+        state.tasks[0].agent_status = Some("wip: building".to_string());
+        state.record_status_history(100);
+        state.record_status_history(200);
+        state.tasks[0].agent_status = Some("blocked: tests".to_string());
+        state.record_status_history(300);
+
+        let history: Vec<_> = state.status_history["task-0"].iter().copied().collect();
+        assert_eq!(history, vec![(100, 'w'), (300, 'b')]);
+    }
 
-        let d = state.tasks.iter().find(|t| t.name == "d").unwrap();
-        // Columns: [grandparent b has siblings → │ ] [parent c has no siblings → "  "] then ╰─
-        let prefix = state.tree_prefix(d);
+    #[test]
+    fn command_templates_and_viewer_options() {
+        let template = split_words("nvim +{line} -u 'my init.lua'");
+        let command = command_from_template(&template, "/t/c.md", 12);
+        assert_eq!(command.path, PathBuf::from("nvim"));
+        assert_eq!(command.args, vec!["+12", "-u", "my init.lua", "/t/c.md"]);
+
+        let template = split_words("code --goto {file}:{line}");
+        let command = command_from_template(&template, "/t/c.md", 3);
+        assert_eq!(command.args, vec!["--goto", "/t/c.md:3"]);
+
+        let mut map = BTreeMap::new();
+        assert_eq!(Config::from_map(&map).editor, None);
+        assert_eq!(Config::from_map(&map).pager_pane, PanePlacement::Floating);
+        map.insert("pager".to_string(), "glow -p".to_string());
+        map.insert("pager_pane".to_string(), "in-place".to_string());
+        let config = Config::from_map(&map);
         assert_eq!(
-            prefix,
-            "\x1b[90m│ \x1b[0m  \x1b[90m╰─\x1b[0m"
+            config.pager,
+            Some(vec!["glow".to_string(), "-p".to_string()])
         );
+        assert_eq!(config.pager_pane, PanePlacement::InPlace);
+        map.insert("editor".to_string(), "  ".to_string());
+        map.insert("editor_tiled".to_string(), "true".to_string());
+        let config = Config::from_map(&map);
+        assert_eq!(config.editor, None);
+        assert!(config.editor_tiled);
     }
 
     #[test]
-    fn render_task_wip_shows_green_bullet() {
+    fn file_manager_runs_in_the_task_directory() {
+        let mut map = BTreeMap::new();
+        assert_eq!(Config::from_map(&map).file_manager, None);
+        map.insert("file_manager".to_string(), "yazi --client-id 1".to_string());
+        let words = Config::from_map(&map).file_manager.unwrap();
+        let command = file_manager_command(&words, std::path::Path::new(".yaks/api"));
+        assert_eq!(command.path, PathBuf::from("yazi"));
+        assert_eq!(command.args, vec!["--client-id", "1"]);
+        assert_eq!(command.cwd, Some(PathBuf::from(".yaks/api")));
+    }
+
+    #[test]
+    fn shell_commands_run_with_the_selected_task_in_the_environment() {
+        let dir = std::path::Path::new(".yaks/api");
+        let command = shell_command("ls $YAK_DIR", "api-1", "api", dir);
+        assert_eq!(command.path, PathBuf::from("env"));
+        assert_eq!(
+            command.args,
+            vec![
+                "YAK_ID=api-1",
+                "YAK_PATH=api",
+                "YAK_DIR=.yaks/api",
+                "sh",
+                "-c",
+                "ls $YAK_DIR"
+            ]
+        );
+
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-        set_field(&yaks, "my-task", "state", "wip");
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        state.run_command_line("!");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Usage: :!<shell command>")
+        );
+        state.run_command_line("! ls $YAK_DIR ");
+        let (_, logged) = state.log.back().unwrap();
+        assert_eq!(logged, "run: ls $YAK_DIR (for api)");
+    }
 
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
-        };
-        state.refresh_tasks();
+    #[test]
+    fn actions_substitute_task_variables() {
+        let mut map = BTreeMap::new();
+        map.insert("action.test".to_string(), "cargo test -p {id}".to_string());
+        map.insert(
+            "action.notes".to_string(),
+            "sh -c 'ls {dir}/notes'".to_string(),
+        );
+        let config = Config::from_map(&map);
+        assert_eq!(
+            config.actions.keys().collect::<Vec<_>>(),
+            vec!["notes", "test"]
+        );
 
-        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
-        let rendered = state.render_task(task);
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        state.config = config;
+        let dir = std::path::Path::new(".yaks/api");
+        let command = action_command(&state.config.actions["test"], &state.tasks[0], dir);
+        assert_eq!(command.path, PathBuf::from("cargo"));
+        assert_eq!(
+            command.args,
+            vec!["test", "-p", state.tasks[0].yak_id.as_str()]
+        );
+        let command = action_command(&state.config.actions["notes"], &state.tasks[0], dir);
+        assert_eq!(command.args, vec!["-c", "ls .yaks/api/notes"]);
 
-        assert!(rendered.contains("●"), "rendered: {:?}", rendered);
+        state.open_action_picker();
+        let Mode::Picker(picker) = &state.mode else {
+            panic!("expected the action picker");
+        };
+        assert_eq!(picker.items[1].value, "test");
     }
 
     #[test]
-    fn render_task_done_shows_strikethrough() {
+    fn pinned_tasks_are_listed_above_the_tree_regardless_of_filters() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-        set_field(&yaks, "my-task", "state", "done");
+        for path in ["api/retries", "docs"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "api/retries");
+        state.toggle_pinned();
+        assert_eq!(
+            state
+                .repository
+                .get_field("api/retries", "pinned")
+                .as_deref(),
+            Some("true")
+        );
+        assert_eq!(state.toast_message.as_deref(), Some("Pinned retries"));
 
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
-        };
+        state.filter = Some("docs".to_string());
+        state.config.plain = true;
+        assert_eq!(
+            state.pinned_lines(),
+            vec![(1, "* retries  (api/retries)".to_string())]
+        );
+
+        state.undo_last();
         state.refresh_tasks();
+        assert!(state.pinned_lines().is_empty());
+    }
 
-        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
-        let rendered = state.render_task(task);
+    #[test]
+    fn right_click_opens_a_context_menu_for_the_row() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["api/retries", "docs"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        state.render_tree(24, 80);
+        let line = state
+            .row_tasks
+            .iter()
+            .position(|&task| task == Some(1))
+            .unwrap();
+        assert!(state.handle_mouse(Mouse::RightClick(line as isize, 3)));
+        assert_eq!(selected_path(&state), "api/retries");
+        let Mode::Picker(picker) = &state.mode else {
+            panic!("expected the context menu");
+        };
+        assert_eq!(picker.purpose, PickerPurpose::ContextMenu);
 
-        assert!(rendered.contains("\x1b[9m"));
-        assert!(rendered.contains("my-task"));
-        assert!(rendered.contains("\x1b[0m"));
+        state.mode = Mode::Normal;
+        state.apply_picker(PickerPurpose::ContextMenu, "delete");
+        let Mode::Preview(preview) = &state.mode else {
+            panic!("expected a preview");
+        };
+        assert_eq!(preview.title, "Delete 1 task?");
+        assert_eq!(strip_ansi(&preview.lines[0]), "- api/retries");
+        state.confirm_preview();
+        assert!(!yaks.join("api/retries").exists());
+        assert!(state.tasks.iter().all(|t| t.path != "api/retries"));
+        assert!(!state.handle_mouse(Mouse::LeftClick(100, 0)));
     }
 
     #[test]
-    fn render_task_todo_shows_white() {
+    fn pasted_lines_become_subtasks_after_confirming() {
+        let lines: Vec<_> = outline_lines("- Fix retries\n\n* [ ] Write docs \r\nShip it!\n")
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        assert_eq!(lines, vec!["Fix retries", "Write docs", "Ship it!"]);
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
+        create_task(&yaks, "api/fix-retries");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "api");
+
+        let context = BTreeMap::from([
+            ("action".to_string(), "paste".to_string()),
+            ("task".to_string(), "api".to_string()),
+        ]);
+        assert!(state.receive_paste(Some(0), b"Fix retries\nnotes\n", b"", &context));
+        let Mode::Picker(picker) = state.mode.clone() else {
+            panic!("expected a confirmation");
         };
-        state.refresh_tasks();
+        let labels: Vec<_> = picker
+            .items
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect();
+        assert_eq!(
+            labels,
+            vec!["Create 2 tasks", "  only Fix retries", "  only notes"]
+        );
 
-        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
-        let rendered = state.render_task(task);
+        state.mode = Mode::Normal;
+        state.apply_picker(PickerPurpose::Paste, &picker.items[0].value);
+        let Mode::Preview(preview) = &state.mode else {
+            panic!("expected a preview");
+        };
+        let lines: Vec<_> = preview.lines.iter().map(|line| strip_ansi(line)).collect();
+        assert_eq!(lines, vec!["+ api/fix-retries-2", "+ api/notes-2"]);
+        state.confirm_preview();
+        assert_eq!(state.toast_message.as_deref(), Some("Created 2 tasks"));
+        assert_eq!(selected_path(&state), "api/fix-retries-2");
+        let task = state
+            .tasks
+            .iter()
+            .find(|t| t.path == "api/notes-2")
+            .unwrap();
+        assert_eq!((task.name.as_str(), task.state), ("notes", TaskState::Todo));
 
-        assert!(rendered.contains("○"));
-        assert!(rendered.contains("\x1b[37m"));
+        assert!(state.receive_paste(Some(1), b"", b"xclip: not found\n", &context));
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Couldn't read the clipboard: xclip: not found")
+        );
     }
 
     #[test]
-    fn refresh_tasks_sets_is_last_sibling() {
-        let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "task-a");
-        create_task(&yaks, "task-b");
-        create_task(&yaks, "task-c");
+    fn indented_outlines_are_imported_as_a_subtree() {
+        let outline = "Launch\n  Docs\n    Guide\n  - Blog\n\tTweet\nRetro\n";
+        assert_eq!(
+            outline_lines(outline),
+            vec![
+                (0, "Launch".to_string()),
+                (1, "Docs".to_string()),
+                (2, "Guide".to_string()),
+                (1, "Blog".to_string()),
+                (2, "Tweet".to_string()),
+                (0, "Retro".to_string()),
+            ]
+        );
 
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
+        let (temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        fs::write(temp.path().join("plan.txt"), outline).unwrap();
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "api");
+        state.run_command_line("import plan.txt");
+        let Mode::Picker(picker) = state.mode.clone() else {
+            panic!("expected a confirmation");
         };
-        state.refresh_tasks();
-
-        let task_a = state.tasks.iter().find(|t| t.name == "task-a").unwrap();
-        let task_b = state.tasks.iter().find(|t| t.name == "task-b").unwrap();
-        let task_c = state.tasks.iter().find(|t| t.name == "task-c").unwrap();
+        assert_eq!(picker.title, "Import under api");
+        assert_eq!(picker.items[3].label, "  only     Guide");
 
-        assert!(!task_a.is_last_sibling);
-        assert!(!task_b.is_last_sibling);
-        assert!(task_c.is_last_sibling);
+        state.mode = Mode::Normal;
+        state.apply_picker(PickerPurpose::Paste, &picker.items[0].value);
+        let Mode::Preview(preview) = &state.mode else {
+            panic!("expected a preview");
+        };
+        assert_eq!(strip_ansi(&preview.lines[2]), "+ api/launch/docs/guide");
+        state.confirm_preview();
+        let paths: Vec<_> = state.tasks.iter().map(|t| t.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "api",
+                "api/launch",
+                "api/launch/blog",
+                "api/launch/blog/tweet",
+                "api/launch/docs",
+                "api/launch/docs/guide",
+                "api/retro",
+            ]
+        );
     }
 
     #[test]
-    fn render_task_with_assignment_shows_agent() {
+    fn dragging_a_row_onto_a_task_moves_it_there() {
         let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "my-task");
-        set_field(&yaks, "my-task", "assigned-to", "bob");
+        for path in ["api/retries", "docs/retries"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        state.render_tree(24, 80);
+        let line = |state: &State, path: &str| {
+            let index = state.tasks.iter().position(|t| t.path == path).unwrap();
+            state
+                .row_tasks
+                .iter()
+                .position(|&task| task == Some(index))
+                .unwrap() as isize
+        };
 
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            ..Default::default()
+        assert!(state.handle_mouse(Mouse::LeftClick(line(&state, "docs/retries"), 2)));
+        assert!(state.handle_mouse(Mouse::Hold(line(&state, "docs"), 2)));
+        let docs = state.tasks.iter().find(|t| t.path == "docs").unwrap();
+        assert_eq!(strip_ansi(state.drop_marker(docs)), " ✗");
+        assert!(state.handle_mouse(Mouse::Hold(line(&state, "api"), 2)));
+        let api = state.tasks.iter().find(|t| t.path == "api").unwrap();
+        assert_eq!(strip_ansi(state.drop_marker(api)), " ⇐ drop here");
+
+        assert!(state.handle_mouse(Mouse::Release(line(&state, "api"), 2)));
+        assert_eq!(state.drag, None);
+        let Mode::Preview(preview) = &state.mode else {
+            panic!("expected a preview");
         };
-        state.refresh_tasks();
+        assert_eq!(
+            strip_ansi(&preview.lines[0]),
+            "> docs/retries → api/retries-2"
+        );
+        assert!(yaks.join("docs/retries").exists());
+        state.confirm_preview();
+        assert!(yaks.join("api/retries-2").is_dir());
+        assert!(!yaks.join("docs/retries").exists());
+        assert_eq!(selected_path(&state), "api/retries-2");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Moved retries under api")
+        );
 
-        let task = state.tasks.iter().find(|t| t.name == "my-task").unwrap();
-        assert!(
-            task.assigned_to.is_some(),
-            "assigned_to: {:?}",
-            task.assigned_to
+        state.move_task("api", "api/retries");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Can't move api under api/retries")
         );
+    }
 
-        let rendered = state.render_task(task);
-        assert!(rendered.contains("bob"), "rendered: {:?}", rendered);
+    #[test]
+    fn table_view_sorts_by_the_chosen_column() {
+        let (_temp, yaks) = mock_yaks();
+        for (path, state, due) in [("a", "done", "2026-03-01"), ("b", "todo", "2026-01-01")] {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "state", state);
+            set_field(&yaks, path, "due", due);
+        }
+        create_task(&yaks, "c");
+        set_field(&yaks, "c", "state", "wip");
+        let mut state = refreshed_state(&yaks);
+        state.config.selection = SelectionStyle::Marker;
+        let paths = |state: &State| -> Vec<String> {
+            state
+                .table_tasks()
+                .iter()
+                .map(|&i| state.tasks[i].path.clone())
+                .collect()
+        };
+        assert_eq!(paths(&state), vec!["a", "b", "c"]);
+
+        state.cycle_table_sort(TableColumn::State);
+        assert_eq!(paths(&state), vec!["b", "c", "a"]);
+        state.cycle_table_sort(TableColumn::State);
+        assert_eq!(paths(&state), vec!["a", "c", "b"]);
+
+        // After the "  " marker gutter come 4-wide task, 5-wide state and
+        // 12-wide assignee columns, each followed by a space
+        assert_eq!(state.table_column_at(13), Some(TableColumn::Assignee));
+        state.view = View::Table;
+        assert!(state.handle_mouse(Mouse::LeftClick(1, 26)));
+        assert_eq!(state.table_sort, Some((TableColumn::Due, false)));
+        assert_eq!(paths(&state), vec!["c", "b", "a"]);
     }
 
     #[test]
-    fn refresh_tasks_handles_empty_directory() {
+    fn tabs_filter_the_tree_and_keep_their_own_selection() {
         let (_temp, yaks) = mock_yaks();
+        let tasks = [
+            ("api", "wip"),
+            ("api/retries", "todo"),
+            ("docs", "wip"),
+            ("old", "done"),
+        ];
+        for (path, state) in tasks {
+            create_task(&yaks, path);
+            set_field(&yaks, path, "state", state);
+        }
+        set_field(&yaks, "docs", "assigned-to", "alice");
+        let mut map = BTreeMap::new();
+        map.insert("tab.Alice".to_string(), "wip todo @alice".to_string());
+        map.insert("tab.bad".to_string(), "nonsense".to_string());
+        let mut state = refreshed_state(&yaks);
+        state.config = Config::from_map(&map);
+        let names: Vec<_> = state.tabs().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(
+            names,
+            vec!["All", "WIP", "Blocked", "Done", "Mine", "Alice"]
+        );
 
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            selected_index: 5,
-            ..Default::default()
+        let visible = |state: &State| -> Vec<String> {
+            state
+                .visible
+                .iter()
+                .map(|&i| state.tasks[i].path.clone())
+                .collect()
         };
-        state.refresh_tasks();
+        select_path(&mut state, "old");
+        state.switch_tab(1);
+        assert_eq!(visible(&state), vec!["api", "docs"]);
+        select_path(&mut state, "docs");
+        state.switch_tab(-2);
+        assert_eq!(state.tab, 5);
+        assert_eq!(visible(&state), vec!["docs"]);
+        state.switch_tab(1);
+        assert_eq!(selected_path(&state), "old");
+        state.switch_tab(1);
+        assert_eq!(selected_path(&state), "docs");
+        state.config.plain = true;
+        assert!(strip_ansi(&state.tab_strip()).contains("[WIP]"));
+    }
 
-        assert!(state.tasks.is_empty());
-        assert_eq!(state.selected_index, 0);
+    #[test]
+    fn split_words_honours_quotes() {
+        assert_eq!(
+            split_words(r#"status api-1 "wip: running tests" 'x y'"#),
+            vec!["status", "api-1", "wip: running tests", "x y"]
+        );
     }
 
     #[test]
-    fn escape_single_quoted_empty() {
-        assert_eq!(escape_single_quoted(""), "''");
+    fn parse_pipe_command_reads_status() {
+        assert_eq!(
+            parse_pipe_command("status api-1 wip: running tests"),
+            Ok(PipeCommand::Status {
+                yak_id: "api-1".to_string(),
+                status: "wip: running tests".to_string(),
+            })
+        );
+        assert!(parse_pipe_command("status").is_err());
+        assert!(parse_pipe_command("explode now").is_err());
     }
 
     #[test]
-    fn escape_single_quoted_no_special() {
-        assert_eq!(escape_single_quoted("foo-bar"), "'foo-bar'");
+    fn pipe_status_writes_field_and_flashes_row() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "infra/api");
+        set_field(&yaks, "infra/api", "id", "api-a1b2");
+        let mut state = refreshed_state(&yaks);
+
+        let reply = state.handle_pipe_command(PipeCommand::Status {
+            yak_id: "api-a1b2".to_string(),
+            status: "wip: running tests".to_string(),
+        });
+        assert!(reply.is_ok());
+        let task = state.tasks.iter().find(|t| t.path == "infra/api").unwrap();
+        assert_eq!(task.agent_status.as_deref(), Some("wip: running tests"));
+        assert!(state.flashed.contains_key("infra/api"));
+
+        state.tick_flashes();
+        state.tick_flashes();
+        assert!(state.flashed.is_empty());
     }
 
     #[test]
-    fn escape_single_quoted_with_single_quote() {
-        assert_eq!(escape_single_quoted("it's"), "'it'\\''s'");
+    fn pipe_status_rejects_unknown_yak() {
+        let mut state = state_with_tasks(1);
+        let reply = state.handle_pipe_command(PipeCommand::Status {
+            yak_id: "nope".to_string(),
+            status: "wip: x".to_string(),
+        });
+        assert_eq!(reply, Err("no such yak: nope".to_string()));
     }
 
     #[test]
-    fn get_task_uses_id_file_when_present() {
-        let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "parent/my-task");
-        set_field(&yaks, "parent/my-task", "id", "my-task-a1b2");
+    fn config_reads_notify_backends() {
+        let defaults = Config::from_map(&BTreeMap::new());
+        assert_eq!(
+            defaults.notify_backends(NotifyEvent::Blocked),
+            vec![NotifyBackend::Toast]
+        );
+        assert!(defaults.notify_backends(NotifyEvent::Done).is_empty());
+        assert_eq!(defaults.notify_command, "notify-send");
 
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("parent/my-task", 1);
+        let mut map = BTreeMap::new();
+        map.insert(
+            "notify.blocked".to_string(),
+            "toast, osc777,command".to_string(),
+        );
+        map.insert("notify.done".to_string(), "osc9".to_string());
+        map.insert("notify.overdue".to_string(), "toast,bell".to_string());
+        map.insert(
+            "notify_command".to_string(),
+            "terminal-notifier -message".to_string(),
+        );
+        let config = Config::from_map(&map);
+        assert_eq!(
+            config.notify_backends(NotifyEvent::Blocked),
+            vec![
+                NotifyBackend::Toast,
+                NotifyBackend::Osc777,
+                NotifyBackend::Command
+            ]
+        );
+        assert_eq!(
+            config.notify_backends(NotifyEvent::Done),
+            vec![NotifyBackend::Osc9]
+        );
+        assert_eq!(
+            config.notify_backends(NotifyEvent::Overdue),
+            vec![NotifyBackend::Toast, NotifyBackend::Bell]
+        );
+        assert_eq!(config.notify_command, "terminal-notifier -message");
 
-        assert_eq!(task.yak_id, "my-task-a1b2");
+        map.insert("notify.blocked".to_string(), "off".to_string());
+        assert!(Config::from_map(&map)
+            .notify_backends(NotifyEvent::Blocked)
+            .is_empty());
     }
 
     #[test]
-    fn get_task_falls_back_to_leaf_slug_for_id_when_id_file_absent() {
-        let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "parent/my-task");
+    fn status_changes_to_blocked_raise_notifications() {
+        let mut state = state_with_tasks(2);
+        state.tasks[0].agent_status = Some("blocked: already".to_string());
+        state.record_status_history(100);
+        assert!(state.notifications.is_empty());
+
+        state.tasks[1].agent_status = Some("blocked: needs review".to_string());
+        state.tasks[1].assigned_to = Some("claude".to_string());
+        state.record_status_history(200);
+        assert_eq!(
+            state.notifications,
+            vec![Notification {
+                event: NotifyEvent::Blocked,
+                title: "Agent blocked".to_string(),
+                body: "claude on task-1: blocked: needs review".to_string(),
+            }]
+        );
 
-        let repo = TaskRepository::new(yaks);
-        let task = repo.get_task("parent/my-task", 1);
+        // The command backend runs notify_command with title and body as $1 and $2
+        let map = BTreeMap::from([
+            ("notify.blocked".to_string(), "command".to_string()),
+            ("notify_command".to_string(), "notify-send".to_string()),
+        ]);
+        state.config = Config::from_map(&map);
+        assert_eq!(
+            state.config.notify_backends(NotifyEvent::Blocked),
+            vec![NotifyBackend::Command]
+        );
+        assert_eq!(
+            notify_command_args(&state.config.notify_command, &state.notifications[0]),
+            vec![
+                "sh",
+                "-c",
+                "notify-send \"$1\" \"$2\"",
+                "sh",
+                "Agent blocked",
+                "claude on task-1: blocked: needs review",
+            ]
+        );
+        state.dispatch_notifications();
+        assert!(state.notifications.is_empty());
+        let (_, logged) = state.log.back().unwrap();
+        assert_eq!(logged, "run: notify-send (Agent blocked)");
+    }
 
-        assert_eq!(task.yak_id, "my-task");
+    #[test]
+    fn osc_notification_strips_control_characters() {
+        let notification = Notification {
+            event: NotifyEvent::Blocked,
+            title: "Agent; blocked".to_string(),
+            body: "bad\x07body".to_string(),
+        };
+        assert_eq!(
+            osc_notification(NotifyBackend::Osc777, &notification).as_deref(),
+            Some("\x1b]777;notify;Agent, blocked;badbody\x07")
+        );
+        assert_eq!(
+            osc_notification(NotifyBackend::Osc9, &notification).as_deref(),
+            Some("\x1b]9;Agent; blocked: badbody\x07")
+        );
+        assert_eq!(osc_notification(NotifyBackend::Toast, &notification), None);
     }
 
     #[test]
-    fn refresh_tasks_selected_index_bounded() {
-        let (_temp, yaks) = mock_yaks();
-        create_task(&yaks, "task-a");
+    fn fit_color_downgrades_to_terminal_depth() {
+        let rgb = "\x1b[38;2;255;136;0m";
+        assert_eq!(fit_color(rgb, ColorDepth::TrueColor), rgb);
+        assert_eq!(fit_color(rgb, ColorDepth::Ansi256), "\x1b[38;5;208m");
+        assert_eq!(fit_color(rgb, ColorDepth::Ansi16), "\x1b[33m");
+        assert_eq!(fit_color("\x1b[48;5;250m", ColorDepth::Ansi16), "\x1b[47m");
+        assert_eq!(
+            fit_color("\x1b[1;38;5;1m", ColorDepth::Ansi16),
+            "\x1b[1;31m"
+        );
+        assert_eq!(fit_color("\x1b[31m", ColorDepth::Ansi16), "\x1b[31m");
+    }
 
-        let repo = TaskRepository::new(yaks.clone());
-        let mut state = State {
-            repository: repo,
-            selected_index: 10,
-            ..Default::default()
+    #[test]
+    fn color_depth_detects_from_environment() {
+        assert_eq!(
+            ColorDepth::detect(Some("truecolor"), None),
+            ColorDepth::TrueColor
+        );
+        assert_eq!(
+            ColorDepth::detect(None, Some("xterm-256color")),
+            ColorDepth::Ansi256
+        );
+        assert_eq!(ColorDepth::detect(None, Some("linux")), ColorDepth::Ansi16);
+        assert_eq!(ColorDepth::detect(None, None), ColorDepth::Ansi256);
+    }
+
+    #[test]
+    fn plain_mode_renders_state_as_text_without_escapes() {
+        let mut map = BTreeMap::new();
+        map.insert("plain".to_string(), "true".to_string());
+        let state = State {
+            config: Config::from_map(&map),
+            ..State::default()
         };
-        state.refresh_tasks();
+        let task = TaskLine {
+            name: "api".to_string(),
+            depth: 2,
+            assigned_to: Some("alice".to_string()),
+            agent_status: Some("blocked: waiting on review".to_string()),
+            ..TaskLine::default()
+        };
+        assert_eq!(
+            state.render_task(&task),
+            "    [BLOCKED] api [alice]: waiting on review"
+        );
 
-        assert_eq!(state.selected_index, 0);
+        let done = TaskLine {
+            name: "docs".to_string(),
+            state: TaskState::Done,
+            ..TaskLine::default()
+        };
+        assert_eq!(state.render_task(&done), "[DONE] docs");
     }
 
     #[test]
-    fn highlight_line_uses_explicit_bg_not_reverse_video() {
-        let state = State::default();
-        let result = state.highlight_line("hello", "   ");
-        assert!(result.starts_with("\x1b[48;5;237m"), "should start with explicit bg: {:?}", result);
-        assert!(!result.contains("\x1b[7m"), "should not use reverse video: {:?}", result);
-        assert!(result.ends_with("\x1b[0m"), "should end with reset: {:?}", result);
+    fn zoom_shows_only_the_selected_subtree() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "epic/story/leaf");
+        create_task(&yaks, "epic/story2");
+        create_task(&yaks, "epic-two");
+        let mut state = refreshed_state(&yaks);
+        select_path(&mut state, "epic/story");
+
+        state.handle_sequence('z', &KeyWithModifier::new(BareKey::Char('i')));
+        let shown: Vec<&str> = state
+            .visible
+            .iter()
+            .map(|&i| state.tasks[i].path.as_str())
+            .collect();
+        assert_eq!(shown, vec!["epic/story", "epic/story/leaf"]);
+        assert_eq!(selected_path(&state), "epic/story");
+        let leaf = state
+            .tasks
+            .iter()
+            .find(|t| t.path == "epic/story/leaf")
+            .unwrap();
+        assert_eq!(strip_ansi(&state.tree_prefix(leaf)), "╰─");
+
+        state.zoom_out();
+        assert_eq!(state.zoom.as_deref(), Some("epic"));
+        assert_eq!(state.visible.len(), 4);
+        state.zoom_out();
+        assert_eq!(state.zoom, None);
+        assert_eq!(state.visible.len(), 5);
     }
 
     #[test]
-    fn highlight_line_reestablishes_bg_after_reset() {
-        let state = State::default();
-        // A line that contains a reset mid-way (e.g. from colored text)
-        let line = "\x1b[32mfoo\x1b[0mbar";
-        let result = state.highlight_line(line, "");
-        // After each \x1b[0m the bg color should be re-established
-        assert!(result.contains("\x1b[0m\x1b[48;5;237m"), "bg not re-established after reset: {:?}", result);
+    fn read_only_refuses_changes_and_marks_the_header() {
+        let (_temp, yaks) = mock_yaks();
+        create_task(&yaks, "api");
+        let mut state = refreshed_state(&yaks);
+        state.apply_configuration(BTreeMap::from([(
+            "read_only".to_string(),
+            "true".to_string(),
+        )]));
+
+        assert!(state.store_field("api", "state", Some("done")).is_err());
+        assert!(!yaks.join("api/state").exists());
+        assert!(state.repository.add_task("", "new").is_err());
+        assert!(!yaks.join("new").exists());
+        state.run_shell_command("touch x");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Read-only: shell commands disabled")
+        );
+        state.edit_selected_task_context();
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Read-only: editing disabled")
+        );
+        assert!(!yaks.join("api/context.md").exists());
+        state.open_selected_task_url();
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Read-only: the opener disabled")
+        );
+        state.run_command_line("view save triage");
+        assert_eq!(
+            state.toast_message.as_deref(),
+            Some("Read-only: saving views disabled")
+        );
+        assert!(state.views.is_empty());
+        state.config.notify_command = "notify-send".to_string();
+        state
+            .config
+            .notify
+            .insert(NotifyEvent::Blocked, vec![NotifyBackend::Command]);
+        state.notifications.push(Notification {
+            event: NotifyEvent::Blocked,
+            title: "Agent blocked".to_string(),
+            body: "api".to_string(),
+        });
+        state.dispatch_notifications();
+        assert!(!state
+            .log
+            .iter()
+            .any(|(_, line)| line.starts_with("run: notify-send")));
+        assert!(strip_ansi(&state.header_line().unwrap()).contains("Read-only"));
     }
 
     #[test]
-    fn highlight_line_padding_uses_same_bg() {
-        let state = State::default();
-        let result = state.highlight_line("hi", "     ");
-        // The bg is set at start before both the text and the padding
-        let bg = "\x1b[48;5;237m";
-        assert!(result.starts_with(bg));
-        // padding is inside the bg scope (before the final reset)
-        let reset_pos = result.rfind("\x1b[0m").unwrap();
-        assert!(reset_pos == result.len() - "\x1b[0m".len(), "final reset should be at end: {:?}", result);
+    fn deleting_tagged_tasks_previews_every_path_first() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["api/retries", "api/docs", "web"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        state
+            .tagged
+            .extend(["api".to_string(), "api/docs".to_string(), "web".to_string()]);
+
+        state.confirm_delete();
+        let Mode::Preview(preview) = state.mode.clone() else {
+            panic!("expected a preview");
+        };
+        assert_eq!(preview.title, "Delete 4 tasks?");
+        assert_eq!(
+            preview.change,
+            PendingChange::Delete(vec!["api".into(), "web".into()])
+        );
+        state.handle_key(KeyWithModifier::new(BareKey::Esc));
+        assert_eq!(state.mode, Mode::Normal);
+        assert!(yaks.join("api/docs").exists());
+
+        state.mode = Mode::Preview(preview);
+        state.handle_key(KeyWithModifier::new(BareKey::Enter));
+        assert_eq!(state.toast_message.as_deref(), Some("Deleted 2 tasks"));
+        assert!(!yaks.join("api").exists() && !yaks.join("web").exists());
+        assert!(state.tagged.is_empty());
+    }
+
+    #[test]
+    fn deleted_tasks_go_to_the_trash_and_can_be_restored() {
+        let (_temp, yaks) = mock_yaks();
+        for path in ["api/retries", "api/docs"] {
+            create_task(&yaks, path);
+        }
+        let mut state = refreshed_state(&yaks);
+        state.delete_tasks(&["api/retries".to_string()]);
+        state.delete_tasks(&["api".to_string()]);
+        assert!(!yaks.join("api").exists());
+
+        state.run_command_line("trash");
+        assert_eq!(state.view, View::Trash);
+        let paths: Vec<_> = state
+            .trash
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .collect();
+        assert_eq!(paths, vec!["api", "api/retries"]);
+        assert!(yaks
+            .join(TRASH_DIR)
+            .join(&state.trash[0].stamp)
+            .join("api/docs")
+            .is_dir());
+
+        // Its parent is still in the trash, so it comes back at the top level
+        state.view_selected = 1;
+        state.handle_key(KeyWithModifier::new(BareKey::Enter));
+        assert_eq!(state.toast_message.as_deref(), Some("Restored retries"));
+        assert!(yaks.join("retries").is_dir());
+        assert_eq!(state.trash.len(), 1);
+
+        let today = day_number(now_secs(), 0);
+        assert_eq!(state.repository.empty_trash(today).unwrap(), 0);
+        assert_eq!(state.repository.empty_trash(today + 1).unwrap(), 1);
+        assert!(state.repository.trash_entries().is_empty());
+
+        // A subtask deleted after its old parent does not land inside it
+        create_task(&yaks, "web");
+        state.repository.remove_task("web").unwrap();
+        create_task(&yaks, "web/ui");
+        state.repository.remove_task("web/ui").unwrap();
+        let entries = state.repository.trash_entries();
+        assert_eq!(entries.len(), 2);
+        assert_ne!(entries[0].stamp, entries[1].stamp);
     }
 }
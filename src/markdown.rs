@@ -0,0 +1,275 @@
+//! A small markdown-to-ANSI renderer for showing context.md inside the
+//! plugin. It styles the handful of constructs task notes actually use —
+//! headers, fenced code, checkboxes, bullets, quotes and inline emphasis —
+//! and passes everything else through unchanged.
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Render markdown source as one styled line per source line. Fence lines
+/// themselves are dropped; code in a fence tagged with a known language is
+/// highlighted, other code is dimmed.
+pub fn render(source: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    // `Some(syntax)` inside a fence, `None` outside.
+    let mut fence: Option<Option<Syntax>> = None;
+    for line in source.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence = match fence {
+                Some(_) => None,
+                None => Some(Syntax::for_language(trimmed[3..].trim())),
+            };
+            continue;
+        }
+        match &fence {
+            Some(Some(syntax)) => lines.push(format!("  {}", syntax.highlight(line))),
+            Some(None) => lines.push(format!("{}  {}{}", DIM, line, RESET)),
+            None => lines.push(render_line(line)),
+        }
+    }
+    lines
+}
+
+/// Keywords and comment syntax of a fenced code language.
+struct Syntax {
+    keywords: &'static [&'static str],
+    line_comment: &'static str,
+}
+
+impl Syntax {
+    fn for_language(tag: &str) -> Option<Syntax> {
+        let (keywords, line_comment): (&'static [&'static str], _) =
+            match tag.to_ascii_lowercase().as_str() {
+                "rust" | "rs" => (
+                    &[
+                        "as", "break", "const", "continue", "else", "enum", "false", "fn", "for",
+                        "if", "impl", "in", "let", "loop", "match", "mod", "mut", "pub", "return",
+                        "self", "Self", "struct", "trait", "true", "type", "use", "where", "while",
+                    ],
+                    "//",
+                ),
+                "python" | "py" => (
+                    &[
+                        "and", "as", "class", "def", "elif", "else", "False", "for", "from", "if",
+                        "import", "in", "is", "lambda", "None", "not", "or", "pass", "return",
+                        "True", "while", "with", "yield",
+                    ],
+                    "#",
+                ),
+                "sh" | "bash" | "shell" | "zsh" | "console" => (
+                    &[
+                        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for",
+                        "function", "if", "in", "local", "then", "while",
+                    ],
+                    "#",
+                ),
+                "javascript" | "js" | "typescript" | "ts" => (
+                    &[
+                        "async",
+                        "await",
+                        "class",
+                        "const",
+                        "else",
+                        "export",
+                        "false",
+                        "for",
+                        "function",
+                        "if",
+                        "import",
+                        "interface",
+                        "let",
+                        "new",
+                        "null",
+                        "return",
+                        "this",
+                        "true",
+                        "type",
+                        "undefined",
+                        "var",
+                        "while",
+                    ],
+                    "//",
+                ),
+                "go" => (
+                    &[
+                        "defer",
+                        "else",
+                        "false",
+                        "for",
+                        "func",
+                        "go",
+                        "if",
+                        "import",
+                        "interface",
+                        "nil",
+                        "package",
+                        "range",
+                        "return",
+                        "struct",
+                        "true",
+                        "type",
+                        "var",
+                    ],
+                    "//",
+                ),
+                _ => return None,
+            };
+        Some(Syntax {
+            keywords,
+            line_comment,
+        })
+    }
+
+    /// Color keywords, strings, numbers and a trailing line comment.
+    fn highlight(&self, line: &str) -> String {
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(c) = rest.chars().next() {
+            if rest.starts_with(self.line_comment) {
+                out.push_str(&format!("\x1b[90m{}{}", rest, RESET));
+                break;
+            }
+            let token_len = if c == '"' || c == '\'' {
+                let close = rest[1..].find(c).map_or(rest.len(), |i| i + 2);
+                out.push_str(&format!("\x1b[32m{}{}", &rest[..close], RESET));
+                close
+            } else if c.is_alphanumeric() || c == '_' {
+                let len = rest
+                    .find(|ch: char| !(ch.is_alphanumeric() || ch == '_'))
+                    .unwrap_or(rest.len());
+                let word = &rest[..len];
+                if self.keywords.contains(&word) {
+                    out.push_str(&format!("\x1b[35m{}{}", word, RESET));
+                } else if c.is_ascii_digit() {
+                    out.push_str(&format!("\x1b[36m{}{}", word, RESET));
+                } else {
+                    out.push_str(word);
+                }
+                len
+            } else {
+                out.push(c);
+                c.len_utf8()
+            };
+            rest = &rest[token_len..];
+        }
+        out
+    }
+}
+
+fn render_line(line: &str) -> String {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let trimmed = line.trim_start();
+
+    let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+    if (1..=6).contains(&hashes) && trimmed[hashes..].starts_with(' ') {
+        let title = trimmed[hashes..].trim();
+        let underline = if hashes == 1 { "\x1b[4m" } else { "" };
+        return format!("{}{}{}{}", BOLD, underline, render_inline(title), RESET);
+    }
+    if let Some(quote) = trimmed.strip_prefix('>') {
+        return format!("{}{}│ {}{}", indent, DIM, quote.trim_start(), RESET);
+    }
+    for bullet in ["- ", "* ", "+ "] {
+        let Some(item) = trimmed.strip_prefix(bullet) else {
+            continue;
+        };
+        if let Some(text) = item.strip_prefix("[ ] ") {
+            return format!("{}☐ {}", indent, render_inline(text));
+        }
+        if let Some(text) = item
+            .strip_prefix("[x] ")
+            .or_else(|| item.strip_prefix("[X] "))
+        {
+            return format!(
+                "{}\x1b[32m☑\x1b[0m {}{}{}",
+                indent,
+                DIM,
+                render_inline(text),
+                RESET
+            );
+        }
+        return format!("{}• {}", indent, render_inline(item));
+    }
+    format!("{}{}", indent, render_inline(trimmed))
+}
+
+/// Style `**bold**` and `` `code` `` spans; unmatched markers stay literal.
+fn render_inline(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    loop {
+        let bold = rest.find("**");
+        let code = rest.find('`');
+        let (start, marker, style) = match (bold, code) {
+            (Some(b), Some(c)) if c < b => (c, "`", DIM),
+            (Some(b), _) => (b, "**", BOLD),
+            (None, Some(c)) => (c, "`", DIM),
+            (None, None) => break,
+        };
+        let inner_start = start + marker.len();
+        let Some(len) = rest[inner_start..].find(marker) else {
+            out.push_str(&rest[..inner_start]);
+            rest = &rest[inner_start..];
+            continue;
+        };
+        out.push_str(&rest[..start]);
+        out.push_str(&format!(
+            "{}{}{}",
+            style,
+            &rest[inner_start..inner_start + len],
+            RESET
+        ));
+        rest = &rest[inner_start + len + marker.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styles_headers_code_and_checkboxes() {
+        let lines = render(
+            "# Plan\n- [ ] write **tests**\n- [x] ship\n* note\n```\nlet x = 1;\n```\n> why\n",
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "\x1b[1m\x1b[4mPlan\x1b[0m".to_string(),
+                "☐ write \x1b[1mtests\x1b[0m".to_string(),
+                "\x1b[32m☑\x1b[0m \x1b[2mship\x1b[0m".to_string(),
+                "• note".to_string(),
+                "\x1b[2m  let x = 1;\x1b[0m".to_string(),
+                "\x1b[2m│ why\x1b[0m".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_fences_tagged_with_a_known_language() {
+        let lines = render("```rust\nlet n = 42; // \"why\"\n```\n```text\nlet n\n```");
+        assert_eq!(
+            lines,
+            vec![
+                "  \x1b[35mlet\x1b[0m n = \x1b[36m42\x1b[0m; \x1b[90m// \"why\"\x1b[0m".to_string(),
+                "\x1b[2m  let n\x1b[0m".to_string(),
+            ]
+        );
+        let python = Syntax::for_language("py").unwrap();
+        assert_eq!(
+            python.highlight("def f(): return 'a#b'"),
+            "\x1b[35mdef\x1b[0m f(): \x1b[35mreturn\x1b[0m \x1b[32m'a#b'\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn leaves_unmatched_markers_and_plain_text_alone() {
+        assert_eq!(render_inline("a ** b `c` d"), "a ** b \x1b[2mc\x1b[0m d");
+        assert_eq!(render_line("#hashtag"), "#hashtag");
+        assert_eq!(render_line("  indented"), "  indented");
+    }
+}